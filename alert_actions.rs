@@ -0,0 +1,407 @@
+use std::collections::{HashMap, VecDeque};
+use sysinfo::Pid;
+use crate::alerts::{parse_duration, AlertEvent, AlertRule, AlertState};
+use crate::pause_resume::ProcessController;
+use crate::priority;
+use crate::process_groups::ProcessGroupManager;
+use crate::signal::{self, ProcessHandle};
+use crate::snapshot::ProcessSnapshot;
+use crate::stop::{self, StopJob, StopPolicies};
+
+/// What to do to the process an alert fired for
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ActionKind {
+    Pause,
+    /// Pause the process and all of its descendants
+    GroupPause,
+    Renice(i32),
+    /// Stop the process by its stop policy, signal after signal until it exits
+    Stop,
+    /// Send one configured signal
+    Kill(i32),
+}
+
+impl ActionKind {
+    /// e.g. "renice to +10", for messages
+    pub fn describe(&self) -> String {
+        match self {
+            ActionKind::Pause => "pause".to_string(),
+            ActionKind::GroupPause => "pause the group of".to_string(),
+            ActionKind::Renice(nice) => format!("renice to {:+}", nice),
+            ActionKind::Stop => "stop".to_string(),
+            ActionKind::Kill(signo) => format!("send {} to", signal::signal_name(*signo)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ActionMode {
+    /// Only report what would have been done
+    DryRun,
+    /// Ask before acting (in the TUI; headless runs skip the action)
+    Confirm,
+    Auto,
+}
+
+/// An action attached to an alert rule
+#[derive(Clone, Debug)]
+pub struct AlertAction {
+    pub kind: ActionKind,
+    pub mode: ActionMode,
+    /// At most `limit` actions per `per` seconds for the rule
+    pub limit: usize,
+    pub per: u64,
+}
+
+impl AlertAction {
+    /// Build from the rule file fields `action`, `nice`, `signal`, `mode` and `limit`
+    pub fn parse(
+        action: &str,
+        nice: Option<i32>,
+        signal_name: Option<&str>,
+        mode: Option<&str>,
+        limit: Option<&str>,
+    ) -> Result<Self, String> {
+        let kind = match action.to_lowercase().as_str() {
+            "pause" => ActionKind::Pause,
+            "group-pause" | "group_pause" => ActionKind::GroupPause,
+            "renice" => match nice {
+                Some(nice) if (-20..=19).contains(&nice) => ActionKind::Renice(nice),
+                Some(nice) => return Err(format!("nice must be between -20 and 19, got {}", nice)),
+                None => return Err("renice needs a 'nice' value".to_string()),
+            },
+            "kill" => match signal_name {
+                Some(name) => ActionKind::Kill(
+                    signal::parse_signal(name).ok_or_else(|| format!("unknown signal '{}'", name))?,
+                ),
+                None => ActionKind::Stop,
+            },
+            other => {
+                return Err(format!("unknown action '{}' (expected pause, group-pause, renice or kill)", other))
+            },
+        };
+        if nice.is_some() && !matches!(kind, ActionKind::Renice(_)) {
+            return Err("'nice' only applies to the renice action".to_string());
+        }
+        if signal_name.is_some() && !matches!(kind, ActionKind::Kill(_)) {
+            return Err("'signal' only applies to the kill action".to_string());
+        }
+
+        // Safe by default: a rule has to opt in to acting on its own
+        let mode = match mode.map(|m| m.to_lowercase()).as_deref() {
+            None | Some("dry-run") | Some("dry_run") => ActionMode::DryRun,
+            Some("confirm") => ActionMode::Confirm,
+            Some("auto") => ActionMode::Auto,
+            Some(other) => return Err(format!("unknown mode '{}' (expected dry-run, confirm or auto)", other)),
+        };
+
+        let (limit, per) = match limit {
+            Some(text) => parse_limit(text)?,
+            None => (3, 3600),
+        };
+        Ok(AlertAction { kind, mode, limit, per })
+    }
+}
+
+// "3/1h" -> (3, 3600)
+fn parse_limit(text: &str) -> Result<(usize, u64), String> {
+    let (count, per) = text
+        .split_once('/')
+        .ok_or_else(|| format!("invalid limit '{}' (expected e.g. 3/1h)", text))?;
+    let count = count
+        .trim()
+        .parse::<usize>()
+        .map_err(|_| format!("invalid limit '{}' (expected e.g. 3/1h)", text))?;
+    Ok((count, parse_duration(per)?))
+}
+
+/// An action waiting for the user to confirm it
+#[derive(Debug)]
+pub struct PendingAction {
+    pub rule: String,
+    pub pid: u32,
+    pub process: String,
+    pub kind: ActionKind,
+    /// When the alert fired, seconds since the epoch
+    pub time: u64,
+    /// When the process started, as the alert saw it
+    pub start_time: u64,
+    /// The process the alert fired for, opened right then so that a PID reused
+    /// since is never acted on
+    pub handle: ProcessHandle,
+}
+
+impl PendingAction {
+    pub fn describe(&self) -> String {
+        format!("{} {} (PID {})", self.kind.describe(), self.process, self.pid)
+    }
+}
+
+#[derive(Debug)]
+pub enum ActionOutcome {
+    Done(String),
+    DryRun(String),
+    NeedsConfirm(PendingAction),
+    Skipped(String),
+    Failed(String),
+}
+
+impl ActionOutcome {
+    /// One line for the notifications area or the log
+    pub fn message(&self) -> String {
+        match self {
+            ActionOutcome::Done(message) | ActionOutcome::Skipped(message) | ActionOutcome::Failed(message) => {
+                message.clone()
+            },
+            ActionOutcome::DryRun(message) => format!("[dry-run] {}", message),
+            ActionOutcome::NeedsConfirm(pending) => {
+                format!("{}: waiting for confirmation to {}", pending.rule, pending.describe())
+            },
+        }
+    }
+}
+
+/// The primitives actions are carried out with, so a paused process shows up
+/// (and is resumed on exit) like one paused by hand
+pub struct ActionContext<'a> {
+    pub controller: &'a mut ProcessController,
+    pub groups: &'a mut ProcessGroupManager,
+    pub processes: &'a [ProcessSnapshot],
+    pub physical_cores: usize,
+}
+
+/// Runs alert actions and enforces each rule's rate limit
+pub struct ActionRunner {
+    // Times actions were carried out, per rule
+    executed: HashMap<String, VecDeque<u64>>,
+    stop_policies: StopPolicies,
+    // Stops still climbing their ladder, with the rule that started them
+    stops: Vec<(String, StopJob)>,
+}
+
+impl ActionRunner {
+    pub fn new() -> Self {
+        ActionRunner {
+            executed: HashMap::new(),
+            // A broken config is reported where processes are killed by hand
+            stop_policies: StopPolicies::load().unwrap_or_default(),
+            stops: Vec::new(),
+        }
+    }
+
+    /// How the stops started by earlier actions ended, once they have
+    pub fn finished_stops(&mut self) -> Vec<ActionOutcome> {
+        let mut outcomes = Vec::new();
+        let mut i = 0;
+        while i < self.stops.len() {
+            if !self.stops[i].1.is_finished() {
+                i += 1;
+                continue;
+            }
+            let (rule, job) = self.stops.remove(i);
+            let report = job.finish();
+            let message = format!("{}: {}", rule, report.headline());
+            outcomes.push(if report.stopped() { ActionOutcome::Done(message) } else { ActionOutcome::Failed(message) });
+        }
+        outcomes
+    }
+
+    /// React to an alert event; `None` when the rule has no action or nothing fired
+    pub fn handle(&mut self, event: &AlertEvent, rule: &AlertRule, context: &mut ActionContext) -> Option<ActionOutcome> {
+        let action = rule.action.as_ref()?;
+        if event.state != AlertState::Fired {
+            return None;
+        }
+        let pid = event.pid?;
+        let process = event.process.clone().unwrap_or_default();
+        if let Some(reason) = protected(pid) {
+            return Some(ActionOutcome::Skipped(format!("{}: not acting on {}", rule.name, reason)));
+        }
+        let start_time = event.start_time.unwrap_or(0);
+        let handle = match ProcessHandle::open_seen(pid, start_time) {
+            Ok(handle) => handle,
+            Err(_) => {
+                return Some(ActionOutcome::Skipped(format!("{}: {} (PID {}) is no longer running", rule.name, process, pid)))
+            },
+        };
+        let pending = PendingAction { rule: rule.name.clone(), pid, process, kind: action.kind, time: event.time, start_time, handle };
+
+        if !self.allowed(&rule.name, action, event.time) {
+            return Some(ActionOutcome::Skipped(format!(
+                "{}: rate limit of {} actions per {} reached, not going to {}",
+                rule.name,
+                action.limit,
+                crate::alerts::format_duration(action.per),
+                pending.describe()
+            )));
+        }
+
+        Some(match action.mode {
+            ActionMode::DryRun => ActionOutcome::DryRun(format!("{}: would {}", rule.name, pending.describe())),
+            ActionMode::Confirm => ActionOutcome::NeedsConfirm(pending),
+            ActionMode::Auto => self.execute(&pending, action, context),
+        })
+    }
+
+    /// Carry out an action, e.g. once the user confirmed it
+    pub fn execute(&mut self, pending: &PendingAction, action: &AlertAction, context: &mut ActionContext) -> ActionOutcome {
+        // The process may have exited, or its PID been reused, since the alert fired
+        if !pending.handle.is_alive() {
+            return ActionOutcome::Skipped(format!(
+                "{}: {} (PID {}) is no longer running",
+                pending.rule, pending.process, pending.pid
+            ));
+        }
+        if !self.allowed(&pending.rule, action, crate::history::now()) {
+            return ActionOutcome::Skipped(format!("{}: rate limit reached, not going to {}", pending.rule, pending.describe()));
+        }
+
+        // Every action goes through the handle, never the bare PID
+        let result = match pending.kind {
+            ActionKind::Pause if context.controller.is_paused(&Pid::from_u32(pending.pid)) => Ok(()),
+            ActionKind::Pause => pending
+                .handle
+                .try_clone()
+                .map_err(|e| e.to_string())
+                .and_then(|handle| context.controller.pause(handle)),
+            ActionKind::GroupPause => pending
+                .handle
+                .try_clone()
+                .map_err(|e| e.to_string())
+                .and_then(|handle| context.groups.pause_group_of(context.processes, handle))
+                .map(|_| ()),
+            ActionKind::Renice(nice) => priority::renice(&pending.handle, nice).map(|_| ()).map_err(|e| e.desc().to_string()),
+            ActionKind::Kill(signo) => pending.handle.send_number(signo).map_err(|e| e.to_string()),
+            ActionKind::Stop => return self.start_stop(pending, context),
+        };
+
+        match result {
+            Ok(()) => {
+                self.executed.entry(pending.rule.clone()).or_default().push_back(crate::history::now());
+                ActionOutcome::Done(format!("{}: did {}", pending.rule, pending.describe()))
+            },
+            Err(e) => ActionOutcome::Failed(format!("{}: failed to {}: {}", pending.rule, pending.describe(), e)),
+        }
+    }
+
+    /// Let the stops still running finish, e.g. before exiting
+    pub fn wait_for_stops(&mut self) {
+        for (_, job) in self.stops.drain(..) {
+            job.finish();
+        }
+    }
+
+    // The stop runs on its own thread; `finished_stops` tells how it ended
+    fn start_stop(&mut self, pending: &PendingAction, context: &mut ActionContext) -> ActionOutcome {
+        if self.stops.iter().any(|(_, job)| job.pid == pending.pid) {
+            return ActionOutcome::Skipped(format!("{}: {} (PID {}) is already being stopped", pending.rule, pending.process, pending.pid));
+        }
+        let policy = self.stop_policies.for_process(pending.pid, context.processes, context.physical_cores);
+        let mut pids = stop::targets(pending.pid, &policy, context.groups, context.processes);
+        // The process asked for is the one the alert saw, whatever the snapshot now says
+        if let Some(first) = pids.first_mut() {
+            first.1 = pending.start_time;
+        }
+        let message = format!("{}: stopping {} (PID {}) with {}", pending.rule, pending.process, pending.pid, policy);
+        self.stops.push((pending.rule.clone(), StopJob::start(&pending.process, pids, policy)));
+        self.executed.entry(pending.rule.clone()).or_default().push_back(crate::history::now());
+        ActionOutcome::Done(message)
+    }
+
+    fn allowed(&mut self, rule: &str, action: &AlertAction, now: u64) -> bool {
+        let times = self.executed.entry(rule.to_string()).or_default();
+        while times.front().is_some_and(|time| *time + action.per <= now) {
+            times.pop_front();
+        }
+        times.len() < action.limit
+    }
+}
+
+impl Default for ActionRunner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Processes an automatic action must never touch
+fn protected(pid: u32) -> Option<&'static str> {
+    if pid == 1 {
+        Some("PID 1")
+    } else if pid == std::process::id() {
+        Some("Pulse itself")
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::{Child, Command};
+    use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
+
+    fn pending(child: &Child, kind: ActionKind) -> PendingAction {
+        PendingAction {
+            rule: "test".to_string(),
+            pid: child.id(),
+            process: "sleep".to_string(),
+            kind,
+            time: crate::history::now(),
+            start_time: 0,
+            handle: ProcessHandle::open(child.id()).unwrap(),
+        }
+    }
+
+    fn run(pending: &PendingAction) -> ActionOutcome {
+        let action = AlertAction { kind: pending.kind, mode: ActionMode::Auto, limit: 10, per: 60 };
+        let mut context = ActionContext {
+            controller: &mut ProcessController::new(),
+            groups: &mut ProcessGroupManager::new(),
+            processes: &[],
+            physical_cores: 1,
+        };
+        ActionRunner::new().execute(pending, &action, &mut context)
+    }
+
+    #[test]
+    fn acts_on_the_process_the_alert_fired_for() {
+        let mut child = Command::new("sleep").arg("30").spawn().unwrap();
+        let pending = pending(&child, ActionKind::Kill(libc::SIGTERM));
+        assert!(matches!(run(&pending), ActionOutcome::Done(_)));
+        assert!(!child.wait().unwrap().success());
+    }
+
+    #[test]
+    fn pauses_through_the_held_handle() {
+        let mut child = Command::new("sleep").arg("30").spawn().unwrap();
+        let pending = pending(&child, ActionKind::Pause);
+        let outcome = run(&pending);
+        assert!(matches!(outcome, ActionOutcome::Done(_)), "{:?}", outcome);
+        // Blocks until the SIGSTOP has landed
+        let stopped = waitpid(nix::unistd::Pid::from_raw(child.id() as i32), Some(WaitPidFlag::WUNTRACED));
+        child.kill().unwrap();
+        assert!(matches!(stopped, Ok(WaitStatus::Stopped(_, _))), "{:?}", stopped);
+        child.wait().unwrap();
+    }
+
+    #[test]
+    fn does_not_renice_an_exited_process() {
+        let mut child = Command::new("sleep").arg("30").spawn().unwrap();
+        let handle = ProcessHandle::open(child.id()).unwrap();
+        child.kill().unwrap();
+        child.wait().unwrap();
+        assert_eq!(priority::renice(&handle, 5), Err(nix::errno::Errno::ESRCH));
+    }
+
+    #[test]
+    fn skips_a_process_that_exited_since() {
+        let mut child = Command::new("sleep").arg("30").spawn().unwrap();
+        let pending = pending(&child, ActionKind::Kill(libc::SIGTERM));
+        child.kill().unwrap();
+        child.wait().unwrap();
+        match run(&pending) {
+            ActionOutcome::Skipped(message) => assert!(message.contains("no longer running"), "{}", message),
+            other => panic!("expected a skip, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,423 @@
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use serde::Deserialize;
+use serde_json::json;
+use sysinfo::System;
+use crate::alerts::{self, AlertEvent, AlertState, Amount, Severity};
+
+// Webhooks and hooks that take longer than this are given up on
+const DEFAULT_TIMEOUT: u64 = 10;
+
+/// Where fired and resolved alerts are delivered
+#[derive(Clone, Debug)]
+pub enum SinkKind {
+    /// Append one JSON object per line
+    Log(PathBuf),
+    /// POST the JSON object to a URL
+    Webhook { url: String, headers: Vec<(String, String)> },
+    /// A desktop notification through `notify-send`
+    Notify,
+    /// Run a shell command with the alert in `PULSE_ALERT_*` environment variables
+    Exec(String),
+}
+
+#[derive(Clone, Debug)]
+pub struct AlertSink {
+    pub kind: SinkKind,
+    /// Alerts below this severity are not delivered
+    pub severity: Severity,
+    /// Whether resolved alerts are delivered too
+    pub resolved: bool,
+    pub timeout: u64,
+}
+
+impl AlertSink {
+    /// Whether this sink wants `event`
+    pub fn accepts(&self, event: &AlertEvent) -> bool {
+        event.severity >= self.severity && (self.resolved || event.state == AlertState::Fired)
+    }
+
+    /// e.g. "webhook http://localhost:9000/alerts", for listings and errors
+    pub fn describe(&self) -> String {
+        match &self.kind {
+            SinkKind::Log(path) => format!("log {}", path.display()),
+            SinkKind::Webhook { url, .. } => format!("webhook {}", url),
+            SinkKind::Notify => "notify-send".to_string(),
+            SinkKind::Exec(command) => format!("exec {}", command),
+        }
+    }
+
+    /// Deliver one alert, waiting for it to complete
+    pub fn deliver(&self, event: &AlertEvent) -> Result<(), String> {
+        let timeout = Duration::from_secs(self.timeout);
+        match &self.kind {
+            SinkKind::Log(path) => append_line(path, &event_json(event).to_string()),
+            SinkKind::Webhook { url, headers } => {
+                let mut request = ureq::post(url).timeout(timeout).set("Content-Type", "application/json");
+                for (name, value) in headers {
+                    request = request.set(name, value);
+                }
+                match request.send_string(&event_json(event).to_string()) {
+                    Ok(_) => Ok(()),
+                    Err(ureq::Error::Status(code, response)) => {
+                        Err(format!("HTTP {} {}", code, response.status_text()))
+                    },
+                    // The transport error repeats the URL, which describe() already shows
+                    Err(e) => {
+                        let message = e.to_string();
+                        Err(message.strip_prefix(&format!("{}: ", url)).unwrap_or(&message).to_string())
+                    },
+                }
+            },
+            SinkKind::Notify => {
+                let urgency = match (event.state, event.severity) {
+                    (AlertState::Resolved, _) | (_, Severity::Info) => "low",
+                    (_, Severity::Warning) => "normal",
+                    (_, Severity::Critical) => "critical",
+                };
+                let title = match event.state {
+                    AlertState::Fired => format!("Pulse: {}", event.rule),
+                    AlertState::Resolved => format!("Pulse: {} resolved", event.rule),
+                };
+                let mut command = Command::new("notify-send");
+                command.args(["--app-name=Pulse", "--urgency", urgency, &title, &event.message]);
+                run(command, timeout)
+            },
+            SinkKind::Exec(script) => {
+                let mut command = Command::new("sh");
+                command.arg("-c").arg(script).envs(event_env(event));
+                run(command, timeout)
+            },
+        }
+    }
+}
+
+/// The alert as delivered to the log and webhook sinks
+pub fn event_json(event: &AlertEvent) -> serde_json::Value {
+    json!({
+        "time": event.time,
+        "host": System::host_name(),
+        "rule": event.rule,
+        "severity": event.severity.as_str(),
+        "state": event.state.as_str(),
+        "pid": event.pid,
+        "process": event.process,
+        "metric": event.metric.as_str(),
+        "value": event.value,
+        "threshold": event.threshold,
+        "message": event.message,
+    })
+}
+
+// PULSE_ALERT_RULE etc.; PID and PROCESS are empty for rules over all matching processes
+fn event_env(event: &AlertEvent) -> Vec<(String, String)> {
+    [
+        ("RULE", event.rule.clone()),
+        ("SEVERITY", event.severity.as_str().to_string()),
+        ("STATE", event.state.as_str().to_string()),
+        ("TIME", event.time.to_string()),
+        ("PID", event.pid.map(|pid| pid.to_string()).unwrap_or_default()),
+        ("PROCESS", event.process.clone().unwrap_or_default()),
+        ("METRIC", event.metric.as_str().to_string()),
+        ("VALUE", event.value.to_string()),
+        ("THRESHOLD", event.threshold.to_string()),
+        ("MESSAGE", event.message.clone()),
+        ("JSON", event_json(event).to_string()),
+    ]
+    .into_iter()
+    .map(|(name, value)| (format!("PULSE_ALERT_{}", name), value))
+    .collect()
+}
+
+fn append_line(path: &Path, line: &str) -> Result<(), String> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent).map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+    }
+    // Reopened for every alert so the log can be rotated underneath us
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    writeln!(file, "{}", line).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+// Run a command without letting it write over the screen, killing it after `timeout`
+fn run(mut command: Command, timeout: Duration) -> Result<(), String> {
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| format!("Failed to run {:?}: {}", command.get_program(), e))?;
+
+    let deadline = Instant::now() + timeout;
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) if status.success() => return Ok(()),
+            Ok(Some(status)) => return Err(format!("exited with {}", status)),
+            Ok(None) if Instant::now() >= deadline => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(format!("timed out after {}", alerts::format_duration(timeout.as_secs())));
+            },
+            Ok(None) => thread::sleep(Duration::from_millis(50)),
+            Err(e) => return Err(e.to_string()),
+        }
+    }
+}
+
+/// Delivers alerts to the sinks on a background thread so a slow webhook or
+/// hook never holds up a refresh
+pub struct SinkDispatcher {
+    sinks: usize,
+    sender: Option<Sender<AlertEvent>>,
+    errors: Receiver<String>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl SinkDispatcher {
+    pub fn start(sinks: Vec<AlertSink>) -> Self {
+        let (sender, events) = mpsc::channel::<AlertEvent>();
+        let (error_sender, errors) = mpsc::channel();
+        let count = sinks.len();
+        let thread = (!sinks.is_empty()).then(|| {
+            thread::spawn(move || {
+                for event in events {
+                    for sink in sinks.iter().filter(|s| s.accepts(&event)) {
+                        if let Err(e) = sink.deliver(&event) {
+                            let _ = error_sender.send(format!("Alert sink {} failed: {}", sink.describe(), e));
+                        }
+                    }
+                }
+            })
+        });
+        SinkDispatcher { sinks: count, sender: Some(sender), errors, thread }
+    }
+
+    /// Number of configured sinks
+    pub fn len(&self) -> usize {
+        self.sinks
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sinks == 0
+    }
+
+    pub fn send(&self, event: &AlertEvent) {
+        if self.thread.is_some() {
+            if let Some(sender) = &self.sender {
+                let _ = sender.send(event.clone());
+            }
+        }
+    }
+
+    /// Delivery failures since the last call
+    pub fn errors(&self) -> Vec<String> {
+        self.errors.try_iter().collect()
+    }
+}
+
+impl Drop for SinkDispatcher {
+    // Finish delivering what was already sent
+    fn drop(&mut self) {
+        self.sender.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct SinkFile {
+    #[serde(default, rename = "sink")]
+    sinks: Vec<SinkSpec>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SinkSpec {
+    #[serde(rename = "type")]
+    kind: String,
+    path: Option<String>,
+    url: Option<String>,
+    headers: Option<BTreeMap<String, String>>,
+    command: Option<String>,
+    severity: Option<String>,
+    resolved: Option<bool>,
+    timeout: Option<Amount>,
+}
+
+/// Load the `[[sink]]` tables of an alerts file
+pub fn load_sinks(path: &Path) -> Result<Vec<AlertSink>, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    parse_sinks(&text).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// Sinks from the default alerts file; none when it does not exist
+pub fn load_default_sinks() -> Result<Vec<AlertSink>, String> {
+    let path = alerts::default_config_path();
+    if path.exists() {
+        load_sinks(&path)
+    } else {
+        Ok(Vec::new())
+    }
+}
+
+pub fn parse_sinks(text: &str) -> Result<Vec<AlertSink>, String> {
+    let file: SinkFile = toml::from_str(text).map_err(|e| e.to_string())?;
+    file.sinks
+        .into_iter()
+        .enumerate()
+        .map(|(index, spec)| build_sink(spec).map_err(|e| format!("sink {}: {}", index + 1, e)))
+        .collect()
+}
+
+fn build_sink(spec: SinkSpec) -> Result<AlertSink, String> {
+    let kind = match spec.kind.to_lowercase().as_str() {
+        "log" => SinkKind::Log(PathBuf::from(spec.path.as_deref().ok_or("a log sink needs a 'path'")?)),
+        "webhook" => {
+            let url = spec.url.clone().ok_or("a webhook sink needs a 'url'")?;
+            if !url.starts_with("http://") && !url.starts_with("https://") {
+                return Err(format!("invalid webhook url '{}' (expected http:// or https://)", url));
+            }
+            SinkKind::Webhook { url, headers: spec.headers.clone().unwrap_or_default().into_iter().collect() }
+        },
+        "notify" => SinkKind::Notify,
+        "exec" => SinkKind::Exec(spec.command.clone().ok_or("an exec sink needs a 'command'")?),
+        other => return Err(format!("unknown sink type '{}' (expected log, webhook, notify or exec)", other)),
+    };
+
+    // Catch fields that belong to another sink type, which would otherwise be silently ignored
+    let unused = [
+        ("path", spec.path.is_some() && !matches!(kind, SinkKind::Log(_))),
+        ("url", spec.url.is_some() && !matches!(kind, SinkKind::Webhook { .. })),
+        ("headers", spec.headers.is_some() && !matches!(kind, SinkKind::Webhook { .. })),
+        ("command", spec.command.is_some() && !matches!(kind, SinkKind::Exec(_))),
+    ];
+    if let Some((field, _)) = unused.iter().find(|(_, unused)| *unused) {
+        return Err(format!("'{}' does not apply to a {} sink", field, spec.kind));
+    }
+
+    Ok(AlertSink {
+        kind,
+        severity: spec.severity.as_deref().map(Severity::parse).transpose()?.unwrap_or(Severity::Info),
+        resolved: spec.resolved.unwrap_or(true),
+        timeout: spec.timeout.as_ref().map(Amount::seconds).transpose()?.unwrap_or(DEFAULT_TIMEOUT).max(1),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read};
+    use std::net::TcpListener;
+    use crate::alerts::Metric;
+
+    fn event(state: AlertState) -> AlertEvent {
+        AlertEvent {
+            rule: "busy".to_string(),
+            severity: Severity::Warning,
+            state,
+            time: 1_700_000_000,
+            pid: Some(42),
+            start_time: Some(1_699_990_000),
+            process: Some("worker".to_string()),
+            metric: Metric::Cpu,
+            value: 95.5,
+            threshold: 90.0,
+            message: "worker (42) cpu 95.5% > 90%".to_string(),
+        }
+    }
+
+    fn sink(kind: SinkKind) -> AlertSink {
+        AlertSink { kind, severity: Severity::Info, resolved: true, timeout: 5 }
+    }
+
+    // Answer one request with `status`, handing back its header lines and body
+    fn serve_once(listener: TcpListener, status: &'static str) -> thread::JoinHandle<(Vec<String>, String)> {
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut head = Vec::new();
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let line = line.trim_end().to_string();
+                if line.is_empty() {
+                    break;
+                }
+                head.push(line);
+            }
+            let length = head
+                .iter()
+                .find_map(|line| line.to_lowercase().strip_prefix("content-length:").map(|n| n.trim().parse::<usize>().unwrap()))
+                .unwrap_or(0);
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            write!(reader.get_mut(), "HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", status).unwrap();
+            (head, String::from_utf8(body).unwrap())
+        })
+    }
+
+    #[test]
+    fn webhook_posts_the_alert_as_json() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/alerts", listener.local_addr().unwrap());
+        let server = serve_once(listener, "200 OK");
+
+        let headers = vec![("X-Token".to_string(), "secret".to_string())];
+        sink(SinkKind::Webhook { url, headers }).deliver(&event(AlertState::Fired)).unwrap();
+
+        let (head, body) = server.join().unwrap();
+        assert_eq!(head[0], "POST /alerts HTTP/1.1");
+        assert!(head.iter().any(|line| line.eq_ignore_ascii_case("content-type: application/json")));
+        assert!(head.iter().any(|line| line.eq_ignore_ascii_case("x-token: secret")));
+        let body: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(body["rule"], "busy");
+        assert_eq!(body["severity"], "warning");
+        assert_eq!(body["state"], "fired");
+        assert_eq!(body["pid"], 42);
+        assert_eq!(body["process"], "worker");
+        assert_eq!(body["metric"], "cpu");
+        assert_eq!(body["value"], 95.5);
+        assert_eq!(body["threshold"], 90.0);
+        assert_eq!(body["time"], 1_700_000_000);
+    }
+
+    #[test]
+    fn webhook_error_status_is_reported() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let server = serve_once(listener, "503 Service Unavailable");
+
+        let result = sink(SinkKind::Webhook { url, headers: Vec::new() }).deliver(&event(AlertState::Fired));
+        server.join().unwrap();
+        assert_eq!(result, Err("HTTP 503 Service Unavailable".to_string()));
+    }
+
+    #[test]
+    fn log_sink_appends_a_json_line_per_alert() {
+        let dir = std::env::temp_dir().join(format!("pulse-sinks-{}", std::process::id()));
+        let path = dir.join("alerts.jsonl");
+        let _ = fs::remove_dir_all(&dir);
+
+        let log = sink(SinkKind::Log(path.clone()));
+        log.deliver(&event(AlertState::Fired)).unwrap();
+        log.deliver(&event(AlertState::Resolved)).unwrap();
+
+        let text = fs::read_to_string(&path).unwrap();
+        let lines: Vec<serde_json::Value> = text.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["state"], "fired");
+        assert_eq!(lines[1]["state"], "resolved");
+        assert_eq!(lines[1]["message"], "worker (42) cpu 95.5% > 90%");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
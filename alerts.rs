@@ -0,0 +1,942 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use crate::alert_actions::AlertAction;
+use crate::filter::{parse_size, ProcessFilter};
+use crate::snapshot::{ProcessSnapshot, ProcessState};
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    Warning,
+    Critical,
+}
+
+impl Severity {
+    pub(crate) fn parse(text: &str) -> Result<Self, String> {
+        match text.to_lowercase().as_str() {
+            "info" => Ok(Severity::Info),
+            "warning" | "warn" => Ok(Severity::Warning),
+            "critical" | "crit" => Ok(Severity::Critical),
+            other => Err(format!("unknown severity '{}' (expected info, warning or critical)", other)),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Critical => "critical",
+        }
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// What a rule measures. Per-process metrics are checked for every matching
+/// process separately; the others count the matching processes as a whole.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Metric {
+    /// CPU% scaled by physical cores, as shown in the process list
+    Cpu,
+    /// Memory as a percentage of total RAM
+    Mem,
+    /// Resident memory in bytes
+    Rss,
+    /// Bytes of RSS gained over the rule's `window`
+    RssGrowth,
+    Threads,
+    /// Number of matching processes in the zombie state
+    Zombies,
+    /// Number of matching processes
+    Count,
+}
+
+impl Metric {
+    fn parse(text: &str) -> Result<Self, String> {
+        match text.to_lowercase().as_str() {
+            "cpu" => Ok(Metric::Cpu),
+            "mem" => Ok(Metric::Mem),
+            "rss" => Ok(Metric::Rss),
+            "rss_growth" => Ok(Metric::RssGrowth),
+            "threads" => Ok(Metric::Threads),
+            "zombies" => Ok(Metric::Zombies),
+            "count" | "absent" => Ok(Metric::Count),
+            other => Err(format!(
+                "unknown metric '{}' (expected cpu, mem, rss, rss_growth, threads, zombies, count or absent)",
+                other
+            )),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Metric::Cpu => "cpu",
+            Metric::Mem => "mem",
+            Metric::Rss => "rss",
+            Metric::RssGrowth => "rss_growth",
+            Metric::Threads => "threads",
+            Metric::Zombies => "zombies",
+            Metric::Count => "count",
+        }
+    }
+
+    pub fn per_process(&self) -> bool {
+        matches!(self, Metric::Cpu | Metric::Mem | Metric::Rss | Metric::RssGrowth | Metric::Threads)
+    }
+
+    fn is_size(&self) -> bool {
+        matches!(self, Metric::Rss | Metric::RssGrowth)
+    }
+
+    pub fn format_value(&self, value: f64) -> String {
+        match self {
+            Metric::Cpu | Metric::Mem => format!("{:.1}%", value),
+            Metric::Rss | Metric::RssGrowth => format_size(value),
+            _ => format!("{}", value),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Direction {
+    Above,
+    Below,
+}
+
+/// A named alert rule, usually loaded from the alerts config file
+#[derive(Clone, Debug)]
+pub struct AlertRule {
+    pub name: String,
+    pub severity: Severity,
+    /// Processes the rule looks at; all of them when `None`
+    pub filter: Option<ProcessFilter>,
+    pub metric: Metric,
+    pub direction: Direction,
+    pub threshold: f64,
+    /// Level the value has to get back past before the alert resolves (hysteresis)
+    pub clear: f64,
+    /// Seconds the condition has to hold before the alert fires
+    pub sustain: u64,
+    /// Minimum seconds between two firings for the same process
+    pub cooldown: u64,
+    /// Seconds of history `rss_growth` compares against
+    pub window: u64,
+    /// What to do to the process when the rule fires
+    pub action: Option<AlertAction>,
+}
+
+impl AlertRule {
+    fn breaches(&self, value: f64) -> bool {
+        match self.direction {
+            Direction::Above => value > self.threshold,
+            Direction::Below => value < self.threshold,
+        }
+    }
+
+    fn recovered(&self, value: f64) -> bool {
+        match self.direction {
+            Direction::Above => value <= self.clear,
+            Direction::Below => value >= self.clear,
+        }
+    }
+
+    /// Short description, e.g. `cpu > 80.0% for 30s (name:java)`
+    pub fn describe(&self) -> String {
+        let op = match self.direction {
+            Direction::Above => ">",
+            Direction::Below => "<",
+        };
+        let mut text = format!("{} {} {}", self.metric.as_str(), op, self.metric.format_value(self.threshold));
+        if self.sustain > 0 {
+            text.push_str(&format!(" for {}", format_duration(self.sustain)));
+        }
+        if let Some(filter) = &self.filter {
+            text.push_str(&format!(" ({})", filter.expression()));
+        }
+        text
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AlertState {
+    Fired,
+    Resolved,
+}
+
+impl AlertState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AlertState::Fired => "fired",
+            AlertState::Resolved => "resolved",
+        }
+    }
+}
+
+/// A rule firing or resolving
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AlertEvent {
+    pub rule: String,
+    pub severity: Severity,
+    pub state: AlertState,
+    /// Seconds since the epoch
+    pub time: u64,
+    /// The process, for per-process rules
+    pub pid: Option<u32>,
+    /// When that process started, telling it apart from a later one given its PID
+    #[serde(default)]
+    pub start_time: Option<u64>,
+    pub process: Option<String>,
+    pub metric: Metric,
+    pub value: f64,
+    pub threshold: f64,
+    pub message: String,
+}
+
+// What a rule is tracking: one process (PID plus start time, so a reused PID
+// starts fresh) or the matching processes as a whole
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+enum Subject {
+    Process(u32, u64),
+    Aggregate,
+}
+
+#[derive(Default)]
+struct RuleState {
+    breach_since: Option<u64>,
+    active: bool,
+    last_fired: Option<u64>,
+    // Name of the process, to report it when it exits while firing
+    process: Option<String>,
+}
+
+/// Evaluates alert rules against every process on each refresh
+pub struct AlertEngine {
+    rules: Vec<AlertRule>,
+    states: HashMap<(usize, Subject), RuleState>,
+    rss_history: HashMap<(u32, u64), VecDeque<(u64, u64)>>,
+}
+
+impl AlertEngine {
+    pub fn new(rules: Vec<AlertRule>) -> Self {
+        AlertEngine {
+            rules,
+            states: HashMap::new(),
+            rss_history: HashMap::new(),
+        }
+    }
+
+    pub fn rules(&self) -> &[AlertRule] {
+        &self.rules
+    }
+
+    /// Change the threshold of a rule by name (used by the W shortcut). `clear`
+    /// moves with it, keeping the hysteresis gap.
+    pub fn set_threshold(&mut self, name: &str, threshold: f64) -> bool {
+        match self.rules.iter_mut().find(|r| r.name == name) {
+            Some(rule) => {
+                rule.clear = threshold + (rule.clear - rule.threshold);
+                rule.threshold = threshold;
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Number of alerts currently firing
+    pub fn active_count(&self) -> usize {
+        self.states.values().filter(|s| s.active).count()
+    }
+
+    /// Check all rules against the current processes and return what fired or resolved.
+    /// `now` is in seconds since the epoch.
+    pub fn evaluate(&mut self, now: u64, processes: &[ProcessSnapshot], physical_cores: usize) -> Vec<AlertEvent> {
+        // Threads share their process's CPU and memory, so only processes count
+        let processes: Vec<&ProcessSnapshot> = processes.iter().filter(|p| !p.is_thread).collect();
+        self.track_rss(now, &processes);
+
+        let mut events = Vec::new();
+        let mut seen = HashSet::new();
+
+        for index in 0..self.rules.len() {
+            let rule = self.rules[index].clone();
+            let matching: Vec<&ProcessSnapshot> = processes
+                .iter()
+                .copied()
+                .filter(|p| rule.filter.as_ref().is_none_or(|f| f.matches(p, physical_cores)))
+                .collect();
+
+            if rule.metric.per_process() {
+                for process in matching {
+                    let subject = Subject::Process(process.pid, process.start_time);
+                    let value = match self.process_value(&rule, process, physical_cores, now) {
+                        Some(value) => value,
+                        None => continue,
+                    };
+                    seen.insert((index, subject));
+                    if let Some(event) = self.step(index, subject, value, now, Some(process)) {
+                        events.push(event);
+                    }
+                }
+            } else {
+                let value = match rule.metric {
+                    Metric::Zombies => matching.iter().filter(|p| p.state == ProcessState::Zombie).count(),
+                    _ => matching.len(),
+                } as f64;
+                seen.insert((index, Subject::Aggregate));
+                if let Some(event) = self.step(index, Subject::Aggregate, value, now, None) {
+                    events.push(event);
+                }
+            }
+        }
+
+        // Forget processes that exited or no longer match, resolving their alerts
+        let gone: Vec<(usize, Subject)> = self.states.keys().filter(|key| !seen.contains(key)).copied().collect();
+        for key in gone {
+            let state = self.states.remove(&key);
+            if let (Some(state), (index, Subject::Process(pid, start_time))) = (state, key) {
+                if state.active {
+                    events.push(self.gone_event(index, pid, start_time, state.process.unwrap_or_default(), now));
+                }
+            }
+        }
+        events
+    }
+
+    fn process_value(&self, rule: &AlertRule, process: &ProcessSnapshot, physical_cores: usize, now: u64) -> Option<f64> {
+        Some(match rule.metric {
+            Metric::Cpu => process.cpu as f64 / physical_cores.max(1) as f64,
+            Metric::Mem => process.mem,
+            Metric::Rss => process.memory as f64,
+            Metric::Threads => process.threads as f64,
+            Metric::RssGrowth => {
+                let history = self.rss_history.get(&(process.pid, process.start_time))?;
+                let since = now.saturating_sub(rule.window);
+                let (_, oldest) = history.iter().find(|(time, _)| *time >= since)?;
+                process.memory as f64 - *oldest as f64
+            },
+            _ => return None,
+        })
+    }
+
+    // Advance one rule/subject pair; returns an event when the alert fires or resolves
+    fn step(
+        &mut self,
+        index: usize,
+        subject: Subject,
+        value: f64,
+        now: u64,
+        process: Option<&ProcessSnapshot>,
+    ) -> Option<AlertEvent> {
+        let rule = &self.rules[index];
+        let state = self.states.entry((index, subject)).or_default();
+
+        let state_change = if state.active {
+            if !rule.recovered(value) {
+                return None;
+            }
+            state.active = false;
+            state.breach_since = None;
+            AlertState::Resolved
+        } else {
+            if !rule.breaches(value) {
+                state.breach_since = None;
+                return None;
+            }
+            let since = *state.breach_since.get_or_insert(now);
+            if now.saturating_sub(since) < rule.sustain {
+                return None;
+            }
+            if state.last_fired.is_some_and(|last| now.saturating_sub(last) < rule.cooldown) {
+                return None;
+            }
+            state.active = true;
+            state.last_fired = Some(now);
+            state.process = process.map(|p| p.name.clone());
+            AlertState::Fired
+        };
+
+        let subject_text = match process {
+            Some(p) => format!("{} (PID {})", p.name, p.pid),
+            None => match &rule.filter {
+                Some(filter) => format!("processes matching '{}'", filter.expression()),
+                None => "all processes".to_string(),
+            },
+        };
+        let message = match state_change {
+            AlertState::Fired => format!(
+                "[{}] {}: {} {} is {} ({})",
+                rule.severity.as_str().to_uppercase(),
+                rule.name,
+                subject_text,
+                rule.metric.as_str(),
+                rule.metric.format_value(value),
+                rule.describe()
+            ),
+            AlertState::Resolved => format!(
+                "[RESOLVED] {}: {} {} is back to {}",
+                rule.name,
+                subject_text,
+                rule.metric.as_str(),
+                rule.metric.format_value(value)
+            ),
+        };
+
+        Some(AlertEvent {
+            rule: rule.name.clone(),
+            severity: rule.severity,
+            state: state_change,
+            time: now,
+            pid: process.map(|p| p.pid),
+            start_time: process.map(|p| p.start_time),
+            process: process.map(|p| p.name.clone()),
+            metric: rule.metric,
+            value,
+            threshold: rule.threshold,
+            message,
+        })
+    }
+
+    fn gone_event(&self, index: usize, pid: u32, start_time: u64, name: String, now: u64) -> AlertEvent {
+        let rule = &self.rules[index];
+        AlertEvent {
+            rule: rule.name.clone(),
+            severity: rule.severity,
+            state: AlertState::Resolved,
+            time: now,
+            pid: Some(pid),
+            start_time: Some(start_time),
+            message: format!("[RESOLVED] {}: {} (PID {}) exited or no longer matches", rule.name, name, pid),
+            process: Some(name),
+            metric: rule.metric,
+            value: 0.0,
+            threshold: rule.threshold,
+        }
+    }
+
+    // Keep RSS samples for as long as the longest rss_growth window needs them
+    fn track_rss(&mut self, now: u64, processes: &[&ProcessSnapshot]) {
+        let window = self
+            .rules
+            .iter()
+            .filter(|r| r.metric == Metric::RssGrowth)
+            .map(|r| r.window)
+            .max();
+        let window = match window {
+            Some(window) => window,
+            None => return,
+        };
+
+        let mut alive = HashSet::new();
+        for process in processes {
+            let key = (process.pid, process.start_time);
+            alive.insert(key);
+            let samples = self.rss_history.entry(key).or_default();
+            samples.push_back((now, process.memory));
+            while samples.front().is_some_and(|(time, _)| *time < now.saturating_sub(window)) {
+                samples.pop_front();
+            }
+        }
+        self.rss_history.retain(|key, _| alive.contains(key));
+    }
+}
+
+/// The rules used when no alerts file exists: the old fixed 10% CPU and memory thresholds
+pub fn default_rules() -> Vec<AlertRule> {
+    [("cpu", Metric::Cpu), ("memory", Metric::Mem)]
+        .into_iter()
+        .map(|(name, metric)| AlertRule {
+            name: name.to_string(),
+            severity: Severity::Warning,
+            filter: None,
+            metric,
+            direction: Direction::Above,
+            threshold: 10.0,
+            clear: 10.0,
+            sustain: 0,
+            cooldown: 60,
+            window: 0,
+            action: None,
+        })
+        .collect()
+}
+
+/// `$XDG_CONFIG_HOME/pulse`, falling back to `~/.config/pulse`
+pub fn config_dir() -> PathBuf {
+    let base = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+        .unwrap_or_else(|| PathBuf::from("/etc"));
+    base.join("pulse")
+}
+
+/// `alerts.toml` in the config directory
+pub fn default_config_path() -> PathBuf {
+    config_dir().join("alerts.toml")
+}
+
+#[derive(Deserialize)]
+struct RuleFile {
+    #[serde(default, rename = "rule")]
+    rules: Vec<RuleSpec>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleSpec {
+    name: String,
+    severity: Option<String>,
+    #[serde(rename = "match")]
+    filter: Option<String>,
+    metric: String,
+    above: Option<Amount>,
+    below: Option<Amount>,
+    clear: Option<Amount>,
+    #[serde(rename = "for")]
+    sustain: Option<Amount>,
+    cooldown: Option<Amount>,
+    window: Option<Amount>,
+    action: Option<String>,
+    nice: Option<i32>,
+    signal: Option<String>,
+    mode: Option<String>,
+    limit: Option<String>,
+}
+
+// Numbers may be written bare or with a unit: "500M", "30s"
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub(crate) enum Amount {
+    Number(f64),
+    Text(String),
+}
+
+impl Amount {
+    fn size(&self) -> Result<f64, String> {
+        match self {
+            Amount::Number(n) => Ok(*n),
+            Amount::Text(text) => parse_size(text).map(|bytes| bytes as f64),
+        }
+    }
+
+    fn number(&self) -> Result<f64, String> {
+        match self {
+            Amount::Number(n) => Ok(*n),
+            Amount::Text(text) => text
+                .trim_end_matches('%')
+                .parse()
+                .map_err(|_| format!("invalid number '{}'", text)),
+        }
+    }
+
+    pub(crate) fn seconds(&self) -> Result<u64, String> {
+        match self {
+            Amount::Number(n) if *n >= 0.0 => Ok(*n as u64),
+            Amount::Number(n) => Err(format!("invalid duration {}", n)),
+            Amount::Text(text) => parse_duration(text),
+        }
+    }
+}
+
+/// Load rules from a TOML file of `[[rule]]` tables
+pub fn load_rules(path: &Path) -> Result<Vec<AlertRule>, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    parse_rules(&text).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// Rules from the default config file, or the built-in defaults when it does not exist
+pub fn load_default_rules() -> Result<Vec<AlertRule>, String> {
+    let path = default_config_path();
+    if path.exists() {
+        load_rules(&path)
+    } else {
+        Ok(default_rules())
+    }
+}
+
+pub fn parse_rules(text: &str) -> Result<Vec<AlertRule>, String> {
+    let file: RuleFile = toml::from_str(text).map_err(|e| e.to_string())?;
+    let mut names = HashSet::new();
+    let mut rules = Vec::new();
+    for spec in file.rules {
+        if !names.insert(spec.name.clone()) {
+            return Err(format!("duplicate rule name '{}'", spec.name));
+        }
+        let name = spec.name.clone();
+        rules.push(build_rule(spec).map_err(|e| format!("rule '{}': {}", name, e))?);
+    }
+    Ok(rules)
+}
+
+fn build_rule(spec: RuleSpec) -> Result<AlertRule, String> {
+    let metric = Metric::parse(&spec.metric)?;
+    let amount = |a: &Amount| if metric.is_size() { a.size() } else { a.number() };
+
+    let (direction, threshold) = match (&spec.above, &spec.below) {
+        (Some(above), None) => (Direction::Above, amount(above)?),
+        (None, Some(below)) => (Direction::Below, amount(below)?),
+        (None, None) if spec.metric.eq_ignore_ascii_case("absent") => (Direction::Below, 1.0),
+        (None, None) => return Err("needs 'above' or 'below'".to_string()),
+        (Some(_), Some(_)) => return Err("'above' and 'below' cannot both be set".to_string()),
+    };
+    let clear = match &spec.clear {
+        Some(clear) => amount(clear)?,
+        None => threshold,
+    };
+    let valid_clear = match direction {
+        Direction::Above => clear <= threshold,
+        Direction::Below => clear >= threshold,
+    };
+    if !valid_clear {
+        return Err("'clear' must be on the healthy side of the threshold".to_string());
+    }
+
+    let window = spec.window.as_ref().map(Amount::seconds).transpose()?;
+    if metric == Metric::RssGrowth && window.is_none() {
+        return Err("rss_growth needs a 'window', e.g. window = \"5m\"".to_string());
+    }
+
+    let action = match &spec.action {
+        Some(action) => {
+            if !metric.per_process() {
+                return Err(format!("actions need a per-process metric, not '{}'", metric.as_str()));
+            }
+            Some(AlertAction::parse(
+                action,
+                spec.nice,
+                spec.signal.as_deref(),
+                spec.mode.as_deref(),
+                spec.limit.as_deref(),
+            )?)
+        },
+        None if spec.nice.is_some() || spec.signal.is_some() || spec.mode.is_some() || spec.limit.is_some() => {
+            return Err("'nice', 'signal', 'mode' and 'limit' need an 'action'".to_string());
+        },
+        None => None,
+    };
+
+    Ok(AlertRule {
+        name: spec.name,
+        severity: spec.severity.as_deref().map(Severity::parse).transpose()?.unwrap_or(Severity::Warning),
+        filter: spec.filter.as_deref().map(ProcessFilter::parse).transpose()?,
+        metric,
+        direction,
+        threshold,
+        clear,
+        sustain: spec.sustain.as_ref().map(Amount::seconds).transpose()?.unwrap_or(0),
+        cooldown: spec.cooldown.as_ref().map(Amount::seconds).transpose()?.unwrap_or(300),
+        window: window.unwrap_or(0),
+        action,
+    })
+}
+
+/// `90`, `30s`, `5m`, `1h` or `1d` as seconds
+pub fn parse_duration(text: &str) -> Result<u64, String> {
+    let text = text.trim();
+    let (number, multiplier) = match text.chars().last() {
+        Some('s') => (&text[..text.len() - 1], 1),
+        Some('m') => (&text[..text.len() - 1], 60),
+        Some('h') => (&text[..text.len() - 1], 3600),
+        Some('d') => (&text[..text.len() - 1], 86400),
+        _ => (text, 1),
+    };
+    number
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(multiplier))
+        .ok_or_else(|| format!("invalid duration '{}' (use e.g. 30s, 5m, 1h)", text))
+}
+
+pub fn format_duration(seconds: u64) -> String {
+    if seconds > 0 && seconds.is_multiple_of(3600) {
+        format!("{}h", seconds / 3600)
+    } else if seconds > 0 && seconds.is_multiple_of(60) {
+        format!("{}m", seconds / 60)
+    } else {
+        format!("{}s", seconds)
+    }
+}
+
+pub(crate) fn format_size(bytes: f64) -> String {
+    let sign = if bytes < 0.0 { "-" } else { "" };
+    let bytes = bytes.abs();
+    if bytes >= 1024.0 * 1024.0 * 1024.0 {
+        format!("{}{:.1}G", sign, bytes / 1024.0 / 1024.0 / 1024.0)
+    } else if bytes >= 1024.0 * 1024.0 {
+        format!("{}{:.1}M", sign, bytes / 1024.0 / 1024.0)
+    } else {
+        format!("{}{:.0}K", sign, bytes / 1024.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alert_actions::ActionKind;
+
+    fn process(pid: u32, name: &str, cpu: f32, memory: u64) -> ProcessSnapshot {
+        ProcessSnapshot {
+            pid,
+            ppid: Some(1),
+            name: name.to_string(),
+            cmdline: vec![name.to_string()],
+            uid: Some(1000),
+            user: "user".to_string(),
+            cpu,
+            memory,
+            virtual_memory: 0,
+            mem: 1.0,
+            nice: 0,
+            pgrp: pid as i32,
+            tpgid: -1,
+            state: ProcessState::Sleep,
+            start_time: 1_700_000_000,
+            threads: 1,
+            read_bytes: 0,
+            written_bytes: 0,
+            cgroup: String::new(),
+            is_thread: false,
+        }
+    }
+
+    fn engine(rules: &str) -> AlertEngine {
+        AlertEngine::new(parse_rules(rules).unwrap())
+    }
+
+    // What fired or resolved when `processes` are seen at `now`
+    fn states(engine: &mut AlertEngine, now: u64, processes: &[ProcessSnapshot]) -> Vec<AlertState> {
+        engine.evaluate(now, processes, 1).iter().map(|e| e.state).collect()
+    }
+
+    #[test]
+    fn fires_once_sustained_and_resolves_past_clear() {
+        let mut engine = engine(
+            r#"[[rule]]
+            name = "hot"
+            metric = "cpu"
+            above = 50
+            clear = 30
+            for = "10s"
+            cooldown = 0"#,
+        );
+        let at = |cpu| [process(42, "worker", cpu, 0)];
+
+        assert_eq!(states(&mut engine, 0, &at(60.0)), []);
+        assert_eq!(states(&mut engine, 5, &at(60.0)), []);
+        let events = engine.evaluate(10, &at(60.0), 1);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].state, AlertState::Fired);
+        assert_eq!((events[0].pid, events[0].start_time), (Some(42), Some(1_700_000_000)));
+        assert_eq!(engine.active_count(), 1);
+
+        // Below the threshold but not yet back to `clear`
+        assert_eq!(states(&mut engine, 15, &at(40.0)), []);
+        assert_eq!(states(&mut engine, 20, &at(30.0)), [AlertState::Resolved]);
+        assert_eq!(engine.active_count(), 0);
+
+        // A dip starts the sustain period over
+        assert_eq!(states(&mut engine, 100, &at(60.0)), []);
+        assert_eq!(states(&mut engine, 105, &at(10.0)), []);
+        assert_eq!(states(&mut engine, 110, &at(60.0)), []);
+        assert_eq!(states(&mut engine, 119, &at(60.0)), []);
+        assert_eq!(states(&mut engine, 120, &at(60.0)), [AlertState::Fired]);
+    }
+
+    #[test]
+    fn keeps_the_clear_gap_when_the_threshold_moves() {
+        let mut engine = engine(
+            r#"[[rule]]
+            name = "hot"
+            metric = "cpu"
+            above = 50
+            clear = 30
+
+            [[rule]]
+            name = "idle"
+            metric = "cpu"
+            below = 5
+            clear = 10"#,
+        );
+        assert!(engine.set_threshold("hot", 80.0));
+        assert!(engine.set_threshold("idle", 2.0));
+        assert!(!engine.set_threshold("missing", 1.0));
+        let limits: Vec<(f64, f64)> = engine.rules().iter().map(|r| (r.threshold, r.clear)).collect();
+        assert_eq!(limits, [(80.0, 60.0), (2.0, 7.0)]);
+    }
+
+    #[test]
+    fn waits_out_the_cooldown() {
+        let mut engine = engine(
+            r#"[[rule]]
+            name = "hot"
+            metric = "cpu"
+            above = 50
+            cooldown = "1m""#,
+        );
+        let at = |cpu| [process(42, "worker", cpu, 0)];
+
+        assert_eq!(states(&mut engine, 0, &at(60.0)), [AlertState::Fired]);
+        assert_eq!(states(&mut engine, 1, &at(10.0)), [AlertState::Resolved]);
+        assert_eq!(states(&mut engine, 2, &at(60.0)), []);
+        assert_eq!(states(&mut engine, 59, &at(60.0)), []);
+        assert_eq!(states(&mut engine, 60, &at(60.0)), [AlertState::Fired]);
+    }
+
+    #[test]
+    fn resolves_when_the_process_exits() {
+        let mut engine = engine(
+            r#"[[rule]]
+            name = "hot"
+            metric = "cpu"
+            above = 50"#,
+        );
+        assert_eq!(states(&mut engine, 0, &[process(42, "worker", 60.0, 0)]), [AlertState::Fired]);
+        let events = engine.evaluate(1, &[], 1);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].state, AlertState::Resolved);
+        assert_eq!((events[0].pid, events[0].start_time), (Some(42), Some(1_700_000_000)));
+        assert!(events[0].message.contains("exited"), "{}", events[0].message);
+    }
+
+    #[test]
+    fn counts_absent_and_zombie_processes() {
+        let mut engine = engine(
+            r#"[[rule]]
+            name = "gone"
+            match = "name:worker"
+            metric = "absent"
+
+            [[rule]]
+            name = "undead"
+            metric = "zombies"
+            above = 0"#,
+        );
+        let worker = process(42, "worker", 0.0, 0);
+        let mut zombie = process(43, "defunct", 0.0, 0);
+        zombie.state = ProcessState::Zombie;
+
+        assert_eq!(states(&mut engine, 0, std::slice::from_ref(&worker)), []);
+        let events = engine.evaluate(1, &[zombie], 1);
+        let fired: Vec<(&str, Option<u32>)> = events.iter().map(|e| (e.rule.as_str(), e.pid)).collect();
+        assert_eq!(fired, [("gone", None), ("undead", None)]);
+        assert!(events.iter().all(|e| e.state == AlertState::Fired));
+        assert_eq!(events[1].value, 1.0);
+
+        assert_eq!(states(&mut engine, 2, &[worker]), [AlertState::Resolved, AlertState::Resolved]);
+    }
+
+    #[test]
+    fn measures_rss_growth_over_the_window() {
+        let mut engine = engine(
+            r#"[[rule]]
+            name = "leak"
+            metric = "rss_growth"
+            above = "100M"
+            window = "1m""#,
+        );
+        let at = |mib: u64| [process(42, "worker", 0.0, mib << 20)];
+
+        assert_eq!(states(&mut engine, 0, &at(100)), []);
+        assert_eq!(states(&mut engine, 30, &at(150)), []);
+        let events = engine.evaluate(60, &at(250), 1);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].state, AlertState::Fired);
+        assert_eq!(events[0].value, (150 << 20) as f64);
+
+        // The 100M sample has left the window, so growth is measured from 150M
+        assert_eq!(states(&mut engine, 61, &at(250)), [AlertState::Resolved]);
+    }
+
+    #[test]
+    fn parses_rules() {
+        let rules = parse_rules(
+            r#"[[rule]]
+            name = "big"
+            severity = "critical"
+            match = "name:java"
+            metric = "rss"
+            above = "2G"
+            clear = "1.5G"
+            for = "30s"
+
+            [[rule]]
+            name = "gone"
+            metric = "absent"
+
+            [[rule]]
+            name = "nice"
+            metric = "cpu"
+            above = "90%"
+            action = "renice"
+            nice = 10"#,
+        )
+        .unwrap();
+
+        assert_eq!(rules.len(), 3);
+        let big = &rules[0];
+        assert_eq!((big.severity, big.metric, big.direction), (Severity::Critical, Metric::Rss, Direction::Above));
+        assert_eq!((big.threshold, big.clear), ((2u64 << 30) as f64, (3u64 << 29) as f64));
+        assert_eq!((big.sustain, big.cooldown), (30, 300));
+        assert_eq!(big.filter.as_ref().map(|f| f.expression()), Some("name:java"));
+
+        let gone = &rules[1];
+        assert_eq!((gone.metric, gone.direction, gone.threshold, gone.clear), (Metric::Count, Direction::Below, 1.0, 1.0));
+
+        let nice = &rules[2];
+        assert_eq!((nice.threshold, nice.clear), (90.0, 90.0));
+        assert_eq!(nice.action.as_ref().map(|a| a.kind), Some(ActionKind::Renice(10)));
+    }
+
+    #[test]
+    fn rejects_bad_rules() {
+        let rule = |body: &str| format!("[[rule]]\nname = \"r\"\n{}", body);
+        for (body, error) in [
+            ("metric = \"cpu\"", "needs 'above' or 'below'"),
+            ("metric = \"cpu\"\nabove = 5\nbelow = 1", "cannot both be set"),
+            ("metric = \"load\"\nabove = 5", "unknown metric 'load'"),
+            ("metric = \"cpu\"\nabove = 50\nclear = 60", "'clear' must be on the healthy side"),
+            ("metric = \"cpu\"\nbelow = 5\nclear = 1", "'clear' must be on the healthy side"),
+            ("metric = \"rss_growth\"\nabove = \"10M\"", "needs a 'window'"),
+            ("metric = \"rss\"\nabove = \"lots\"", "Invalid size 'lots'"),
+            ("metric = \"cpu\"\nabove = 5\nfor = \"soon\"", "invalid duration 'soon'"),
+            ("metric = \"count\"\nabove = 5\naction = \"kill\"", "actions need a per-process metric"),
+            ("metric = \"cpu\"\nabove = 5\nnice = 5", "need an 'action'"),
+            ("metric = \"cpu\"\nabove = 5\nseverity = \"dire\"", "dire"),
+            ("metric = \"cpu\"\nabove = 5\ncolour = \"red\"", "colour"),
+        ] {
+            match parse_rules(&rule(body)) {
+                Err(message) => assert!(message.contains(error), "{:?}: {}", body, message),
+                Ok(_) => panic!("{:?} was accepted", body),
+            }
+        }
+
+        let twice = format!("{}\n{}", rule("metric = \"cpu\"\nabove = 5"), rule("metric = \"mem\"\nabove = 5"));
+        assert_eq!(parse_rules(&twice).err(), Some("duplicate rule name 'r'".to_string()));
+    }
+
+    #[test]
+    fn durations() {
+        for (text, seconds) in [("30", 30), ("30s", 30), ("5m", 300), (" 1h ", 3600), ("2d", 172_800)] {
+            assert_eq!(parse_duration(text), Ok(seconds), "{:?}", text);
+        }
+        for text in ["", "m", "soon", "-5s", "1.5h", "99999999999999999999", "213503982334602d"] {
+            assert_eq!(
+                parse_duration(text),
+                Err(format!("invalid duration '{}' (use e.g. 30s, 5m, 1h)", text.trim())),
+                "{:?}",
+                text
+            );
+        }
+    }
+}
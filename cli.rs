@@ -0,0 +1,1452 @@
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use nix::errno::Errno;
+use sysinfo::Pid;
+use crate::alert_actions::{ActionContext, ActionMode, ActionOutcome, ActionRunner};
+use crate::alert_sinks::{self, AlertSink, SinkDispatcher};
+use crate::alerts::{self, AlertEngine, AlertEvent, AlertRule, AlertState, Metric, Severity};
+use crate::columns::{self, Column, ColumnConfig};
+use crate::csv_export::{self, CsvOptions, SummaryFormat};
+use crate::daemon::{self, DaemonClient, DaemonConfig, Request};
+use crate::export_diff::{DiffThresholds, ExportDiff, ImportedExport};
+use crate::exporter::{self, ExportData};
+use crate::filter::{self, ProcessFilter};
+use crate::health::{HealthCheck, Probe};
+use crate::history::{self, format_time, History, HistoryConfig, Recorder};
+use crate::json_export::{JsonStream, StreamConfig, StreamMode};
+use crate::metrics::{MetricsConfig, MetricsExporter};
+use crate::pause_resume::{ProcessAction, ProcessController};
+use crate::priority;
+use crate::process_groups::ProcessGroupManager;
+use crate::replay::Replay;
+use crate::restart::{ProcessRestarter, RestartError};
+use crate::services::ServiceManagers;
+use crate::signal::{self, SignalError};
+use crate::snapshot::{sort_snapshots, Collector, ProcessSnapshot, SortMode, SystemSummary};
+use crate::stop::{self, StopJob, StopPolicies, StopPolicy, StopStep};
+use crate::supervisor::{Supervisor, SupervisorPolicy, Target};
+use crate::tui;
+
+// Exit codes for scripts calling `pulse <command>`
+pub const EXIT_OK: i32 = 0;
+/// The action was attempted and failed
+pub const EXIT_FAILED: i32 = 1;
+/// Bad command line
+pub const EXIT_USAGE: i32 = 2;
+/// The target process does not exist
+pub const EXIT_NOT_FOUND: i32 = 3;
+/// Not allowed to act on the target process
+pub const EXIT_PERMISSION: i32 = 4;
+
+pub fn usage() -> String {
+    "Usage: pulse [COMMAND]\n\
+     \n\
+     Without a command the interactive monitor is started.\n\
+     \n\
+     Commands:\n\
+     \x20 pause <pid>...                  Stop processes with SIGSTOP\n\
+     \x20 resume <pid>...                 Continue processes with SIGCONT\n\
+     \x20 group-pause <ppid>              Pause a process and all of its descendants\n\
+     \x20 group-resume <ppid>             Resume a process and all of its descendants\n\
+     \x20 kill <pid>... [--ladder LADDER] [--subtree]\n\
+     \x20                                 Stop processes gracefully: signals in turn until they\n\
+     \x20                                 exit, by their stop policy or e.g. 'SIGINT 5s SIGKILL'\n\
+     \x20 kill <pid>... --signal SIG      Send just SIG, by name or number\n\
+     \x20 nice <pid> <value>              Set the nice value of a process\n\
+     \x20 restart <pid> [--alive DUR] [--tcp ADDR | --socket PATH | --check CMD] [--timeout DUR]\n\
+     \x20         [--retries N] [--rollback]\n\
+     \x20                                 Restart a process as it was started, and wait until\n\
+     \x20                                 it stays up for --alive (2s) and the probe answers\n\
+     \x20 service status|restart|stop <pid>...\n\
+     \x20                                 Act through the manager that owns a process:\n\
+     \x20                                 systemd, OpenRC or SysV, runit or supervisord\n\
+     \x20 keep-alive <pid|pattern>... [--backoff DUR] [--max-backoff DUR] [--max-exits N] [--window DUR]\n\
+     \x20                                 Start a process again whenever it exits, waiting\n\
+     \x20                                 --backoff (1s), doubled up to --max-backoff (1m),\n\
+     \x20                                 until it exits --max-exits (5) times in --window (1m)\n\
+     \x20 keep-alive --release <pid|pattern>...\n\
+     \x20                                 Stop keeping processes alive; they keep running\n\
+     \x20 export [--format json|csv|sqlite|parquet|html] [--filter EXPR] [--columns LIST] [--output PATH] [--at TIME]\n\
+     \x20                                 Export processes, optionally only those matching EXPR\n\
+     \x20                                 (with --at, the processes recorded at TIME)\n\
+     \x20        [--delimiter C] [--no-header] [--summary json|csv|none]\n\
+     \x20                                 CSV options; the system summary is written next to\n\
+     \x20                                 the CSV as NAME.summary.json unless changed\n\
+     \x20 export --stream [--diff] [--interval SECS] [--filter EXPR] [--columns LIST] [--output PATH]\n\
+     \x20        [--rotate-size SIZE] [--rotate-every DUR] [--keep N]\n\
+     \x20                                 Write newline-delimited JSON every interval until\n\
+     \x20                                 interrupted, to stdout unless --output is given\n\
+     \x20 diff <before> <after> [--json] [--cpu POINTS] [--mem POINTS]\n\
+     \x20                                 Compare two JSON or CSV exports: started and exited\n\
+     \x20                                 processes, and CPU, memory or nice changes\n\
+     \x20 record [--interval SECS] [--top N]\n\
+     \x20                                 Record metrics history until interrupted, with every\n\
+     \x20                                 process or only the N busiest of each sample\n\
+     \x20 history at <TIME> [--top N]     Show what was running at TIME, e.g. 03:12, -10m,\n\
+     \x20                                 \"2024-05-01 03:12\" or epoch seconds\n\
+     \x20 replay [--at TIME] [FILE...]    Browse recorded snapshots in the monitor, read-only:\n\
+     \x20                                 the metrics history (from the latest sample unless\n\
+     \x20                                 --at is given) or JSON, CSV, stream or SQLite exports\n\
+     \x20 tree                            Print the process tree\n\
+     \x20 columns [screen|export LIST]    List the columns, or save the columns (e.g.\n\
+     \x20                                 pid,user,cpu,rss,cmdline) shown on screen or exported\n\
+     \x20 alerts check [--config PATH]    Validate the alert rules and list them\n\
+     \x20 alerts watch [--config PATH] [--interval SECS]\n\
+     \x20                                 Evaluate the alert rules and print alerts as they fire\n\
+     \x20 alerts test [--config PATH]     Send a test alert to every configured sink\n\
+     \x20 metrics [--listen ADDR] [--interval SECS] [--top N | --all] [--names A,B] [--match EXPR]\n\
+     \x20                                 Serve Prometheus metrics on http://ADDR/metrics\n\
+     \x20 daemon [--socket PATH] [--interval SECS] [--config PATH] [--no-record] [--metrics ADDR]\n\
+     \x20                                 Keep recording history, evaluating alerts and\n\
+     \x20                                 tracking paused processes in the foreground\n\
+     \x20 daemon status|stop [--socket PATH]\n\
+     \x20                                 Show or stop the running daemon\n\
+     \x20 help                            Show this message\n\
+     \n\
+     While a daemon is running, pause, resume and the group commands go\n\
+     through it so it keeps track of the paused processes, and keep-alive\n\
+     hands the processes to it; otherwise keep-alive stays in the foreground.\n\
+     \n\
+     Exit codes: 0 success, 1 action failed, 2 usage error,\n\
+     3 process not found, 4 permission denied.\n"
+        .to_string()
+}
+
+/// Run a headless command and return the process exit code
+pub fn run(args: &[String]) -> i32 {
+    let (command, rest) = match args.split_first() {
+        Some((command, rest)) => (command.as_str(), rest),
+        None => {
+            eprint!("{}", usage());
+            return EXIT_USAGE;
+        },
+    };
+
+    match command {
+        "pause" => control(rest, ProcessAction::Pause),
+        "resume" => control(rest, ProcessAction::Resume),
+        "group-pause" => control_group(rest, ProcessAction::Pause),
+        "group-resume" => control_group(rest, ProcessAction::Resume),
+        "kill" => kill(rest),
+        "nice" => nice(rest),
+        "restart" => restart(rest),
+        "keep-alive" => keep_alive(rest),
+        "service" => service_command(rest),
+        "export" => export(rest),
+        "diff" => diff(rest),
+        "replay" => replay(rest),
+        "tree" => tree(rest),
+        "columns" => columns_command(rest),
+        "record" => record(rest),
+        "history" => history_query(rest),
+        "alerts" => alerts_command(rest),
+        "daemon" => daemon_command(rest),
+        "metrics" => serve_metrics(rest),
+        "help" | "--help" | "-h" => {
+            print!("{}", usage());
+            EXIT_OK
+        },
+        other => usage_error(&format!("unknown command '{}'", other)),
+    }
+}
+
+fn usage_error(message: &str) -> i32 {
+    eprintln!("pulse: {}", message);
+    eprintln!("Run 'pulse help' for usage.");
+    EXIT_USAGE
+}
+
+fn parse_pid(text: &str) -> Result<u32, i32> {
+    match text.parse::<u32>() {
+        Ok(pid) if pid > 0 => Ok(pid),
+        _ => Err(usage_error(&format!("invalid PID '{}'", text))),
+    }
+}
+
+// Collect processes with CPU usage filled in; sysinfo needs two samples for that
+fn collect() -> (Vec<ProcessSnapshot>, SystemSummary) {
+    let mut collector = Collector::new();
+    collector.refresh();
+    thread::sleep(sysinfo::MINIMUM_CPU_UPDATE_INTERVAL);
+    collector.refresh();
+    let mut processes = collector.snapshot();
+    sort_snapshots(&mut processes, SortMode::Pid);
+    let summary = collector.summary();
+    (processes, summary)
+}
+
+fn signal_exit_code(error: &SignalError) -> i32 {
+    match error {
+        SignalError::NoSuchProcess(_) | SignalError::ProcessReplaced(_) => EXIT_NOT_FOUND,
+        SignalError::PermissionDenied(_) => EXIT_PERMISSION,
+        _ => EXIT_FAILED,
+    }
+}
+
+// With several PIDs the worst outcome decides the exit code
+fn worse(current: i32, next: i32) -> i32 {
+    if current == EXIT_OK { next } else { current.max(next) }
+}
+
+fn control(args: &[String], action: ProcessAction) -> i32 {
+    if args.is_empty() {
+        return usage_error("expected at least one PID");
+    }
+    let verb = match action {
+        ProcessAction::Pause => "Paused",
+        ProcessAction::Resume => "Resumed",
+    };
+
+    let mut controller = ProcessController::new();
+    let mut daemon = DaemonClient::connect(&daemon::default_socket_path()).ok();
+    let mut code = EXIT_OK;
+    for arg in args {
+        let pid = match parse_pid(arg) {
+            Ok(pid) => pid,
+            Err(code) => return code,
+        };
+        if !signal::process_exists(pid) {
+            eprintln!("Process {} does not exist", pid);
+            code = worse(code, EXIT_NOT_FOUND);
+            continue;
+        }
+        let result = match &mut daemon {
+            // A PID given on the command line means whatever runs as it now
+            Some(daemon) => daemon.control(pid, 0, action.clone()),
+            None => controller.control_process(Pid::from_u32(pid), 0, action.clone()),
+        };
+        match result {
+            Ok(()) => println!("{} process {}", verb, pid),
+            Err(e) => {
+                eprintln!("{}", e);
+                code = worse(code, EXIT_FAILED);
+            },
+        }
+    }
+    code
+}
+
+fn control_group(args: &[String], action: ProcessAction) -> i32 {
+    let pid = match args {
+        [pid] => match parse_pid(pid) {
+            Ok(pid) => pid,
+            Err(code) => return code,
+        },
+        _ => return usage_error("expected exactly one parent PID"),
+    };
+
+    let (processes, _) = collect();
+    let Some(start_time) = processes.iter().find(|p| p.pid == pid).map(|p| p.start_time) else {
+        eprintln!("Process {} does not exist", pid);
+        return EXIT_NOT_FOUND;
+    };
+
+    let verb = match action {
+        ProcessAction::Pause => "Paused",
+        ProcessAction::Resume => "Resumed",
+    };
+    let result = match DaemonClient::connect(&daemon::default_socket_path()) {
+        Ok(mut daemon) => daemon.control_group(pid, start_time, action),
+        Err(_) => ProcessGroupManager::new().control_group(&processes, Pid::from_u32(pid), action),
+    };
+    match result {
+        Ok(count) => {
+            println!("{} {} processes in the group of {}", verb, count, pid);
+            EXIT_OK
+        },
+        Err(e) => {
+            eprintln!("{}", e);
+            EXIT_FAILED
+        },
+    }
+}
+
+fn kill(args: &[String]) -> i32 {
+    let mut signo = None;
+    let mut ladder = None;
+    let mut subtree = false;
+    let mut pids = Vec::new();
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--signal" | "-s" => {
+                let value = match iter.next() {
+                    Some(value) => value,
+                    None => return usage_error("--signal needs a value"),
+                };
+                signo = match signal::parse_signal(value) {
+                    Some(signo) => Some(signo),
+                    None => return usage_error(&format!("unknown signal '{}'", value)),
+                };
+            },
+            "--ladder" => {
+                let value = match iter.next() {
+                    Some(value) => value,
+                    None => return usage_error("--ladder needs a value"),
+                };
+                ladder = match StopPolicy::parse_ladder(value) {
+                    Ok(steps) => Some(steps),
+                    Err(e) => return usage_error(&e),
+                };
+            },
+            "--subtree" => subtree = true,
+            _ => match parse_pid(arg) {
+                Ok(pid) => pids.push(pid),
+                Err(code) => return code,
+            },
+        }
+    }
+    if pids.is_empty() {
+        return usage_error("expected at least one PID");
+    }
+    let Some(signo) = signo else { return stop_gracefully(&pids, ladder, subtree) };
+    if ladder.is_some() || subtree {
+        return usage_error("--signal sends a single signal; leave out --ladder and --subtree");
+    }
+
+    let name = signal::signal_name(signo);
+    let mut code = EXIT_OK;
+    for (pid, result) in signal::send_to_all(&pids, signo) {
+        match result {
+            Ok(()) => println!("Sent {} to process {}", name, pid),
+            Err(e) => {
+                eprintln!("{}", e);
+                code = worse(code, signal_exit_code(&e));
+            },
+        }
+    }
+    code
+}
+
+// Climb each process's stop ladder at the same time, printing each step
+fn stop_gracefully(pids: &[u32], ladder: Option<Vec<StopStep>>, subtree: bool) -> i32 {
+    let policies = StopPolicies::load().unwrap_or_else(|e| {
+        eprintln!("pulse: {}; using the default stop policy", e);
+        StopPolicies::new()
+    });
+    let (processes, summary) = collect();
+    let mut groups = ProcessGroupManager::new();
+    let mut code = EXIT_OK;
+    let mut jobs = Vec::new();
+    for &pid in pids {
+        let Some(process) = processes.iter().find(|p| p.pid == pid) else {
+            eprintln!("Process {} does not exist", pid);
+            code = worse(code, EXIT_NOT_FOUND);
+            continue;
+        };
+        let mut policy = policies.for_process(pid, &processes, summary.physical_cores);
+        if let Some(steps) = &ladder {
+            policy.steps = steps.clone();
+        }
+        policy.subtree |= subtree;
+        let targets = stop::targets(pid, &policy, &mut groups, &processes);
+        println!("Stopping {} ({}) with {}", process.name, pid, policy);
+        jobs.push((StopJob::start(&process.name, targets, policy), None));
+    }
+
+    while !jobs.is_empty() {
+        thread::sleep(Duration::from_millis(100));
+        let mut i = 0;
+        while i < jobs.len() {
+            let (job, shown) = &mut jobs[i];
+            if let Some(progress) = job.progress().filter(|p| *shown != Some(p.step)) {
+                println!("{}", progress.message());
+                *shown = Some(progress.step);
+            }
+            if !job.is_finished() {
+                i += 1;
+                continue;
+            }
+            let report = jobs.remove(i).0.finish();
+            if report.stopped() {
+                println!("{}", report.headline());
+            } else {
+                eprintln!("{}", report.headline());
+                code = worse(code, report.errors.first().map(signal_exit_code).unwrap_or(EXIT_FAILED));
+            }
+        }
+    }
+    code
+}
+
+fn nice(args: &[String]) -> i32 {
+    let (pid, value) = match args {
+        [pid, value] => (pid, value),
+        _ => return usage_error("expected <pid> <value>"),
+    };
+    let pid = match parse_pid(pid) {
+        Ok(pid) => pid,
+        Err(code) => return code,
+    };
+    let value = match value.parse::<i32>() {
+        Ok(value) if (-20..=19).contains(&value) => value,
+        _ => return usage_error(&format!("nice value must be between -20 and 19, got '{}'", value)),
+    };
+    if !signal::process_exists(pid) {
+        eprintln!("Process {} does not exist", pid);
+        return EXIT_NOT_FOUND;
+    }
+
+    match priority::set_priority(pid as i32, value) {
+        Ok(message) => {
+            println!("{}", message);
+            EXIT_OK
+        },
+        Err(e) => {
+            eprintln!("Failed to set nice value for PID {}: {}", pid, e.desc());
+            match e {
+                Errno::EPERM | Errno::EACCES => EXIT_PERMISSION,
+                Errno::ESRCH => EXIT_NOT_FOUND,
+                _ => EXIT_FAILED,
+            }
+        },
+    }
+}
+
+fn restart(args: &[String]) -> i32 {
+    let mut check = HealthCheck::new();
+    let mut pid = None;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg == "--rollback" {
+            check.rollback = true;
+            continue;
+        }
+        if !arg.starts_with("--") {
+            match (pid, parse_pid(arg)) {
+                (None, Ok(parsed)) => pid = Some(parsed),
+                (Some(_), Ok(_)) => return usage_error("expected exactly one PID"),
+                (_, Err(code)) => return code,
+            }
+            continue;
+        }
+        let value = match arg.as_str() {
+            "--alive" | "--timeout" | "--retries" | "--tcp" | "--socket" | "--check" => match iter.next() {
+                Some(value) => value.clone(),
+                None => return usage_error(&format!("{} needs a value", arg)),
+            },
+            other => return usage_error(&format!("unexpected argument '{}'", other)),
+        };
+        match arg.as_str() {
+            "--alive" | "--timeout" => match alerts::parse_duration(&value) {
+                Ok(seconds) if arg == "--alive" => check.alive_for = Duration::from_secs(seconds),
+                Ok(seconds) => check.timeout = Duration::from_secs(seconds),
+                Err(e) => return usage_error(&e),
+            },
+            "--retries" => match value.parse::<u32>() {
+                Ok(retries) => check.retries = retries,
+                Err(_) => return usage_error(&format!("invalid count '{}'", value)),
+            },
+            _ if check.probe.is_some() => return usage_error("give only one of --tcp, --socket and --check"),
+            "--tcp" => check.probe = Some(Probe::Tcp(value)),
+            "--socket" => check.probe = Some(Probe::Unix(value.into())),
+            _ => check.probe = Some(Probe::Command(value)),
+        }
+    }
+    let Some(pid) = pid else { return usage_error("expected exactly one PID") };
+
+    let mut restarter = ProcessRestarter::new();
+    restarter.health_check = check;
+    match restarter.restart_process(Pid::from_u32(pid)) {
+        Ok(report) => {
+            println!("{}", report.headline());
+            for line in report.details() {
+                println!("  {}", line);
+            }
+            EXIT_OK
+        },
+        Err(e) => {
+            eprintln!("{}", e);
+            match e {
+                RestartError::NotFound(_) | RestartError::NotRunning(_) => EXIT_NOT_FOUND,
+                _ => EXIT_FAILED,
+            }
+        },
+    }
+}
+
+fn service_command(args: &[String]) -> i32 {
+    let (action, pids) = match args.split_first() {
+        Some((action, pids)) if matches!(action.as_str(), "status" | "restart" | "stop") && !pids.is_empty() => {
+            (action.as_str(), pids)
+        },
+        _ => return usage_error("expected status, restart or stop and at least one PID"),
+    };
+    let managers = ServiceManagers::new();
+    let mut code = EXIT_OK;
+    for arg in pids {
+        let pid = match parse_pid(arg) {
+            Ok(pid) => pid,
+            Err(code) => return code,
+        };
+        if !signal::process_exists(pid) {
+            eprintln!("Process {} does not exist", pid);
+            code = worse(code, EXIT_NOT_FOUND);
+            continue;
+        }
+        let Some((backend, service)) = managers.owner(pid) else {
+            eprintln!("Process {} is not run by a service manager Pulse knows", pid);
+            code = worse(code, EXIT_FAILED);
+            continue;
+        };
+        let result = match action {
+            "status" => backend.status(&service).map(|status| {
+                let main = status.pid.map(|pid| format!(", main PID {}", pid)).unwrap_or_default();
+                format!("Process {} belongs to {} {}: {}{}", pid, backend.name(), service.name, status.detail, main)
+            }),
+            "restart" => backend.restart(&service).map(|_| format!("Restarted {} through {}", service.name, backend.name())),
+            _ => backend.stop(&service).map(|_| format!("Stopped {} through {}", service.name, backend.name())),
+        };
+        match result {
+            Ok(message) => println!("{}", message),
+            Err(e) => {
+                eprintln!("{}", e);
+                code = worse(code, EXIT_FAILED);
+            },
+        }
+    }
+    code
+}
+
+fn keep_alive(args: &[String]) -> i32 {
+    let mut policy = SupervisorPolicy::new();
+    let mut release = false;
+    let mut targets = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg == "--release" {
+            release = true;
+            continue;
+        }
+        if !arg.starts_with("--") {
+            if let Err(e) = Target::parse(arg) {
+                return usage_error(&e);
+            }
+            targets.push(arg.clone());
+            continue;
+        }
+        let value = match arg.as_str() {
+            "--backoff" | "--max-backoff" | "--window" | "--max-exits" => match iter.next() {
+                Some(value) => value,
+                None => return usage_error(&format!("{} needs a value", arg)),
+            },
+            other => return usage_error(&format!("unexpected argument '{}'", other)),
+        };
+        if arg == "--max-exits" {
+            match value.parse::<u32>() {
+                Ok(count) if count > 0 => policy.max_exits = count,
+                _ => return usage_error(&format!("invalid count '{}'", value)),
+            }
+            continue;
+        }
+        let seconds = match alerts::parse_duration(value) {
+            Ok(seconds) => Duration::from_secs(seconds),
+            Err(e) => return usage_error(&e),
+        };
+        match arg.as_str() {
+            "--backoff" => policy.backoff = seconds,
+            "--max-backoff" => policy.max_backoff = seconds,
+            _ => policy.window = seconds,
+        }
+    }
+    if targets.is_empty() {
+        return usage_error("expected at least one PID or pattern");
+    }
+    // A PID must exist now; a pattern may match later
+    if !release {
+        for target in &targets {
+            if let Ok(Target::Pid(pid)) = Target::parse(target) {
+                if !signal::process_exists(pid) {
+                    eprintln!("Process {} does not exist", pid);
+                    return EXIT_NOT_FOUND;
+                }
+            }
+        }
+    }
+
+    let mut daemon = match DaemonClient::connect(&daemon::default_socket_path()) {
+        Ok(daemon) => daemon,
+        Err(_) if release => {
+            eprintln!("No daemon is running, so nothing is being kept alive");
+            return EXIT_NOT_FOUND;
+        },
+        Err(_) => return supervise(&targets, policy),
+    };
+    let mut code = EXIT_OK;
+    for target in &targets {
+        let result = if release { daemon.release(target) } else { daemon.keep_alive(target, policy.clone()) };
+        match result {
+            Ok(message) => println!("{}", message),
+            Err(e) => {
+                eprintln!("{}", e);
+                code = worse(code, EXIT_FAILED);
+            },
+        }
+    }
+    code
+}
+
+// Keep the targets alive from this process until interrupted
+fn supervise(targets: &[String], policy: SupervisorPolicy) -> i32 {
+    let stop = Arc::new(AtomicBool::new(false));
+    for signo in [signal_hook::consts::SIGINT, signal_hook::consts::SIGTERM] {
+        if let Err(e) = signal_hook::flag::register(signo, stop.clone()) {
+            eprintln!("Failed to install signal handler: {}", e);
+            return EXIT_FAILED;
+        }
+    }
+
+    let mut collector = Collector::new();
+    collector.refresh();
+    let processes = collector.snapshot();
+    let cores = collector.summary().physical_cores;
+    let mut supervisor = Supervisor::new();
+    for target in targets {
+        match supervisor.keep_alive(target, policy.clone(), &processes, cores) {
+            Ok(message) => println!("{}", message),
+            Err(e) => eprintln!("{}", e),
+        }
+    }
+    if supervisor.is_empty() {
+        return EXIT_FAILED;
+    }
+    println!("No daemon is running; keeping them alive until interrupted (Ctrl+C to stop)");
+
+    'supervise: while !stop.load(Ordering::Relaxed) {
+        // Sleep in short steps so Ctrl+C is handled promptly
+        for _ in 0..5 {
+            if stop.load(Ordering::Relaxed) {
+                break 'supervise;
+            }
+            thread::sleep(Duration::from_millis(200));
+        }
+        collector.refresh();
+        let processes = collector.snapshot();
+        for line in supervisor.check(&processes, collector.summary().physical_cores) {
+            println!("{} {}", format_time(history::now()), line);
+        }
+        // Nothing left to look after
+        if supervisor.status().iter().all(|entry| entry.crash_loop) {
+            return EXIT_FAILED;
+        }
+    }
+    EXIT_OK
+}
+
+fn export(args: &[String]) -> i32 {
+    let mut format = "json".to_string();
+    let mut filter = None;
+    let mut output = None;
+    let mut at = None;
+    let mut stream = None;
+    let mut columns = None;
+    let mut csv_options = None;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--stream" => {
+                stream.get_or_insert_with(StreamConfig::new);
+                continue;
+            },
+            "--diff" => {
+                stream.get_or_insert_with(StreamConfig::new).mode = StreamMode::Diff;
+                continue;
+            },
+            "--no-header" => {
+                csv_options.get_or_insert_with(CsvOptions::new).header = false;
+                continue;
+            },
+            _ => {},
+        }
+        let value = match arg.as_str() {
+            "--format" | "--filter" | "--columns" | "--output" | "-o" | "--at" | "--interval" | "--rotate-size"
+            | "--rotate-every" | "--keep" | "--delimiter" | "--summary" => match iter.next() {
+                Some(value) => value.clone(),
+                None => return usage_error(&format!("{} needs a value", arg)),
+            },
+            other => return usage_error(&format!("unexpected argument '{}'", other)),
+        };
+        match arg.as_str() {
+            "--interval" => match value.parse::<u64>() {
+                Ok(seconds) if seconds > 0 => stream.get_or_insert_with(StreamConfig::new).interval = seconds,
+                _ => return usage_error(&format!("invalid interval '{}'", value)),
+            },
+            "--rotate-size" => match filter::parse_size(&value) {
+                Ok(size) if size > 0 => stream.get_or_insert_with(StreamConfig::new).rotate_size = Some(size),
+                Ok(_) => return usage_error("--rotate-size must be at least 1 byte"),
+                Err(e) => return usage_error(&e),
+            },
+            "--rotate-every" => match alerts::parse_duration(&value) {
+                Ok(seconds) if seconds > 0 => stream.get_or_insert_with(StreamConfig::new).rotate_every = Some(seconds),
+                Ok(_) => return usage_error("--rotate-every must be at least 1s"),
+                Err(e) => return usage_error(&e),
+            },
+            "--keep" => match value.parse::<usize>() {
+                Ok(keep) => stream.get_or_insert_with(StreamConfig::new).keep = keep,
+                Err(_) => return usage_error(&format!("invalid count '{}'", value)),
+            },
+            "--delimiter" => match csv_export::parse_delimiter(&value) {
+                Ok(delimiter) => csv_options.get_or_insert_with(CsvOptions::new).delimiter = delimiter,
+                Err(e) => return usage_error(&e),
+            },
+            "--summary" => match SummaryFormat::parse(&value) {
+                Ok(summary) => csv_options.get_or_insert_with(CsvOptions::new).summary = summary,
+                Err(e) => return usage_error(&e),
+            },
+            "--columns" => match columns::parse_columns(&value) {
+                Ok(parsed) => columns = Some(parsed),
+                Err(e) => return usage_error(&e),
+            },
+            "--format" => format = value.to_lowercase(),
+            "--filter" => match ProcessFilter::parse(&value) {
+                Ok(parsed) => filter = Some(parsed),
+                Err(e) => return usage_error(&e),
+            },
+            "--at" => match history::parse_time(&value) {
+                Ok(time) => at = Some(time),
+                Err(e) => return usage_error(&e),
+            },
+            _ => output = Some(value),
+        }
+    }
+
+    if csv_options.is_some() && format != "csv" {
+        return usage_error("--delimiter, --no-header and --summary only apply to --format csv");
+    }
+    let columns = match columns {
+        Some(columns) => columns,
+        None => match ColumnConfig::load() {
+            Ok(config) => config.export,
+            Err(e) => {
+                eprintln!("{}", e);
+                return EXIT_FAILED;
+            },
+        },
+    };
+
+    if let Some(mut config) = stream {
+        if !matches!(format.as_str(), "json" | "ndjson") {
+            return usage_error("--stream only writes JSON");
+        }
+        if at.is_some() {
+            return usage_error("--stream cannot be combined with --at");
+        }
+        config.columns = columns;
+        config.output = output.filter(|path| path != "-").map(std::path::PathBuf::from);
+        if config.output.is_none() && (config.rotate_size.is_some() || config.rotate_every.is_some()) {
+            return usage_error("rotation needs --output PATH");
+        }
+        return stream_export(config, filter);
+    }
+
+    let exporter = match exporter::for_format(&format, csv_options.unwrap_or_default()) {
+        Some(exporter) => exporter,
+        None => {
+            return usage_error(&format!(
+                "unknown export format '{}' (expected {})",
+                format,
+                exporter::FORMATS.join(", ")
+            ))
+        },
+    };
+    let (processes, summary) = match at {
+        Some(time) => match recorded_at(time) {
+            Ok(recorded) => recorded,
+            Err(code) => return code,
+        },
+        None => collect(),
+    };
+    let processes = match &filter {
+        Some(filter) => filter.apply(&processes, summary.physical_cores),
+        None => processes,
+    };
+
+    let time = at.unwrap_or_else(history::now);
+    let data = ExportData {
+        time,
+        processes: &processes,
+        summary: &summary,
+        columns: &columns,
+        history: if format == "html" { recent_history(time) } else { Vec::new() },
+    };
+    let path = output.unwrap_or_else(|| exporter.default_filename());
+    let result = exporter.export(&data, Path::new(&path));
+
+    match result {
+        Ok(message) => {
+            println!("{}", message);
+            EXIT_OK
+        },
+        Err(e) => {
+            eprintln!("Export failed: {}", e);
+            EXIT_FAILED
+        },
+    }
+}
+
+fn stream_export(config: StreamConfig, filter: Option<ProcessFilter>) -> i32 {
+    let stop = Arc::new(AtomicBool::new(false));
+    for signo in [signal_hook::consts::SIGINT, signal_hook::consts::SIGTERM] {
+        if let Err(e) = signal_hook::flag::register(signo, stop.clone()) {
+            eprintln!("Failed to install signal handler: {}", e);
+            return EXIT_FAILED;
+        }
+    }
+
+    let interval = config.interval;
+    if let Some(path) = &config.output {
+        eprintln!("Streaming processes to {} every {}s (Ctrl+C to stop)", path.display(), interval);
+    }
+    let mut stream = match JsonStream::open(config) {
+        Ok(stream) => stream,
+        Err(e) => {
+            eprintln!("{}", e);
+            return EXIT_FAILED;
+        },
+    };
+
+    let mut collector = Collector::new();
+    collector.refresh();
+    'stream: while !stop.load(Ordering::Relaxed) {
+        collector.refresh();
+        let processes = collector.snapshot();
+        let processes = match &filter {
+            Some(filter) => filter.apply(&processes, collector.summary().physical_cores),
+            None => processes,
+        };
+        match stream.write(history::now(), &processes) {
+            Ok(_) => {},
+            // The reader went away, e.g. `| head`
+            Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => break,
+            Err(e) => {
+                eprintln!("Export failed: {}", e);
+                return EXIT_FAILED;
+            },
+        }
+        for _ in 0..interval * 5 {
+            if stop.load(Ordering::Relaxed) {
+                break 'stream;
+            }
+            thread::sleep(Duration::from_millis(200));
+        }
+    }
+    EXIT_OK
+}
+
+fn tree(args: &[String]) -> i32 {
+    if !args.is_empty() {
+        return usage_error("tree takes no arguments");
+    }
+    let (processes, _) = collect();
+    let mut manager = ProcessGroupManager::new();
+    manager.force_update(&processes);
+    print!("{}", manager.format_process_tree());
+    EXIT_OK
+}
+
+fn columns_command(args: &[String]) -> i32 {
+    let mut config = match ColumnConfig::load() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            return EXIT_FAILED;
+        },
+    };
+    let (target, list) = match args {
+        [] => {
+            println!("{:<14} {:<8} DESCRIPTION", "NAME", "HEADER");
+            for column in Column::ALL {
+                println!("{:<14} {:<8} {}", column.name(), column.header(), column.description());
+            }
+            println!();
+            println!("screen: {}", columns::format_columns(&config.screen));
+            println!("export: {}", columns::format_columns(&config.export));
+            return EXIT_OK;
+        },
+        [target, list] if target == "screen" => (&mut config.screen, list),
+        [target, list] if target == "export" => (&mut config.export, list),
+        _ => return usage_error("expected 'columns [screen|export LIST]'"),
+    };
+    *target = if list == "default" {
+        columns::default_columns()
+    } else {
+        match columns::parse_columns(list) {
+            Ok(parsed) => parsed,
+            Err(e) => return usage_error(&e),
+        }
+    };
+    match config.save() {
+        Ok(path) => {
+            println!("Saved to {}", path.display());
+            EXIT_OK
+        },
+        Err(e) => {
+            eprintln!("{}", e);
+            EXIT_FAILED
+        },
+    }
+}
+
+// The processes stored in the history at `time`, for `export --at`
+fn recorded_at(time: u64) -> Result<(Vec<ProcessSnapshot>, SystemSummary), i32> {
+    let mut history = match History::open(&history::default_history_dir()) {
+        Ok(history) => history,
+        Err(e) => {
+            eprintln!("{}", e);
+            return Err(EXIT_FAILED);
+        },
+    };
+    match history.at(time) {
+        Some(record) => {
+            let cores = Collector::new().summary().physical_cores;
+            Ok((record.to_snapshots(), record.summary(cores)))
+        },
+        None => {
+            eprintln!("No history recorded at {}", format_time(time));
+            Err(EXIT_NOT_FOUND)
+        },
+    }
+}
+
+fn diff(args: &[String]) -> i32 {
+    let mut paths = Vec::new();
+    let mut json = false;
+    let mut thresholds = DiffThresholds::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--json" => json = true,
+            "--cpu" | "--mem" => {
+                let value = match iter.next() {
+                    Some(value) => value,
+                    None => return usage_error(&format!("{} needs a value", arg)),
+                };
+                let points = match value.parse::<f64>() {
+                    Ok(points) if points >= 0.0 => points,
+                    _ => return usage_error(&format!("invalid percentage '{}'", value)),
+                };
+                if arg == "--cpu" {
+                    thresholds.cpu = points;
+                } else {
+                    thresholds.mem = points;
+                }
+            },
+            other if other.starts_with("--") => return usage_error(&format!("unexpected argument '{}'", other)),
+            path => paths.push(path),
+        }
+    }
+    let [before, after] = paths[..] else {
+        return usage_error("expected two export files");
+    };
+
+    let result = ImportedExport::load(Path::new(before))
+        .and_then(|before| Ok((before, ImportedExport::load(Path::new(after))?)))
+        .and_then(|(before, after)| ExportDiff::compare(&before, &after, &thresholds));
+    let diff = match result {
+        Ok(diff) => diff,
+        Err(e) => {
+            eprintln!("{}", e);
+            return EXIT_FAILED;
+        },
+    };
+    if json {
+        println!("{}", serde_json::to_string_pretty(&diff.to_json()).unwrap_or_default());
+    } else {
+        print!("{}", diff.to_table());
+    }
+    EXIT_OK
+}
+
+fn replay(args: &[String]) -> i32 {
+    let mut at = None;
+    let mut files = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--at" => match iter.next().map(|value| history::parse_time(value)) {
+                Some(Ok(time)) => at = Some(time),
+                Some(Err(e)) => return usage_error(&e),
+                None => return usage_error("--at needs a value"),
+            },
+            other if other.starts_with("--") => return usage_error(&format!("unexpected argument '{}'", other)),
+            path => files.push(std::path::PathBuf::from(path)),
+        }
+    }
+
+    let result = if files.is_empty() {
+        Replay::from_history(&history::default_history_dir(), at)
+    } else {
+        Replay::from_files(&files).and_then(|mut replay| {
+            if let Some(time) = at {
+                replay.seek(time)?;
+            }
+            Ok(replay)
+        })
+    };
+    match result {
+        Ok(replay) => {
+            tui::replay(replay);
+            EXIT_OK
+        },
+        Err(e) => {
+            eprintln!("{}", e);
+            EXIT_FAILED
+        },
+    }
+}
+
+// The last hour of system samples before `time`, for the report charts
+fn recent_history(time: u64) -> Vec<history::SystemSample> {
+    match History::open(&history::default_history_dir()) {
+        Ok(mut history) => history.range(time.saturating_sub(3600), time, 120).into_iter().map(|r| r.system).collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+fn record(args: &[String]) -> i32 {
+    let mut config = HistoryConfig::default();
+    for pair in args.chunks(2) {
+        match pair {
+            [flag, value] if flag == "--interval" => match value.parse::<u64>() {
+                Ok(interval) if interval > 0 => config.interval = interval,
+                _ => return usage_error(&format!("invalid interval '{}'", value)),
+            },
+            [flag, value] if flag == "--top" => match value.parse::<usize>() {
+                Ok(top) if top > 0 => config.max_processes = Some(top),
+                _ => return usage_error(&format!("invalid count '{}'", value)),
+            },
+            _ => return usage_error("expected [--interval SECS] [--top N]"),
+        }
+    }
+
+    let stop = Arc::new(AtomicBool::new(false));
+    for signo in [signal_hook::consts::SIGINT, signal_hook::consts::SIGTERM] {
+        if let Err(e) = signal_hook::flag::register(signo, stop.clone()) {
+            eprintln!("Failed to install signal handler: {}", e);
+            return EXIT_FAILED;
+        }
+    }
+
+    let dir = config.dir.clone();
+    let mut recorder = match Recorder::start(config) {
+        Ok(recorder) => recorder,
+        Err(e) => {
+            eprintln!("{}", e);
+            return EXIT_FAILED;
+        },
+    };
+    println!("Recording metrics history to {} (Ctrl+C to stop)", dir.display());
+    while !stop.load(Ordering::Relaxed) {
+        thread::sleep(Duration::from_millis(200));
+        for error in recorder.errors() {
+            eprintln!("{}", error);
+        }
+    }
+    recorder.stop();
+    EXIT_OK
+}
+
+fn serve_metrics(args: &[String]) -> i32 {
+    let mut config = MetricsConfig::new();
+    let mut interval = 5;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg == "--all" {
+            config.top = None;
+            continue;
+        }
+        let value = match iter.next() {
+            Some(value) => value,
+            None => return usage_error(&format!("{} needs a value", arg)),
+        };
+        match arg.as_str() {
+            "--listen" => config.listen = value.clone(),
+            "--interval" => match value.parse::<u64>() {
+                Ok(seconds) if seconds > 0 => interval = seconds,
+                _ => return usage_error(&format!("invalid interval '{}'", value)),
+            },
+            "--top" => match value.parse::<usize>() {
+                Ok(top) => config.top = Some(top),
+                Err(_) => return usage_error(&format!("invalid count '{}'", value)),
+            },
+            "--names" => config.names = value.split(',').map(|n| n.trim().to_string()).filter(|n| !n.is_empty()).collect(),
+            "--match" => match ProcessFilter::parse(value) {
+                Ok(filter) => config.filter = Some(filter),
+                Err(e) => return usage_error(&e),
+            },
+            other => return usage_error(&format!("unexpected argument '{}'", other)),
+        }
+    }
+
+    let stop = Arc::new(AtomicBool::new(false));
+    for signo in [signal_hook::consts::SIGINT, signal_hook::consts::SIGTERM] {
+        if let Err(e) = signal_hook::flag::register(signo, stop.clone()) {
+            eprintln!("Failed to install signal handler: {}", e);
+            return EXIT_FAILED;
+        }
+    }
+
+    let exporter = match MetricsExporter::start(config) {
+        Ok(exporter) => exporter,
+        Err(e) => {
+            eprintln!("{}", e);
+            return EXIT_FAILED;
+        },
+    };
+    println!("Serving metrics on http://{}/metrics every {}s (Ctrl+C to stop)", exporter.address(), interval);
+
+    let mut collector = Collector::new();
+    collector.refresh();
+    'serve: while !stop.load(Ordering::Relaxed) {
+        collector.refresh();
+        exporter.update(&collector.snapshot(), &collector.summary(), collector.core_usage());
+        // Sleep in short steps so Ctrl+C is handled promptly
+        for _ in 0..interval * 5 {
+            if stop.load(Ordering::Relaxed) {
+                break 'serve;
+            }
+            thread::sleep(Duration::from_millis(200));
+        }
+    }
+    EXIT_OK
+}
+
+fn history_query(args: &[String]) -> i32 {
+    let (time, rest) = match args {
+        [sub, time, rest @ ..] if sub == "at" => (time, rest),
+        _ => return usage_error("expected 'history at <TIME> [--top N]'"),
+    };
+    let time = match history::parse_time(time) {
+        Ok(time) => time,
+        Err(e) => return usage_error(&e),
+    };
+    let top = match rest {
+        [] => 10,
+        [flag, value] if flag == "--top" => match value.parse::<usize>() {
+            Ok(top) => top,
+            Err(_) => return usage_error(&format!("invalid count '{}'", value)),
+        },
+        _ => return usage_error("expected [--top N]"),
+    };
+
+    let mut history = match History::open(&history::default_history_dir()) {
+        Ok(history) => history,
+        Err(e) => {
+            eprintln!("{}", e);
+            return EXIT_FAILED;
+        },
+    };
+    let record = match history.at(time) {
+        Some(record) => record,
+        None => {
+            eprintln!("No history recorded at {}", format_time(time));
+            return EXIT_NOT_FOUND;
+        },
+    };
+
+    let system = &record.system;
+    let gib = |bytes: u64| bytes as f64 / 1024.0 / 1024.0 / 1024.0;
+    let kind = if record.resolution >= 60 { "one-minute average" } else { "sample" };
+    println!("{} at {}", kind, format_time(system.time));
+    println!(
+        "CPU: {:.1}%  Memory: {:.1}/{:.1} GiB  Swap: {:.1}/{:.1} GiB  Load: {:.2} {:.2} {:.2}  Processes: {}",
+        system.cpu,
+        gib(system.mem_used),
+        gib(system.mem_total),
+        gib(system.swap_used),
+        gib(system.swap_total),
+        system.load[0],
+        system.load[1],
+        system.load[2],
+        system.process_count,
+    );
+    println!();
+    println!("{:>8} {:<24} {:>8} {:>8}", "PID", "NAME", "CPU%", "MEM%");
+    for p in record.processes.iter().take(top) {
+        println!("{:>8} {:<24} {:>8.1} {:>8.1}", p.pid, p.name, p.cpu, p.mem);
+    }
+    EXIT_OK
+}
+
+fn alerts_command(args: &[String]) -> i32 {
+    let (subcommand, rest) = match args.split_first() {
+        Some((subcommand, rest)) => (subcommand.as_str(), rest),
+        None => return usage_error("expected 'alerts check', 'alerts watch' or 'alerts test'"),
+    };
+
+    let mut config = None;
+    let mut interval = 2;
+    let mut iter = rest.iter();
+    while let Some(arg) = iter.next() {
+        let value = match iter.next() {
+            Some(value) => value,
+            None => return usage_error(&format!("{} needs a value", arg)),
+        };
+        match arg.as_str() {
+            "--config" => config = Some(std::path::PathBuf::from(value)),
+            "--interval" if subcommand == "watch" => match value.parse::<u64>() {
+                Ok(seconds) if seconds > 0 => interval = seconds,
+                _ => return usage_error(&format!("invalid interval '{}'", value)),
+            },
+            other => return usage_error(&format!("unexpected argument '{}'", other)),
+        }
+    }
+
+    let path = config.unwrap_or_else(alerts::default_config_path);
+    let (rules, sinks) = if path.exists() {
+        match alerts::load_rules(&path).and_then(|rules| Ok((rules, alert_sinks::load_sinks(&path)?))) {
+            Ok(config) => config,
+            Err(e) => {
+                eprintln!("{}", e);
+                return EXIT_FAILED;
+            },
+        }
+    } else {
+        println!("{} does not exist, using the built-in rules", path.display());
+        (alerts::default_rules(), Vec::new())
+    };
+
+    match subcommand {
+        "check" => {
+            print_rules(&rules);
+            for sink in &sinks {
+                println!("sink: {}", describe_sink(sink));
+            }
+            EXIT_OK
+        },
+        "watch" => watch_alerts(rules, sinks, interval),
+        "test" => test_sinks(&sinks),
+        other => usage_error(&format!("unknown alerts command '{}'", other)),
+    }
+}
+
+fn daemon_command(args: &[String]) -> i32 {
+    let (subcommand, rest) = match args.first().map(String::as_str) {
+        Some(sub @ ("status" | "stop")) => (sub, &args[1..]),
+        _ => ("run", args),
+    };
+
+    let mut config = DaemonConfig::new();
+    let mut iter = rest.iter();
+    while let Some(arg) = iter.next() {
+        if arg == "--no-record" && subcommand == "run" {
+            config.record_history = false;
+            continue;
+        }
+        let value = match iter.next() {
+            Some(value) => value,
+            None => return usage_error(&format!("{} needs a value", arg)),
+        };
+        match arg.as_str() {
+            "--socket" => config.socket = std::path::PathBuf::from(value),
+            "--config" if subcommand == "run" => config.alerts = std::path::PathBuf::from(value),
+            "--metrics" if subcommand == "run" => {
+                let mut metrics = MetricsConfig::new();
+                metrics.listen = value.clone();
+                config.metrics = Some(metrics);
+            },
+            "--interval" if subcommand == "run" => match value.parse::<u64>() {
+                Ok(seconds) if seconds > 0 => config.interval = seconds,
+                _ => return usage_error(&format!("invalid interval '{}'", value)),
+            },
+            other => return usage_error(&format!("unexpected argument '{}'", other)),
+        }
+    }
+
+    if subcommand == "run" {
+        let stop = Arc::new(AtomicBool::new(false));
+        for signo in [signal_hook::consts::SIGINT, signal_hook::consts::SIGTERM] {
+            if let Err(e) = signal_hook::flag::register(signo, stop.clone()) {
+                eprintln!("Failed to install signal handler: {}", e);
+                return EXIT_FAILED;
+            }
+        }
+        return match daemon::run(config, stop) {
+            Ok(()) => EXIT_OK,
+            Err(e) => {
+                eprintln!("{}", e);
+                EXIT_FAILED
+            },
+        };
+    }
+
+    let mut client = match DaemonClient::connect(&config.socket) {
+        Ok(client) => client,
+        Err(e) => {
+            eprintln!("{}", e);
+            return EXIT_NOT_FOUND;
+        },
+    };
+    let result = if subcommand == "stop" {
+        client.request(&Request::Shutdown).map(|_| println!("Asked the daemon to stop"))
+    } else {
+        client.status().map(|status| {
+            println!("Daemon PID {} on {}, running since {}", status.pid, client.path().display(), format_time(status.started));
+            println!("Refreshing every {}s, {}", status.interval, if status.recording { "recording history" } else { "not recording history" });
+            println!("Alert rules: {} ({} firing), sinks: {}", status.rules.join(", "), status.firing, status.sinks);
+            let paused: Vec<String> = status.paused.iter().map(u32::to_string).collect();
+            println!("Paused: {}", if paused.is_empty() { "none".to_string() } else { paused.join(" ") });
+            if status.supervised.is_empty() {
+                println!("Kept alive: none");
+            }
+            for entry in &status.supervised {
+                let pid = entry.pid.map(|pid| format!(" PID {},", pid)).unwrap_or_default();
+                println!("Kept alive: {} ({}){} {}, {} restarts", entry.target, entry.name, pid, entry.state, entry.restarts);
+            }
+        })
+    };
+    match result {
+        Ok(()) => EXIT_OK,
+        Err(e) => {
+            eprintln!("{}", e);
+            EXIT_FAILED
+        },
+    }
+}
+
+fn print_rules(rules: &[AlertRule]) {
+    for rule in rules {
+        println!(
+            "{:<20} {:<8} {}  (clear at {}, cooldown {})",
+            rule.name,
+            rule.severity,
+            rule.describe(),
+            rule.metric.format_value(rule.clear),
+            alerts::format_duration(rule.cooldown)
+        );
+        if let Some(action) = &rule.action {
+            let mode = match action.mode {
+                ActionMode::DryRun => "dry-run",
+                ActionMode::Confirm => "confirm",
+                ActionMode::Auto => "auto",
+            };
+            println!(
+                "{:<20} then {} the process ({}, at most {} per {})",
+                "",
+                action.kind.describe(),
+                mode,
+                action.limit,
+                alerts::format_duration(action.per)
+            );
+        }
+    }
+}
+
+fn describe_sink(sink: &AlertSink) -> String {
+    format!(
+        "{} ({} and above{})",
+        sink.describe(),
+        sink.severity,
+        if sink.resolved { ", resolved too" } else { "" }
+    )
+}
+
+// Deliver a made-up alert to each sink in turn, reporting what happened
+fn test_sinks(sinks: &[AlertSink]) -> i32 {
+    if sinks.is_empty() {
+        println!("No sinks configured");
+        return EXIT_OK;
+    }
+    let event = AlertEvent {
+        rule: "pulse-test".to_string(),
+        severity: Severity::Critical,
+        state: AlertState::Fired,
+        time: history::now(),
+        pid: Some(std::process::id()),
+        start_time: None,
+        process: Some("pulse".to_string()),
+        metric: Metric::Cpu,
+        value: 0.0,
+        threshold: 0.0,
+        message: "Test alert from pulse alerts test".to_string(),
+    };
+    let mut code = EXIT_OK;
+    for sink in sinks {
+        match sink.deliver(&event) {
+            Ok(()) => println!("{}: ok", sink.describe()),
+            Err(e) => {
+                println!("{}: {}", sink.describe(), e);
+                code = EXIT_FAILED;
+            },
+        }
+    }
+    code
+}
+
+fn watch_alerts(rules: Vec<AlertRule>, sinks: Vec<AlertSink>, interval: u64) -> i32 {
+    let stop = Arc::new(AtomicBool::new(false));
+    for signo in [signal_hook::consts::SIGINT, signal_hook::consts::SIGTERM] {
+        if let Err(e) = signal_hook::flag::register(signo, stop.clone()) {
+            eprintln!("Failed to install signal handler: {}", e);
+            return EXIT_FAILED;
+        }
+    }
+
+    println!(
+        "Watching {} alert rules every {}s, delivering to {} sinks (Ctrl+C to stop)",
+        rules.len(),
+        interval,
+        sinks.len()
+    );
+    let sinks = SinkDispatcher::start(sinks);
+    let mut engine = AlertEngine::new(rules);
+    let mut runner = ActionRunner::new();
+    let mut controller = ProcessController::new();
+    let mut groups = ProcessGroupManager::new();
+    let mut collector = Collector::new();
+    collector.refresh();
+    'watch: while !stop.load(Ordering::Relaxed) {
+        // Sleep in short steps so Ctrl+C is handled promptly
+        for _ in 0..interval * 5 {
+            if stop.load(Ordering::Relaxed) {
+                break 'watch;
+            }
+            thread::sleep(Duration::from_millis(200));
+        }
+        collector.refresh();
+        let processes = collector.snapshot();
+        let summary = collector.summary();
+        groups.force_update(&processes);
+
+        for event in engine.evaluate(history::now(), &processes, summary.physical_cores) {
+            println!("{} {}", format_time(event.time), event.message);
+            sinks.send(&event);
+            let rule = match engine.rules().iter().find(|r| r.name == event.rule) {
+                Some(rule) => rule,
+                None => continue,
+            };
+            let mut context = ActionContext {
+                controller: &mut controller,
+                groups: &mut groups,
+                processes: &processes,
+                physical_cores: summary.physical_cores,
+            };
+            let message = match runner.handle(&event, rule, &mut context) {
+                // Nobody to ask without the interactive screen
+                Some(ActionOutcome::NeedsConfirm(pending)) => {
+                    format!("{}: not going to {} without confirmation (use the TUI or mode = \"auto\")", pending.rule, pending.describe())
+                },
+                Some(outcome) => outcome.message(),
+                None => continue,
+            };
+            println!("{} {}", format_time(event.time), message);
+        }
+        for outcome in runner.finished_stops() {
+            println!("{} {}", format_time(history::now()), outcome.message());
+        }
+        for error in sinks.errors() {
+            eprintln!("{} {}", format_time(history::now()), error);
+        }
+    }
+
+    // Like the TUI, leave nothing paused behind, and no process halfway stopped
+    controller.resume_all();
+    groups.resume_all();
+    runner.wait_for_stops();
+    EXIT_OK
+}
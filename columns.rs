@@ -0,0 +1,383 @@
+use std::fs;
+use std::path::PathBuf;
+use chrono::{Local, TimeZone};
+use serde_json::{json, Value};
+use crate::alerts;
+use crate::snapshot::ProcessSnapshot;
+
+/// How the typed exporters (SQLite, Parquet) store a column
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ColumnKind {
+    Integer,
+    Real,
+    Text,
+}
+
+/// A column value for the typed exporters; `Null` when it is not known
+#[derive(Clone, Debug, PartialEq)]
+pub enum TypedValue {
+    Integer(i64),
+    Real(f64),
+    Text(String),
+    Null,
+}
+
+/// A field of a process that the on-screen table and the exporters can show.
+/// `name` is the JSON key and what `--columns` and the config file take.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Column {
+    Pid,
+    Ppid,
+    User,
+    Cpu,
+    Mem,
+    Rss,
+    Virt,
+    Nice,
+    FgBg,
+    State,
+    Threads,
+    StartTime,
+    ReadBytes,
+    WrittenBytes,
+    Cgroup,
+    Command,
+    Cmdline,
+}
+
+impl Column {
+    pub const ALL: [Column; 17] = [
+        Column::Pid,
+        Column::Ppid,
+        Column::User,
+        Column::Cpu,
+        Column::Mem,
+        Column::Rss,
+        Column::Virt,
+        Column::Nice,
+        Column::FgBg,
+        Column::State,
+        Column::Threads,
+        Column::StartTime,
+        Column::ReadBytes,
+        Column::WrittenBytes,
+        Column::Cgroup,
+        Column::Command,
+        Column::Cmdline,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Column::Pid => "pid",
+            Column::Ppid => "ppid",
+            Column::User => "username",
+            Column::Cpu => "cpu",
+            Column::Mem => "mem",
+            Column::Rss => "rss",
+            Column::Virt => "virt",
+            Column::Nice => "nice",
+            Column::FgBg => "fg_bg",
+            Column::State => "state",
+            Column::Threads => "threads",
+            Column::StartTime => "start_time",
+            Column::ReadBytes => "read_bytes",
+            Column::WrittenBytes => "written_bytes",
+            Column::Cgroup => "cgroup",
+            Column::Command => "command",
+            Column::Cmdline => "cmdline",
+        }
+    }
+
+    /// Column title on screen and in the CSV header
+    pub fn header(&self) -> &'static str {
+        match self {
+            Column::Pid => "PID",
+            Column::Ppid => "PPID",
+            Column::User => "USER",
+            Column::Cpu => "CPU%",
+            Column::Mem => "MEM%",
+            Column::Rss => "RSS",
+            Column::Virt => "VIRT",
+            Column::Nice => "NICE",
+            Column::FgBg => "FG/BG",
+            Column::State => "STATE",
+            Column::Threads => "THREADS",
+            Column::StartTime => "START",
+            Column::ReadBytes => "READ",
+            Column::WrittenBytes => "WRITTEN",
+            Column::Cgroup => "CGROUP",
+            Column::Command => "COMMAND",
+            Column::Cmdline => "CMDLINE",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            Column::Pid => "process ID",
+            Column::Ppid => "parent process ID",
+            Column::User => "owner of the process",
+            Column::Cpu => "CPU usage, 100 per fully used core",
+            Column::Mem => "resident memory as a percentage of RAM",
+            Column::Rss => "resident memory in bytes",
+            Column::Virt => "virtual memory in bytes",
+            Column::Nice => "nice value",
+            Column::FgBg => "FG when its group owns the terminal, else BG",
+            Column::State => "scheduler state",
+            Column::Threads => "number of threads",
+            Column::StartTime => "start time in seconds since the epoch",
+            Column::ReadBytes => "bytes read from storage",
+            Column::WrittenBytes => "bytes written to storage",
+            Column::Cgroup => "control group path",
+            Column::Command => "process name",
+            Column::Cmdline => "full command line",
+        }
+    }
+
+    /// Accepts the name, the header and a few common spellings, in any case
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let text = text.trim().to_lowercase();
+        let alias = match text.as_str() {
+            "user" => Some(Column::User),
+            "priority" => Some(Column::Nice),
+            "vsz" => Some(Column::Virt),
+            "start" => Some(Column::StartTime),
+            "read" => Some(Column::ReadBytes),
+            "written" | "write" => Some(Column::WrittenBytes),
+            "name" | "comm" => Some(Column::Command),
+            "args" => Some(Column::Cmdline),
+            _ => None,
+        };
+        alias
+            .or_else(|| Column::ALL.iter().copied().find(|c| c.name() == text || c.header().to_lowercase() == text))
+            .ok_or_else(|| format!("unknown column '{}' (see 'pulse columns')", text))
+    }
+
+    /// Width on screen; longer values are cut short
+    pub fn width(&self) -> usize {
+        match self {
+            Column::Pid | Column::Ppid => 6,
+            Column::User => 15,
+            Column::Cpu | Column::Mem | Column::Nice | Column::FgBg => 6,
+            Column::Rss | Column::Virt | Column::ReadBytes | Column::WrittenBytes => 8,
+            Column::Threads => 7,
+            Column::StartTime => 11,
+            Column::State => 10,
+            Column::Cgroup => 30,
+            Column::Command => 30,
+            Column::Cmdline => 50,
+        }
+    }
+
+    /// Numbers line up on the right
+    pub fn right_aligned(&self) -> bool {
+        matches!(
+            self,
+            Column::Cpu | Column::Mem | Column::Rss | Column::Virt | Column::Threads | Column::ReadBytes | Column::WrittenBytes
+        )
+    }
+
+    pub fn kind(&self) -> ColumnKind {
+        match self {
+            Column::Cpu | Column::Mem => ColumnKind::Real,
+            Column::User | Column::FgBg | Column::State | Column::Cgroup | Column::Command | Column::Cmdline => {
+                ColumnKind::Text
+            },
+            _ => ColumnKind::Integer,
+        }
+    }
+
+    /// The value for the typed exporters, of the column's kind
+    pub fn typed(&self, p: &ProcessSnapshot) -> TypedValue {
+        let value = self.json(p);
+        match self.kind() {
+            _ if value.is_null() => TypedValue::Null,
+            ColumnKind::Integer => value.as_i64().map(TypedValue::Integer).unwrap_or(TypedValue::Null),
+            ColumnKind::Real => value.as_f64().map(TypedValue::Real).unwrap_or(TypedValue::Null),
+            ColumnKind::Text => TypedValue::Text(self.text(p)),
+        }
+    }
+
+    /// The value for the JSON exporters
+    pub fn json(&self, p: &ProcessSnapshot) -> Value {
+        match self {
+            Column::Pid => json!(p.pid),
+            Column::Ppid => json!(p.ppid),
+            Column::User => json!(p.user),
+            Column::Cpu => json!(p.cpu),
+            Column::Mem => json!(p.mem),
+            Column::Rss => json!(p.memory),
+            Column::Virt => json!(p.virtual_memory),
+            Column::Nice => json!(p.nice),
+            Column::FgBg => json!(p.fg_bg()),
+            Column::State => json!(p.state.as_str()),
+            Column::Threads => json!(p.threads),
+            Column::StartTime => json!(p.start_time),
+            Column::ReadBytes => json!(p.read_bytes),
+            Column::WrittenBytes => json!(p.written_bytes),
+            Column::Cgroup => json!(p.cgroup),
+            Column::Command => json!(p.name),
+            Column::Cmdline => json!(p.cmdline),
+        }
+    }
+
+    /// The value for the CSV exporter: exact figures, unformatted
+    pub fn text(&self, p: &ProcessSnapshot) -> String {
+        match self {
+            Column::Pid => p.pid.to_string(),
+            Column::Ppid => p.ppid.map(|ppid| ppid.to_string()).unwrap_or_default(),
+            Column::User => p.user.clone(),
+            Column::Cpu => format!("{:.1}", p.cpu),
+            Column::Mem => format!("{:.1}", p.mem),
+            Column::Rss => p.memory.to_string(),
+            Column::Virt => p.virtual_memory.to_string(),
+            Column::Nice => p.nice.to_string(),
+            Column::FgBg => p.fg_bg().to_string(),
+            Column::State => p.state.as_str().to_string(),
+            Column::Threads => p.threads.to_string(),
+            Column::StartTime => p.start_time.to_string(),
+            Column::ReadBytes => p.read_bytes.to_string(),
+            Column::WrittenBytes => p.written_bytes.to_string(),
+            Column::Cgroup => p.cgroup.clone(),
+            Column::Command => p.name.clone(),
+            Column::Cmdline => p.command_line(),
+        }
+    }
+
+    /// The value as shown on screen, with sizes and times made readable
+    pub fn display(&self, p: &ProcessSnapshot) -> String {
+        match self {
+            Column::Rss => alerts::format_size(p.memory as f64),
+            Column::Virt => alerts::format_size(p.virtual_memory as f64),
+            Column::ReadBytes => alerts::format_size(p.read_bytes as f64),
+            Column::WrittenBytes => alerts::format_size(p.written_bytes as f64),
+            Column::StartTime => match Local.timestamp_opt(p.start_time as i64, 0).single() {
+                // Today's processes by time, older ones by date
+                Some(t) if t.date_naive() == Local::now().date_naive() => t.format("%H:%M:%S").to_string(),
+                Some(t) => t.format("%b %d %H:%M").to_string(),
+                None => String::new(),
+            },
+            _ => self.text(p),
+        }
+    }
+
+    /// Whether a streamed diff reports a change from `old` to `new`. CPU and memory
+    /// must move by a few points, and the byte counts, which move all the time,
+    /// never count as a change on their own.
+    pub fn changed(&self, old: &Value, new: &Value) -> bool {
+        let delta = || (old.as_f64().unwrap_or(0.0) - new.as_f64().unwrap_or(0.0)).abs();
+        match self {
+            Column::Cpu => delta() >= 5.0,
+            Column::Mem => delta() >= 1.0,
+            Column::Rss | Column::Virt | Column::ReadBytes | Column::WrittenBytes => false,
+            _ => old != new,
+        }
+    }
+}
+
+/// The columns Pulse has always shown and exported
+pub fn default_columns() -> Vec<Column> {
+    vec![
+        Column::Pid,
+        Column::User,
+        Column::Cpu,
+        Column::Mem,
+        Column::Nice,
+        Column::FgBg,
+        Column::State,
+        Column::Command,
+    ]
+}
+
+/// "pid,user,cpu" -> [Pid, User, Cpu]
+pub fn parse_columns(text: &str) -> Result<Vec<Column>, String> {
+    let columns: Vec<Column> =
+        text.split(',').filter(|name| !name.trim().is_empty()).map(Column::parse).collect::<Result<_, _>>()?;
+    if columns.is_empty() {
+        return Err("no columns given".to_string());
+    }
+    Ok(columns)
+}
+
+/// "pid,username,cpu", the form `parse_columns` takes
+pub fn format_columns(columns: &[Column]) -> String {
+    columns.iter().map(Column::name).collect::<Vec<_>>().join(",")
+}
+
+/// The column choice from the `[columns]` table of `config.toml`
+#[derive(Clone, Debug)]
+pub struct ColumnConfig {
+    /// The process list of the interactive monitor
+    pub screen: Vec<Column>,
+    /// The JSON and CSV exports
+    pub export: Vec<Column>,
+}
+
+impl ColumnConfig {
+    pub fn new() -> Self {
+        ColumnConfig { screen: default_columns(), export: default_columns() }
+    }
+
+    /// `config.toml` in the config directory
+    pub fn path() -> PathBuf {
+        alerts::config_dir().join("config.toml")
+    }
+
+    /// The saved choice; the defaults when nothing was saved
+    pub fn load() -> Result<Self, String> {
+        let path = Self::path();
+        let mut config = Self::new();
+        if !path.exists() {
+            return Ok(config);
+        }
+        let table = read_table(&path)?;
+        let columns = match table.get("columns") {
+            Some(toml::Value::Table(columns)) => columns,
+            Some(_) => return Err(format!("{}: 'columns' must be a table", path.display())),
+            None => return Ok(config),
+        };
+        for (key, value) in columns {
+            let list = value
+                .as_array()
+                .map(|names| names.iter().map(|n| n.as_str().unwrap_or("").to_string()).collect::<Vec<_>>().join(","))
+                .ok_or_else(|| format!("{}: columns.{} must be a list of column names", path.display(), key))?;
+            let parsed = parse_columns(&list).map_err(|e| format!("{}: columns.{}: {}", path.display(), key, e))?;
+            match key.as_str() {
+                "screen" => config.screen = parsed,
+                "export" => config.export = parsed,
+                other => return Err(format!("{}: unknown key columns.{} (expected screen or export)", path.display(), other)),
+            }
+        }
+        Ok(config)
+    }
+
+    /// Write the choice back, keeping the rest of `config.toml` as it was
+    pub fn save(&self) -> Result<PathBuf, String> {
+        let path = Self::path();
+        let mut table = if path.exists() { read_table(&path)? } else { toml::Table::new() };
+        let list = |columns: &[Column]| {
+            toml::Value::Array(columns.iter().map(|c| toml::Value::String(c.name().to_string())).collect())
+        };
+        let mut columns = toml::Table::new();
+        columns.insert("screen".to_string(), list(&self.screen));
+        columns.insert("export".to_string(), list(&self.export));
+        table.insert("columns".to_string(), toml::Value::Table(columns));
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+        }
+        let text = toml::to_string(&table).map_err(|e| e.to_string())?;
+        fs::write(&path, text).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        Ok(path)
+    }
+}
+
+impl Default for ColumnConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn read_table(path: &std::path::Path) -> Result<toml::Table, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    text.parse::<toml::Table>().map_err(|e| format!("{}: {}", path.display(), e))
+}
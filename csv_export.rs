@@ -1,127 +1,253 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use serde_json::{json, Map, Value};
+use sysinfo::System;
+use crate::exporter::{ExportData, Exporter};
+use crate::snapshot::SystemSummary;
 
+/// Where the system summary of a CSV export goes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SummaryFormat {
+    /// `<name>.summary.json` next to the export
+    Json,
+    /// `<name>.summary.csv`, a header and a single record
+    Csv,
+    None,
+}
 
-use std::fs::File;
-use std::io::{self, Write};
-use std::path::Path;
-use sysinfo::{Process, Pid, ProcessStatus, System};
-use users::get_user_by_uid;
+impl SummaryFormat {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        match text.to_lowercase().as_str() {
+            "json" => Ok(SummaryFormat::Json),
+            "csv" => Ok(SummaryFormat::Csv),
+            "none" => Ok(SummaryFormat::None),
+            other => Err(format!("unknown summary format '{}' (expected json, csv or none)", other)),
+        }
+    }
+}
 
-pub struct CsvExporter;
+#[derive(Clone, Debug)]
+pub struct CsvOptions {
+    pub delimiter: char,
+    /// Whether the first record names the columns
+    pub header: bool,
+    pub summary: SummaryFormat,
+}
+
+impl CsvOptions {
+    pub fn new() -> Self {
+        CsvOptions { delimiter: ',', header: true, summary: SummaryFormat::Json }
+    }
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// `,`, `;`, `|`, `tab` or `\t`. Quotes and line breaks cannot separate fields.
+pub fn parse_delimiter(text: &str) -> Result<char, String> {
+    let delimiter = match text {
+        "tab" | "\\t" => '\t',
+        _ => {
+            let mut chars = text.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => c,
+                _ => return Err(format!("invalid delimiter '{}' (expected a single character or 'tab')", text)),
+            }
+        },
+    };
+    if matches!(delimiter, '"' | '\r' | '\n') {
+        return Err(format!("{:?} cannot be used as the delimiter", delimiter));
+    }
+    Ok(delimiter)
+}
+
+/// Writes the processes as RFC 4180 CSV (CRLF line breaks, fields quoted only
+/// when they need to be) and the system summary as a sidecar file
+pub struct CsvExporter {
+    options: CsvOptions,
+}
 
 impl CsvExporter {
-    pub fn export_processes(
-        processes: &[&Process], 
-        system: &System,
-        filepath: &str
-    ) -> Result<String, String> {
-        // Create or open file
-        let path = Path::new(filepath);
-        let mut file = match File::create(&path) {
-            Ok(file) => file,
+    pub fn new(options: CsvOptions) -> Self {
+        CsvExporter { options }
+    }
+}
+
+impl Exporter for CsvExporter {
+    fn extension(&self) -> &'static str {
+        "csv"
+    }
+
+    fn export(&self, data: &ExportData, path: &Path) -> Result<String, String> {
+        let options = &self.options;
+        let columns = data.columns;
+        let mut file = match File::create(path) {
+            Ok(file) => BufWriter::new(file),
             Err(e) => return Err(format!("Failed to create CSV file: {}", e)),
         };
-        
-        // Write header
-        if let Err(e) = writeln!(file, "PID,USER,CPU%,MEM%,PRIORITY,FG/BG,STATE,COMMAND") {
-            return Err(format!("Failed to write CSV header: {}", e));
+
+        if options.header {
+            let header: Vec<String> = columns.iter().map(|c| c.header().to_string()).collect();
+            if let Err(e) = write_record(&mut file, &header, options.delimiter) {
+                return Err(format!("Failed to write CSV header: {}", e));
+            }
         }
-        
-        // Write process data
-        for process in processes {
-            let pid: Pid = process.pid();
-            let cpu = process.cpu_usage();
-            let mem = (process.memory() as f64 / system.total_memory() as f64) * 100.0;
-            
-            // Get priority (nice value)
-            let stat_path = format!("/proc/{}/stat", pid);
-            let stat_content = std::fs::read_to_string(&stat_path).unwrap_or_default();
-            let parts: Vec<&str> = stat_content.split_whitespace().collect();
-            
-            let nice = parts.get(18)
-                .and_then(|s| s.parse::<i32>().ok())
-                .unwrap_or(0);
-                
-            // Get foreground/background status
-            let pgrp = parts.get(4).and_then(|s| s.parse::<i32>().ok()).unwrap_or(0);
-            let tpgid = parts.get(7).and_then(|s| s.parse::<i32>().ok()).unwrap_or(0);
-            let fg_bg = if pgrp == tpgid { "FG" } else { "BG" };
-            
-            // Get process state
-            let state = match process.status() {
-                ProcessStatus::Run => "Running",
-                ProcessStatus::Sleep => "Sleep",
-                ProcessStatus::Idle => "Idle",
-                ProcessStatus::Stop => "Stopped",
-                ProcessStatus::Zombie => "Zombie",
-                _ => "Other",
-            };
-            
-            // Get username
-            let username = process.user_id()
-                .and_then(|uid| get_user_by_uid(**uid))
-                .map(|u| u.name().to_string_lossy().into_owned())
-                .unwrap_or_else(|| "Unknown".to_string());
-                
-            // Get command
-            let command = process.name();
-            
-            // Write the line, escaping quotation marks in strings
-            if let Err(e) = writeln!(
-                file,
-                "{},{},{:.1},{:.1},{},{},{},\"{}\"",
-                pid,
-                username.replace("\"", "\"\""),
-                cpu,
-                mem,
-                nice,
-                fg_bg,
-                state,
-                command.replace("\"", "\"\"")
-            ) {
+        for process in data.processes {
+            let fields: Vec<String> = columns.iter().map(|c| c.text(process)).collect();
+            if let Err(e) = write_record(&mut file, &fields, options.delimiter) {
                 return Err(format!("Failed to write process data: {}", e));
             }
         }
-        
-        // Add system summary at the end
-        if let Err(e) = writeln!(file, "\nSYSTEM SUMMARY") {
-            return Err(format!("Failed to write summary header: {}", e));
-        }
-        
-        let mem_percent = (system.used_memory() as f64 / system.total_memory() as f64) * 100.0;
-        let mem_gb = system.total_memory() as f64 / 1_073_741_824.0;
-        let mem_used_gb = system.used_memory() as f64 / 1_073_741_824.0;
-        let num_cores = system.physical_core_count().unwrap_or(1);
-        
-        if let Err(e) = writeln!(
-            file,
-            "Memory Usage,{:.1}GB / {:.1}GB ({:.1}%)",
-            mem_used_gb, mem_gb, mem_percent
-        ) {
-            return Err(format!("Failed to write memory summary: {}", e));
+        if let Err(e) = file.flush() {
+            return Err(format!("Failed to write process data: {}", e));
         }
-        
-        if let Err(e) = writeln!(
-            file,
-            "CPUs,{} cores",
-            num_cores
-        ) {
-            return Err(format!("Failed to write CPU summary: {}", e));
+
+        let fields = summary_fields(data.time, data.summary, data.processes.len());
+        let sidecar = match options.summary {
+            SummaryFormat::Json => {
+                let sidecar = summary_path(path, "json");
+                let text = serde_json::to_string_pretty(&Value::Object(fields)).map_err(|e| e.to_string())?;
+                std::fs::write(&sidecar, text + "\n").map_err(|e| format!("Failed to write {}: {}", sidecar.display(), e))?;
+                Some(sidecar)
+            },
+            SummaryFormat::Csv => {
+                let sidecar = summary_path(path, "csv");
+                let names: Vec<String> = fields.keys().cloned().collect();
+                let values: Vec<String> = fields
+                    .values()
+                    .map(|v| match v {
+                        Value::String(s) => s.clone(),
+                        Value::Null => String::new(),
+                        other => other.to_string(),
+                    })
+                    .collect();
+                let mut text = Vec::new();
+                write_record(&mut text, &names, options.delimiter)
+                    .and_then(|_| write_record(&mut text, &values, options.delimiter))
+                    .map_err(|e| e.to_string())?;
+                std::fs::write(&sidecar, text).map_err(|e| format!("Failed to write {}: {}", sidecar.display(), e))?;
+                Some(sidecar)
+            },
+            SummaryFormat::None => None,
+        };
+
+        Ok(match sidecar {
+            Some(sidecar) => format!("Process data exported to {} (summary in {})", path.display(), sidecar.display()),
+            None => format!("Process data exported to {}", path.display()),
+        })
+    }
+}
+
+// One record, terminated by CRLF as RFC 4180 asks
+fn write_record(out: &mut impl Write, fields: &[String], delimiter: char) -> std::io::Result<()> {
+    let fields: Vec<String> = fields.iter().map(|f| escape(f, delimiter)).collect();
+    write!(out, "{}\r\n", fields.join(&delimiter.to_string()))
+}
+
+// Quote a field when it holds the delimiter, a quote or a line break, doubling any quotes
+fn escape(field: &str, delimiter: char) -> String {
+    if field.contains([delimiter, '"', '\r', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Split CSV text into records, undoing the quoting of `write_record`. Lines may
+/// end in CRLF or LF; blank lines are skipped.
+pub(crate) fn read_records(text: &str, delimiter: char) -> Result<Vec<Vec<String>>, String> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut line = 1;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if quoted {
+            match c {
+                '"' if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                },
+                '"' => quoted = false,
+                _ => {
+                    if c == '\n' {
+                        line += 1;
+                    }
+                    field.push(c);
+                },
+            }
+            continue;
         }
-        
-        if let Err(e) = writeln!(
-            file,
-            "Processes,{}",
-            processes.len()
-        ) {
-            return Err(format!("Failed to write process count: {}", e));
+        match c {
+            '"' if field.is_empty() => quoted = true,
+            '\r' if chars.peek() == Some(&'\n') => {},
+            '\n' => {
+                line += 1;
+                record.push(std::mem::take(&mut field));
+                if record.len() > 1 || !record[0].is_empty() {
+                    records.push(std::mem::take(&mut record));
+                }
+                record.clear();
+            },
+            c if c == delimiter => record.push(std::mem::take(&mut field)),
+            _ => field.push(c),
         }
-        
-        Ok(format!("Process data exported to {}", filepath))
     }
-    
-    pub fn get_default_filename() -> String {
-        use chrono::Local;
-        let now = Local::now();
-        format!("pulse_export_{}.csv", now.format("%Y%m%d_%H%M%S"))
+    if quoted {
+        return Err(format!("unterminated quoted field at line {}", line));
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    Ok(records)
+}
+
+// report.csv -> report.summary.json
+pub(crate) fn summary_path(path: &Path, extension: &str) -> PathBuf {
+    let stem = path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    path.with_file_name(format!("{}.summary.{}", stem, extension))
+}
+
+// The system summary with typed fields: bytes, percentages and counts as numbers
+pub(crate) fn summary_fields(time: u64, summary: &SystemSummary, processes: usize) -> Map<String, Value> {
+    let fields = json!({
+        "time": time,
+        "host": System::host_name(),
+        "processes": processes,
+        "physical_cores": summary.physical_cores,
+        "cpu_percent": summary.cpu_usage,
+        "memory_total_bytes": summary.total_memory,
+        "memory_used_bytes": summary.used_memory,
+        "memory_percent": summary.mem_percent(),
+        "swap_total_bytes": summary.total_swap,
+        "swap_used_bytes": summary.used_swap,
+        "load_1m": summary.load_average[0],
+        "load_5m": summary.load_average[1],
+        "load_15m": summary.load_average[2],
+    });
+    match fields {
+        Value::Object(fields) => fields,
+        _ => Map::new(),
+    }
+}
+
+/// The summary back from the fields of `summary_fields`, e.g. read from a sidecar
+/// or a SQLite export; missing figures are zero
+pub(crate) fn summary_from_fields(fields: &Map<String, Value>, physical_cores: usize) -> SystemSummary {
+    let number = |key: &str| fields.get(key).and_then(Value::as_f64).unwrap_or(0.0);
+    SystemSummary {
+        total_memory: number("memory_total_bytes") as u64,
+        used_memory: number("memory_used_bytes") as u64,
+        total_swap: number("swap_total_bytes") as u64,
+        used_swap: number("swap_used_bytes") as u64,
+        physical_cores: fields.get("physical_cores").and_then(Value::as_u64).map_or(physical_cores, |n| n as usize),
+        cpu_usage: number("cpu_percent") as f32,
+        load_average: [number("load_1m"), number("load_5m"), number("load_15m")],
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,605 @@
+use std::collections::VecDeque;
+use std::env;
+use std::fs::{self, DirBuilder, Permissions};
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+use nix::unistd::getuid;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sysinfo::Pid;
+use crate::alert_actions::{ActionContext, ActionOutcome, ActionRunner};
+use crate::alert_sinks::{self, SinkDispatcher};
+use crate::alerts::{self, AlertEngine, AlertEvent};
+use crate::history::{self, format_time, HistoryConfig, Recorder};
+use crate::metrics::{MetricsConfig, MetricsExporter};
+use crate::pause_resume::{ProcessAction, ProcessController};
+use crate::process_groups::ProcessGroupManager;
+use crate::snapshot::{Collector, ProcessSnapshot};
+use crate::supervisor::{SupervisedStatus, Supervisor, SupervisorPolicy};
+
+// Alert events kept for clients that poll for them
+const MAX_EVENTS: usize = 256;
+// How long a client waits for the daemon to answer
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// `$PULSE_SOCKET`, else `$XDG_RUNTIME_DIR/pulse/pulse.sock`, else `/tmp/pulse-<uid>/pulse.sock`
+pub fn default_socket_path() -> PathBuf {
+    match env::var_os("PULSE_SOCKET") {
+        Some(path) => PathBuf::from(path),
+        None => socket_dir().join("pulse.sock"),
+    }
+}
+
+// The directory of the default socket, which only its owner may use
+fn socket_dir() -> PathBuf {
+    match env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) => Path::new(&dir).join("pulse"),
+        None => PathBuf::from(format!("/tmp/pulse-{}", getuid())),
+    }
+}
+
+/// A request from a client: one JSON object per line, e.g. `{"command":"pause","pids":[1234]}`
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "command", rename_all = "kebab-case")]
+pub enum Request {
+    Status,
+    /// `start_times` are those the client saw the processes start at, in the
+    /// order of `pids`; a PID reused since is left alone. Missing or 0 ones are
+    /// not checked.
+    Pause {
+        pids: Vec<u32>,
+        #[serde(default)]
+        start_times: Vec<u64>,
+    },
+    Resume {
+        pids: Vec<u32>,
+        #[serde(default)]
+        start_times: Vec<u64>,
+    },
+    GroupPause {
+        pid: u32,
+        #[serde(default)]
+        start_time: u64,
+    },
+    GroupResume {
+        pid: u32,
+        #[serde(default)]
+        start_time: u64,
+    },
+    /// Alert events numbered `since` and later
+    Events { since: u64 },
+    SetThreshold { rule: String, value: f64 },
+    /// A PID or filter expression to relaunch whenever it exits
+    KeepAlive {
+        target: String,
+        #[serde(default)]
+        policy: SupervisorPolicy,
+    },
+    /// Stop keeping a target alive, by the target or its current PID
+    Release { target: String },
+    Shutdown,
+}
+
+/// The answer to a request, also one JSON object per line
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Response {
+    pub ok: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Value::is_null")]
+    pub data: Value,
+}
+
+impl Response {
+    fn success(data: Value) -> Self {
+        Response { ok: true, error: None, data }
+    }
+
+    fn failure(error: String) -> Self {
+        Response { ok: false, error: Some(error), data: Value::Null }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct DaemonStatus {
+    pub pid: u32,
+    /// Seconds since the epoch
+    pub started: u64,
+    pub interval: u64,
+    pub recording: bool,
+    pub rules: Vec<String>,
+    pub sinks: usize,
+    pub firing: usize,
+    /// Processes the daemon paused and will resume when it stops
+    pub paused: Vec<u32>,
+    /// Number the next alert event will get
+    pub next_event: u64,
+    /// Processes the daemon keeps alive
+    #[serde(default)]
+    pub supervised: Vec<SupervisedStatus>,
+}
+
+/// Alert events returned by an `events` request
+#[derive(Serialize, Deserialize, Debug)]
+pub struct EventBatch {
+    /// Where the next `events` request should start
+    pub next: u64,
+    pub events: Vec<AlertEvent>,
+}
+
+pub struct DaemonConfig {
+    pub socket: PathBuf,
+    pub interval: u64,
+    pub record_history: bool,
+    /// File with the alert rules and sinks; the built-in rules are used if it does not exist
+    pub alerts: PathBuf,
+    /// Serve Prometheus metrics as well
+    pub metrics: Option<MetricsConfig>,
+}
+
+impl DaemonConfig {
+    pub fn new() -> Self {
+        DaemonConfig {
+            socket: default_socket_path(),
+            interval: 2,
+            record_history: true,
+            alerts: alerts::default_config_path(),
+            metrics: None,
+        }
+    }
+}
+
+impl Default for DaemonConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Everything the daemon keeps between refreshes, shared with the client threads
+struct DaemonState {
+    collector: Collector,
+    processes: Vec<ProcessSnapshot>,
+    physical_cores: usize,
+    controller: ProcessController,
+    groups: ProcessGroupManager,
+    supervisor: Supervisor,
+    engine: AlertEngine,
+    actions: ActionRunner,
+    sinks: SinkDispatcher,
+    metrics: Option<MetricsExporter>,
+    events: VecDeque<(u64, AlertEvent)>,
+    next_event: u64,
+    started: u64,
+    interval: u64,
+    recording: bool,
+}
+
+impl DaemonState {
+    fn collect(&mut self) {
+        self.collector.refresh();
+        self.processes = self.collector.snapshot();
+        let summary = self.collector.summary();
+        self.physical_cores = summary.physical_cores;
+        self.groups.force_update(&self.processes);
+        if let Some(metrics) = &self.metrics {
+            metrics.update(&self.processes, &summary, self.collector.core_usage());
+        }
+
+        // Paused processes that exited since are no longer ours to resume
+        let processes = &self.processes;
+        let gone: Vec<Pid> = self
+            .paused()
+            .into_iter()
+            .filter(|pid| !processes.iter().any(|p| p.pid == *pid))
+            .map(Pid::from_u32)
+            .collect();
+        for pid in gone {
+            self.controller.remove_terminated_process(&pid);
+            self.groups.remove_terminated_process(&pid);
+        }
+    }
+
+    // One refresh: collect, relaunch kept-alive processes, evaluate the alert
+    // rules and run their actions. Returns the lines to log.
+    fn refresh(&mut self) -> Vec<String> {
+        self.collect();
+        let mut log: Vec<String> = self
+            .supervisor
+            .check(&self.processes, self.physical_cores)
+            .into_iter()
+            .map(|line| format!("{} {}", format_time(history::now()), line))
+            .collect();
+        for event in self.engine.evaluate(history::now(), &self.processes, self.physical_cores) {
+            log.push(format!("{} {}", format_time(event.time), event.message));
+            self.sinks.send(&event);
+
+            if let Some(rule) = self.engine.rules().iter().find(|r| r.name == event.rule) {
+                let mut context = ActionContext {
+                    controller: &mut self.controller,
+                    groups: &mut self.groups,
+                    processes: &self.processes,
+                    physical_cores: self.physical_cores,
+                };
+                let message = match self.actions.handle(&event, rule, &mut context) {
+                    Some(ActionOutcome::NeedsConfirm(pending)) => Some(format!(
+                        "{}: not going to {} without confirmation (use the TUI or mode = \"auto\")",
+                        pending.rule,
+                        pending.describe()
+                    )),
+                    Some(outcome) => Some(outcome.message()),
+                    None => None,
+                };
+                if let Some(message) = message {
+                    log.push(format!("{} {}", format_time(event.time), message));
+                }
+            }
+
+            self.events.push_back((self.next_event, event));
+            self.next_event += 1;
+            if self.events.len() > MAX_EVENTS {
+                self.events.pop_front();
+            }
+        }
+        for outcome in self.actions.finished_stops() {
+            log.push(format!("{} {}", format_time(history::now()), outcome.message()));
+        }
+        for error in self.sinks.errors() {
+            log.push(format!("{} {}", format_time(history::now()), error));
+        }
+        log
+    }
+
+    fn paused(&self) -> Vec<u32> {
+        let mut paused: Vec<u32> = self
+            .controller
+            .get_paused_processes()
+            .iter()
+            .chain(self.groups.get_paused_processes().iter())
+            .map(|pid| pid.as_u32())
+            .collect();
+        paused.sort_unstable();
+        paused.dedup();
+        paused
+    }
+
+    fn status(&self) -> DaemonStatus {
+        DaemonStatus {
+            pid: std::process::id(),
+            started: self.started,
+            interval: self.interval,
+            recording: self.recording,
+            rules: self.engine.rules().iter().map(|r| r.name.clone()).collect(),
+            sinks: self.sinks.len(),
+            firing: self.engine.active_count(),
+            paused: self.paused(),
+            next_event: self.next_event,
+            supervised: self.supervisor.status(),
+        }
+    }
+
+    fn handle(&mut self, request: Request, stop: &AtomicBool) -> Response {
+        match request {
+            Request::Status => Response::success(json!(self.status())),
+            Request::Pause { pids, start_times } => self.control(&pids, &start_times, ProcessAction::Pause),
+            Request::Resume { pids, start_times } => self.control(&pids, &start_times, ProcessAction::Resume),
+            Request::GroupPause { pid, start_time } => self.control_group(pid, start_time, ProcessAction::Pause),
+            Request::GroupResume { pid, start_time } => self.control_group(pid, start_time, ProcessAction::Resume),
+            Request::Events { since } => {
+                let events = self.events.iter().filter(|(seq, _)| *seq >= since).map(|(_, e)| e.clone()).collect();
+                Response::success(json!(EventBatch { next: self.next_event, events }))
+            },
+            Request::SetThreshold { rule, value } => {
+                if self.engine.set_threshold(&rule, value) {
+                    Response::success(Value::Null)
+                } else {
+                    Response::failure(format!("no alert rule named '{}'", rule))
+                }
+            },
+            Request::KeepAlive { target, policy } => {
+                match self.supervisor.keep_alive(&target, policy, &self.processes, self.physical_cores) {
+                    Ok(message) => {
+                        println!("{} {}", format_time(history::now()), message);
+                        Response::success(json!({ "message": message }))
+                    },
+                    Err(e) => Response::failure(e),
+                }
+            },
+            Request::Release { target } => match self.supervisor.release(&target) {
+                Some(message) => {
+                    println!("{} {}", format_time(history::now()), message);
+                    Response::success(json!({ "message": message }))
+                },
+                None => Response::failure(format!("'{}' is not being kept alive", target)),
+            },
+            Request::Shutdown => {
+                stop.store(true, Ordering::Relaxed);
+                Response::success(Value::Null)
+            },
+        }
+    }
+
+    fn control(&mut self, pids: &[u32], start_times: &[u64], action: ProcessAction) -> Response {
+        let errors: Vec<String> = pids
+            .iter()
+            .enumerate()
+            .filter_map(|(i, &pid)| {
+                let start_time = start_times.get(i).copied().unwrap_or(0);
+                self.controller.control_process(Pid::from_u32(pid), start_time, action.clone()).err()
+            })
+            .collect();
+        if errors.is_empty() {
+            Response::success(Value::Null)
+        } else {
+            Response::failure(errors.join("; "))
+        }
+    }
+
+    fn control_group(&mut self, pid: u32, start_time: u64, action: ProcessAction) -> Response {
+        let seen = |p: &ProcessSnapshot| p.pid == pid && (start_time == 0 || p.start_time == start_time);
+        // The process may have started since the last refresh
+        if !self.processes.iter().any(seen) {
+            self.collect();
+        }
+        if !self.processes.iter().any(|p| p.pid == pid) {
+            return Response::failure(format!("Process {} does not exist", pid));
+        }
+        if !self.processes.iter().any(seen) {
+            return Response::failure(format!("PID {} now belongs to a different process", pid));
+        }
+        match self.groups.control_group(&self.processes, Pid::from_u32(pid), action) {
+            Ok(count) => Response::success(json!({ "count": count })),
+            Err(e) => Response::failure(e),
+        }
+    }
+}
+
+/// Run the daemon in the foreground until `stop` is set, by a signal or a
+/// `shutdown` request. Processes it paused are resumed before it returns.
+pub fn run(config: DaemonConfig, stop: Arc<AtomicBool>) -> Result<(), String> {
+    let (rules, sinks) = if config.alerts.exists() {
+        (alerts::load_rules(&config.alerts)?, alert_sinks::load_sinks(&config.alerts)?)
+    } else {
+        (alerts::default_rules(), Vec::new())
+    };
+    let metrics = config.metrics.map(MetricsExporter::start).transpose()?;
+    let listener = bind(&config.socket)?;
+
+    // Another recorder may own the history already; the daemon runs without then
+    let recorder = if config.record_history {
+        match Recorder::start(HistoryConfig::default()) {
+            Ok(recorder) => Some(recorder),
+            Err(e) => {
+                println!("{}; not recording history", e);
+                None
+            },
+        }
+    } else {
+        None
+    };
+
+    println!(
+        "Pulse daemon listening on {} ({} alert rules, {} sinks, every {}s)",
+        config.socket.display(),
+        rules.len(),
+        sinks.len(),
+        config.interval
+    );
+    if let Some(metrics) = &metrics {
+        println!("Serving metrics on http://{}/metrics", metrics.address());
+    }
+    let mut collector = Collector::new();
+    collector.refresh();
+    let state = Arc::new(Mutex::new(DaemonState {
+        collector,
+        processes: Vec::new(),
+        physical_cores: 1,
+        controller: ProcessController::new(),
+        groups: ProcessGroupManager::new(),
+        supervisor: Supervisor::new(),
+        engine: AlertEngine::new(rules),
+        actions: ActionRunner::new(),
+        sinks: SinkDispatcher::start(sinks),
+        metrics,
+        events: VecDeque::new(),
+        next_event: 0,
+        started: history::now(),
+        interval: config.interval,
+        recording: recorder.is_some(),
+    }));
+
+    let accept_state = state.clone();
+    let accept_stop = stop.clone();
+    let acceptor = thread::spawn(move || {
+        for stream in listener.incoming() {
+            if accept_stop.load(Ordering::Relaxed) {
+                break;
+            }
+            let Ok(stream) = stream else { continue };
+            let state = accept_state.clone();
+            let stop = accept_stop.clone();
+            thread::spawn(move || serve(stream, state, stop));
+        }
+    });
+
+    let interval = Duration::from_secs(config.interval);
+    while !stop.load(Ordering::Relaxed) {
+        // Sleep in short steps so a signal or shutdown request is handled promptly
+        let deadline = Instant::now() + interval;
+        while Instant::now() < deadline && !stop.load(Ordering::Relaxed) {
+            thread::sleep(Duration::from_millis(200));
+        }
+        if stop.load(Ordering::Relaxed) {
+            break;
+        }
+        for line in lock(&state).refresh() {
+            println!("{}", line);
+        }
+        for error in recorder.iter().flat_map(Recorder::errors) {
+            println!("{} {}", format_time(history::now()), error);
+        }
+    }
+
+    // Wake the acceptor so it sees the stop flag
+    let _ = UnixStream::connect(&config.socket);
+    let _ = acceptor.join();
+    let _ = fs::remove_file(&config.socket);
+    drop(recorder);
+
+    let mut state = lock(&state);
+    state.controller.resume_all();
+    state.groups.resume_all();
+    state.actions.wait_for_stops();
+    println!("Pulse daemon stopped");
+    Ok(())
+}
+
+// A client thread that panicked while holding the lock leaves the state usable
+fn lock(state: &Mutex<DaemonState>) -> MutexGuard<'_, DaemonState> {
+    state.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn bind(path: &Path) -> Result<UnixListener, String> {
+    if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+        DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(dir)
+            .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        // Anyone can create /tmp/pulse-<uid> first and then read or swap the socket
+        if dir == socket_dir() {
+            let metadata = fs::symlink_metadata(dir).map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?;
+            if !metadata.is_dir() || metadata.uid() != getuid().as_raw() || metadata.mode() & 0o077 != 0 {
+                return Err(format!(
+                    "Refusing to use {}: it must be a directory owned by you that only you can access",
+                    dir.display()
+                ));
+            }
+        }
+    }
+    if path.exists() {
+        if UnixStream::connect(path).is_ok() {
+            return Err(format!("A daemon is already listening on {}", path.display()));
+        }
+        // Left behind by a daemon that did not shut down cleanly
+        fs::remove_file(path).map_err(|e| format!("Failed to remove stale socket {}: {}", path.display(), e))?;
+    }
+    let listener = UnixListener::bind(path).map_err(|e| format!("Failed to listen on {}: {}", path.display(), e))?;
+    // Only the owner may pause processes through the daemon
+    fs::set_permissions(path, Permissions::from_mode(0o600))
+        .map_err(|e| format!("Failed to set permissions on {}: {}", path.display(), e))?;
+    Ok(listener)
+}
+
+// Answer one client's requests until it disconnects
+fn serve(stream: UnixStream, state: Arc<Mutex<DaemonState>>, stop: Arc<AtomicBool>) {
+    let mut writer = match stream.try_clone() {
+        Ok(writer) => writer,
+        Err(_) => return,
+    };
+    for line in BufReader::new(stream).lines() {
+        let Ok(line) = line else { break };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = match serde_json::from_str::<Request>(&line) {
+            Ok(request) => lock(&state).handle(request, &stop),
+            Err(e) => Response::failure(format!("invalid request: {}", e)),
+        };
+        let text = serde_json::to_string(&response).unwrap_or_else(|_| "{\"ok\":false}".to_string());
+        if writeln!(writer, "{}", text).is_err() {
+            break;
+        }
+    }
+}
+
+/// A connection to a running daemon. Requests are answered in order, so one
+/// client can be kept open for as long as the TUI stays attached.
+pub struct DaemonClient {
+    path: PathBuf,
+    reader: BufReader<UnixStream>,
+    writer: UnixStream,
+}
+
+impl DaemonClient {
+    pub fn connect(path: &Path) -> Result<Self, String> {
+        let stream =
+            UnixStream::connect(path).map_err(|e| format!("No daemon listening on {}: {}", path.display(), e))?;
+        stream
+            .set_read_timeout(Some(CLIENT_TIMEOUT))
+            .and_then(|_| stream.set_write_timeout(Some(CLIENT_TIMEOUT)))
+            .map_err(|e| e.to_string())?;
+        let writer = stream.try_clone().map_err(|e| e.to_string())?;
+        Ok(DaemonClient { path: path.to_path_buf(), reader: BufReader::new(stream), writer })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Send a request and wait for the answer; `Err` carries the daemon's error message
+    pub fn request(&mut self, request: &Request) -> Result<Value, String> {
+        let text = serde_json::to_string(request).map_err(|e| e.to_string())?;
+        writeln!(self.writer, "{}", text).map_err(|e| format!("Lost the connection to the daemon: {}", e))?;
+
+        let mut line = String::new();
+        match self.reader.read_line(&mut line) {
+            Ok(0) => return Err("The daemon closed the connection".to_string()),
+            Ok(_) => {},
+            Err(e) => return Err(format!("Lost the connection to the daemon: {}", e)),
+        }
+        let response: Response =
+            serde_json::from_str(&line).map_err(|e| format!("Invalid answer from the daemon: {}", e))?;
+        if response.ok {
+            Ok(response.data)
+        } else {
+            Err(response.error.unwrap_or_else(|| "The request failed".to_string()))
+        }
+    }
+
+    pub fn status(&mut self) -> Result<DaemonStatus, String> {
+        let data = self.request(&Request::Status)?;
+        serde_json::from_value(data).map_err(|e| format!("Invalid answer from the daemon: {}", e))
+    }
+
+    pub fn events(&mut self, since: u64) -> Result<EventBatch, String> {
+        let data = self.request(&Request::Events { since })?;
+        serde_json::from_value(data).map_err(|e| format!("Invalid answer from the daemon: {}", e))
+    }
+
+    /// Pause or resume `pid`, if it still started at `start_time` (0 when not known)
+    pub fn control(&mut self, pid: u32, start_time: u64, action: ProcessAction) -> Result<(), String> {
+        let (pids, start_times) = (vec![pid], vec![start_time]);
+        let request = match action {
+            ProcessAction::Pause => Request::Pause { pids, start_times },
+            ProcessAction::Resume => Request::Resume { pids, start_times },
+        };
+        self.request(&request).map(|_| ())
+    }
+
+    /// Pause or resume a process and its descendants; returns how many processes were signalled
+    pub fn control_group(&mut self, pid: u32, start_time: u64, action: ProcessAction) -> Result<usize, String> {
+        let request = match action {
+            ProcessAction::Pause => Request::GroupPause { pid, start_time },
+            ProcessAction::Resume => Request::GroupResume { pid, start_time },
+        };
+        let data = self.request(&request)?;
+        Ok(data["count"].as_u64().unwrap_or(0) as usize)
+    }
+
+    /// Have the daemon keep a PID or pattern alive; returns its message
+    pub fn keep_alive(&mut self, target: &str, policy: SupervisorPolicy) -> Result<String, String> {
+        let data = self.request(&Request::KeepAlive { target: target.to_string(), policy })?;
+        Ok(data["message"].as_str().unwrap_or_default().to_string())
+    }
+
+    pub fn release(&mut self, target: &str) -> Result<String, String> {
+        let data = self.request(&Request::Release { target: target.to_string() })?;
+        Ok(data["message"].as_str().unwrap_or_default().to_string())
+    }
+}
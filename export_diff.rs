@@ -0,0 +1,286 @@
+use std::fs;
+use std::path::Path;
+use serde_json::{json, Map, Value};
+use crate::columns::{Column, ColumnKind};
+use crate::csv_export;
+use crate::snapshot::{ProcessSnapshot, ProcessState};
+
+/// Processes read back from a JSON or CSV export, one object per process keyed by
+/// column name, with the command line as a single string
+pub struct ImportedExport {
+    pub columns: Vec<Column>,
+    pub processes: Vec<Map<String, Value>>,
+}
+
+impl ImportedExport {
+    /// Read a file written by the JSON or CSV exporter, telling them apart by the
+    /// extension or, failing that, by the first character
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let extension = path.extension().map(|e| e.to_string_lossy().to_lowercase()).unwrap_or_default();
+        let json = match extension.as_str() {
+            "json" => true,
+            "csv" | "tsv" => false,
+            _ => text.trim_start().starts_with('['),
+        };
+        let imported = if json { Self::from_json(&text) } else { Self::from_csv(&text) };
+        imported.map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    fn from_json(text: &str) -> Result<Self, String> {
+        let rows: Vec<Map<String, Value>> =
+            serde_json::from_str(text).map_err(|e| format!("expected a JSON array of processes ({})", e))?;
+        let mut columns = Vec::new();
+        let mut processes = Vec::new();
+        for row in rows {
+            let process = import_row(row);
+            for key in process.keys() {
+                let column = Column::parse(key)?;
+                if !columns.contains(&column) {
+                    columns.push(column);
+                }
+            }
+            processes.push(process);
+        }
+        Ok(ImportedExport { columns, processes })
+    }
+
+    fn from_csv(text: &str) -> Result<Self, String> {
+        // The delimiter is whichever of the usual ones the header uses
+        let first_line = text.lines().next().unwrap_or_default();
+        let delimiter = [',', '\t', ';', '|'].into_iter().find(|d| first_line.contains(*d)).unwrap_or(',');
+        let mut records = csv_export::read_records(text, delimiter)?.into_iter();
+        let header = records.next().ok_or("the file is empty")?;
+        let columns: Vec<Column> = header
+            .iter()
+            .map(|name| Column::parse(name))
+            .collect::<Result<_, _>>()
+            .map_err(|e| format!("{} in the header (exported with --no-header?)", e))?;
+
+        let mut processes = Vec::new();
+        for (index, record) in records.enumerate() {
+            if record.len() != columns.len() {
+                return Err(format!("record {} has {} fields, expected {}", index + 2, record.len(), columns.len()));
+            }
+            let mut process = Map::new();
+            for (column, field) in columns.iter().zip(record) {
+                let value = match column.kind() {
+                    _ if field.is_empty() && column.kind() != ColumnKind::Text => Some(Value::Null),
+                    ColumnKind::Integer => field.parse::<i64>().map(Value::from).ok(),
+                    ColumnKind::Real => field.parse::<f64>().map(Value::from).ok(),
+                    ColumnKind::Text => Some(Value::String(field.clone())),
+                }
+                .ok_or_else(|| format!("record {}: invalid {} '{}'", index + 2, column.header(), field))?;
+                process.insert(column.name().to_string(), value);
+            }
+            processes.push(process);
+        }
+        Ok(ImportedExport { columns, processes })
+    }
+}
+
+/// The columns of one exported JSON object, keyed by column name. Keys that are
+/// not columns (e.g. `time` in a stream line) are left out.
+pub(crate) fn import_row(row: Map<String, Value>) -> Map<String, Value> {
+    let mut process = Map::new();
+    for (key, value) in row {
+        let Ok(column) = Column::parse(&key) else { continue };
+        let value = match value {
+            Value::Array(args) => Value::String(args.iter().filter_map(Value::as_str).collect::<Vec<_>>().join(" ")),
+            value => value,
+        };
+        process.insert(column.name().to_string(), value);
+    }
+    process
+}
+
+/// An imported process as a snapshot. Fields the export did not have are left
+/// empty, as for the processes stored in the metrics history.
+pub fn to_snapshot(row: &Map<String, Value>) -> ProcessSnapshot {
+    let number = |key: &str| row.get(key).and_then(Value::as_f64).unwrap_or(0.0);
+    let text = |key: &str| row.get(key).and_then(Value::as_str).unwrap_or_default().to_string();
+    let name = text(Column::Command.name());
+    let cmdline = text(Column::Cmdline.name());
+    let foreground = text(Column::FgBg.name()) == "FG";
+    ProcessSnapshot {
+        pid: number("pid") as u32,
+        ppid: row.get("ppid").and_then(Value::as_u64).map(|ppid| ppid as u32),
+        name: if name.is_empty() { cmdline.split_whitespace().next().unwrap_or_default().to_string() } else { name },
+        cmdline: cmdline.split_whitespace().map(str::to_string).collect(),
+        uid: None,
+        user: row.get("username").and_then(Value::as_str).unwrap_or("Unknown").to_string(),
+        cpu: number("cpu") as f32,
+        memory: number("rss") as u64,
+        virtual_memory: number("virt") as u64,
+        mem: number("mem"),
+        nice: number("nice") as i32,
+        pgrp: 0,
+        tpgid: if foreground { 0 } else { -1 },
+        state: row.get("state").and_then(Value::as_str).map_or(ProcessState::Other, ProcessState::from_label),
+        start_time: number("start_time") as u64,
+        threads: row.get("threads").and_then(Value::as_u64).unwrap_or(1) as u32,
+        read_bytes: number("read_bytes") as u64,
+        written_bytes: number("written_bytes") as u64,
+        cgroup: text(Column::Cgroup.name()),
+        is_thread: false,
+    }
+}
+
+/// How far CPU and memory must move to count as a change, in percentage points
+#[derive(Clone, Debug)]
+pub struct DiffThresholds {
+    pub cpu: f64,
+    pub mem: f64,
+}
+
+impl DiffThresholds {
+    pub fn new() -> Self {
+        DiffThresholds { cpu: 10.0, mem: 1.0 }
+    }
+}
+
+impl Default for DiffThresholds {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// What changed between two exports
+pub struct ExportDiff {
+    /// Processes only in the second export
+    pub started: Vec<Map<String, Value>>,
+    /// Processes only in the first export
+    pub exited: Vec<Map<String, Value>>,
+    /// Processes in both whose CPU, memory or nice value moved, with the `changes`
+    pub changed: Vec<(Map<String, Value>, Map<String, Value>)>,
+}
+
+impl ExportDiff {
+    /// Processes are matched by PID. A PID whose start time (or, without start
+    /// times, command) differs was reused, so it counts as one process exiting
+    /// and another starting.
+    pub fn compare(before: &ImportedExport, after: &ImportedExport, thresholds: &DiffThresholds) -> Result<Self, String> {
+        for (export, which) in [(before, "first"), (after, "second")] {
+            if !export.columns.contains(&Column::Pid) {
+                return Err(format!("the {} export has no pid column", which));
+            }
+        }
+        let pid = |p: &Map<String, Value>| p.get("pid").and_then(Value::as_u64);
+        // The start time when both have it, as kernel workers rename themselves
+        let same_process = |old: &Map<String, Value>, new: &Map<String, Value>| {
+            match (old.get("start_time"), new.get("start_time"), old.get("command"), new.get("command")) {
+                (Some(a), Some(b), _, _) | (_, _, Some(a), Some(b)) => a == b,
+                _ => true,
+            }
+        };
+        let find = |export: &ImportedExport, p: &Map<String, Value>| {
+            export.processes.iter().find(|other| pid(other) == pid(p) && same_process(p, other)).cloned()
+        };
+
+        let mut diff = ExportDiff { started: Vec::new(), exited: Vec::new(), changed: Vec::new() };
+        for process in &after.processes {
+            if find(before, process).is_none() {
+                diff.started.push(process.clone());
+            }
+        }
+        for old in &before.processes {
+            let Some(new) = find(after, old) else {
+                diff.exited.push(old.clone());
+                continue;
+            };
+            let mut changes = Map::new();
+            for (key, threshold) in [("cpu", Some(thresholds.cpu)), ("mem", Some(thresholds.mem)), ("nice", None)] {
+                let (Some(from), Some(to)) = (old.get(key), new.get(key)) else { continue };
+                let moved = match threshold {
+                    Some(threshold) => {
+                        let delta = (from.as_f64().unwrap_or(0.0) - to.as_f64().unwrap_or(0.0)).abs();
+                        delta > 0.0 && delta >= threshold
+                    },
+                    None => from != to,
+                };
+                if moved {
+                    changes.insert(key.to_string(), json!({ "from": from, "to": to }));
+                }
+            }
+            if !changes.is_empty() {
+                diff.changed.push((new, changes));
+            }
+        }
+        Ok(diff)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.started.is_empty() && self.exited.is_empty() && self.changed.is_empty()
+    }
+
+    /// `{"started": [...], "exited": [...], "changed": [...]}`; changed processes
+    /// carry a `changes` object shaped like the one of `export --stream --diff`
+    pub fn to_json(&self) -> Value {
+        let changed: Vec<Value> = self
+            .changed
+            .iter()
+            .map(|(process, changes)| {
+                let mut object = process.clone();
+                object.insert("changes".to_string(), Value::Object(changes.clone()));
+                Value::Object(object)
+            })
+            .collect();
+        json!({ "started": self.started, "exited": self.exited, "changed": changed })
+    }
+
+    /// Tables of the started, exited and changed processes
+    pub fn to_table(&self) -> String {
+        let mut out = String::new();
+        let row = |p: &Map<String, Value>| {
+            format!(
+                "{:>8} {:<15} {:>6} {:>6} {:>5}  {}\n",
+                field(p, "pid"),
+                field(p, "username"),
+                field(p, "cpu"),
+                field(p, "mem"),
+                field(p, "nice"),
+                field(p, "command")
+            )
+        };
+        for (title, processes) in [("Started", &self.started), ("Exited", &self.exited)] {
+            if processes.is_empty() {
+                continue;
+            }
+            out.push_str(&format!("{} ({}):\n", title, processes.len()));
+            out.push_str(&format!("{:>8} {:<15} {:>6} {:>6} {:>5}  {}\n", "PID", "USER", "CPU%", "MEM%", "NICE", "COMMAND"));
+            for process in processes {
+                out.push_str(&row(process));
+            }
+            out.push('\n');
+        }
+        if !self.changed.is_empty() {
+            out.push_str(&format!("Changed ({}):\n", self.changed.len()));
+            out.push_str(&format!("{:>8} {:<24} {}\n", "PID", "COMMAND", "CHANGES"));
+            for (process, changes) in &self.changed {
+                let changes: Vec<String> = changes
+                    .iter()
+                    .map(|(key, change)| format!("{} {} -> {}", key, format_value(&change["from"]), format_value(&change["to"])))
+                    .collect();
+                out.push_str(&format!("{:>8} {:<24} {}\n", field(process, "pid"), field(process, "command"), changes.join(", ")));
+            }
+            out.push('\n');
+        }
+        if self.is_empty() {
+            out.push_str("No differences\n");
+        }
+        out
+    }
+}
+
+fn field(process: &Map<String, Value>, key: &str) -> String {
+    process.get(key).map(format_value).unwrap_or_else(|| "-".to_string())
+}
+
+fn format_value(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Null => "-".to_string(),
+        Value::Number(n) if n.is_f64() => format!("{:.1}", n.as_f64().unwrap_or(0.0)),
+        other => other.to_string(),
+    }
+}
@@ -0,0 +1,52 @@
+use std::path::Path;
+use chrono::Local;
+use crate::columns::Column;
+use crate::csv_export::{CsvExporter, CsvOptions};
+use crate::history::SystemSample;
+use crate::html_export::HtmlExporter;
+use crate::json_export::JsonExporter;
+use crate::parquet_export::ParquetExporter;
+use crate::snapshot::{ProcessSnapshot, SystemSummary};
+use crate::sqlite_export::SqliteExporter;
+
+/// The formats `--format` takes
+pub const FORMATS: [&str; 5] = ["json", "csv", "sqlite", "parquet", "html"];
+
+/// Everything an exporter may write: the processes at one moment and the
+/// figures of the machine they ran on
+pub struct ExportData<'a> {
+    /// When the processes were collected, in seconds since the epoch
+    pub time: u64,
+    pub processes: &'a [ProcessSnapshot],
+    pub summary: &'a SystemSummary,
+    pub columns: &'a [Column],
+    /// System samples leading up to `time` from the metrics history, for the
+    /// formats that chart them; empty when none were recorded
+    pub history: Vec<SystemSample>,
+}
+
+/// A file format processes can be exported to
+pub trait Exporter {
+    /// Extension of the default file name
+    fn extension(&self) -> &'static str;
+
+    /// Write `data` to `path` and describe what was written
+    fn export(&self, data: &ExportData, path: &Path) -> Result<String, String>;
+
+    /// e.g. `pulse_export_20240501_031200.csv`
+    fn default_filename(&self) -> String {
+        format!("pulse_export_{}.{}", Local::now().format("%Y%m%d_%H%M%S"), self.extension())
+    }
+}
+
+/// The exporter for a `--format` name
+pub fn for_format(format: &str, csv: CsvOptions) -> Option<Box<dyn Exporter>> {
+    match format {
+        "json" => Some(Box::new(JsonExporter)),
+        "csv" => Some(Box::new(CsvExporter::new(csv))),
+        "sqlite" | "db" => Some(Box::new(SqliteExporter)),
+        "parquet" => Some(Box::new(ParquetExporter)),
+        "html" => Some(Box::new(HtmlExporter)),
+        _ => None,
+    }
+}
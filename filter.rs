@@ -0,0 +1,481 @@
+use regex::Regex;
+use crate::snapshot::ProcessSnapshot;
+
+/// A parsed filter expression for the process list and exports.
+///
+/// An expression is a list of space-separated terms that must all match:
+///
+/// - `name:ssh`, `cmd:--queue`, `user:postgres` match a substring (case-insensitive)
+/// - `cmd:~/worker.*--queue/` matches a regular expression instead
+/// - `state:R` or `state:sleep` match the ps state letter or the state label
+/// - `cpu>20`, `mem>=5`, `nice<0`, `pid=42`, `ppid=1`, `threads>8`, `rss>500M` compare numbers
+/// - a bare word matches the name or command line (`queue=high` too, as it names
+///   no numeric field), a bare number matches the PID
+/// - a leading `!` negates a term, e.g. `!user:root`
+///
+/// Quote values containing spaces: `cmd:"my app"`.
+#[derive(Clone, Debug)]
+pub struct ProcessFilter {
+    expression: String,
+    terms: Vec<Term>,
+}
+
+#[derive(Clone, Debug)]
+struct Term {
+    negated: bool,
+    kind: TermKind,
+}
+
+#[derive(Clone, Debug)]
+enum TermKind {
+    Text(TextField, TextMatch),
+    Compare(NumField, CompareOp, f64),
+    State(String),
+    /// Bare word: name or command line
+    Any(TextMatch),
+    /// Bare number: PID contains the digits, like the old PID search
+    PidDigits(String),
+}
+
+#[derive(Clone, Copy, Debug)]
+enum TextField {
+    Name,
+    Cmd,
+    User,
+}
+
+#[derive(Clone, Debug)]
+enum TextMatch {
+    Substring(String),
+    Regex(Regex),
+}
+
+#[derive(Clone, Copy, Debug)]
+enum NumField {
+    Cpu,
+    Mem,
+    Rss,
+    Nice,
+    Pid,
+    Ppid,
+    Threads,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum CompareOp {
+    Gt,
+    Ge,
+    Lt,
+    Le,
+    Eq,
+    Ne,
+}
+
+impl ProcessFilter {
+    pub fn parse(expression: &str) -> Result<Self, String> {
+        let mut terms = Vec::new();
+        for token in tokenize(expression)? {
+            terms.push(parse_term(&token)?);
+        }
+        Ok(ProcessFilter {
+            expression: expression.trim().to_string(),
+            terms,
+        })
+    }
+
+    /// The expression as typed, for display
+    pub fn expression(&self) -> &str {
+        &self.expression
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    /// Check one process. `physical_cores` scales `cpu` the same way the process
+    /// list does, so `cpu>20` means what the user sees on screen.
+    pub fn matches(&self, process: &ProcessSnapshot, physical_cores: usize) -> bool {
+        self.terms
+            .iter()
+            .all(|term| term.kind.matches(process, physical_cores) != term.negated)
+    }
+
+    /// Keep only the matching processes
+    pub fn apply(&self, processes: &[ProcessSnapshot], physical_cores: usize) -> Vec<ProcessSnapshot> {
+        processes
+            .iter()
+            .filter(|p| self.matches(p, physical_cores))
+            .cloned()
+            .collect()
+    }
+}
+
+impl TermKind {
+    fn matches(&self, process: &ProcessSnapshot, physical_cores: usize) -> bool {
+        match self {
+            TermKind::Text(field, text) => match field {
+                TextField::Name => text.matches(&process.name),
+                TextField::Cmd => text.matches(&process.command_line()),
+                TextField::User => text.matches(&process.user),
+            },
+            TermKind::Compare(field, op, value) => {
+                let actual = match field {
+                    NumField::Cpu => process.cpu as f64 / physical_cores.max(1) as f64,
+                    NumField::Mem => process.mem,
+                    NumField::Rss => process.memory as f64,
+                    NumField::Nice => process.nice as f64,
+                    NumField::Pid => process.pid as f64,
+                    NumField::Ppid => match process.ppid {
+                        Some(ppid) => ppid as f64,
+                        None => return false,
+                    },
+                    NumField::Threads => process.threads as f64,
+                };
+                op.compare(actual, *value)
+            },
+            TermKind::State(state) => {
+                // A single letter is the ps code (case-sensitive: 't' is tracing),
+                // anything longer is a prefix of the state label
+                if state.chars().count() == 1 {
+                    state.starts_with(process.state.code())
+                } else {
+                    process.state.as_str().to_lowercase().replace(' ', "").starts_with(&state.to_lowercase())
+                }
+            },
+            TermKind::Any(text) => text.matches(&process.name) || text.matches(&process.command_line()),
+            TermKind::PidDigits(digits) => process.pid.to_string().contains(digits.as_str()),
+        }
+    }
+}
+
+impl TextMatch {
+    fn matches(&self, haystack: &str) -> bool {
+        match self {
+            TextMatch::Substring(needle) => haystack.to_lowercase().contains(needle.as_str()),
+            TextMatch::Regex(re) => re.is_match(haystack),
+        }
+    }
+}
+
+impl CompareOp {
+    fn compare(&self, actual: f64, expected: f64) -> bool {
+        match self {
+            CompareOp::Gt => actual > expected,
+            CompareOp::Ge => actual >= expected,
+            CompareOp::Lt => actual < expected,
+            CompareOp::Le => actual <= expected,
+            CompareOp::Eq => actual == expected,
+            CompareOp::Ne => actual != expected,
+        }
+    }
+}
+
+// Split on whitespace, keeping "quoted values" and ~/regexes/ (which may contain spaces) whole
+fn tokenize(expression: &str) -> Result<Vec<String>, String> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut chars = expression.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c.is_whitespace() {
+            if !current.is_empty() {
+                tokens.push(std::mem::take(&mut current));
+            }
+        } else if c == '"' {
+            // Quotes only group, they are not part of the value
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some(c) => current.push(c),
+                    None => return Err("Unterminated quote in filter".to_string()),
+                }
+            }
+        } else if c == '~' && chars.peek() == Some(&'/') {
+            current.push('~');
+            current.push(chars.next().unwrap_or('/'));
+            loop {
+                match chars.next() {
+                    Some('\\') => {
+                        current.push('\\');
+                        if let Some(escaped) = chars.next() {
+                            current.push(escaped);
+                        }
+                    },
+                    Some('/') => {
+                        current.push('/');
+                        break;
+                    },
+                    Some(c) => current.push(c),
+                    None => return Err("Unterminated regex in filter (expected closing /)".to_string()),
+                }
+            }
+        } else {
+            current.push(c);
+        }
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+    Ok(tokens)
+}
+
+fn parse_term(token: &str) -> Result<Term, String> {
+    let (negated, body) = match token.strip_prefix('!') {
+        Some(rest) => (true, rest),
+        None => (false, token),
+    };
+    if body.is_empty() {
+        return Err(format!("Empty filter term '{}'", token));
+    }
+
+    // Comparisons first: `cpu>20` has no ':' and must not be read as a bare word
+    if let Some(kind) = parse_comparison(body)? {
+        return Ok(Term { negated, kind });
+    }
+
+    let kind = match body.split_once(':') {
+        Some((key, value)) => {
+            if value.is_empty() {
+                return Err(format!("Missing value for '{}:'", key));
+            }
+            match key.to_lowercase().as_str() {
+                "name" => TermKind::Text(TextField::Name, parse_text(value)?),
+                "cmd" => TermKind::Text(TextField::Cmd, parse_text(value)?),
+                "user" => TermKind::Text(TextField::User, parse_text(value)?),
+                "state" => TermKind::State(value.to_string()),
+                "pid" | "ppid" | "cpu" | "mem" | "rss" | "nice" | "threads" => {
+                    // `pid:42` is shorthand for `pid=42`
+                    return parse_term(&format!("{}{}={}", if negated { "!" } else { "" }, key, value));
+                },
+                other => return Err(format!("Unknown filter field '{}'", other)),
+            }
+        },
+        None if body.chars().all(|c| c.is_ascii_digit()) => TermKind::PidDigits(body.to_string()),
+        None => TermKind::Any(parse_text(body)?),
+    };
+    Ok(Term { negated, kind })
+}
+
+fn parse_comparison(body: &str) -> Result<Option<TermKind>, String> {
+    let start = match body.find(['>', '<', '=', '!']) {
+        Some(start) if start > 0 => start,
+        _ => return Ok(None),
+    };
+    let rest = &body[start..];
+    // Only a numeric field makes a comparison: `queue=high`, `a<b`, `~/a>b/` or `--level=2` are text
+    let field = match body[..start].to_lowercase().as_str() {
+        "cpu" => NumField::Cpu,
+        "mem" => NumField::Mem,
+        "rss" => NumField::Rss,
+        "nice" => NumField::Nice,
+        "pid" => NumField::Pid,
+        "ppid" => NumField::Ppid,
+        "threads" => NumField::Threads,
+        _ => return Ok(None),
+    };
+
+    // Longest operators first
+    let (op, value) = [
+        (">=", CompareOp::Ge),
+        ("<=", CompareOp::Le),
+        ("!=", CompareOp::Ne),
+        ("==", CompareOp::Eq),
+        (">", CompareOp::Gt),
+        ("<", CompareOp::Lt),
+        ("=", CompareOp::Eq),
+    ]
+    .iter()
+    .find_map(|(symbol, op)| rest.strip_prefix(symbol).map(|value| (*op, value)))
+    .ok_or_else(|| format!("Invalid comparison '{}'", body))?;
+
+    let value = match field {
+        NumField::Rss => parse_size(value)? as f64,
+        _ => value
+            .trim_end_matches('%')
+            .parse::<f64>()
+            .map_err(|_| format!("Invalid number '{}' in '{}'", value, body))?,
+    };
+    Ok(Some(TermKind::Compare(field, op, value)))
+}
+
+/// Bytes from plain bytes or a K/M/G suffix (powers of 1024), e.g. `512K`,
+/// `1.5G`, `10MB` or `10MiB`. Shared by `rss` filters, alert rules and
+/// `--rotate-size`.
+pub fn parse_size(value: &str) -> Result<u64, String> {
+    let invalid = || format!("Invalid size '{}' (use e.g. 512K, 10M, 1G)", value);
+    let upper = value.trim().to_uppercase();
+    let trimmed = upper.trim_end_matches('B').trim_end_matches('I');
+    let (number, multiplier) = match trimmed.chars().last() {
+        Some('K') => (&trimmed[..trimmed.len() - 1], 1 << 10),
+        Some('M') => (&trimmed[..trimmed.len() - 1], 1 << 20),
+        Some('G') => (&trimmed[..trimmed.len() - 1], 1 << 30),
+        _ => (trimmed, 1u64),
+    };
+    if let Ok(n) = number.parse::<u64>() {
+        return n.checked_mul(multiplier).ok_or_else(invalid);
+    }
+    // Fractions such as 1.5G
+    match number.parse::<f64>() {
+        Ok(n) if n >= 0.0 && n * (multiplier as f64) < u64::MAX as f64 => Ok((n * multiplier as f64) as u64),
+        _ => Err(invalid()),
+    }
+}
+
+fn parse_text(value: &str) -> Result<TextMatch, String> {
+    match value.strip_prefix("~/").and_then(|v| v.strip_suffix('/')) {
+        Some(pattern) => Regex::new(&pattern.replace("\\/", "/"))
+            .map(TextMatch::Regex)
+            .map_err(|e| format!("Invalid regex '{}': {}", pattern, e)),
+        None => Ok(TextMatch::Substring(value.to_lowercase())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::snapshot::ProcessState;
+
+    fn process(pid: u32, name: &str, cmdline: &str, user: &str) -> ProcessSnapshot {
+        ProcessSnapshot {
+            pid,
+            ppid: Some(1),
+            name: name.to_string(),
+            cmdline: cmdline.split(' ').map(str::to_string).collect(),
+            uid: Some(1000),
+            user: user.to_string(),
+            cpu: 50.0,
+            memory: 600 << 20,
+            virtual_memory: 0,
+            mem: 4.0,
+            nice: 5,
+            pgrp: pid as i32,
+            tpgid: -1,
+            state: ProcessState::Sleep,
+            start_time: 0,
+            threads: 12,
+            read_bytes: 0,
+            written_bytes: 0,
+            cgroup: String::new(),
+            is_thread: false,
+        }
+    }
+
+    #[test]
+    fn parses() {
+        for expression in [
+            "",
+            "name:ssh",
+            "cmd:--queue user:postgres",
+            "cmd:~/worker.*--queue/",
+            "state:R state:sleep",
+            "cpu>20 mem>=5 nice<0 pid=42 ppid==1 threads!=8 rss<=500M",
+            "cpu>20%",
+            "!user:root",
+            "cmd:\"my app\"",
+            "~/a>b/",
+            "~/x=1|y<2/",
+            "--level=2",
+            "cmd:a=b",
+            "queue=high",
+            "a<b",
+            "cpux>5",
+            "pid:42",
+            "123",
+        ] {
+            assert!(ProcessFilter::parse(expression).is_ok(), "{:?} should parse", expression);
+        }
+    }
+
+    #[test]
+    fn rejects() {
+        for (expression, error) in [
+            ("!", "Empty filter term '!'"),
+            ("name:", "Missing value for 'name:'"),
+            ("color:red", "Unknown filter field 'color'"),
+            ("cpu>lots", "Invalid number 'lots' in 'cpu>lots'"),
+            ("rss>big", "Invalid size 'big' (use e.g. 512K, 10M, 1G)"),
+            ("rss>99999999999G", "Invalid size '99999999999G' (use e.g. 512K, 10M, 1G)"),
+            ("cpu!5", "Invalid comparison 'cpu!5'"),
+            ("cmd:\"my app", "Unterminated quote in filter"),
+            ("cmd:~/worker", "Unterminated regex in filter (expected closing /)"),
+        ] {
+            assert_eq!(ProcessFilter::parse(expression).unwrap_err(), error, "{:?}", expression);
+        }
+        assert!(ProcessFilter::parse("~/(/").unwrap_err().starts_with("Invalid regex '('"));
+    }
+
+    #[test]
+    fn sizes() {
+        for (text, bytes) in [
+            ("1048576", 1 << 20),
+            ("512K", 512 << 10),
+            ("512k", 512 << 10),
+            ("10M", 10 << 20),
+            ("10MB", 10 << 20),
+            ("10MiB", 10 << 20),
+            ("1.5G", 3 << 29),
+        ] {
+            assert_eq!(parse_size(text), Ok(bytes), "{:?}", text);
+        }
+        for text in ["", "big", "-1M", "99999999999G", "1e30G"] {
+            assert!(parse_size(text).is_err(), "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn matches_processes() {
+        let worker = process(4242, "worker", "/usr/bin/worker --queue high", "alice");
+        for (expression, expected) in [
+            ("", true),
+            ("name:WORK", true),
+            ("name:ssh", false),
+            ("cmd:--queue", true),
+            ("cmd:\"queue high\"", true),
+            ("cmd:~/worker.*--queue/", true),
+            ("cmd:~/^worker/", false),
+            ("user:alice", true),
+            ("!user:alice", false),
+            ("state:S", true),
+            ("state:sl", true),
+            ("state:R", false),
+            // 50% of one core out of two
+            ("cpu>20", true),
+            ("cpu>30", false),
+            ("mem>=4", true),
+            ("rss>500M", true),
+            ("rss>1G", false),
+            ("nice=5", true),
+            ("nice<0", false),
+            ("pid=4242", true),
+            ("pid:4242", true),
+            ("ppid=1", true),
+            ("threads!=12", false),
+            ("42", true),
+            ("99", false),
+            ("high", true),
+            ("~/q.e.e/", true),
+            ("~/e>h/", false),
+            ("name:worker cpu>20 !state:Z", true),
+            ("name:worker cpu>30", false),
+        ] {
+            let filter = ProcessFilter::parse(expression).unwrap();
+            assert_eq!(filter.matches(&worker, 2), expected, "{:?}", expression);
+        }
+    }
+
+    #[test]
+    fn regex_with_comparison_characters_matches_text() {
+        let arrow = process(7, "pipe", "sh -c a>b", "root");
+        assert!(ProcessFilter::parse("~/a>b/").unwrap().matches(&arrow, 1));
+        assert!(ProcessFilter::parse("cmd:~/a>b/").unwrap().matches(&arrow, 1));
+    }
+
+    #[test]
+    fn words_with_comparison_characters_match_text() {
+        let worker = process(8, "worker", "/usr/bin/worker queue=high a<b", "alice");
+        for (expression, expected) in [("queue=high", true), ("a<b", true), ("queue=low", false), ("a>b", false)] {
+            let filter = ProcessFilter::parse(expression).unwrap();
+            assert_eq!(filter.matches(&worker, 1), expected, "{:?}", expression);
+        }
+    }
+}
@@ -1,6 +1,5 @@
-
 use std::env;
-use sysinfo::{ProcessStatus, System};
+use process_manager::snapshot::Collector;
 
 fn main() {
     let args: Vec<String> = env::args().collect();
@@ -13,8 +12,8 @@ fn main() {
         }
     }
 
-    let mut system = System::new_all();
-    system.refresh_all();
+    let mut collector = Collector::new();
+    collector.refresh();
 
     if show_header {
         println!("{:<8} {:<10} {:<10} {:<12} {:<30}",
@@ -22,40 +21,19 @@ fn main() {
         println!("{:-<80}", "");
     }
 
-    let mut processes: Vec<_> = system.processes().iter().collect();
-    processes.sort_by_key(|&(pid, _)| pid);
-
-    for (&pid, process) in processes {
-        let state = match process.status() {
-            ProcessStatus::Run => "Running",
-            ProcessStatus::Idle => "Idle",
-            ProcessStatus::Sleep => "Sleep",
-            ProcessStatus::Stop => "Stopped",
-            ProcessStatus::Zombie => "Zombie",
-            ProcessStatus::Tracing => "Tracing",
-            ProcessStatus::Dead => "Dead",
-            ProcessStatus::Wakekill => "Wakekill",
-            ProcessStatus::Waking => "Waking",
-            ProcessStatus::LockBlocked => "Locked",
-            ProcessStatus::Unknown(_) => "Unknown",
-            _ => "Other",
-        };
-
-        let memory_mb = process.memory() as f64 / 1024.0 / 1024.0;
-        let cmd = process.cmd().join(" ");
-        let display_cmd = if cmd.is_empty() {
-            process.name().to_string()
-        } else {
-            cmd
-        };
+    let mut processes = collector.snapshot();
+    processes.sort_by_key(|p| p.pid);
+
+    for process in processes {
+        let memory_mb = process.memory as f64 / 1024.0 / 1024.0;
 
         println!(
             "{:<8} {:<10.1} {:<10.1} {:<12} {:.30}",
-            pid,
-            process.cpu_usage(),
+            process.pid,
+            process.cpu,
             memory_mb,
-            state,
-            display_cmd
+            process.state.as_str(),
+            process.command_line()
         );
     }
 }
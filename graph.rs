@@ -0,0 +1,160 @@
+use std::fmt::Write;
+
+/// Render a line chart of `(x, y)` samples as plain text.
+///
+/// Lines end with `\r\n` so the chart can be written straight into the raw-mode
+/// screen buffer.
+pub fn render_chart(title: &str, data: &[(f32, f32)], width: usize, height: usize) -> String {
+    let mut out = String::new();
+    writeln!(out, "\x1B[1m{}:\x1B[0m\r", title).unwrap();
+
+    if data.is_empty() {
+        writeln!(out, "No data to display.\r").unwrap();
+        return out;
+    }
+
+    let width = width.max(10);
+    let height = height.max(3);
+
+    let y_min = data.iter().map(|(_, y)| *y).fold(f32::INFINITY, f32::min).min(0.0);
+    let mut y_max = data.iter().map(|(_, y)| *y).fold(f32::NEG_INFINITY, f32::max);
+    if y_max <= y_min {
+        y_max = y_min + 1.0;
+    }
+
+    // Row (counted from the top) of the point drawn in each column,
+    // resampling the data to the chart width
+    let points: Vec<usize> = (0..width)
+        .map(|col| {
+            let sample = (col * data.len() / width).min(data.len() - 1);
+            let y = data[sample].1;
+            let level = ((y - y_min) / (y_max - y_min) * (height - 1) as f32).round() as usize;
+            height - 1 - level.min(height - 1)
+        })
+        .collect();
+
+    for i in 0..height {
+        let row: String = points.iter().map(|&p| if p == i { '•' } else { ' ' }).collect();
+        let label = if i == 0 {
+            format!("{:>7.1}", y_max)
+        } else if i == height - 1 {
+            format!("{:>7.1}", y_min)
+        } else {
+            " ".repeat(7)
+        };
+        writeln!(out, "{} │{}\r", label, row).unwrap();
+    }
+    writeln!(out, "{} └{}\r", " ".repeat(7), "─".repeat(width)).unwrap();
+
+    out
+}
+
+/// One line of a multi-series chart
+pub struct ChartSeries {
+    pub label: String,
+    /// ANSI color used for the line and its legend entry
+    pub color: &'static str,
+    /// `(x, y)` points sorted by x
+    pub points: Vec<(f32, f32)>,
+}
+
+/// Colors assigned to series in order
+pub const SERIES_COLORS: [&str; 8] = [
+    "\x1B[38;5;82m",
+    "\x1B[38;5;39m",
+    "\x1B[38;5;208m",
+    "\x1B[38;5;201m",
+    "\x1B[38;5;220m",
+    "\x1B[38;5;50m",
+    "\x1B[38;5;196m",
+    "\x1B[38;5;147m",
+];
+
+/// Render several series over a shared x range as lines of exactly `width + 9`
+/// visible columns (without line endings), so charts can be placed side by side.
+///
+/// The y axis starts at 0 and grows to fit the data, but never below `min_y_max`.
+/// Later series are drawn over earlier ones where they overlap.
+pub fn render_multi_chart(
+    title: &str,
+    series: &[ChartSeries],
+    x_range: (f32, f32),
+    width: usize,
+    height: usize,
+    min_y_max: f32,
+) -> Vec<String> {
+    let width = width.max(10);
+    let height = height.max(2);
+    let (x_min, x_max) = x_range;
+    let x_span = (x_max - x_min).max(f32::EPSILON);
+
+    let y_max = series
+        .iter()
+        .flat_map(|s| s.points.iter().map(|(_, y)| *y))
+        .fold(min_y_max.max(f32::EPSILON), f32::max);
+
+    // Color index per cell, drawn series by series
+    let mut grid: Vec<Vec<Option<usize>>> = vec![vec![None; width]; height];
+    for (index, s) in series.iter().enumerate() {
+        let column = |x: f32| ((x - x_min) / x_span * (width - 1) as f32).round() as isize;
+        let row = |y: f32| {
+            let level = ((y / y_max) * (height - 1) as f32).round().clamp(0.0, (height - 1) as f32) as usize;
+            height - 1 - level
+        };
+        // Interpolate between neighbouring points so sparse data still draws a line
+        for pair in s.points.windows(2) {
+            let ((x0, y0), (x1, y1)) = (pair[0], pair[1]);
+            let (c0, c1) = (column(x0), column(x1));
+            for c in c0.max(0)..=c1.min(width as isize - 1) {
+                let t = if c1 == c0 { 1.0 } else { (c - c0) as f32 / (c1 - c0) as f32 };
+                grid[row(y0 + (y1 - y0) * t)][c as usize] = Some(index);
+            }
+        }
+        if let [(x, y)] = s.points[..] {
+            let c = column(x);
+            if (0..width as isize).contains(&c) {
+                grid[row(y)][c as usize] = Some(index);
+            }
+        }
+    }
+
+    let mut lines = Vec::with_capacity(height + 2);
+
+    // Title and legend with the latest value of each series, cut to fit
+    let mut header = format!("\x1B[1m{}\x1B[0m", title);
+    let mut visible = title.chars().count();
+    for s in series {
+        let entry = match s.points.last() {
+            Some((_, y)) => format!(" ■ {} {:.1}", s.label, y),
+            None => format!(" ■ {} -", s.label),
+        };
+        let entry_width = entry.chars().count();
+        if visible + entry_width > width + 9 {
+            break;
+        }
+        visible += entry_width;
+        write!(header, "{}{}\x1B[0m", s.color, entry).unwrap();
+    }
+    header.push_str(&" ".repeat(width + 9 - visible.min(width + 9)));
+    lines.push(header);
+
+    for (i, cells) in grid.iter().enumerate() {
+        let label = if i == 0 {
+            format!("{:>7.1}", y_max)
+        } else if i == height - 1 {
+            format!("{:>7.1}", 0.0)
+        } else {
+            " ".repeat(7)
+        };
+        let mut line = format!("{} │", label);
+        for cell in cells {
+            match cell {
+                Some(index) => write!(line, "{}•\x1B[0m", series[*index].color).unwrap(),
+                None => line.push(' '),
+            }
+        }
+        lines.push(line);
+    }
+    lines.push(format!("{} └{}", " ".repeat(7), "─".repeat(width)));
+    lines
+}
@@ -0,0 +1,336 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::graph::{render_multi_chart, ChartSeries, SERIES_COLORS};
+use crate::history::{History, HistoryRecord};
+use crate::snapshot::{ProcessSnapshot, SystemSummary};
+
+// Live samples older than the longest window are dropped
+const MAX_WINDOW: f64 = 3600.0;
+// Space between charts placed side by side
+const COLUMN_GAP: usize = 2;
+
+/// How much time the graph panel shows
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum GraphWindow {
+    OneMinute,
+    FiveMinutes,
+    OneHour,
+}
+
+impl GraphWindow {
+    pub fn seconds(&self) -> u64 {
+        match self {
+            GraphWindow::OneMinute => 60,
+            GraphWindow::FiveMinutes => 300,
+            GraphWindow::OneHour => 3600,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            GraphWindow::OneMinute => "1m",
+            GraphWindow::FiveMinutes => "5m",
+            GraphWindow::OneHour => "1h",
+        }
+    }
+
+    pub fn next(&self) -> Self {
+        match self {
+            GraphWindow::OneMinute => GraphWindow::FiveMinutes,
+            GraphWindow::FiveMinutes => GraphWindow::OneHour,
+            GraphWindow::OneHour => GraphWindow::OneMinute,
+        }
+    }
+}
+
+#[derive(Clone)]
+struct SystemPoint {
+    time: f64,
+    /// Per logical CPU; empty for points read from the history, which does not keep them
+    cores: Vec<f32>,
+    mem: f32,
+    swap: f32,
+    load: [f32; 3],
+}
+
+#[derive(Clone, Copy)]
+struct ProcessPoint {
+    time: f64,
+    /// Scaled by the number of physical cores, like the process list
+    cpu: f32,
+    mem: f32,
+}
+
+/// Overlaid CPU/memory graphs for a set of PIDs plus system-wide CPU per core,
+/// memory, swap and load. Samples are taken on every refresh; when the window
+/// reaches further back than that, older points come from the metrics history.
+pub struct GraphPanel {
+    window: GraphWindow,
+    pids: Vec<u32>,
+    names: HashMap<u32, String>,
+    physical_cores: usize,
+    system: VecDeque<SystemPoint>,
+    processes: HashMap<u32, VecDeque<ProcessPoint>>,
+    recorded_system: Vec<SystemPoint>,
+    recorded_processes: HashMap<u32, Vec<ProcessPoint>>,
+    /// Where the window ends while replaying recorded data; the present otherwise
+    end: Option<f64>,
+}
+
+impl GraphPanel {
+    pub fn new() -> Self {
+        GraphPanel {
+            window: GraphWindow::FiveMinutes,
+            pids: Vec::new(),
+            names: HashMap::new(),
+            physical_cores: 1,
+            system: VecDeque::new(),
+            processes: HashMap::new(),
+            recorded_system: Vec::new(),
+            recorded_processes: HashMap::new(),
+            end: None,
+        }
+    }
+
+    pub fn window(&self) -> GraphWindow {
+        self.window
+    }
+
+    pub fn set_window(&mut self, window: GraphWindow) {
+        self.window = window;
+    }
+
+    pub fn pids(&self) -> &[u32] {
+        &self.pids
+    }
+
+    /// Choose the processes to graph. Live samples already taken for them are kept.
+    pub fn watch(&mut self, pids: Vec<u32>, processes: &[ProcessSnapshot]) {
+        for &pid in &pids {
+            if let Some(process) = processes.iter().find(|p| p.pid == pid) {
+                self.names.insert(pid, process.name.clone());
+            }
+        }
+        self.processes.retain(|pid, _| pids.contains(pid));
+        self.pids = pids;
+    }
+
+    /// Record one refresh worth of data
+    pub fn sample(&mut self, processes: &[ProcessSnapshot], summary: &SystemSummary, cores: Vec<f32>) {
+        let time = now();
+        self.physical_cores = summary.physical_cores.max(1);
+
+        self.system.push_back(SystemPoint {
+            time,
+            cores,
+            mem: percent(summary.used_memory, summary.total_memory),
+            swap: percent(summary.used_swap, summary.total_swap),
+            load: summary.load_average.map(|l| l as f32),
+        });
+        while self.system.front().is_some_and(|p| p.time < time - MAX_WINDOW) {
+            self.system.pop_front();
+        }
+
+        for &pid in &self.pids {
+            // Exited processes keep their line up to the moment they went away
+            let Some(process) = processes.iter().find(|p| p.pid == pid) else { continue };
+            let points = self.processes.entry(pid).or_default();
+            points.push_back(ProcessPoint {
+                time,
+                cpu: process.cpu / self.physical_cores as f32,
+                mem: process.mem as f32,
+            });
+            while points.front().is_some_and(|p| p.time < time - MAX_WINDOW) {
+                points.pop_front();
+            }
+        }
+    }
+
+    /// Load the current window from the metrics history so longer windows are
+    /// filled in even when Pulse has not been running that long
+    pub fn backfill(&mut self, history: &mut History) {
+        let to = now() as u64;
+        let from = to.saturating_sub(self.window.seconds());
+        let records = history.range(from, to, GraphWindow::OneHour.seconds() as usize);
+        self.load_records(&records);
+    }
+
+    /// Show only recorded data, in a window ending at `end` rather than now
+    pub fn show_recorded(&mut self, end: u64, records: &[HistoryRecord], physical_cores: usize) {
+        self.end = Some(end as f64);
+        self.physical_cores = physical_cores.max(1);
+        self.system.clear();
+        self.processes.clear();
+        self.load_records(records);
+    }
+
+    fn load_records(&mut self, records: &[HistoryRecord]) {
+        let cores = self.physical_cores as f32;
+
+        self.recorded_system = records
+            .iter()
+            .map(|r| SystemPoint {
+                time: r.system.time as f64,
+                cores: Vec::new(),
+                mem: percent(r.system.mem_used, r.system.mem_total),
+                swap: percent(r.system.swap_used, r.system.swap_total),
+                load: r.system.load,
+            })
+            .collect();
+
+        self.recorded_processes.clear();
+        for record in records {
+            for p in record.processes.iter().filter(|p| self.pids.contains(&p.pid)) {
+                self.names.entry(p.pid).or_insert_with(|| p.name.clone());
+                self.recorded_processes.entry(p.pid).or_default().push(ProcessPoint {
+                    time: record.system.time as f64,
+                    cpu: p.cpu / cores,
+                    mem: p.mem,
+                });
+            }
+        }
+    }
+
+    /// Draw the panel into `width` x `height` cells, lines ending in `\r\n`
+    pub fn render(&self, width: usize, height: usize) -> String {
+        let end = self.end.unwrap_or_else(now);
+        let start = end - self.window.seconds() as f64;
+        let x_range = (0.0, (end - start) as f32);
+
+        let watched = if self.pids.is_empty() {
+            "none (mark processes or enter PIDs with V)".to_string()
+        } else {
+            self.pids
+                .iter()
+                .map(|pid| format!("{} ({})", pid, self.names.get(pid).map_or("?", |n| n.as_str())))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        let mut out = format!(
+            "\x1B[1mGraphs\x1B[0m | Window: {} | PIDs: {}\r\n",
+            self.window.label(),
+            watched
+        );
+
+        let system = self.system_points(start);
+        let mut charts: Vec<(&str, Vec<ChartSeries>, f32)> = Vec::new();
+        if !self.pids.is_empty() {
+            charts.push(("Process CPU%", self.process_series(start, |p| p.cpu), 1.0));
+            charts.push(("Process MEM%", self.process_series(start, |p| p.mem), 1.0));
+        }
+
+        let core_count = system.iter().map(|p| p.cores.len()).max().unwrap_or(0);
+        let cores = (0..core_count)
+            .map(|core| ChartSeries {
+                label: format!("cpu{}", core),
+                color: SERIES_COLORS[core % SERIES_COLORS.len()],
+                points: system
+                    .iter()
+                    .filter_map(|p| p.cores.get(core).map(|&usage| ((p.time - start) as f32, usage)))
+                    .collect(),
+            })
+            .collect();
+        charts.push(("CPU per core %", cores, 100.0));
+
+        let memory = vec![
+            system_series("mem", 0, &system, start, |p| p.mem),
+            system_series("swap", 1, &system, start, |p| p.swap),
+        ];
+        charts.push(("Memory & swap %", memory, 100.0));
+
+        let load = vec![
+            system_series("1m", 0, &system, start, |p| p.load[0]),
+            system_series("5m", 1, &system, start, |p| p.load[1]),
+            system_series("15m", 2, &system, start, |p| p.load[2]),
+        ];
+        charts.push(("Load average", load, 1.0));
+
+        // Two columns on wide terminals, one otherwise
+        let columns = if width >= 120 { 2 } else { 1 };
+        let rows = charts.len().div_ceil(columns);
+        let available = height.saturating_sub(1);
+        // Each chart adds a legend line and an axis line around its plot area
+        let chart_height = (available / rows).saturating_sub(2).max(2);
+        let chart_width = (width / columns).saturating_sub(9 + COLUMN_GAP).max(10);
+
+        for row in charts.chunks(columns) {
+            let rendered: Vec<Vec<String>> = row
+                .iter()
+                .map(|(title, series, min_y_max)| {
+                    render_multi_chart(title, series, x_range, chart_width, chart_height, *min_y_max)
+                })
+                .collect();
+            for line in 0..chart_height + 2 {
+                let parts: Vec<&str> = rendered.iter().map(|lines| lines[line].as_str()).collect();
+                out.push_str(&parts.join(&" ".repeat(COLUMN_GAP)));
+                out.push_str("\r\n");
+            }
+        }
+        out
+    }
+
+    // Recorded points before the first live one, then the live points, all inside the window
+    fn system_points(&self, start: f64) -> Vec<SystemPoint> {
+        let first_live = self.system.front().map_or(f64::MAX, |p| p.time);
+        self.recorded_system
+            .iter()
+            .filter(|p| p.time < first_live)
+            .chain(self.system.iter())
+            .filter(|p| p.time >= start)
+            .cloned()
+            .collect()
+    }
+
+    fn process_series(&self, start: f64, value: impl Fn(&ProcessPoint) -> f32) -> Vec<ChartSeries> {
+        self.pids
+            .iter()
+            .enumerate()
+            .map(|(index, pid)| {
+                let live = self.processes.get(pid);
+                let first_live = live.and_then(|points| points.front()).map_or(f64::MAX, |p| p.time);
+                let recorded = self.recorded_processes.get(pid).into_iter().flatten().filter(|p| p.time < first_live);
+                ChartSeries {
+                    label: pid.to_string(),
+                    color: SERIES_COLORS[index % SERIES_COLORS.len()],
+                    points: recorded
+                        .chain(live.into_iter().flatten())
+                        .filter(|p| p.time >= start)
+                        .map(|p| ((p.time - start) as f32, value(p)))
+                        .collect(),
+                }
+            })
+            .collect()
+    }
+}
+
+impl Default for GraphPanel {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn system_series(
+    label: &str,
+    color: usize,
+    points: &[SystemPoint],
+    start: f64,
+    value: impl Fn(&SystemPoint) -> f32,
+) -> ChartSeries {
+    ChartSeries {
+        label: label.to_string(),
+        color: SERIES_COLORS[color],
+        points: points.iter().map(|p| ((p.time - start) as f32, value(p))).collect(),
+    }
+}
+
+fn percent(used: u64, total: u64) -> f32 {
+    if total == 0 {
+        0.0
+    } else {
+        (used as f64 / total as f64 * 100.0) as f32
+    }
+}
+
+fn now() -> f64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs_f64()).unwrap_or(0.0)
+}
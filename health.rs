@@ -0,0 +1,156 @@
+use std::fmt;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::signal::ProcessHandle;
+
+// How often a running check looks again
+const POLL: Duration = Duration::from_millis(200);
+
+/// Something that answers once the restarted process is ready
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Probe {
+    /// `HOST:PORT`, or a bare port on localhost, accepts a connection
+    Tcp(String),
+    /// A Unix socket accepts a connection
+    Unix(PathBuf),
+    /// A shell command exits with status 0
+    Command(String),
+}
+
+impl Probe {
+    fn check(&self, timeout: Duration) -> Result<(), String> {
+        match self {
+            Probe::Tcp(address) => {
+                let address = if address.contains(':') { address.clone() } else { format!("127.0.0.1:{}", address) };
+                let addresses = address.to_socket_addrs().map_err(|e| format!("{}: {}", address, e))?;
+                let mut last = format!("{} does not resolve", address);
+                for resolved in addresses {
+                    match TcpStream::connect_timeout(&resolved, timeout.min(Duration::from_secs(1))) {
+                        Ok(_) => return Ok(()),
+                        Err(e) => last = format!("{}: {}", address, e),
+                    }
+                }
+                Err(last)
+            },
+            Probe::Unix(path) => UnixStream::connect(path).map(drop).map_err(|e| format!("{}: {}", path.display(), e)),
+            Probe::Command(command) => {
+                let mut child = Command::new("sh")
+                    .args(["-c", command])
+                    .stdin(Stdio::null())
+                    .stdout(Stdio::null())
+                    .stderr(Stdio::null())
+                    .spawn()
+                    .map_err(|e| format!("Failed to run '{}': {}", command, e))?;
+                let deadline = Instant::now() + timeout;
+                loop {
+                    match child.try_wait() {
+                        Ok(Some(status)) if status.success() => return Ok(()),
+                        Ok(Some(status)) => return Err(format!("'{}' exited with {}", command, status)),
+                        Ok(None) if Instant::now() < deadline => thread::sleep(POLL),
+                        _ => {
+                            let _ = child.kill();
+                            let _ = child.wait();
+                            return Err(format!("'{}' did not finish in time", command));
+                        },
+                    }
+                }
+            },
+        }
+    }
+}
+
+impl fmt::Display for Probe {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Probe::Tcp(address) => write!(f, "TCP {}", address),
+            Probe::Unix(path) => write!(f, "socket {}", path.display()),
+            Probe::Command(command) => write!(f, "'{}'", command),
+        }
+    }
+}
+
+/// What a restarted process must do to count as healthy, and what happens when
+/// it does not
+#[derive(Clone, Debug)]
+pub struct HealthCheck {
+    /// How long the new process must keep running
+    pub alive_for: Duration,
+    pub probe: Option<Probe>,
+    /// How long the probe may take to succeed, after `alive_for`
+    pub timeout: Duration,
+    /// How many more times to start the process when it is not healthy
+    pub retries: u32,
+    /// When every attempt failed and the executable was replaced on disk (e.g.
+    /// by an upgrade), start the one that was running before
+    pub rollback: bool,
+}
+
+impl HealthCheck {
+    pub fn new() -> Self {
+        HealthCheck {
+            alive_for: Duration::from_secs(2),
+            probe: None,
+            timeout: Duration::from_secs(10),
+            retries: 0,
+            rollback: false,
+        }
+    }
+
+    /// Wait until the process has stayed up for `alive_for` and the probe, if
+    /// any, answers. The error says what went wrong.
+    pub fn verify(&self, pid: u32) -> Result<(), String> {
+        let handle = ProcessHandle::open(pid).map_err(|_| format!("PID {} exited at once", pid))?;
+        let started = Instant::now();
+        let exited = || format!("PID {} exited after {:.1}s", pid, started.elapsed().as_secs_f64());
+        while started.elapsed() < self.alive_for {
+            if !handle.is_alive() {
+                return Err(exited());
+            }
+            thread::sleep(POLL);
+        }
+        if !handle.is_alive() {
+            return Err(exited());
+        }
+        match &self.probe {
+            Some(probe) => self.wait_for(probe, Some(&handle)).map_err(|e| {
+                if handle.is_alive() {
+                    format!("PID {} is running but {} is not ready ({})", pid, probe, e)
+                } else {
+                    exited()
+                }
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Wait for the probe alone, e.g. after a service manager restarted the process
+    pub fn verify_probe(&self) -> Result<(), String> {
+        match &self.probe {
+            Some(probe) => self.wait_for(probe, None).map_err(|e| format!("{} is not ready ({})", probe, e)),
+            None => Ok(()),
+        }
+    }
+
+    // Check the probe until it succeeds, the timeout passes or the process exits
+    fn wait_for(&self, probe: &Probe, handle: Option<&ProcessHandle>) -> Result<(), String> {
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let result = probe.check(remaining.max(POLL));
+            if result.is_ok() || Instant::now() >= deadline || handle.is_some_and(|h| !h.is_alive()) {
+                return result;
+            }
+            thread::sleep(POLL);
+        }
+    }
+}
+
+impl Default for HealthCheck {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -2,39 +2,54 @@ pub fn get_help_text() -> String {
     let mut help = String::new();
     use std::fmt::Write;
 
-    writeln!(help, "{}{}Pulse - Linux Process Monitor Help{}\r\n", "\x1B[1m", "\x1B[38;5;82m", "\x1B[0m").unwrap();
+    writeln!(help, "\x1B[1m\x1B[38;5;82mPulse - Linux Process Monitor Help\x1B[0m\r\n").unwrap();
 
-    writeln!(help, "{}General Commands:{}\r", "\x1B[38;5;39m", "\x1B[0m").unwrap();
+    writeln!(help, "\x1B[38;5;39mGeneral Commands:\x1B[0m\r").unwrap();
     writeln!(help, "  Q       Quit the application\r").unwrap();
+    writeln!(help, "  ↑ / ↓   Move the row cursor (PgUp/PgDn/Home/End to jump)\r").unwrap();
+    writeln!(help, "  Space   Mark/unmark the cursor row; K/Z/R/A/N/E/J act on the marks\r").unwrap();
+    writeln!(help, "  Esc     Clear the marks and the cursor\r").unwrap();
     writeln!(help, "  C       Sort by CPU usage\r").unwrap();
     writeln!(help, "  M       Sort by Memory usage\r").unwrap();
     writeln!(help, "  P       Sort by PID\r").unwrap();
-    writeln!(help, "  S       Search by PID\r").unwrap();
-    writeln!(help, "  K       Kill a process\r").unwrap();
+    writeln!(help, "  S       Filter processes, e.g. user:root state:R cpu>20 mem>5 cmd:~/regex/\r").unwrap();
+    writeln!(help, "  K       Stop a process by its stop policy (SIGTERM, SIGKILL after 5s by default)\r").unwrap();
+    writeln!(help, "  X       Send any signal to a process, its subtree or its group\r").unwrap();
     writeln!(help, "  Z       Pause/Resume a process\r").unwrap();
     writeln!(help, "  R       Restart a process\r").unwrap();
+    writeln!(help, "  A       Keep a process alive (restarted with backoff when it exits), or release it\r").unwrap();
     writeln!(help, "  N       Set nice value (priority)\r").unwrap();
     writeln!(help, "  G       Pause/Resume process group\r").unwrap();
     writeln!(help, "  T       Show process tree view\r").unwrap();
     writeln!(help, "  J       Export as JSON\r").unwrap();
     writeln!(help, "  E       Export as CSV\r").unwrap();
+    writeln!(help, "  O       Choose the columns of the process list\r").unwrap();
+    writeln!(help, "  D       Attach to / detach from the Pulse daemon\r").unwrap();
     writeln!(help, "  H       Show this help screen\r\n").unwrap();
 
-    writeln!(help, "{}Tree View Navigation:{}\r", "\x1B[38;5;39m", "\x1B[0m").unwrap();
+    writeln!(help, "\x1B[38;5;39mTree View Navigation:\x1B[0m\r").unwrap();
     writeln!(help, "  ↑ / ↓   Navigate process tree\r").unwrap();
-    writeln!(help, "  Enter   Select a process for action\r").unwrap();
+    writeln!(help, "  ← / →   Collapse / expand a node (Enter toggles, * expands all)\r").unwrap();
+    writeln!(help, "  /  n    Search by name or PID, jump to the next match\r").unwrap();
+    writeln!(help, "  Z G N   Pause/resume, group pause/resume, set nice on the node\r").unwrap();
+    writeln!(help, "  K R X   Kill, restart or send a signal to the node\r").unwrap();
     writeln!(help, "  Esc     Exit tree view\r\n").unwrap();
 
+    writeln!(help, "\x1B[38;5;39mReplay (pulse replay):\x1B[0m\r").unwrap();
+    writeln!(help, "  ← / →   Previous / next snapshot (also in the graphs)\r").unwrap();
+    writeln!(help, "  [ / ]   Ten snapshots back / forward (also in the tree)\r").unwrap();
+    writeln!(help, "  @       Go to a time, e.g. 03:12 or -10m\r").unwrap();
+    writeln!(help, "          K, X, Z, R, A, N, G and D are turned off\r\n").unwrap();
+
     writeln!(
         help,
-        "{}Pulse{} is a real-time Linux process monitor that lets you sort, search, manage,\r\n\
-         and export process data. Use this interface to efficiently interact with running tasks.\r\n",
-        "\x1B[38;5;147m", "\x1B[0m"
+        "\x1B[38;5;147mPulse\x1B[0m is a real-time Linux process monitor that lets you sort, search, manage,\r\n\
+         and export process data. Use this interface to efficiently interact with running tasks.\r\n"
     )
     .unwrap();
 
 
-    writeln!(help, "Press {}ESC{} to return.\r", "\x1B[1m", "\x1B[0m").unwrap();
+    writeln!(help, "Press \x1B[1mESC\x1B[0m to return.\r").unwrap();
 
     help
 }
@@ -0,0 +1,1113 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use chrono::{Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone};
+use nix::fcntl::{flock, FlockArg};
+use crate::snapshot::{Collector, ProcessSnapshot, ProcessState, SystemSummary};
+
+const MAGIC: &[u8; 8] = b"PULSEHST";
+const DATA_MAGIC: &[u8; 8] = b"PULSEDAT";
+const VERSION: u32 = 3;
+const HEADER_SIZE: u64 = 64;
+// ts + cpu + 4 memory/swap counters + 3 load averages + process count + data offset + entry count
+const SLOT_SIZE: usize = 8 + 4 + 4 * 8 + 3 * 4 + 4 + 8 + 4;
+// ts + entry count, in front of a sample's entries in the data file
+const RECORD_HEADER_SIZE: usize = 8 + 4;
+// pid + ppid + uid + cpu + mem + state + nice + threads
+const ENTRY_SIZE: usize = 4 + 4 + 4 + 4 + 4 + 1 + 1 + 2;
+// The name log is compacted once it has doubled, but never below this
+const NAMES_COMPACT_SIZE: u64 = 1 << 20;
+
+/// Where and how much history is kept
+#[derive(Clone, Debug)]
+pub struct HistoryConfig {
+    pub dir: PathBuf,
+    /// Seconds between raw samples
+    pub interval: u64,
+    /// Number of raw samples kept (a week at 1s by default)
+    pub raw_capacity: u64,
+    /// Bytes for the processes of the raw samples; once full, the oldest
+    /// samples make room, so this can end the history before `raw_capacity`
+    pub raw_bytes: u64,
+    /// Number of one-minute averages kept once raw samples have been overwritten
+    pub minute_capacity: u64,
+    pub minute_bytes: u64,
+    /// Keep only this many processes per sample, the busiest by CPU and then by
+    /// memory; every process when `None`
+    pub max_processes: Option<usize>,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        HistoryConfig {
+            dir: default_history_dir(),
+            interval: 1,
+            raw_capacity: 7 * 24 * 3600,
+            raw_bytes: 512 << 20,
+            minute_capacity: 90 * 24 * 60,
+            minute_bytes: 512 << 20,
+            max_processes: None,
+        }
+    }
+}
+
+/// `$XDG_DATA_HOME/pulse/history`, falling back to `~/.local/share/pulse/history`
+pub fn default_history_dir() -> PathBuf {
+    let base = env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".local/share")))
+        .unwrap_or_else(|| PathBuf::from("/tmp"));
+    base.join("pulse").join("history")
+}
+
+/// Machine-wide figures at one point in time
+#[derive(Clone, Debug, Default)]
+pub struct SystemSample {
+    /// Seconds since the epoch
+    pub time: u64,
+    /// Usage across all CPUs, 0-100
+    pub cpu: f32,
+    pub mem_used: u64,
+    pub mem_total: u64,
+    pub swap_used: u64,
+    pub swap_total: u64,
+    pub load: [f32; 3],
+    /// Number of processes running at the time, including those not stored
+    pub process_count: u32,
+}
+
+#[derive(Clone, Debug)]
+pub struct ProcessSample {
+    pub pid: u32,
+    pub name: String,
+    /// Raw sysinfo CPU (100% per fully used core)
+    pub cpu: f32,
+    /// Memory as a percentage of total RAM
+    pub mem: f32,
+    pub ppid: Option<u32>,
+    pub uid: Option<u32>,
+    pub state: ProcessState,
+    pub nice: i32,
+    pub threads: u32,
+    /// Arguments joined by spaces
+    pub cmdline: String,
+}
+
+impl ProcessSample {
+    fn from_snapshot(p: &ProcessSnapshot) -> Self {
+        ProcessSample {
+            pid: p.pid,
+            name: p.name.clone(),
+            cpu: p.cpu,
+            mem: p.mem as f32,
+            ppid: p.ppid,
+            uid: p.uid,
+            state: p.state,
+            nice: p.nice,
+            threads: p.threads,
+            cmdline: p.cmdline.join(" "),
+        }
+    }
+}
+
+/// One stored sample: the system plus its processes, busiest first
+#[derive(Clone, Debug)]
+pub struct HistoryRecord {
+    pub system: SystemSample,
+    pub processes: Vec<ProcessSample>,
+    /// Seconds covered by the record: the raw interval, or 60 for a one-minute average
+    pub resolution: u64,
+}
+
+impl HistoryRecord {
+    /// Build a record from a live collection, keeping the `limit` busiest processes
+    /// (`usize::MAX` for all of them)
+    pub fn from_snapshot(time: u64, processes: &[ProcessSnapshot], summary: &SystemSummary, limit: usize) -> Self {
+        let mut busiest: Vec<&ProcessSnapshot> = processes.iter().filter(|p| !p.is_thread).collect();
+        busiest.sort_by(|a, b| b.cpu.total_cmp(&a.cpu).then_with(|| b.memory.cmp(&a.memory)));
+
+        HistoryRecord {
+            system: SystemSample {
+                time,
+                cpu: summary.cpu_usage,
+                mem_used: summary.used_memory,
+                mem_total: summary.total_memory,
+                swap_used: summary.used_swap,
+                swap_total: summary.total_swap,
+                load: summary.load_average.map(|l| l as f32),
+                process_count: busiest.len() as u32,
+            },
+            processes: busiest
+                .into_iter()
+                .take(limit)
+                .map(ProcessSample::from_snapshot)
+                .collect(),
+            resolution: 1,
+        }
+    }
+
+    /// Stored processes as snapshots, so the exporters and the replay views can
+    /// use them. Fields that are not recorded are left empty.
+    pub fn to_snapshots(&self) -> Vec<ProcessSnapshot> {
+        let mut users: HashMap<u32, String> = HashMap::new();
+        self.processes
+            .iter()
+            .map(|p| ProcessSnapshot {
+                pid: p.pid,
+                ppid: p.ppid,
+                name: p.name.clone(),
+                // Only the joined arguments are recorded
+                cmdline: if p.cmdline.is_empty() { Vec::new() } else { vec![p.cmdline.clone()] },
+                uid: p.uid,
+                user: match p.uid {
+                    Some(uid) => users
+                        .entry(uid)
+                        .or_insert_with(|| {
+                            users::get_user_by_uid(uid)
+                                .map(|u| u.name().to_string_lossy().into_owned())
+                                .unwrap_or_else(|| uid.to_string())
+                        })
+                        .clone(),
+                    None => "Unknown".to_string(),
+                },
+                cpu: p.cpu,
+                memory: (p.mem as f64 / 100.0 * self.system.mem_total as f64) as u64,
+                virtual_memory: 0,
+                mem: p.mem as f64,
+                nice: p.nice,
+                pgrp: 0,
+                tpgid: -1,
+                state: p.state,
+                start_time: 0,
+                threads: p.threads,
+                read_bytes: 0,
+                written_bytes: 0,
+                cgroup: String::new(),
+                is_thread: false,
+            })
+            .collect()
+    }
+
+    /// The figures the CSV exporter puts in its summary block
+    pub fn summary(&self, physical_cores: usize) -> SystemSummary {
+        SystemSummary {
+            total_memory: self.system.mem_total,
+            used_memory: self.system.mem_used,
+            total_swap: self.system.swap_total,
+            used_swap: self.system.swap_used,
+            physical_cores,
+            cpu_usage: self.system.cpu,
+            load_average: self.system.load.map(|l| l as f64),
+        }
+    }
+}
+
+/// A file of fixed-size slots indexing samples by time: the sample for time `t`
+/// lives in slot `(t / interval) % capacity`. How many processes a sample has
+/// varies, so they are kept in a `DataFile` the slot points into.
+struct RingFile {
+    file: File,
+    interval: u64,
+    capacity: u64,
+    data: DataFile,
+}
+
+impl RingFile {
+    /// Open a ring for writing, starting a new one if the file is missing or has a different layout
+    fn create(path: &Path, interval: u64, capacity: u64, data_bytes: u64) -> Result<Self, String> {
+        let mut file = open_for_writing(path)?;
+        let data = DataFile::create(&path.with_extension("data"), data_bytes)?;
+
+        let matches = ring_layout(&mut file) == Some((interval, capacity));
+        if !matches || data.fresh {
+            let mut header = [0u8; HEADER_SIZE as usize];
+            header[0..8].copy_from_slice(MAGIC);
+            header[8..12].copy_from_slice(&VERSION.to_le_bytes());
+            header[12..16].copy_from_slice(&(interval as u32).to_le_bytes());
+            header[16..24].copy_from_slice(&capacity.to_le_bytes());
+            file.set_len(0).map_err(|e| e.to_string())?;
+            file.seek(SeekFrom::Start(0)).map_err(|e| e.to_string())?;
+            file.write_all(&header).map_err(|e| e.to_string())?;
+            // Sparse file: unwritten slots read back as zeros, i.e. empty
+            file.set_len(HEADER_SIZE + capacity * SLOT_SIZE as u64).map_err(|e| e.to_string())?;
+        }
+
+        Ok(RingFile { file, interval, capacity, data })
+    }
+
+    /// Open an existing ring read-only, taking the layout from its header
+    fn open(path: &Path) -> Result<Self, String> {
+        let mut file = File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+        let (interval, capacity) =
+            ring_layout(&mut file).ok_or_else(|| format!("{} is not a Pulse history file", path.display()))?;
+        let data = DataFile::open(&path.with_extension("data"))?;
+        Ok(RingFile { file, interval, capacity, data })
+    }
+
+    fn offset(&self, time: u64) -> u64 {
+        HEADER_SIZE + ((time / self.interval) % self.capacity) * SLOT_SIZE as u64
+    }
+
+    /// Oldest time still held, given the newest
+    fn retention(&self) -> u64 {
+        self.interval * self.capacity
+    }
+
+    fn write(&mut self, record: &HistoryRecord, names: &mut NameTable) -> Result<(), String> {
+        let system = &record.system;
+        // A sample larger than the whole data file keeps its busiest processes
+        let fits = (self.data.room() as usize).saturating_sub(RECORD_HEADER_SIZE) / ENTRY_SIZE;
+        let entries = &record.processes[..record.processes.len().min(fits)];
+        let mut data = Vec::with_capacity(entries.len() * ENTRY_SIZE);
+        for entry in entries {
+            names.remember(entry.pid, system.time, &entry.name, &entry.cmdline)?;
+            data.extend_from_slice(&entry.pid.to_le_bytes());
+            data.extend_from_slice(&entry.ppid.unwrap_or(0).to_le_bytes());
+            data.extend_from_slice(&entry.uid.unwrap_or(u32::MAX).to_le_bytes());
+            data.extend_from_slice(&entry.cpu.to_le_bytes());
+            data.extend_from_slice(&entry.mem.to_le_bytes());
+            data.push(entry.state.code() as u8);
+            data.push(entry.nice.clamp(-128, 127) as i8 as u8);
+            data.extend_from_slice(&(entry.threads.min(u16::MAX as u32) as u16).to_le_bytes());
+        }
+        let at = self.data.append(system.time, entries.len() as u32, &data)?;
+
+        let mut buf = Vec::with_capacity(SLOT_SIZE);
+        buf.extend_from_slice(&system.time.to_le_bytes());
+        buf.extend_from_slice(&system.cpu.to_le_bytes());
+        for value in [system.mem_used, system.mem_total, system.swap_used, system.swap_total] {
+            buf.extend_from_slice(&value.to_le_bytes());
+        }
+        for load in system.load {
+            buf.extend_from_slice(&load.to_le_bytes());
+        }
+        buf.extend_from_slice(&system.process_count.to_le_bytes());
+        buf.extend_from_slice(&at.to_le_bytes());
+        buf.extend_from_slice(&(entries.len() as u32).to_le_bytes());
+
+        let offset = self.offset(system.time);
+        self.file.seek(SeekFrom::Start(offset)).map_err(|e| e.to_string())?;
+        self.file.write_all(&buf).map_err(|e| e.to_string())
+    }
+
+    /// Time of the newest sample held, read from every slot
+    fn newest(&mut self) -> Option<u64> {
+        let mut newest: Option<u64> = None;
+        let mut time = [0u8; 8];
+        for slot in 0..self.capacity {
+            self.file.seek(SeekFrom::Start(HEADER_SIZE + slot * SLOT_SIZE as u64)).ok()?;
+            self.file.read_exact(&mut time).ok()?;
+            let stored = u64::from_le_bytes(time);
+            if stored > 0 && newest.is_none_or(|n| stored > n) {
+                newest = Some(stored);
+            }
+        }
+        newest
+    }
+
+    /// The sample for the interval containing `time`, if it was recorded and
+    /// neither it nor its processes have been overwritten yet
+    fn read(&mut self, time: u64, names: &NameTable) -> Option<HistoryRecord> {
+        let mut buf = [0u8; SLOT_SIZE];
+        self.file.seek(SeekFrom::Start(self.offset(time))).ok()?;
+        self.file.read_exact(&mut buf).ok()?;
+
+        let stored = read_u64(&buf, 0);
+        if stored == 0 || stored / self.interval != time / self.interval {
+            return None;
+        }
+
+        let count = read_u32(&buf, SLOT_SIZE - 4) as usize;
+        let data = self.data.read(read_u64(&buf, SLOT_SIZE - 12), stored, count)?;
+        let processes = data
+            .chunks_exact(ENTRY_SIZE)
+            .map(|entry| {
+                let pid = read_u32(entry, 0);
+                let (name, cmdline) = names.name_at(pid, stored).unwrap_or(("?", ""));
+                ProcessSample {
+                    pid,
+                    name: name.to_string(),
+                    cpu: read_f32(entry, 12),
+                    mem: read_f32(entry, 16),
+                    ppid: Some(read_u32(entry, 4)).filter(|&ppid| ppid != 0),
+                    uid: Some(read_u32(entry, 8)).filter(|&uid| uid != u32::MAX),
+                    state: ProcessState::from_code(entry[20] as char),
+                    nice: entry[21] as i8 as i32,
+                    threads: u16::from_le_bytes([entry[22], entry[23]]) as u32,
+                    cmdline: cmdline.to_string(),
+                }
+            })
+            .collect();
+
+        Some(HistoryRecord {
+            system: SystemSample {
+                time: stored,
+                cpu: read_f32(&buf, 8),
+                mem_used: read_u64(&buf, 12),
+                mem_total: read_u64(&buf, 20),
+                swap_used: read_u64(&buf, 28),
+                swap_total: read_u64(&buf, 36),
+                load: [read_f32(&buf, 44), read_f32(&buf, 48), read_f32(&buf, 52)],
+                process_count: read_u32(&buf, 56),
+            },
+            processes,
+            resolution: self.interval,
+        })
+    }
+}
+
+/// The processes of each sample, written one after another and wrapping round
+/// to overwrite the oldest once the file is full. Each sample starts with its
+/// time, so a slot pointing at data since overwritten is recognised.
+struct DataFile {
+    file: File,
+    size: u64,
+    // Where the next sample goes
+    position: u64,
+    // Started over rather than continued, so the ring's slots point nowhere
+    fresh: bool,
+}
+
+impl DataFile {
+    fn create(path: &Path, size: u64) -> Result<Self, String> {
+        let mut file = open_for_writing(path)?;
+        let size = size.max(HEADER_SIZE + (RECORD_HEADER_SIZE + ENTRY_SIZE) as u64);
+        if let Some((stored, position)) = data_layout(&mut file).filter(|(stored, _)| *stored == size) {
+            return Ok(DataFile { file, size: stored, position, fresh: false });
+        }
+        let mut header = [0u8; HEADER_SIZE as usize];
+        header[0..8].copy_from_slice(DATA_MAGIC);
+        header[8..12].copy_from_slice(&VERSION.to_le_bytes());
+        header[16..24].copy_from_slice(&size.to_le_bytes());
+        file.set_len(0).map_err(|e| e.to_string())?;
+        file.seek(SeekFrom::Start(0)).map_err(|e| e.to_string())?;
+        file.write_all(&header).map_err(|e| e.to_string())?;
+        file.set_len(size).map_err(|e| e.to_string())?;
+        let mut data = DataFile { file, size, position: HEADER_SIZE, fresh: true };
+        data.save_position()?;
+        Ok(data)
+    }
+
+    fn open(path: &Path) -> Result<Self, String> {
+        let mut file = File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+        let (size, position) =
+            data_layout(&mut file).ok_or_else(|| format!("{} is not a Pulse history file", path.display()))?;
+        Ok(DataFile { file, size, position, fresh: false })
+    }
+
+    /// Bytes one sample may take at most
+    fn room(&self) -> u64 {
+        self.size - HEADER_SIZE
+    }
+
+    /// Write a sample's entries, returning where they went
+    fn append(&mut self, time: u64, count: u32, entries: &[u8]) -> Result<u64, String> {
+        let length = (RECORD_HEADER_SIZE + entries.len()) as u64;
+        if self.position + length > self.size {
+            self.position = HEADER_SIZE;
+        }
+        let mut buf = Vec::with_capacity(length as usize);
+        buf.extend_from_slice(&time.to_le_bytes());
+        buf.extend_from_slice(&count.to_le_bytes());
+        buf.extend_from_slice(entries);
+        let at = self.position;
+        self.file.seek(SeekFrom::Start(at)).map_err(|e| e.to_string())?;
+        self.file.write_all(&buf).map_err(|e| e.to_string())?;
+        self.position += length;
+        self.save_position()?;
+        Ok(at)
+    }
+
+    fn save_position(&mut self) -> Result<(), String> {
+        self.file.seek(SeekFrom::Start(24)).map_err(|e| e.to_string())?;
+        self.file.write_all(&self.position.to_le_bytes()).map_err(|e| e.to_string())
+    }
+
+    /// The entries written at `at` for the sample at `time`, unless a later
+    /// sample has overwritten them
+    fn read(&mut self, at: u64, time: u64, count: usize) -> Option<Vec<u8>> {
+        let length = RECORD_HEADER_SIZE + count * ENTRY_SIZE;
+        if at < HEADER_SIZE || at + length as u64 > self.size {
+            return None;
+        }
+        let mut buf = vec![0u8; length];
+        self.file.seek(SeekFrom::Start(at)).ok()?;
+        self.file.read_exact(&mut buf).ok()?;
+        if read_u64(&buf, 0) != time || read_u32(&buf, 8) as usize != count {
+            return None;
+        }
+        buf.drain(..RECORD_HEADER_SIZE);
+        Some(buf)
+    }
+}
+
+fn open_for_writing(path: &Path) -> Result<File, String> {
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)
+        .map_err(|e| format!("Failed to open {}: {}", path.display(), e))
+}
+
+/// PID to name and command line mapping, kept as an append-only
+/// `time<TAB>pid<TAB>name<TAB>cmdline` log so that PIDs reused by another
+/// program resolve to the right one. The recorder rewrites it without the
+/// names no sample refers to any more once it has grown.
+struct NameTable {
+    path: PathBuf,
+    names: HashMap<u32, Vec<(u64, String, String)>>,
+    // Bytes of the log already read, or written by the recorder
+    loaded: u64,
+    // Inode of the log read, to notice it was compacted
+    inode: Option<u64>,
+    // When the recorder last wrote each PID, and when it opened the log (for
+    // PIDs it has not written yet)
+    seen: HashMap<u32, u64>,
+    opened: u64,
+    compact_at: u64,
+}
+
+impl NameTable {
+    fn open(path: PathBuf) -> Self {
+        let mut table = NameTable {
+            path,
+            names: HashMap::new(),
+            loaded: 0,
+            inode: None,
+            seen: HashMap::new(),
+            opened: now(),
+            compact_at: 0,
+        };
+        table.reload();
+        table.compact_at = (2 * table.loaded).max(NAMES_COMPACT_SIZE);
+        table
+    }
+
+    /// Pick up names appended by a recorder since the last read
+    fn reload(&mut self) {
+        let mut file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(_) => return,
+        };
+        let inode = file.metadata().map(|m| m.ino()).ok();
+        if inode != self.inode {
+            // Compacted since: read it over
+            self.names.clear();
+            self.loaded = 0;
+            self.inode = inode;
+        }
+        if file.seek(SeekFrom::Start(self.loaded)).is_err() {
+            return;
+        }
+        let mut reader = BufReader::new(file);
+        let mut line = String::new();
+        // Stop at a partial last line; it is read again once complete
+        while reader.read_line(&mut line).is_ok_and(|n| n > 0) && line.ends_with('\n') {
+            self.loaded += line.len() as u64;
+            let mut parts = line.trim_end_matches('\n').splitn(4, '\t');
+            if let (Some(time), Some(pid), Some(name)) = (parts.next(), parts.next(), parts.next()) {
+                if let (Ok(time), Ok(pid)) = (time.parse(), pid.parse()) {
+                    let cmdline = parts.next().unwrap_or_default().to_string();
+                    // Minute averages are written after the raw samples they cover, so times can go back
+                    let entries = self.names.entry(pid).or_default();
+                    let at = entries.partition_point(|(seen, _, _)| *seen <= time);
+                    entries.insert(at, (time, name.to_string(), cmdline));
+                }
+            }
+            line.clear();
+        }
+    }
+
+    fn remember(&mut self, pid: u32, time: u64, name: &str, cmdline: &str) -> Result<(), String> {
+        let name = name.replace(['\t', '\n'], " ");
+        let cmdline = cmdline.replace(['\t', '\n'], " ");
+        let seen = self.seen.entry(pid).or_default();
+        *seen = time.max(*seen);
+        // Nothing to write when the PID already goes by this name at `time`
+        let entries = self.names.entry(pid).or_default();
+        let at = entries.partition_point(|(seen, _, _)| *seen <= time);
+        let current = entries.get(at.saturating_sub(1));
+        if current.is_some_and(|(_, last, last_cmdline)| *last == name && *last_cmdline == cmdline) {
+            return Ok(());
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .map_err(|e| format!("Failed to open {}: {}", self.path.display(), e))?;
+        let line = format!("{}\t{}\t{}\t{}\n", time, pid, name, cmdline);
+        file.write_all(line.as_bytes()).map_err(|e| e.to_string())?;
+        self.loaded += line.len() as u64;
+        if self.inode.is_none() {
+            self.inode = file.metadata().map(|m| m.ino()).ok();
+        }
+        entries.insert(at, (time, name, cmdline));
+        Ok(())
+    }
+
+    /// Rewrite the log without the names no sample from `keep_from` on can
+    /// refer to: a name is needed until the PID got another one or was last written
+    fn compact(&mut self, keep_from: u64) -> Result<(), String> {
+        for (pid, entries) in self.names.iter_mut() {
+            let last_seen = self.seen.get(pid).copied().unwrap_or(self.opened);
+            let needed: Vec<bool> = (0..entries.len())
+                .map(|i| entries.get(i + 1).map_or(last_seen, |(time, _, _)| *time) >= keep_from)
+                .collect();
+            let mut needed = needed.into_iter();
+            entries.retain(|_| needed.next().unwrap_or(true));
+        }
+        self.names.retain(|_, entries| !entries.is_empty());
+        self.seen.retain(|pid, _| self.names.contains_key(pid));
+
+        let mut lines: Vec<(u64, u32, &str, &str)> = self
+            .names
+            .iter()
+            .flat_map(|(pid, entries)| entries.iter().map(|(time, name, cmdline)| (*time, *pid, name.as_str(), cmdline.as_str())))
+            .collect();
+        lines.sort_by_key(|&(time, pid, _, _)| (time, pid));
+        let mut text = String::new();
+        for (time, pid, name, cmdline) in lines {
+            text.push_str(&format!("{}\t{}\t{}\t{}\n", time, pid, name, cmdline));
+        }
+
+        // Readers holding the old log see the new inode and read this one over
+        let temp = self.path.with_extension("log.tmp");
+        fs::write(&temp, &text).map_err(|e| format!("Failed to write {}: {}", temp.display(), e))?;
+        fs::rename(&temp, &self.path).map_err(|e| format!("Failed to replace {}: {}", self.path.display(), e))?;
+        self.loaded = text.len() as u64;
+        self.inode = fs::metadata(&self.path).map(|m| m.ino()).ok();
+        self.compact_at = (2 * self.loaded).max(NAMES_COMPACT_SIZE);
+        Ok(())
+    }
+
+    /// Name and command line of the process `pid` was at `time`
+    fn name_at(&self, pid: u32, time: u64) -> Option<(&str, &str)> {
+        let entries = self.names.get(&pid)?;
+        entries
+            .iter()
+            .rev()
+            .find(|(seen, _, _)| *seen <= time)
+            .or_else(|| entries.first())
+            .map(|(_, name, cmdline)| (name.as_str(), cmdline.as_str()))
+    }
+}
+
+// The header of a ring or data file, if it is one of this version
+fn read_header(file: &mut File, magic: &[u8; 8]) -> Option<[u8; HEADER_SIZE as usize]> {
+    let mut header = [0u8; HEADER_SIZE as usize];
+    file.seek(SeekFrom::Start(0)).ok()?;
+    file.read_exact(&mut header).ok()?;
+    if &header[0..8] != magic || read_u32(&header, 8) != VERSION {
+        return None;
+    }
+    Some(header)
+}
+
+// (interval, capacity) from a ring header
+fn ring_layout(file: &mut File) -> Option<(u64, u64)> {
+    let header = read_header(file, MAGIC)?;
+    let interval = read_u32(&header, 12) as u64;
+    let capacity = read_u64(&header, 16);
+    if interval == 0 || capacity == 0 {
+        return None;
+    }
+    Some((interval, capacity))
+}
+
+// (size, position of the next sample) from a data file header
+fn data_layout(file: &mut File) -> Option<(u64, u64)> {
+    let header = read_header(file, DATA_MAGIC)?;
+    let size = read_u64(&header, 16);
+    let position = read_u64(&header, 24);
+    if !(HEADER_SIZE..=size).contains(&position) {
+        return None;
+    }
+    Some((size, position))
+}
+
+// Running sums for the current minute
+#[derive(Default)]
+struct MinuteAccumulator {
+    minute: u64,
+    samples: u32,
+    system: SystemSample,
+    load: [f64; 3],
+    cpu: f64,
+    // The last sample of each process, with its CPU and memory sums
+    processes: HashMap<u32, (ProcessSample, f64, f64)>,
+}
+
+/// Metrics history stored under `HistoryConfig::dir`
+pub struct History {
+    raw: RingFile,
+    minute: RingFile,
+    names: NameTable,
+    pending: MinuteAccumulator,
+    // Processes kept per one-minute average
+    max_processes: usize,
+    // Held by the writer for as long as it records
+    _lock: Option<File>,
+}
+
+impl History {
+    /// Open recorded history read-only. The layout is taken from the files, so
+    /// readers work whatever settings the recorder was started with.
+    pub fn open(dir: &Path) -> Result<Self, String> {
+        if !dir.join("raw.ring").exists() {
+            return Err(format!("No history recorded in {} (start 'pulse record' first)", dir.display()));
+        }
+        Ok(History {
+            raw: RingFile::open(&dir.join("raw.ring"))?,
+            minute: RingFile::open(&dir.join("minute.ring"))?,
+            names: NameTable::open(dir.join("names.log")),
+            pending: MinuteAccumulator::default(),
+            max_processes: usize::MAX,
+            _lock: None,
+        })
+    }
+
+    /// Open the history for recording. Only one writer is allowed per directory;
+    /// changing the interval or capacities starts the affected ring over.
+    pub fn create(config: &HistoryConfig) -> Result<Self, String> {
+        let dir = &config.dir;
+        fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+
+        let lock_path = dir.join("recorder.lock");
+        let lock = File::create(&lock_path).map_err(|e| format!("Failed to open {}: {}", lock_path.display(), e))?;
+        flock(lock.as_raw_fd(), FlockArg::LockExclusiveNonblock)
+            .map_err(|_| format!("Another recorder is already writing to {}", dir.display()))?;
+
+        Ok(History {
+            raw: RingFile::create(&dir.join("raw.ring"), config.interval, config.raw_capacity, config.raw_bytes)?,
+            minute: RingFile::create(&dir.join("minute.ring"), 60, config.minute_capacity, config.minute_bytes)?,
+            names: NameTable::open(dir.join("names.log")),
+            pending: MinuteAccumulator::default(),
+            max_processes: config.max_processes.unwrap_or(usize::MAX),
+            _lock: Some(lock),
+        })
+    }
+
+    /// Seconds between raw samples
+    pub fn interval(&self) -> u64 {
+        self.raw.interval
+    }
+
+    /// Store a raw sample and fold it into the running one-minute average
+    pub fn record(&mut self, record: &HistoryRecord) -> Result<(), String> {
+        self.raw.write(record, &mut self.names)?;
+        if self.names.loaded >= self.names.compact_at {
+            let retention = self.raw.retention().max(self.minute.retention());
+            self.names.compact(record.system.time.saturating_sub(retention))?;
+        }
+
+        let minute = record.system.time / 60;
+        if self.pending.samples > 0 && self.pending.minute != minute {
+            self.flush_minute()?;
+        }
+        let pending = &mut self.pending;
+        if pending.samples == 0 {
+            pending.minute = minute;
+        }
+        pending.samples += 1;
+        pending.system = record.system.clone();
+        pending.cpu += record.system.cpu as f64;
+        for (sum, load) in pending.load.iter_mut().zip(record.system.load) {
+            *sum += load as f64;
+        }
+        for p in &record.processes {
+            let entry = pending.processes.entry(p.pid).or_insert_with(|| (p.clone(), 0.0, 0.0));
+            entry.0 = p.clone();
+            entry.1 += p.cpu as f64;
+            entry.2 += p.mem as f64;
+        }
+        Ok(())
+    }
+
+    // Write the average of the finished minute to the downsampled ring
+    fn flush_minute(&mut self) -> Result<(), String> {
+        let pending = std::mem::take(&mut self.pending);
+        let n = pending.samples.max(1) as f64;
+
+        // A process missing from some samples counts as idle for them
+        let mut processes: Vec<ProcessSample> = pending
+            .processes
+            .into_iter()
+            .map(|(_, (last, cpu, mem))| ProcessSample { cpu: (cpu / n) as f32, mem: (mem / n) as f32, ..last })
+            .collect();
+        processes.sort_by(|a, b| b.cpu.total_cmp(&a.cpu).then_with(|| b.mem.total_cmp(&a.mem)));
+        processes.truncate(self.max_processes);
+
+        let mut system = pending.system;
+        system.time = pending.minute * 60;
+        system.cpu = (pending.cpu / n) as f32;
+        system.load = pending.load.map(|l| (l / n) as f32);
+
+        let record = HistoryRecord { system, processes, resolution: 60 };
+        self.minute.write(&record, &mut self.names)
+    }
+
+    /// The sample closest to `time` (at or before it): raw while available, else the minute average
+    pub fn at(&mut self, time: u64) -> Option<HistoryRecord> {
+        self.names.reload();
+        // Allow for a few missed ticks when the machine was busy
+        for back in 0..5 {
+            let t = time.saturating_sub(back * self.raw.interval);
+            if let Some(record) = self.raw.read(t, &self.names) {
+                return Some(record);
+            }
+        }
+        (0..2).find_map(|back| self.minute.read(time.saturating_sub(back * 60), &self.names))
+    }
+
+    /// Time of the most recent sample, e.g. the last one before a reboot
+    pub fn latest(&mut self) -> Option<u64> {
+        self.names.reload();
+        // The one-minute averages narrow it down without reading the whole raw ring
+        let Some(minute) = self.minute.newest() else {
+            return self.raw.newest();
+        };
+        let interval = self.raw.interval;
+        let probes = (0..=120 / interval).rev().map(|n| minute + n * interval);
+        let latest = probes.into_iter().find_map(|t| self.raw.read(t, &self.names)).map(|r| r.system.time);
+        Some(latest.unwrap_or(minute))
+    }
+
+    /// All samples between `from` and `to`, using one-minute averages for spans
+    /// longer than `max_points` raw samples or older than the raw ring
+    pub fn range(&mut self, from: u64, to: u64, max_points: usize) -> Vec<HistoryRecord> {
+        self.names.reload();
+        let now = now();
+        let raw_from = now.saturating_sub(self.raw.retention());
+        let raw_points = (to.saturating_sub(from) / self.raw.interval) as usize;
+
+        if from >= raw_from && raw_points <= max_points {
+            let records = Self::read_range(&mut self.raw, &self.names, from, to);
+            // Raw samples also end where their processes were overwritten, and
+            // then the one-minute averages go further back
+            let first = records.first().map(|r| r.system.time);
+            let overwritten = first.is_none_or(|first| first >= from + 60 && self.minute.read(first - 60, &self.names).is_some());
+            if !overwritten {
+                return records;
+            }
+        }
+        Self::read_range(&mut self.minute, &self.names, from, to)
+    }
+
+    fn read_range(ring: &mut RingFile, names: &NameTable, from: u64, to: u64) -> Vec<HistoryRecord> {
+        let step = ring.interval;
+        let mut records = Vec::new();
+        let mut t = from - from % step;
+        while t <= to {
+            if let Some(record) = ring.read(t, names) {
+                records.push(record);
+            }
+            t += step;
+        }
+        records
+    }
+
+    /// CPU and memory of one process over time, as (time, cpu, mem) points
+    pub fn process_series(&mut self, pid: u32, from: u64, to: u64, max_points: usize) -> Vec<(u64, f32, f32)> {
+        self.range(from, to, max_points)
+            .into_iter()
+            .filter_map(|record| {
+                let time = record.system.time;
+                record.processes.iter().find(|p| p.pid == pid).map(|p| (time, p.cpu, p.mem))
+            })
+            .collect()
+    }
+}
+
+/// Samples every process (or the `max_processes` busiest) in a background
+/// thread and writes them to the history
+pub struct Recorder {
+    stop: Arc<AtomicBool>,
+    errors: Receiver<String>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Recorder {
+    /// Start recording. Fails if the history cannot be opened or another recorder owns it.
+    pub fn start(config: HistoryConfig) -> Result<Self, String> {
+        let mut history = History::create(&config)?;
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let stop_flag = stop.clone();
+        let (error_sender, errors) = mpsc::channel();
+        let thread = thread::spawn(move || {
+            let interval = Duration::from_secs(history.interval());
+            let limit = config.max_processes.unwrap_or(usize::MAX);
+            let mut collector = Collector::new();
+            collector.refresh();
+            // Report a failure once, not again every interval until it clears
+            let mut failing = None;
+
+            while !stop_flag.load(Ordering::Relaxed) {
+                // Sleep in short steps so stop() does not wait a whole interval
+                let deadline = SystemTime::now() + interval;
+                while SystemTime::now() < deadline && !stop_flag.load(Ordering::Relaxed) {
+                    thread::sleep(Duration::from_millis(100));
+                }
+
+                collector.refresh();
+                let record = HistoryRecord::from_snapshot(now(), &collector.snapshot(), &collector.summary(), limit);
+                match history.record(&record) {
+                    Ok(()) => failing = None,
+                    Err(e) if failing.as_ref() != Some(&e) => {
+                        let _ = error_sender.send(format!("Failed to record history: {}", e));
+                        failing = Some(e);
+                    },
+                    Err(_) => {},
+                }
+            }
+            // Keep the partial minute so short recordings still reach the downsampled ring
+            if history.pending.samples > 0 {
+                let _ = history.flush_minute();
+            }
+        });
+
+        Ok(Recorder { stop, errors, thread: Some(thread) })
+    }
+
+    /// Recording failures since the last call
+    pub fn errors(&self) -> Vec<String> {
+        self.errors.try_iter().collect()
+    }
+
+    pub fn stop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Current time in seconds since the epoch
+pub fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+/// e.g. `2024-05-01 03:12:00` in local time, the form `parse_time` takes
+pub fn format_time(time: u64) -> String {
+    match Local.timestamp_opt(time as i64, 0).single() {
+        Some(t) => t.format("%Y-%m-%d %H:%M:%S").to_string(),
+        None => time.to_string(),
+    }
+}
+
+/// Parse a point in time for history queries.
+///
+/// Accepts `HH:MM[:SS]` (the most recent such time today or yesterday),
+/// `YYYY-MM-DD HH:MM[:SS]`, relative times such as `-10m`, `-2h` or `-1d`,
+/// `now`, and plain epoch seconds.
+pub fn parse_time(text: &str) -> Result<u64, String> {
+    let text = text.trim();
+    let current = Local::now();
+
+    if text == "now" {
+        return Ok(current.timestamp() as u64);
+    }
+    if let Some(relative) = text.strip_prefix('-') {
+        let (at, unit) = relative.char_indices().last().unwrap_or((0, ' '));
+        let seconds = match unit {
+            's' => 1,
+            'm' => 60,
+            'h' => 3600,
+            'd' => 86400,
+            _ => return Err(format!("Unknown time unit in '{}' (use s, m, h or d)", text)),
+        };
+        let ago = relative[..at]
+            .parse::<u64>()
+            .ok()
+            .and_then(|amount| amount.checked_mul(seconds))
+            .ok_or_else(|| format!("Invalid relative time '{}'", text))?;
+        return Ok((current.timestamp() as u64).saturating_sub(ago));
+    }
+    if let Ok(epoch) = text.parse::<u64>() {
+        return Ok(epoch);
+    }
+
+    let time_of_day = |t: &str| {
+        NaiveTime::parse_from_str(t, "%H:%M:%S").or_else(|_| NaiveTime::parse_from_str(t, "%H:%M"))
+    };
+    let datetime = if let Ok(time) = time_of_day(text) {
+        // A bare time means the last time the clock showed it
+        let today = current.date_naive().and_time(time);
+        if today > current.naive_local() {
+            today - chrono::Duration::days(1)
+        } else {
+            today
+        }
+    } else {
+        let (date, time) = text
+            .split_once([' ', 'T'])
+            .ok_or_else(|| format!("Unrecognised time '{}'", text))?;
+        let date = NaiveDate::parse_from_str(date, "%Y-%m-%d").map_err(|_| format!("Invalid date in '{}'", text))?;
+        let time = time_of_day(time).map_err(|_| format!("Invalid time in '{}'", text))?;
+        NaiveDateTime::new(date, time)
+    };
+
+    Local
+        .from_local_datetime(&datetime)
+        .earliest()
+        .map(|t| t.timestamp() as u64)
+        .ok_or_else(|| format!("'{}' does not exist in the local time zone", text))
+}
+
+fn read_u32(buf: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(buf[at..at + 4].try_into().unwrap_or([0; 4]))
+}
+
+fn read_u64(buf: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(buf[at..at + 8].try_into().unwrap_or([0; 8]))
+}
+
+fn read_f32(buf: &[u8], at: usize) -> f32 {
+    f32::from_le_bytes(buf[at..at + 4].try_into().unwrap_or([0; 4]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(time: u64, pids: &[u32]) -> HistoryRecord {
+        HistoryRecord {
+            system: SystemSample { time, process_count: pids.len() as u32, ..SystemSample::default() },
+            processes: pids
+                .iter()
+                .map(|&pid| ProcessSample {
+                    pid,
+                    name: format!("p{}", pid),
+                    cpu: pid as f32,
+                    mem: 1.0,
+                    ppid: Some(1),
+                    uid: Some(0),
+                    state: ProcessState::Sleep,
+                    nice: -5,
+                    threads: 2,
+                    cmdline: format!("/bin/p{} --flag", pid),
+                })
+                .collect(),
+            resolution: 1,
+        }
+    }
+
+    fn config(name: &str, raw_bytes: u64) -> HistoryConfig {
+        let dir = env::temp_dir().join(format!("pulse-history-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        HistoryConfig { dir, raw_capacity: 100, raw_bytes, minute_capacity: 10, ..HistoryConfig::default() }
+    }
+
+    #[test]
+    fn keeps_every_process() {
+        let config = config("every", 1 << 20);
+        let pids: Vec<u32> = (1..=500).collect();
+        let mut history = History::create(&config).unwrap();
+        history.record(&record(1_000, &pids)).unwrap();
+        let stored = history.at(1_000).unwrap();
+        assert_eq!(stored.processes.len(), 500);
+        assert_eq!(stored.processes[499].name, "p500");
+        fs::remove_dir_all(&config.dir).unwrap();
+    }
+
+    #[test]
+    fn replayed_processes_keep_tree_user_and_command() {
+        let config = config("fields", 1 << 20);
+        let mut history = History::create(&config).unwrap();
+        history.record(&record(1_000, &[1, 42])).unwrap();
+        let snapshots = History::open(&config.dir).unwrap().at(1_000).unwrap().to_snapshots();
+        let p = snapshots.iter().find(|p| p.pid == 42).unwrap();
+        assert_eq!(p.ppid, Some(1));
+        assert_eq!(p.uid, Some(0));
+        assert_eq!(p.user, "root");
+        assert_eq!(p.state, ProcessState::Sleep);
+        assert_eq!(p.nice, -5);
+        assert_eq!(p.threads, 2);
+        assert_eq!(p.cmdline.join(" "), "/bin/p42 --flag");
+        fs::remove_dir_all(&config.dir).unwrap();
+    }
+
+    #[test]
+    fn relative_times() {
+        let now = Local::now().timestamp() as u64;
+        assert!(parse_time("-10m").unwrap().abs_diff(now - 600) <= 1);
+        assert!(parse_time("-2d").unwrap().abs_diff(now - 2 * 86400) <= 1);
+        assert_eq!(parse_time("-5é"), Err("Unknown time unit in '-5é' (use s, m, h or d)".to_string()));
+        assert_eq!(parse_time("-"), Err("Unknown time unit in '-' (use s, m, h or d)".to_string()));
+        assert_eq!(parse_time("-éd"), Err("Invalid relative time '-éd'".to_string()));
+        assert_eq!(
+            parse_time("-999999999999999d"),
+            Err("Invalid relative time '-999999999999999d'".to_string())
+        );
+    }
+
+    #[test]
+    fn compacting_names_keeps_the_ones_still_referred_to() {
+        let config = config("names", 1 << 20);
+        let mut history = History::create(&config).unwrap();
+        let mut reader = History::open(&config.dir).unwrap();
+        let renamed = |time| {
+            let mut record = record(time, &[1]);
+            record.processes[0].name = "q1".to_string();
+            record
+        };
+        history.record(&record(1_000, &[1, 2])).unwrap();
+        history.record(&renamed(1_100)).unwrap();
+        history.record(&renamed(2_000)).unwrap();
+        let name = |history: &mut History, time, pid| {
+            history.at(time).unwrap().processes.into_iter().find(|p| p.pid == pid).map(|p| p.name)
+        };
+        assert_eq!(name(&mut reader, 1_000, 2).as_deref(), Some("p2"));
+
+        // PID 2 was last written at 1000 and PID 1 renamed at 1100, both before 1500
+        history.names.compact(1_500).unwrap();
+        let log = fs::read_to_string(config.dir.join("names.log")).unwrap();
+        assert_eq!(log, "1100\t1\tq1\t/bin/p1 --flag\n");
+
+        // A reader of the old log picks up the compacted one and what follows it
+        history.record(&record(2_001, &[3])).unwrap();
+        assert_eq!(name(&mut reader, 2_000, 1).as_deref(), Some("q1"));
+        assert_eq!(name(&mut reader, 2_001, 3).as_deref(), Some("p3"));
+        fs::remove_dir_all(&config.dir).unwrap();
+    }
+
+    #[test]
+    fn oldest_samples_make_room() {
+        // Room for two samples of ten processes
+        let sample = (RECORD_HEADER_SIZE + 10 * ENTRY_SIZE) as u64;
+        let config = config("wrap", HEADER_SIZE + 2 * sample);
+        let pids: Vec<u32> = (1..=10).collect();
+        let mut history = History::create(&config).unwrap();
+        for time in 1_000..1_003 {
+            history.record(&record(time, &pids)).unwrap();
+        }
+        assert!(history.raw.read(1_000, &history.names).is_none());
+        assert_eq!(history.raw.read(1_001, &history.names).unwrap().processes.len(), 10);
+        assert_eq!(history.raw.read(1_002, &history.names).unwrap().processes.len(), 10);
+
+        // A recorder started again carries on where the last one stopped
+        drop(history);
+        let mut history = History::create(&config).unwrap();
+        history.record(&record(1_003, &pids)).unwrap();
+        assert!(history.raw.read(1_001, &history.names).is_none());
+        assert!(history.raw.read(1_002, &history.names).is_some());
+        fs::remove_dir_all(&config.dir).unwrap();
+    }
+}
@@ -0,0 +1,227 @@
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+use sysinfo::System;
+use crate::alerts;
+use crate::columns::{Column, ColumnKind};
+use crate::exporter::{ExportData, Exporter};
+use crate::history::{format_time, SystemSample};
+use crate::snapshot::ProcessSnapshot;
+
+// How many processes the bar charts show
+const TOP: usize = 10;
+
+const STYLE: &str = "
+body { font-family: system-ui, sans-serif; margin: 2em; color: #222; }
+h1 { font-size: 1.4em; margin-bottom: 0.2em; }
+.meta { color: #666; margin-bottom: 1.5em; }
+.cards { display: flex; flex-wrap: wrap; gap: 1em; margin-bottom: 1.5em; }
+.card { border: 1px solid #ddd; border-radius: 6px; padding: 0.6em 1em; min-width: 9em; }
+.card .label { color: #666; font-size: 0.85em; }
+.card .value { font-size: 1.3em; font-weight: 600; }
+.charts { display: flex; flex-wrap: wrap; gap: 2em; margin-bottom: 1.5em; }
+.chart h2, h2 { font-size: 1.05em; }
+table { border-collapse: collapse; font-size: 0.9em; }
+th, td { padding: 0.25em 0.7em; border-bottom: 1px solid #eee; text-align: left; white-space: nowrap; }
+td.num { text-align: right; font-variant-numeric: tabular-nums; }
+th { cursor: pointer; user-select: none; background: #f4f6f8; position: sticky; top: 0; }
+th.asc::after { content: ' \\25B2'; } th.desc::after { content: ' \\25BC'; }
+";
+
+// Sorts the table by a clicked header, numerically when both values are numbers
+const SCRIPT: &str = "
+document.querySelectorAll('th').forEach((th, index) => th.addEventListener('click', () => {
+  const body = th.closest('table').tBodies[0];
+  const desc = !th.classList.contains('desc');
+  th.parentNode.querySelectorAll('th').forEach(h => h.classList.remove('asc', 'desc'));
+  th.classList.add(desc ? 'desc' : 'asc');
+  const key = row => row.cells[index].dataset.sort ?? row.cells[index].textContent;
+  const rows = Array.from(body.rows).sort((a, b) => {
+    const x = key(a), y = key(b), nx = parseFloat(x), ny = parseFloat(y);
+    const order = !isNaN(nx) && !isNaN(ny) ? nx - ny : x.localeCompare(y);
+    return desc ? -order : order;
+  });
+  rows.forEach(row => body.appendChild(row));
+}));
+";
+
+/// A single self-contained HTML page (no external scripts or styles) with the
+/// system summary, charts of the busiest processes and, when recorded, the
+/// system history, and a sortable process table; made for attaching to tickets
+pub struct HtmlExporter;
+
+impl Exporter for HtmlExporter {
+    fn extension(&self) -> &'static str {
+        "html"
+    }
+
+    fn export(&self, data: &ExportData, path: &Path) -> Result<String, String> {
+        fs::write(path, render(data)).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        Ok(format!("Report written to {}", path.display()))
+    }
+}
+
+fn render(data: &ExportData) -> String {
+    let s = data.summary;
+    let host = System::host_name().unwrap_or_default();
+    let time = format_time(data.time);
+    let mut out = String::new();
+
+    write!(out, "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\">").unwrap();
+    writeln!(out, "<title>Pulse report {} {}</title><style>{}</style></head><body>", escape(&host), time, STYLE).unwrap();
+    writeln!(out, "<h1>Pulse process report</h1><div class=\"meta\">{} at {}</div>", escape(&host), time).unwrap();
+
+    let gib = |bytes: u64| bytes as f64 / 1024.0 / 1024.0 / 1024.0;
+    out.push_str("<div class=\"cards\">");
+    for (label, value) in [
+        ("CPU", format!("{:.1}%", s.cpu_usage)),
+        ("Memory", format!("{:.1} / {:.1} GiB", gib(s.used_memory), gib(s.total_memory))),
+        ("Swap", format!("{:.1} / {:.1} GiB", gib(s.used_swap), gib(s.total_swap))),
+        ("Load", format!("{:.2} {:.2} {:.2}", s.load_average[0], s.load_average[1], s.load_average[2])),
+        ("Physical cores", s.physical_cores.to_string()),
+        ("Processes", data.processes.len().to_string()),
+    ] {
+        write!(out, "<div class=\"card\"><div class=\"label\">{}</div><div class=\"value\">{}</div></div>", label, value)
+            .unwrap();
+    }
+    out.push_str("</div>\n<div class=\"charts\">");
+
+    if data.history.len() > 1 {
+        out.push_str(&history_chart(&data.history));
+    }
+    let mut busiest: Vec<&ProcessSnapshot> = data.processes.iter().filter(|p| !p.is_thread).collect();
+    busiest.sort_by(|a, b| b.cpu.total_cmp(&a.cpu));
+    let cpu: Vec<(String, f64, String)> = busiest
+        .iter()
+        .take(TOP)
+        .map(|p| (bar_label(p), p.cpu as f64, format!("{:.1}%", p.cpu)))
+        .collect();
+    out.push_str(&bar_chart("Top processes by CPU", &cpu, "#e8743b"));
+    busiest.sort_by_key(|p| std::cmp::Reverse(p.memory));
+    let memory: Vec<(String, f64, String)> = busiest
+        .iter()
+        .take(TOP)
+        .map(|p| (bar_label(p), p.memory as f64, alerts::format_size(p.memory as f64)))
+        .collect();
+    out.push_str(&bar_chart("Top processes by memory", &memory, "#4a90d9"));
+    out.push_str("</div>\n");
+
+    out.push_str("<h2>Processes</h2><table><thead><tr>");
+    for column in data.columns {
+        write!(out, "<th title=\"{}\">{}</th>", column.description(), column.header()).unwrap();
+    }
+    out.push_str("</tr></thead><tbody>\n");
+    for process in data.processes {
+        out.push_str("<tr>");
+        for column in data.columns {
+            let class = if column.kind() == ColumnKind::Text { "" } else { " class=\"num\"" };
+            // Readable on screen, sorted by the exact value
+            let shown = column.display(process);
+            let exact = column.text(process);
+            if shown == exact || *column == Column::Cmdline {
+                write!(out, "<td{}>{}</td>", class, escape(&shown)).unwrap();
+            } else {
+                write!(out, "<td{} data-sort=\"{}\">{}</td>", class, escape(&exact), escape(&shown)).unwrap();
+            }
+        }
+        out.push_str("</tr>\n");
+    }
+    write!(out, "</tbody></table>\n<script>{}</script>\n</body></html>\n", SCRIPT).unwrap();
+    out
+}
+
+fn bar_label(p: &ProcessSnapshot) -> String {
+    format!("{} ({})", p.name, p.pid)
+}
+
+// Horizontal bars scaled to the largest value
+fn bar_chart(title: &str, bars: &[(String, f64, String)], color: &str) -> String {
+    let (width, row, label_width) = (460.0, 22.0, 200.0);
+    let max = bars.iter().map(|(_, value, _)| *value).fold(0.0, f64::max).max(f64::EPSILON);
+    let height = row * bars.len().max(1) as f64;
+    let mut svg = format!(
+        "<div class=\"chart\"><h2>{}</h2><svg width=\"{}\" height=\"{}\" font-size=\"12\">",
+        title, width, height
+    );
+    for (i, (label, value, text)) in bars.iter().enumerate() {
+        let y = i as f64 * row;
+        let bar = (width - label_width - 70.0) * value / max;
+        write!(
+            svg,
+            "<text x=\"0\" y=\"{:.0}\">{}</text><rect x=\"{}\" y=\"{:.0}\" width=\"{:.1}\" height=\"{}\" fill=\"{}\"/><text x=\"{:.1}\" y=\"{:.0}\">{}</text>",
+            y + 15.0,
+            escape(&truncate(label, 28)),
+            label_width,
+            y + 3.0,
+            bar,
+            row - 6.0,
+            color,
+            label_width + bar + 5.0,
+            y + 15.0,
+            escape(text)
+        )
+        .unwrap();
+    }
+    svg.push_str("</svg></div>");
+    svg
+}
+
+// System CPU and memory usage over the recorded history, both 0-100%
+fn history_chart(history: &[SystemSample]) -> String {
+    let (width, height, left, bottom) = (520.0, 200.0, 35.0, 20.0);
+    let (first, last) = (history[0].time, history[history.len() - 1].time);
+    let span = (last - first).max(1) as f64;
+    let x = |time: u64| left + (width - left - 10.0) * (time - first) as f64 / span;
+    let y = |percent: f64| (height - bottom) * (1.0 - percent.clamp(0.0, 100.0) / 100.0);
+    let line = |value: &dyn Fn(&SystemSample) -> f64| -> String {
+        history.iter().map(|s| format!("{:.1},{:.1}", x(s.time), y(value(s)))).collect::<Vec<_>>().join(" ")
+    };
+    let cpu = line(&|s| s.cpu as f64);
+    let memory = line(&|s| if s.mem_total == 0 { 0.0 } else { s.mem_used as f64 * 100.0 / s.mem_total as f64 });
+
+    let mut svg = format!(
+        "<div class=\"chart\"><h2>System usage from {} to {}</h2><svg width=\"{}\" height=\"{}\" font-size=\"11\">",
+        format_time(first),
+        format_time(last),
+        width,
+        height
+    );
+    for percent in [0.0, 50.0, 100.0] {
+        write!(
+            svg,
+            "<line x1=\"{}\" x2=\"{}\" y1=\"{:.1}\" y2=\"{:.1}\" stroke=\"#ddd\"/><text x=\"0\" y=\"{:.1}\">{}%</text>",
+            left,
+            width - 10.0,
+            y(percent),
+            y(percent),
+            y(percent) + 4.0,
+            percent
+        )
+        .unwrap();
+    }
+    write!(svg, "<polyline fill=\"none\" stroke=\"#e8743b\" stroke-width=\"1.5\" points=\"{}\"/>", cpu).unwrap();
+    write!(svg, "<polyline fill=\"none\" stroke=\"#4a90d9\" stroke-width=\"1.5\" points=\"{}\"/>", memory).unwrap();
+    write!(
+        svg,
+        "<text x=\"{}\" y=\"{}\" fill=\"#e8743b\">CPU</text><text x=\"{}\" y=\"{}\" fill=\"#4a90d9\">Memory</text>",
+        left,
+        height - 4.0,
+        left + 40.0,
+        height - 4.0
+    )
+    .unwrap();
+    svg.push_str("</svg></div>");
+    svg
+}
+
+fn truncate(text: &str, max: usize) -> String {
+    if text.chars().count() > max {
+        format!("{}...", text.chars().take(max - 3).collect::<String>())
+    } else {
+        text.to_string()
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
@@ -1,75 +1,248 @@
 // json_export.rs
-use serde::Serialize;
-use sysinfo::{Process, Pid, ProcessStatus, System};
-use users::get_user_by_uid;
-use std::{fs::File, io::Write, path::Path};
-
-#[derive(Serialize)]
-struct ProcessInfo {
-    pid: u32,
-    username: String,
-    cpu: f32,
-    mem: f64,
-    nice: i32,
-    fg_bg: String,
-    state: String,
-    command: String,
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use sysinfo::System;
+use crate::columns::{self, Column};
+use crate::exporter::{ExportData, Exporter};
+use crate::snapshot::ProcessSnapshot;
+
+// One process as a JSON object with the chosen columns, in order
+fn process_object(p: &ProcessSnapshot, columns: &[Column]) -> Map<String, Value> {
+    columns.iter().map(|c| (c.name().to_string(), c.json(p))).collect()
 }
 
+/// A pretty-printed array with one object per process
 pub struct JsonExporter;
 
-impl JsonExporter {
-    pub fn export(processes: &[&Process], system: &System, filepath: &str) -> Result<String, String> {
-        let mut data = Vec::new();
-
-        for process in processes {
-            let pid = process.pid();
-            let cpu = process.cpu_usage();
-            let mem = (process.memory() as f64 / system.total_memory() as f64) * 100.0;
-
-            let stat_path = format!("/proc/{}/stat", pid);
-            let stat = std::fs::read_to_string(stat_path).unwrap_or_default();
-            let parts: Vec<&str> = stat.split_whitespace().collect();
-            let nice = parts.get(18).and_then(|s| s.parse().ok()).unwrap_or(0);
-            let pgrp = parts.get(4).and_then(|s| s.parse().ok()).unwrap_or(0);
-            let tpgid = parts.get(7).and_then(|s| s.parse().ok()).unwrap_or(0);
-            let fg_bg = if pgrp == tpgid { "FG" } else { "BG" }.to_string();
-
-            let state = match process.status() {
-                ProcessStatus::Run => "Running",
-                ProcessStatus::Sleep => "Sleep",
-                ProcessStatus::Idle => "Idle",
-                ProcessStatus::Stop => "Stopped",
-                ProcessStatus::Zombie => "Zombie",
-                _ => "Other",
-            }.to_string();
-
-            let username = process.user_id()
-                .and_then(|uid| get_user_by_uid(**uid))
-                .map(|u| u.name().to_string_lossy().into_owned())
-                .unwrap_or_else(|| "Unknown".to_string());
-
-            data.push(ProcessInfo {
-                pid: pid.as_u32(),
-                username,
-                cpu,
-                mem,
-                nice,
-                fg_bg,
-                state,
-                command: process.name().to_string(),
-            });
-        }
+impl Exporter for JsonExporter {
+    fn extension(&self) -> &'static str {
+        "json"
+    }
 
-        let json = serde_json::to_string_pretty(&data).map_err(|e| e.to_string())?;
-        let mut file = File::create(Path::new(filepath)).map_err(|e| e.to_string())?;
+    fn export(&self, data: &ExportData, path: &Path) -> Result<String, String> {
+        let objects: Vec<Map<String, Value>> = data.processes.iter().map(|p| process_object(p, data.columns)).collect();
+
+        let json = serde_json::to_string_pretty(&objects).map_err(|e| e.to_string())?;
+        let mut file = File::create(path).map_err(|e| e.to_string())?;
         file.write_all(json.as_bytes()).map_err(|e| e.to_string())?;
-        Ok(format!("Process data exported to {}", filepath))
+        Ok(format!("Process data exported to {}", path.display()))
+    }
+}
+
+/// What a streaming export writes each interval
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StreamMode {
+    /// One line per process
+    Snapshot,
+    /// Only processes that started, exited or changed since the last interval
+    Diff,
+}
+
+#[derive(Clone, Debug)]
+pub struct StreamConfig {
+    pub mode: StreamMode,
+    /// Seconds between snapshots
+    pub interval: u64,
+    /// `None` writes to stdout
+    pub output: Option<PathBuf>,
+    /// Rotate the file once it grows past this many bytes
+    pub rotate_size: Option<u64>,
+    /// Rotate the file once it has been written to for this many seconds
+    pub rotate_every: Option<u64>,
+    /// Rotated files kept next to the current one, as `<output>.1` (newest) to `<output>.N`
+    pub keep: usize,
+    pub columns: Vec<Column>,
+}
+
+impl StreamConfig {
+    pub fn new() -> Self {
+        StreamConfig {
+            mode: StreamMode::Snapshot,
+            interval: 2,
+            output: None,
+            rotate_size: None,
+            rotate_every: None,
+            keep: 5,
+            columns: columns::default_columns(),
+        }
+    }
+}
+
+impl Default for StreamConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Processes by PID, with their start time to tell a reused PID apart
+type Seen = HashMap<u32, (u64, Map<String, Value>)>;
+
+/// Writes newline-delimited JSON, one object per process, for piping into jq,
+/// Vector, Loki and the like
+pub struct JsonStream {
+    config: StreamConfig,
+    host: Option<String>,
+    out: Box<dyn Write>,
+    // Bytes written to and time of opening the current file, for rotation
+    written: u64,
+    opened: Instant,
+    // Processes seen in the last interval
+    previous: Option<Seen>,
+}
+
+impl JsonStream {
+    pub fn open(config: StreamConfig) -> Result<Self, String> {
+        let (out, written): (Box<dyn Write>, u64) = match &config.output {
+            Some(path) => {
+                let file = open_append(path)?;
+                let written = file.metadata().map(|m| m.len()).unwrap_or(0);
+                (Box::new(file), written)
+            },
+            None => (Box::new(io::stdout()), 0),
+        };
+        Ok(JsonStream { config, host: System::host_name(), out, written, opened: Instant::now(), previous: None })
     }
 
-    pub fn get_default_filename() -> String {
-        use chrono::Local;
-        let now = Local::now();
-        format!("pulse_export_{}.json", now.format("%Y%m%d_%H%M%S"))
+    /// Write the lines for one interval and return how many were written.
+    /// Threads are left out; their figures are part of their process.
+    pub fn write(&mut self, time: u64, processes: &[ProcessSnapshot]) -> io::Result<usize> {
+        if self.rotation_due() {
+            self.rotate()?;
+        }
+
+        let current: Seen = processes
+            .iter()
+            .filter(|p| !p.is_thread)
+            .map(|p| (p.pid, (p.start_time, process_object(p, &self.config.columns))))
+            .collect();
+        let mut lines = Vec::new();
+        match (self.config.mode, &self.previous) {
+            (StreamMode::Diff, Some(previous)) => {
+                for (pid, (start, info)) in &current {
+                    match previous.get(pid) {
+                        Some((old_start, old)) if old_start == start => {
+                            let changes = changes(&self.config.columns, old, info);
+                            if !changes.is_empty() {
+                                lines.push(self.line(time, "changed", *pid, info, Some(changes)));
+                            }
+                        },
+                        Some((_, old)) => {
+                            lines.push(self.line(time, "exited", *pid, old, None));
+                            lines.push(self.line(time, "started", *pid, info, None));
+                        },
+                        None => lines.push(self.line(time, "started", *pid, info, None)),
+                    }
+                }
+                for (pid, (_, old)) in previous {
+                    if !current.contains_key(pid) {
+                        lines.push(self.line(time, "exited", *pid, old, None));
+                    }
+                }
+            },
+            // Every file starts with a full snapshot so diffs in it can be followed on their own
+            _ => {
+                for (pid, (_, info)) in &current {
+                    lines.push(self.line(time, "sample", *pid, info, None));
+                }
+            },
+        }
+        lines.sort_by_key(|(pid, _)| *pid);
+
+        let mut text = String::new();
+        for (_, line) in &lines {
+            text.push_str(line);
+            text.push('\n');
+        }
+        self.out.write_all(text.as_bytes())?;
+        self.out.flush()?;
+        self.written += text.len() as u64;
+        self.previous = Some(current);
+        Ok(lines.len())
+    }
+
+    // The PID is always included, even when it is not one of the columns, so lines can be told apart
+    fn line(
+        &self,
+        time: u64,
+        event: &str,
+        pid: u32,
+        fields: &Map<String, Value>,
+        changes: Option<Map<String, Value>>,
+    ) -> (u32, String) {
+        let mut object = Map::new();
+        object.insert("time".to_string(), json!(time));
+        object.insert("host".to_string(), json!(self.host));
+        object.insert("event".to_string(), json!(event));
+        if !fields.contains_key(Column::Pid.name()) {
+            object.insert(Column::Pid.name().to_string(), json!(pid));
+        }
+        object.extend(fields.clone());
+        if let Some(changes) = changes {
+            object.insert("changes".to_string(), Value::Object(changes));
+        }
+        (pid, Value::Object(object).to_string())
+    }
+
+    fn rotation_due(&self) -> bool {
+        self.config.output.is_some()
+            && self.written > 0
+            && (self.config.rotate_size.is_some_and(|size| self.written >= size)
+                || self.config.rotate_every.is_some_and(|every| self.opened.elapsed().as_secs() >= every))
+    }
+
+    // Shift <output>.1 .. <output>.N up by one, dropping the oldest, and start a new file
+    fn rotate(&mut self) -> io::Result<()> {
+        let path = match &self.config.output {
+            Some(path) => path.clone(),
+            None => return Ok(()),
+        };
+        self.out.flush()?;
+        let numbered = |n: usize| PathBuf::from(format!("{}.{}", path.display(), n));
+        if self.config.keep == 0 {
+            fs::remove_file(&path)?;
+        } else {
+            let _ = fs::remove_file(numbered(self.config.keep));
+            for n in (1..self.config.keep).rev() {
+                if numbered(n).exists() {
+                    fs::rename(numbered(n), numbered(n + 1))?;
+                }
+            }
+            fs::rename(&path, numbered(1))?;
+        }
+        self.out = Box::new(open_append(&path).map_err(io::Error::other)?);
+        self.written = 0;
+        self.opened = Instant::now();
+        self.previous = None;
+        Ok(())
+    }
+}
+
+fn open_append(path: &Path) -> Result<File, String> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent).map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+    }
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| format!("Failed to open {}: {}", path.display(), e))
+}
+
+// The columns of a process that changed, with their old and new values
+fn changes(columns: &[Column], old: &Map<String, Value>, new: &Map<String, Value>) -> Map<String, Value> {
+    let mut changes = Map::new();
+    for column in columns {
+        let (from, to) = match (old.get(column.name()), new.get(column.name())) {
+            (Some(from), Some(to)) => (from, to),
+            _ => continue,
+        };
+        if column.changed(from, to) {
+            changes.insert(column.name().to_string(), json!({ "from": from, "to": to }));
+        }
     }
+    changes
 }
@@ -0,0 +1,548 @@
+use std::ffi::{CString, OsString};
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::os::unix::process::CommandExt;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::thread;
+use crate::priority;
+use crate::signal::ProcessHandle;
+use crate::snapshot::read_stat;
+
+// The limits carried over, with their names in /proc/<pid>/limits
+const LIMITS: [(libc::__rlimit_resource_t, &str); 16] = [
+    (libc::RLIMIT_CPU, "cpu time"),
+    (libc::RLIMIT_FSIZE, "file size"),
+    (libc::RLIMIT_DATA, "data size"),
+    (libc::RLIMIT_STACK, "stack size"),
+    (libc::RLIMIT_CORE, "core file size"),
+    (libc::RLIMIT_RSS, "resident set"),
+    (libc::RLIMIT_NPROC, "processes"),
+    (libc::RLIMIT_NOFILE, "open files"),
+    (libc::RLIMIT_MEMLOCK, "locked memory"),
+    (libc::RLIMIT_AS, "address space"),
+    (libc::RLIMIT_LOCKS, "file locks"),
+    (libc::RLIMIT_SIGPENDING, "pending signals"),
+    (libc::RLIMIT_MSGQUEUE, "msgqueue size"),
+    (libc::RLIMIT_NICE, "nice priority"),
+    (libc::RLIMIT_RTPRIO, "realtime priority"),
+    (libc::RLIMIT_RTTIME, "realtime timeout"),
+];
+
+/// One part of how a process was started, which a restart tries to carry over
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Attribute {
+    Arguments,
+    Environment,
+    WorkingDirectory,
+    /// User, group and supplementary groups
+    User,
+    Limits,
+    Nice,
+    Umask,
+    Stdin,
+    Stdout,
+    Stderr,
+    /// Session, process group and controlling terminal
+    Session,
+}
+
+impl Attribute {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Attribute::Arguments => "arguments",
+            Attribute::Environment => "environment",
+            Attribute::WorkingDirectory => "working directory",
+            Attribute::User => "user",
+            Attribute::Limits => "resource limits",
+            Attribute::Nice => "nice value",
+            Attribute::Umask => "umask",
+            Attribute::Stdin => "stdin",
+            Attribute::Stdout => "stdout",
+            Attribute::Stderr => "stderr",
+            Attribute::Session => "session",
+        }
+    }
+
+    // Bit reported back by the child when setting this attribute failed
+    fn bit(self) -> u32 {
+        1 << self as u32
+    }
+}
+
+/// The real user and groups of a process
+#[derive(Clone, Debug)]
+pub struct Credentials {
+    pub uid: u32,
+    pub gid: u32,
+    pub groups: Vec<u32>,
+}
+
+/// Where the process sat among sessions and terminals
+#[derive(Clone, Debug)]
+pub struct SessionInfo {
+    pub session: i32,
+    pub pgrp: i32,
+    /// The controlling terminal, e.g. `/dev/pts/3`
+    pub terminal: Option<PathBuf>,
+}
+
+/// How a process was started, read from `/proc` while it runs, so the same
+/// process can be started again. Each part is an error saying why it could not
+/// be read when that is the case.
+pub struct LaunchSpec {
+    pub pid: u32,
+    /// As in `/proc/<pid>/comm`, at most 15 bytes
+    pub name: String,
+    pub argv: Vec<OsString>,
+    /// None when the binary was deleted or replaced (e.g. by an upgrade); `argv[0]`
+    /// is looked up again then
+    pub exe: Option<PathBuf>,
+    pub env: Result<Vec<(OsString, OsString)>, String>,
+    pub cwd: Result<PathBuf, String>,
+    pub credentials: Result<Credentials, String>,
+    pub limits: Result<Vec<(libc::__rlimit_resource_t, libc::rlimit)>, String>,
+    pub nice: Result<i32, String>,
+    pub umask: Result<u32, String>,
+    pub session: Result<SessionInfo, String>,
+    // Descriptors 0-2, shared with the process or opened again by path
+    stdio: [Result<OwnedFd, String>; 3],
+    // The executable the process ran, kept open even if it is replaced, and its file name
+    previous: Option<(File, OsString)>,
+}
+
+/// A started process and what of its `LaunchSpec` it got
+pub struct Launched {
+    pub pid: u32,
+    pub preserved: Vec<Attribute>,
+    pub lost: Vec<(Attribute, String)>,
+}
+
+// What the child does about its session, decided before forking
+enum SessionPlan {
+    /// The process had no terminal: a session of its own, like a daemon
+    Detach,
+    /// The process shared our session: back into its process group
+    JoinGroup(i32),
+    /// A new session taking the terminal back, which only works once no other
+    /// session holds it
+    Terminal(CString),
+}
+
+impl LaunchSpec {
+    /// Read everything needed to start `pid` again. Only a missing process or
+    /// command line (kernel threads) is an error.
+    pub fn capture(pid: u32) -> Result<Self, String> {
+        let stat = read_stat(pid).ok_or_else(|| format!("Process {} not found", pid))?;
+        let argv: Vec<OsString> = fs::read(format!("/proc/{}/cmdline", pid))
+            .map_err(|e| format!("Failed to read the command line of process {}: {}", pid, e))?
+            .split(|b| *b == 0)
+            .filter(|arg| !arg.is_empty())
+            .map(|arg| OsString::from_vec(arg.to_vec()))
+            .collect();
+        if argv.is_empty() {
+            return Err(format!("Process {} has no command line (kernel thread?)", pid));
+        }
+        let exe_link = fs::read_link(format!("/proc/{}/exe", pid)).ok();
+        let exe = exe_link.clone().filter(|exe| !exe.as_os_str().as_bytes().ends_with(b" (deleted)"));
+        let previous = File::open(format!("/proc/{}/exe", pid)).ok().zip(exe_link.and_then(|link| {
+            let name = link.file_name()?.as_bytes();
+            Some(OsString::from_vec(name.strip_suffix(b" (deleted)").unwrap_or(name).to_vec()))
+        }));
+        let name = fs::read_to_string(format!("/proc/{}/comm", pid)).unwrap_or_default().trim_end().to_string();
+        let status = fs::read_to_string(format!("/proc/{}/status", pid)).unwrap_or_default();
+
+        // Taken while the process still holds them, so pipes and sockets survive
+        let handle = ProcessHandle::open(pid).ok();
+        let stdio = [0, 1, 2].map(|fd| capture_stdio(pid, handle.as_ref(), fd));
+
+        Ok(LaunchSpec {
+            pid,
+            name,
+            argv,
+            exe,
+            env: read_environ(pid),
+            cwd: fs::read_link(format!("/proc/{}/cwd", pid)).map_err(|e| format!("cannot be read: {}", e)),
+            credentials: read_credentials(&status),
+            limits: read_limits(pid),
+            nice: priority::get_nice_value(pid as i32).map_err(|e| format!("cannot be read: {}", e)),
+            umask: status_field(&status, "Umask")
+                .and_then(|mask| u32::from_str_radix(mask, 8).ok())
+                .ok_or_else(|| "not shown by this kernel".to_string()),
+            session: Ok(SessionInfo { session: stat.session, pgrp: stat.pgrp, terminal: terminal_path(pid, stat.tty_nr) }),
+            stdio,
+            previous,
+        })
+    }
+
+    /// Start the process again with everything that could be read. Running as
+    /// another user needs root; the rest is carried over where the kernel allows
+    /// it and listed in `lost` otherwise.
+    pub fn spawn(&self) -> Result<Launched, String> {
+        self.spawn_command(self.command())
+    }
+
+    /// Whether the executable on disk is no longer the one the process ran, e.g.
+    /// after an upgrade
+    pub fn binary_replaced(&self) -> bool {
+        let Some((previous, _)) = &self.previous else { return false };
+        match (&self.exe, previous.metadata()) {
+            (None, _) => true,
+            (Some(exe), Ok(old)) => fs::metadata(exe).map_or(true, |new| (new.dev(), new.ino()) != (old.dev(), old.ino())),
+            (Some(_), Err(_)) => false,
+        }
+    }
+
+    /// Start the executable the process ran before it was replaced. It is copied
+    /// out under its old name for the start and removed once running.
+    pub fn spawn_previous(&self) -> Result<Launched, String> {
+        let (previous, name) = self.previous.as_ref().ok_or("the previous executable could not be kept open")?;
+        let dir = env::temp_dir().join(format!("pulse-rollback-{}", self.pid));
+        let path = dir.join(name);
+        let copied = fs::create_dir_all(&dir).and_then(|_| {
+            let mut source = previous.try_clone()?;
+            source.seek(SeekFrom::Start(0))?;
+            io::copy(&mut source, &mut File::create(&path)?)?;
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o755))
+        });
+        let launched = match copied {
+            Ok(()) => {
+                let mut command = Command::new(&path);
+                command.arg0(&self.argv[0]).args(&self.argv[1..]);
+                self.spawn_command(command)
+            },
+            Err(e) => Err(format!("Failed to copy the previous executable to {}: {}", path.display(), e)),
+        };
+        let _ = fs::remove_dir_all(&dir);
+        launched
+    }
+
+    fn spawn_command(&self, mut command: Command) -> Result<Launched, String> {
+        let mut preserved = vec![Attribute::Arguments];
+        let mut lost = Vec::new();
+
+        match &self.env {
+            Ok(env) => {
+                command.env_clear().envs(env.iter().map(|(key, value)| (key, value)));
+                preserved.push(Attribute::Environment);
+            },
+            Err(e) => lost.push((Attribute::Environment, e.clone())),
+        }
+        match &self.cwd {
+            Ok(cwd) if cwd.is_dir() => {
+                command.current_dir(cwd);
+                preserved.push(Attribute::WorkingDirectory);
+            },
+            Ok(cwd) => lost.push((Attribute::WorkingDirectory, format!("{} no longer exists", cwd.display()))),
+            Err(e) => lost.push((Attribute::WorkingDirectory, e.clone())),
+        }
+        for (fd, attribute) in [(0, Attribute::Stdin), (1, Attribute::Stdout), (2, Attribute::Stderr)] {
+            let stdio = match &self.stdio[fd] {
+                Ok(file) => match file.try_clone() {
+                    Ok(file) => {
+                        preserved.push(attribute);
+                        Stdio::from(file)
+                    },
+                    Err(e) => {
+                        lost.push((attribute, e.to_string()));
+                        Stdio::null()
+                    },
+                },
+                Err(e) => {
+                    lost.push((attribute, e.clone()));
+                    Stdio::null()
+                },
+            };
+            match fd {
+                0 => command.stdin(stdio),
+                1 => command.stdout(stdio),
+                _ => command.stderr(stdio),
+            };
+        }
+
+        // Running as root, the child switches to the user; otherwise it stays ours
+        // SAFETY: geteuid and getegid cannot fail
+        let (euid, egid) = unsafe { (libc::geteuid(), libc::getegid()) };
+        let credentials = match &self.credentials {
+            Ok(c) if euid == 0 => {
+                preserved.push(Attribute::User);
+                Some(c.clone())
+            },
+            Ok(c) if c.uid == euid && c.gid == egid => {
+                preserved.push(Attribute::User);
+                None
+            },
+            Ok(c) => {
+                lost.push((Attribute::User, format!("runs as UID {} instead of {} (needs root)", euid, c.uid)));
+                None
+            },
+            Err(e) => {
+                lost.push((Attribute::User, e.clone()));
+                None
+            },
+        };
+        let limits = self.limits.clone().unwrap_or_default();
+        let nice = self.nice.clone().ok();
+        let umask = self.umask.clone().ok();
+        for (attribute, error) in [
+            (Attribute::Limits, self.limits.as_ref().err()),
+            (Attribute::Nice, self.nice.as_ref().err()),
+            (Attribute::Umask, self.umask.as_ref().err()),
+        ] {
+            if let Some(e) = error {
+                lost.push((attribute, e.clone()));
+            }
+        }
+        // SAFETY: getsid(0) cannot fail for the calling process
+        let own_session = unsafe { libc::getsid(0) };
+        let session = match &self.session {
+            Ok(SessionInfo { terminal: None, .. }) => Some(SessionPlan::Detach),
+            Ok(info) if info.session == own_session => Some(SessionPlan::JoinGroup(info.pgrp)),
+            Ok(SessionInfo { terminal: Some(terminal), .. }) => {
+                CString::new(terminal.as_os_str().as_bytes()).ok().map(SessionPlan::Terminal)
+            },
+            Err(e) => {
+                lost.push((Attribute::Session, e.clone()));
+                None
+            },
+        };
+
+        // The child reports what it could not set through this pipe before exec
+        let mut fds = [0; 2];
+        // SAFETY: pipe2 fills in two new descriptors
+        if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } < 0 {
+            return Err(format!("Failed to create a pipe: {}", io::Error::last_os_error()));
+        }
+        // SAFETY: both descriptors were just created and are owned here
+        let (report_read, report_write) = unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) };
+        let report_fd = report_write.as_raw_fd();
+
+        // SAFETY: the closure runs between fork and exec and only makes
+        // async-signal-safe system calls on data prepared before the fork
+        unsafe {
+            command.pre_exec(move || {
+                let mut failed = 0;
+                let in_session = match &session {
+                    Some(SessionPlan::Detach) => libc::setsid() >= 0,
+                    Some(SessionPlan::JoinGroup(pgrp)) => libc::setpgid(0, *pgrp) >= 0,
+                    Some(SessionPlan::Terminal(terminal)) => {
+                        let tty = libc::open(terminal.as_ptr(), libc::O_RDWR | libc::O_NOCTTY);
+                        let attached = libc::setsid() >= 0 && tty >= 0 && libc::ioctl(tty, libc::TIOCSCTTY, 0) >= 0;
+                        if tty >= 0 {
+                            libc::close(tty);
+                        }
+                        attached
+                    },
+                    None => true,
+                };
+                if !in_session {
+                    failed |= Attribute::Session.bit();
+                }
+                for (resource, limit) in &limits {
+                    if libc::setrlimit(*resource, limit) < 0 {
+                        failed |= Attribute::Limits.bit();
+                    }
+                }
+                if let Some(nice) = nice {
+                    if libc::setpriority(libc::PRIO_PROCESS, 0, nice) < 0 {
+                        failed |= Attribute::Nice.bit();
+                    }
+                }
+                if let Some(umask) = umask {
+                    libc::umask(umask as libc::mode_t);
+                }
+                // The user last, as it gives up the right to do the rest
+                if let Some(c) = &credentials {
+                    if libc::setgroups(c.groups.len(), c.groups.as_ptr()) < 0
+                        || libc::setgid(c.gid) < 0
+                        || libc::setuid(c.uid) < 0
+                    {
+                        return Err(io::Error::last_os_error());
+                    }
+                }
+                libc::write(report_fd, &failed as *const u32 as *const libc::c_void, 4);
+                Ok(())
+            });
+        }
+
+        let mut child = command.spawn().map_err(|e| {
+            format!("Failed to start {}: {}", command.get_program().to_string_lossy(), e)
+        })?;
+        drop(report_write);
+        let mut failed = [0u8; 4];
+        // SAFETY: reads at most 4 bytes into the buffer
+        let read = unsafe { libc::read(report_read.as_raw_fd(), failed.as_mut_ptr() as *mut libc::c_void, 4) };
+        let failed = if read == 4 { u32::from_ne_bytes(failed) } else { 0 };
+
+        for (attribute, ok, reason) in [
+            (Attribute::Limits, self.limits.is_ok(), "raising a hard limit needs root"),
+            (Attribute::Nice, self.nice.is_ok(), "lowering the nice value needs root"),
+            (Attribute::Umask, self.umask.is_ok(), ""),
+            (Attribute::Session, self.session.is_ok(), ""),
+        ] {
+            if !ok {
+                continue;
+            }
+            if failed & attribute.bit() == 0 {
+                preserved.push(attribute);
+            } else if attribute == Attribute::Session {
+                lost.push((attribute, session_reason(&self.session).unwrap_or_default()));
+            } else {
+                lost.push((attribute, reason.to_string()));
+            }
+        }
+
+        let pid = child.id();
+        // Reap the process when it exits so it does not linger as a zombie
+        thread::spawn(move || child.wait());
+        Ok(Launched { pid, preserved, lost })
+    }
+
+    // The command for the same executable under the same name. A script is run
+    // itself (the kernel puts its interpreter back in front); otherwise argv[0]
+    // is used when it still leads to the same binary, else the binary itself.
+    fn command(&self) -> Command {
+        let cwd = self.cwd.as_ref().ok();
+        let path = self.env.as_ref().ok().and_then(|env| env.iter().find(|(key, _)| key == "PATH")).map(|(_, value)| value);
+        let resolve = |name: &OsString| -> Option<PathBuf> {
+            if name.as_bytes().contains(&b'/') {
+                return Some(cwd.map_or_else(|| PathBuf::from(name), |cwd| cwd.join(name))).filter(|p| p.is_file());
+            }
+            env::split_paths(path?).map(|dir| dir.join(name)).find(|p| p.is_file())
+        };
+
+        if let Some(script) = self.argv.get(1).and_then(&resolve) {
+            let named_after = script.file_name().is_some_and(|file| file.as_bytes().starts_with(self.name.as_bytes()));
+            let mut start = [0u8; 2];
+            let has_shebang = File::open(&script).and_then(|mut f| f.read_exact(&mut start)).is_ok() && &start == b"#!";
+            if !self.name.is_empty() && named_after && has_shebang {
+                let mut command = Command::new(&self.argv[1]);
+                command.args(&self.argv[2..]);
+                return command;
+            }
+        }
+        let same_binary = |path: &PathBuf| match (fs::canonicalize(path), &self.exe) {
+            (Ok(path), Some(exe)) => &path == exe,
+            _ => false,
+        };
+        let program = match resolve(&self.argv[0]) {
+            Some(resolved) if same_binary(&resolved) => resolved,
+            _ => self.exe.clone().unwrap_or_else(|| PathBuf::from(&self.argv[0])),
+        };
+        let mut command = Command::new(program);
+        command.arg0(&self.argv[0]).args(&self.argv[1..]);
+        command
+    }
+}
+
+// Why the session could not be restored, for each kind of plan
+fn session_reason(session: &Result<SessionInfo, String>) -> Option<String> {
+    // SAFETY: getsid(0) cannot fail for the calling process
+    let own_session = unsafe { libc::getsid(0) };
+    match session {
+        Ok(SessionInfo { terminal: None, .. }) => Some("could not start a session of its own".to_string()),
+        Ok(info) if info.session == own_session => Some(format!("process group {} is gone", info.pgrp)),
+        Ok(SessionInfo { terminal: Some(terminal), .. }) => {
+            Some(format!("{} is still the terminal of another session", terminal.display()))
+        },
+        Err(_) => None,
+    }
+}
+
+fn read_environ(pid: u32) -> Result<Vec<(OsString, OsString)>, String> {
+    let data = fs::read(format!("/proc/{}/environ", pid)).map_err(|e| format!("cannot be read: {}", e))?;
+    Ok(data
+        .split(|b| *b == 0)
+        .filter_map(|var| {
+            let split = var.iter().position(|b| *b == b'=')?;
+            Some((OsString::from_vec(var[..split].to_vec()), OsString::from_vec(var[split + 1..].to_vec())))
+        })
+        .collect())
+}
+
+// "Uid:\t1000\t1000\t1000\t1000" -> "1000\t1000\t1000\t1000"
+fn status_field<'a>(status: &'a str, name: &str) -> Option<&'a str> {
+    status.lines().find_map(|line| line.strip_prefix(name)?.strip_prefix(':')).map(str::trim)
+}
+
+fn read_credentials(status: &str) -> Result<Credentials, String> {
+    let first = |name: &str| status_field(status, name)?.split_whitespace().next()?.parse().ok();
+    let groups = status_field(status, "Groups")
+        .map(|groups| groups.split_whitespace().filter_map(|g| g.parse().ok()).collect())
+        .unwrap_or_default();
+    match (first("Uid"), first("Gid")) {
+        (Some(uid), Some(gid)) => Ok(Credentials { uid, gid, groups }),
+        _ => Err("cannot be read".to_string()),
+    }
+}
+
+// From /proc/<pid>/limits, which anyone may read, unlike prlimit(2) on another
+// user's process
+fn read_limits(pid: u32) -> Result<Vec<(libc::__rlimit_resource_t, libc::rlimit)>, String> {
+    let text = fs::read_to_string(format!("/proc/{}/limits", pid)).map_err(|e| format!("cannot be read: {}", e))?;
+    let value = |text: &str| match text {
+        "unlimited" => Some(libc::RLIM_INFINITY),
+        n => n.parse().ok(),
+    };
+    LIMITS
+        .iter()
+        .map(|(resource, name)| {
+            let line = text
+                .lines()
+                .find_map(|line| line.strip_prefix("Max ")?.strip_prefix(name))
+                .ok_or_else(|| format!("{} is not listed", name))?;
+            let mut fields = line.split_whitespace();
+            match (fields.next().and_then(value), fields.next().and_then(value)) {
+                (Some(rlim_cur), Some(rlim_max)) => Ok((*resource, libc::rlimit { rlim_cur, rlim_max })),
+                _ => Err(format!("{} cannot be read", name)),
+            }
+        })
+        .collect()
+}
+
+// The terminal device named by the tty_nr field of /proc/<pid>/stat
+fn terminal_path(pid: u32, tty_nr: i32) -> Option<PathBuf> {
+    if tty_nr == 0 {
+        return None;
+    }
+    // Usually one of the standard descriptors points at it
+    let on_stdio = [0, 1, 2]
+        .iter()
+        .filter_map(|fd| fs::read_link(format!("/proc/{}/fd/{}", pid, fd)).ok())
+        .find(|path| path.starts_with("/dev/pts/") || path.starts_with("/dev/tty"));
+    if on_stdio.is_some() {
+        return on_stdio;
+    }
+    let (major, minor) = ((tty_nr >> 8) & 0xfff, (tty_nr & 0xff) | ((tty_nr >> 12) & 0xfff00));
+    match major {
+        136..=143 => Some(PathBuf::from(format!("/dev/pts/{}", (major - 136) * 256 + minor))),
+        4 if minor < 64 => Some(PathBuf::from(format!("/dev/tty{}", minor))),
+        _ => None,
+    }
+}
+
+// The same open file as descriptor `fd` of the process where the kernel lets us
+// have it, or the same path opened again with the same access
+fn capture_stdio(pid: u32, handle: Option<&ProcessHandle>, fd: i32) -> Result<OwnedFd, String> {
+    let target = fs::read_link(format!("/proc/{}/fd/{}", pid, fd)).map_err(|_| "was closed or cannot be read".to_string())?;
+    if let Some(shared) = handle.and_then(|handle| handle.duplicate_fd(fd).ok()) {
+        return Ok(shared);
+    }
+    if !target.is_absolute() || target.as_os_str().as_bytes().ends_with(b" (deleted)") {
+        return Err(format!("{} cannot be opened again", target.display()));
+    }
+    let fdinfo = fs::read_to_string(format!("/proc/{}/fdinfo/{}", pid, fd)).unwrap_or_default();
+    let flags = status_field(&fdinfo, "flags").and_then(|flags| i32::from_str_radix(flags, 8).ok()).unwrap_or(0);
+    let access = flags & libc::O_ACCMODE;
+    OpenOptions::new()
+        .read(access != libc::O_WRONLY)
+        .write(access != libc::O_RDONLY)
+        .append(flags & libc::O_APPEND != 0)
+        .open(&target)
+        .map(OwnedFd::from)
+        .map_err(|e| format!("{} cannot be opened again: {}", target.display(), e))
+}
@@ -0,0 +1,37 @@
+//! Shared building blocks for the Pulse binaries (`pulse`, `pulse1`,
+//! `pulse_graphs` and `get_ps`).
+
+pub mod snapshot;
+pub mod history;
+pub mod replay;
+pub mod filter;
+pub mod alerts;
+pub mod alert_actions;
+pub mod alert_sinks;
+pub mod signal;
+pub mod pause_resume;
+pub mod priority;
+pub mod process_groups;
+pub mod tree_view;
+pub mod launch;
+pub mod health;
+pub mod services;
+pub mod restart;
+pub mod supervisor;
+pub mod stop;
+pub mod columns;
+pub mod exporter;
+pub mod csv_export;
+pub mod json_export;
+pub mod sqlite_export;
+pub mod parquet_export;
+pub mod html_export;
+pub mod export_diff;
+pub mod metrics;
+pub mod help;
+pub mod reptyr;
+pub mod graph;
+pub mod graph_panel;
+pub mod daemon;
+pub mod tui;
+pub mod cli;
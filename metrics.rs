@@ -0,0 +1,255 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use tiny_http::{Header, Response, Server};
+use crate::filter::ProcessFilter;
+use crate::snapshot::{ProcessSnapshot, SystemSummary};
+
+/// Which processes get their own series and where to listen. Processes left out
+/// still count towards the system-wide gauges.
+#[derive(Clone)]
+pub struct MetricsConfig {
+    pub listen: String,
+    /// Only the N busiest processes by CPU; `None` exports all of them
+    pub top: Option<usize>,
+    /// Only processes with one of these names (empty allows all)
+    pub names: Vec<String>,
+    pub filter: Option<ProcessFilter>,
+}
+
+impl MetricsConfig {
+    pub fn new() -> Self {
+        MetricsConfig {
+            listen: "127.0.0.1:9184".to_string(),
+            top: Some(20),
+            names: Vec::new(),
+            filter: None,
+        }
+    }
+
+    /// The processes that get their own series, busiest first
+    pub fn select<'a>(&self, processes: &'a [ProcessSnapshot], physical_cores: usize) -> Vec<&'a ProcessSnapshot> {
+        let mut selected: Vec<&ProcessSnapshot> = processes
+            .iter()
+            // Threads listed as entries of their own are already counted in their process
+            .filter(|p| !p.is_thread)
+            .filter(|p| self.names.is_empty() || self.names.contains(&p.name))
+            .filter(|p| self.filter.as_ref().is_none_or(|f| f.matches(p, physical_cores)))
+            .collect();
+        selected.sort_by(|a, b| b.cpu.total_cmp(&a.cpu).then(a.pid.cmp(&b.pid)));
+        if let Some(top) = self.top {
+            selected.truncate(top);
+        }
+        selected
+    }
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// What a scrape is answered from: the last refresh, already cut down to the selected processes
+struct Sample {
+    processes: Vec<ProcessSnapshot>,
+    total_processes: usize,
+    states: BTreeMap<char, usize>,
+    summary: SystemSummary,
+    cores: Vec<f32>,
+}
+
+/// Serves `/metrics` in the Prometheus text format (or OpenMetrics when the
+/// scraper asks for it) from the figures passed to `update`
+pub struct MetricsExporter {
+    config: MetricsConfig,
+    latest: Arc<Mutex<Option<Sample>>>,
+    server: Arc<Server>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl MetricsExporter {
+    pub fn start(config: MetricsConfig) -> Result<Self, String> {
+        let server =
+            Arc::new(Server::http(&config.listen).map_err(|e| format!("Failed to listen on {}: {}", config.listen, e))?);
+        let latest: Arc<Mutex<Option<Sample>>> = Arc::new(Mutex::new(None));
+
+        let thread_server = server.clone();
+        let thread_latest = latest.clone();
+        let thread = thread::spawn(move || {
+            for request in thread_server.incoming_requests() {
+                let openmetrics = request
+                    .headers()
+                    .iter()
+                    .any(|h| h.field.equiv("Accept") && h.value.as_str().contains("application/openmetrics-text"));
+                let response = match request.url().split('?').next().unwrap_or("") {
+                    "/metrics" => {
+                        let body = match &*thread_latest.lock().unwrap_or_else(|e| e.into_inner()) {
+                            Some(sample) => render(sample, openmetrics),
+                            None => String::new(),
+                        };
+                        let content_type = if openmetrics {
+                            "application/openmetrics-text; version=1.0.0; charset=utf-8"
+                        } else {
+                            "text/plain; version=0.0.4; charset=utf-8"
+                        };
+                        Response::from_string(body).with_header(header("Content-Type", content_type))
+                    },
+                    "/" => Response::from_string("Pulse metrics exporter: see /metrics\n")
+                        .with_header(header("Content-Type", "text/plain; charset=utf-8")),
+                    _ => Response::from_string("Not found\n").with_status_code(404),
+                };
+                let _ = request.respond(response);
+            }
+        });
+
+        Ok(MetricsExporter { config, latest, server, thread: Some(thread) })
+    }
+
+    /// Address actually listened on, e.g. when the port was 0
+    pub fn address(&self) -> String {
+        self.server.server_addr().to_string()
+    }
+
+    /// Publish the figures of the latest refresh
+    pub fn update(&self, processes: &[ProcessSnapshot], summary: &SystemSummary, cores: Vec<f32>) {
+        let mut states = BTreeMap::new();
+        for process in processes.iter().filter(|p| !p.is_thread) {
+            *states.entry(process.state.code()).or_insert(0) += 1;
+        }
+        let sample = Sample {
+            processes: self.config.select(processes, summary.physical_cores).into_iter().cloned().collect(),
+            total_processes: processes.iter().filter(|p| !p.is_thread).count(),
+            states,
+            summary: summary.clone(),
+            cores,
+        };
+        *self.latest.lock().unwrap_or_else(|e| e.into_inner()) = Some(sample);
+    }
+}
+
+impl Drop for MetricsExporter {
+    fn drop(&mut self) {
+        self.server.unblock();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn header(name: &str, value: &str) -> Header {
+    Header::from_bytes(name.as_bytes(), value.as_bytes()).expect("static header is valid")
+}
+
+// Writes one metric family after another
+struct Families {
+    out: String,
+    openmetrics: bool,
+}
+
+impl Families {
+    fn family(&mut self, name: &str, kind: &str, help: &str) {
+        writeln!(self.out, "# HELP {} {}", name, help).unwrap();
+        writeln!(self.out, "# TYPE {} {}", name, kind).unwrap();
+    }
+
+    // Counters are named `<family>_total` in both formats, but Prometheus text
+    // declares the family under the full name
+    fn counter(&mut self, family: &str, help: &str) -> String {
+        let sample = format!("{}_total", family);
+        self.family(if self.openmetrics { family } else { &sample }, "counter", help);
+        sample
+    }
+
+    fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: f64) {
+        self.out.push_str(name);
+        if !labels.is_empty() {
+            let labels: Vec<String> =
+                labels.iter().map(|(key, value)| format!("{}=\"{}\"", key, escape(value))).collect();
+            write!(self.out, "{{{}}}", labels.join(",")).unwrap();
+        }
+        writeln!(self.out, " {}", value).unwrap();
+    }
+}
+
+// Name, help text and how to read the value off a process
+type ProcessMetric = (&'static str, &'static str, fn(&ProcessSnapshot) -> f64);
+
+fn render(sample: &Sample, openmetrics: bool) -> String {
+    let mut f = Families { out: String::new(), openmetrics };
+    let s = &sample.summary;
+
+    f.family("pulse_system_cpu_percent", "gauge", "CPU usage across all CPUs, 0-100.");
+    f.sample("pulse_system_cpu_percent", &[], s.cpu_usage as f64);
+    f.family("pulse_system_cpu_core_percent", "gauge", "CPU usage of each logical CPU, 0-100.");
+    for (core, usage) in sample.cores.iter().enumerate() {
+        f.sample("pulse_system_cpu_core_percent", &[("core", &core.to_string())], *usage as f64);
+    }
+    f.family("pulse_system_physical_cores", "gauge", "Number of physical CPU cores.");
+    f.sample("pulse_system_physical_cores", &[], s.physical_cores as f64);
+    f.family("pulse_system_load_average", "gauge", "Load average over 1, 5 and 15 minutes.");
+    for (period, load) in ["1m", "5m", "15m"].iter().zip(s.load_average) {
+        f.sample("pulse_system_load_average", &[("period", period)], load);
+    }
+    for (name, help, value) in [
+        ("pulse_system_memory_total_bytes", "Total RAM.", s.total_memory),
+        ("pulse_system_memory_used_bytes", "RAM in use.", s.used_memory),
+        ("pulse_system_swap_total_bytes", "Total swap.", s.total_swap),
+        ("pulse_system_swap_used_bytes", "Swap in use.", s.used_swap),
+    ] {
+        f.family(name, "gauge", help);
+        f.sample(name, &[], value as f64);
+    }
+    f.family("pulse_processes", "gauge", "Number of processes by state (ps state letter).");
+    for (state, count) in &sample.states {
+        f.sample("pulse_processes", &[("state", &state.to_string())], *count as f64);
+    }
+    f.family("pulse_exported_processes", "gauge", "Processes with their own series, out of all processes.");
+    f.sample("pulse_exported_processes", &[("selection", "exported")], sample.processes.len() as f64);
+    f.sample("pulse_exported_processes", &[("selection", "total")], sample.total_processes as f64);
+
+    // Per-process families, each labelled by pid, comm and user
+    let labels = |p: &ProcessSnapshot| (p.pid.to_string(), p.name.clone(), p.user.clone());
+    let gauges: [ProcessMetric; 5] = [
+        ("pulse_process_cpu_percent", "CPU usage, 100 per fully used core.", |p| p.cpu as f64),
+        ("pulse_process_resident_memory_bytes", "Resident memory.", |p| p.memory as f64),
+        ("pulse_process_nice", "Nice value.", |p| p.nice as f64),
+        ("pulse_process_threads", "Number of threads.", |p| p.threads as f64),
+        ("pulse_process_start_time_seconds", "Start time since the epoch.", |p| p.start_time as f64),
+    ];
+    for (name, help, value) in gauges {
+        f.family(name, "gauge", help);
+        for p in &sample.processes {
+            let (pid, comm, user) = labels(p);
+            f.sample(name, &[("pid", &pid), ("comm", &comm), ("user", &user)], value(p));
+        }
+    }
+    f.family("pulse_process_state", "gauge", "1 for the current state of the process (ps state letter).");
+    for p in &sample.processes {
+        let (pid, comm, user) = labels(p);
+        let state = p.state.code().to_string();
+        f.sample("pulse_process_state", &[("pid", &pid), ("comm", &comm), ("user", &user), ("state", &state)], 1.0);
+    }
+    let counters: [ProcessMetric; 2] = [
+        ("pulse_process_read_bytes", "Bytes read from storage.", |p| p.read_bytes as f64),
+        ("pulse_process_written_bytes", "Bytes written to storage.", |p| p.written_bytes as f64),
+    ];
+    for (family, help, value) in counters {
+        let name = f.counter(family, help);
+        for p in &sample.processes {
+            let (pid, comm, user) = labels(p);
+            f.sample(&name, &[("pid", &pid), ("comm", &comm), ("user", &user)], value(p));
+        }
+    }
+
+    if openmetrics {
+        f.out.push_str("# EOF\n");
+    }
+    f.out
+}
+
+// Label values may contain anything a process name can
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
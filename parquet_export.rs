@@ -0,0 +1,94 @@
+use std::fs::File;
+use std::path::Path;
+use std::sync::Arc;
+use parquet::basic::Compression;
+use parquet::column::writer::ColumnWriter;
+use parquet::data_type::ByteArray;
+use parquet::file::properties::WriterProperties;
+use parquet::file::writer::SerializedFileWriter;
+use parquet::format::KeyValue;
+use parquet::schema::parser::parse_message_type;
+use serde_json::Value;
+use crate::columns::{ColumnKind, TypedValue};
+use crate::csv_export;
+use crate::exporter::{ExportData, Exporter};
+
+/// Writes the processes as one Parquet row group with a `time` column and one
+/// optional column per export column; the system summary goes in the file's
+/// key-value metadata as `pulse.summary`
+pub struct ParquetExporter;
+
+impl Exporter for ParquetExporter {
+    fn extension(&self) -> &'static str {
+        "parquet"
+    }
+
+    fn export(&self, data: &ExportData, path: &Path) -> Result<String, String> {
+        let mut schema = String::from("message process {\n  required int64 time (TIMESTAMP(MILLIS,true));\n");
+        for column in data.columns {
+            let kind = match column.kind() {
+                ColumnKind::Integer => "int64",
+                ColumnKind::Real => "double",
+                ColumnKind::Text => "binary",
+            };
+            let annotation = if column.kind() == ColumnKind::Text { " (UTF8)" } else { "" };
+            schema.push_str(&format!("  optional {} {}{};\n", kind, column.name(), annotation));
+        }
+        schema.push('}');
+        let schema = Arc::new(parse_message_type(&schema).map_err(|e| e.to_string())?);
+
+        let summary = csv_export::summary_fields(data.time, data.summary, data.processes.len());
+        let properties = WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .set_created_by("pulse".to_string())
+            .set_key_value_metadata(Some(vec![KeyValue::new(
+                "pulse.summary".to_string(),
+                Value::Object(summary).to_string(),
+            )]))
+            .build();
+
+        let file = File::create(path).map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+        let mut writer = SerializedFileWriter::new(file, schema, Arc::new(properties)).map_err(|e| e.to_string())?;
+        let mut group = writer.next_row_group().map_err(|e| e.to_string())?;
+
+        let times = vec![data.time as i64 * 1000; data.processes.len()];
+        let mut column_writer = group.next_column().map_err(|e| e.to_string())?.ok_or("missing time column")?;
+        if let ColumnWriter::Int64ColumnWriter(w) = column_writer.untyped() {
+            w.write_batch(&times, None, None).map_err(|e| e.to_string())?;
+        }
+        column_writer.close().map_err(|e| e.to_string())?;
+
+        for column in data.columns {
+            let values: Vec<TypedValue> = data.processes.iter().map(|p| column.typed(p)).collect();
+            // Definition level 1 for a value, 0 for a null
+            let levels: Vec<i16> = values.iter().map(|v| i16::from(*v != TypedValue::Null)).collect();
+            let mut column_writer = group.next_column().map_err(|e| e.to_string())?.ok_or("missing column")?;
+            let result = match column_writer.untyped() {
+                ColumnWriter::Int64ColumnWriter(w) => {
+                    let present: Vec<i64> =
+                        values.iter().filter_map(|v| if let TypedValue::Integer(n) = v { Some(*n) } else { None }).collect();
+                    w.write_batch(&present, Some(&levels), None)
+                },
+                ColumnWriter::DoubleColumnWriter(w) => {
+                    let present: Vec<f64> =
+                        values.iter().filter_map(|v| if let TypedValue::Real(n) = v { Some(*n) } else { None }).collect();
+                    w.write_batch(&present, Some(&levels), None)
+                },
+                ColumnWriter::ByteArrayColumnWriter(w) => {
+                    let present: Vec<ByteArray> = values
+                        .iter()
+                        .filter_map(|v| if let TypedValue::Text(s) = v { Some(ByteArray::from(s.as_str())) } else { None })
+                        .collect();
+                    w.write_batch(&present, Some(&levels), None)
+                },
+                _ => return Err(format!("unexpected Parquet type for column {}", column.name())),
+            };
+            result.map_err(|e| e.to_string())?;
+            column_writer.close().map_err(|e| e.to_string())?;
+        }
+        group.close().map_err(|e| e.to_string())?;
+        writer.close().map_err(|e| e.to_string())?;
+
+        Ok(format!("Process data exported to {}", path.display()))
+    }
+}
@@ -1,6 +1,8 @@
-use std::process::Command;
-use sysinfo::{Pid, System};
-use std::fs;
+use std::collections::HashMap;
+use nix::sys::signal::Signal;
+use sysinfo::Pid;
+use crate::signal::{self, ProcessHandle, SignalError};
+use crate::snapshot::{read_stat, ProcessState};
 
 // Define actions that can be performed on processes
 #[derive(Clone)]
@@ -11,12 +13,14 @@ pub enum ProcessAction {
 
 pub struct ProcessController {
     paused_processes: Vec<Pid>,
+    handles: HashMap<Pid, ProcessHandle>,
 }
 
 impl ProcessController {
     pub fn new() -> Self {
         ProcessController {
             paused_processes: Vec::new(),
+            handles: HashMap::new(),
         }
     }
 
@@ -30,116 +34,131 @@ impl ProcessController {
 
     pub fn remove_terminated_process(&mut self, pid: &Pid) {
         self.paused_processes.retain(|p| p != pid);
+        self.handles.remove(pid);
     }
 
     // Check if a process is a zombie
     fn is_zombie(pid: Pid) -> bool {
-        let stat_path = format!("/proc/{}/stat", pid);
-        match fs::read_to_string(&stat_path) {
-            Ok(content) => {
-                let parts: Vec<&str> = content.split_whitespace().collect();
-                if let Some(state) = parts.get(2) {
-                    return *state == "Z";
-                }
-                false
-            },
-            Err(_) => false,
+        read_stat(pid.as_u32())
+            .map(|stat| ProcessState::from_code(stat.state) == ProcessState::Zombie)
+            .unwrap_or(false)
+    }
+
+    /// Pause or resume `pid`, if it is still the process a snapshot saw start at
+    /// `start_time` (0 when not known; see `ProcessHandle::open_seen`)
+    pub fn control_process(&mut self, pid: Pid, start_time: u64, action: ProcessAction) -> Result<(), String> {
+        if Self::is_zombie(pid) {
+            return Err(format!("Process {} is a zombie and cannot be paused or resumed.", pid));
         }
+
+        self.signal_process(pid, start_time, &action).map_err(|e| {
+            format!(
+                "Failed to {} process {}: {}",
+                match action {
+                    ProcessAction::Pause => "pause",
+                    ProcessAction::Resume => "resume",
+                },
+                pid,
+                e
+            )
+        })
     }
 
-    pub fn control_process(&mut self, pid: Pid, action: ProcessAction) -> Result<(), String> {
+    /// Pause the process behind `handle`, e.g. one held since an alert fired
+    pub fn pause(&mut self, handle: ProcessHandle) -> Result<(), String> {
+        let pid = Pid::from_u32(handle.pid());
         if Self::is_zombie(pid) {
             return Err(format!("Process {} is a zombie and cannot be paused or resumed.", pid));
         }
+        self.stop(handle).map_err(|e| format!("Failed to pause process {}: {}", pid, e))
+    }
 
-        let signal = match action {
-            ProcessAction::Pause => {
-                if !self.paused_processes.contains(&pid) {
-                    self.paused_processes.push(pid);
-                }
-                "SIGSTOP"
-            },
+    // Deliver SIGSTOP/SIGCONT and update the paused list only once the signal went through
+    fn signal_process(&mut self, pid: Pid, start_time: u64, action: &ProcessAction) -> Result<(), SignalError> {
+        match action {
+            ProcessAction::Pause => self.stop(ProcessHandle::open_seen(pid.as_u32(), start_time)?)?,
             ProcessAction::Resume => {
-                self.paused_processes.retain(|&p| p != pid);
-                "SIGCONT"
-            },
-        };
-
-        let result = Command::new("kill")
-            .arg(format!("-{}", signal))
-            .arg(pid.to_string())
-            .status();
-
-        match result {
-            Ok(status) => {
-                if status.success() {
-                    Ok(())
-                } else {
-                    // Rollback state change
-                    match action {
-                        ProcessAction::Pause => {
-                            self.paused_processes.retain(|&p| p != pid);
-                        },
-                        ProcessAction::Resume => {
-                            if !self.paused_processes.contains(&pid) {
-                                self.paused_processes.push(pid);
-                            }
+                let result = match self.handles.get(&pid) {
+                    Some(handle) => handle.send(Signal::SIGCONT),
+                    None => signal::send_seen(pid.as_u32(), start_time, libc::SIGCONT),
+                };
+                match result {
+                    Ok(()) => self.remove_terminated_process(&pid),
+                    Err(e) => {
+                        // A process that is gone can no longer be paused
+                        if e.is_gone() {
+                            self.remove_terminated_process(&pid);
                         }
-                    }
-                    Err(format!(
-                        "Failed to {} process {}. Process might not exist or you may not have permission.",
-                        match action {
-                            ProcessAction::Pause => "pause",
-                            ProcessAction::Resume => "resume",
-                        },
-                        pid
-                    ))
-                }
-            },
-            Err(e) => {
-                // Rollback state change
-                match action {
-                    ProcessAction::Pause => {
-                        self.paused_processes.retain(|&p| p != pid);
+                        return Err(e);
                     },
-                    ProcessAction::Resume => {
-                        if !self.paused_processes.contains(&pid) {
-                            self.paused_processes.push(pid);
-                        }
-                    }
                 }
-                Err(format!("Error: {}", e))
             },
         }
+        Ok(())
+    }
+
+    fn stop(&mut self, handle: ProcessHandle) -> Result<(), SignalError> {
+        handle.send(Signal::SIGSTOP)?;
+        let pid = Pid::from_u32(handle.pid());
+        if !self.paused_processes.contains(&pid) {
+            self.paused_processes.push(pid);
+        }
+        // Keep the handle so the resume reaches this exact process even if the PID is reused
+        self.handles.insert(pid, handle);
+        Ok(())
     }
 
-    pub fn toggle_process(&mut self, pid: &Pid) -> Result<ProcessAction, String> {
+    pub fn toggle_process(&mut self, pid: &Pid, start_time: u64) -> Result<ProcessAction, String> {
         if self.is_paused(pid) {
-            self.control_process(*pid, ProcessAction::Resume)?;
+            self.control_process(*pid, start_time, ProcessAction::Resume)?;
             Ok(ProcessAction::Resume)
         } else {
-            self.control_process(*pid, ProcessAction::Pause)?;
+            self.control_process(*pid, start_time, ProcessAction::Pause)?;
             Ok(ProcessAction::Pause)
         }
     }
 
     pub fn resume_all(&mut self) {
         let paused_pids = self.paused_processes.clone();
-        let mut pids_to_remove = Vec::new();
-
-        for &pid in paused_pids.iter() {
-            match self.control_process(pid, ProcessAction::Resume) {
-                Ok(_) => {},
-                Err(e) => {
-                    if e.contains("might not exist") {
-                        pids_to_remove.push(pid);
-                    }
-                }
-            }
-        }
 
-        for pid in pids_to_remove {
-            self.remove_terminated_process(&pid);
+        for pid in paused_pids {
+            // Failed resumes of processes that are gone drop out of the list on their own
+            let _ = self.signal_process(pid, 0, &ProcessAction::Resume);
         }
     }
 }
+
+impl Default for ProcessController {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+    use crate::snapshot::Collector;
+
+    #[test]
+    fn does_not_pause_a_reused_pid() {
+        let mut child = Command::new("sleep").arg("30").spawn().unwrap();
+        let pid = Pid::from_u32(child.id());
+        let mut collector = Collector::new();
+        collector.refresh();
+        let start_time = collector.snapshot().iter().find(|p| p.pid == child.id()).unwrap().start_time;
+
+        let mut controller = ProcessController::new();
+        let error = controller.control_process(pid, start_time - 60, ProcessAction::Pause).unwrap_err();
+        assert!(error.contains("now belongs to a different process"), "{}", error);
+        assert!(!controller.is_paused(&pid));
+        assert_ne!(read_stat(child.id()).unwrap().state, 'T');
+
+        controller.control_process(pid, start_time, ProcessAction::Pause).unwrap();
+        assert!(controller.is_paused(&pid));
+        controller.resume_all();
+        assert!(!controller.is_paused(&pid));
+        child.kill().unwrap();
+        child.wait().unwrap();
+    }
+}
@@ -1,24 +1,36 @@
 use libc::{getpriority, PRIO_PROCESS};
 use nix::errno::Errno;
 use libc::setpriority;
+use crate::signal::ProcessHandle;
 
 /// Change this process’s nice value.  
-/// Returns Ok(msg) on success or Err(errmsg) on failure.
-pub fn set_priority(pid: i32, nice: i32) -> Result<String, String> {
+/// Returns Ok(msg) on success, or the errno (e.g. EPERM to lower it without
+/// privileges, ESRCH when the process is gone).
+pub fn set_priority(pid: i32, nice: i32) -> Result<String, Errno> {
     // SAFETY: setpriority is a simple libc call
     let ret = unsafe { setpriority(PRIO_PROCESS, pid as u32, nice) };
     if ret < 0 {
-        let e = Errno::last();
-        Err(format!("{} (errno {})", e.desc(), e as i32))
+        Err(Errno::last())
     } else {
         Ok(format!("Nice set to {:+} for PID {}", nice, pid))
     }
 }
 
+/// `set_priority` for the process behind `handle`: ESRCH once it has exited,
+/// even when its PID already belongs to another process
+pub fn renice(handle: &ProcessHandle, nice: i32) -> Result<String, Errno> {
+    if !handle.is_alive() {
+        return Err(Errno::ESRCH);
+    }
+    set_priority(handle.pid() as i32, nice)
+}
+
 pub fn get_nice_value(pid: i32) -> Result<i32, String> {
+    // -1 is also a valid nice value, so only errno tells a failure apart
+    Errno::clear();
     // SAFETY: getpriority is a simple libc call
     let ret = unsafe { getpriority(PRIO_PROCESS, pid as u32) };
-    if ret < 0 {
+    if ret == -1 && Errno::last() != Errno::UnknownErrno {
         let e = Errno::last();
         Err(format!("{} (errno {})", e.desc(), e as i32))
     } else {
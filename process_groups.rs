@@ -1,7 +1,9 @@
 use std::collections::{HashMap, HashSet};
 use std::time::{Duration, Instant};
-use sysinfo::{ Pid, System};
+use sysinfo::Pid;
 use crate::pause_resume::{ProcessController, ProcessAction};
+use crate::signal::ProcessHandle;
+use crate::snapshot::ProcessSnapshot;
 
 /// Represents a process group with a parent and its children in a tree structure
 #[derive(Clone)]
@@ -51,26 +53,26 @@ impl ProcessGroupManager {
     }
 
     /// Force rebuild the process tree cache
-    pub fn force_update(&mut self, system: &System) {
-        self.rebuild_process_tree(system);
+    pub fn force_update(&mut self, processes: &[ProcessSnapshot]) {
+        self.rebuild_process_tree(processes);
         self.last_update = Instant::now();
     }
 
     /// Build a hierarchical tree of processes
-    fn rebuild_process_tree(&mut self, system: &System) {
+    fn rebuild_process_tree(&mut self, processes: &[ProcessSnapshot]) {
         // Map to store process information by PID
         let mut process_map: HashMap<Pid, (String, Vec<Pid>)> = HashMap::new();
         
         // First pass: collect all processes and their names
-        for (pid, process) in system.processes() {
-            process_map.insert(*pid, (process.name().to_string(), Vec::new()));
+        for process in processes {
+            process_map.insert(process.sys_pid(), (process.name.clone(), Vec::new()));
         }
         
         // Second pass: build parent-child relationships
-        for (pid, process) in system.processes() {
-            if let Some(ppid) = process.parent() {
-                if let Some((_, children)) = process_map.get_mut(&ppid) {
-                    children.push(*pid);
+        for process in processes {
+            if let Some(ppid) = process.ppid {
+                if let Some((_, children)) = process_map.get_mut(&Pid::from_u32(ppid)) {
+                    children.push(process.sys_pid());
                 }
             }
         }
@@ -78,14 +80,14 @@ impl ProcessGroupManager {
         // Build tree structure starting with root processes (those with no parent or parent not in our list)
         let mut root_processes = Vec::new();
         
-        for (pid, process) in system.processes() {
-            let is_root = match process.parent() {
-                Some(ppid) => !process_map.contains_key(&ppid),
+        for process in processes {
+            let is_root = match process.ppid {
+                Some(ppid) => !process_map.contains_key(&Pid::from_u32(ppid)),
                 None => true,
             };
             
             if is_root {
-                root_processes.push(*pid);
+                root_processes.push(process.sys_pid());
             }
         }
         
@@ -99,7 +101,7 @@ impl ProcessGroupManager {
             .collect();
             
         // Also update the flat group cache for backward compatibility
-        self.rebuild_flat_groups(system);
+        self.rebuild_flat_groups(processes);
     }
     
     // Helper method to build a process node recursively
@@ -127,22 +129,22 @@ impl ProcessGroupManager {
     }
     
     // Rebuild flat groups (for backward compatibility)
-    fn rebuild_flat_groups(&mut self, system: &System) {
+    fn rebuild_flat_groups(&mut self, processes: &[ProcessSnapshot]) {
         let mut parent_map: HashMap<Pid, Vec<Pid>> = HashMap::new();
         let mut process_names: HashMap<Pid, String> = HashMap::new();
         
         // First pass: collect all processes and their names
-        for (pid, process) in system.processes() {
-            process_names.insert(*pid, process.name().to_string());
+        for process in processes {
+            process_names.insert(process.sys_pid(), process.name.clone());
         }
         
         // Second pass: build parent-child relationships
-        for (pid, process) in system.processes() {
-            if let Some(ppid) = process.parent() {
-                parent_map.entry(ppid).or_insert_with(Vec::new).push(*pid);
+        for process in processes {
+            if let Some(ppid) = process.ppid {
+                parent_map.entry(Pid::from_u32(ppid)).or_default().push(process.sys_pid());
             } else {
                 // Process with no parent (usually init processes)
-                parent_map.entry(*pid).or_insert_with(Vec::new);
+                parent_map.entry(process.sys_pid()).or_default();
             }
         }
         
@@ -159,29 +161,34 @@ impl ProcessGroupManager {
         }
         
         // Sort groups by parent PID
-        groups.sort_by(|a, b| a.parent_pid.cmp(&b.parent_pid));
+        groups.sort_by_key(|a| a.parent_pid);
         
         self.flat_groups_cache = groups;
     }
 
     /// Get the process tree, updating it only if the cache is stale
-    pub fn get_process_tree(&mut self, system: &System) -> &Vec<ProcessNode> {
+    pub fn get_process_tree(&mut self, processes: &[ProcessSnapshot]) -> &Vec<ProcessNode> {
         if self.last_update.elapsed() >= self.update_interval {
-            self.rebuild_process_tree(system);
+            self.rebuild_process_tree(processes);
             self.last_update = Instant::now();
         }
         &self.process_tree_cache
     }
 
     /// Build a map of all processes grouped by their parent PIDs (uses cache)
-    pub fn build_process_groups(&mut self, system: &System) -> &Vec<ProcessGroup> {
+    pub fn build_process_groups(&mut self, processes: &[ProcessSnapshot]) -> &Vec<ProcessGroup> {
         if self.last_update.elapsed() >= self.update_interval {
-            self.rebuild_process_tree(system);
+            self.rebuild_process_tree(processes);
             self.last_update = Instant::now();
         }
         &self.flat_groups_cache
     }
     
+    /// The tree as of the last update, without rebuilding it
+    pub fn process_tree(&self) -> &[ProcessNode] {
+        &self.process_tree_cache
+    }
+
     /// Format and print the process tree in a Linux-like tree view
     pub fn format_process_tree(&self) -> String {
         let mut result = String::new();
@@ -223,10 +230,10 @@ impl ProcessGroupManager {
     
     /// Get a flattened list of PIDs for a specific parent, including the parent itself
     /// Uses cached data when available
-    pub fn get_group_pids(&mut self, system: &System, parent_pid: Pid) -> Vec<Pid> {
+    pub fn get_group_pids(&mut self, processes: &[ProcessSnapshot], parent_pid: Pid) -> Vec<Pid> {
         // Ensure cache is up to date
         if self.last_update.elapsed() >= self.update_interval {
-            self.rebuild_process_tree(system);
+            self.rebuild_process_tree(processes);
             self.last_update = Instant::now();
         }
         
@@ -248,12 +255,10 @@ impl ProcessGroupManager {
             
             checked_pids.insert(current_pid);
             
-            for (pid, process) in system.processes() {
-                if let Some(ppid) = process.parent() {
-                    if ppid == current_pid {
-                        result.push(*pid);
-                        pids_to_check.push(*pid);
-                    }
+            for process in processes {
+                if process.ppid == Some(current_pid.as_u32()) {
+                    result.push(process.sys_pid());
+                    pids_to_check.push(process.sys_pid());
                 }
             }
         }
@@ -264,7 +269,7 @@ impl ProcessGroupManager {
     // Helper to find PIDs in the cached tree
     fn find_pids_in_tree(&self, target_pid: Pid) -> Option<Vec<Pid>> {
         // Create a function to search for the node with the target PID
-        fn find_node<'a>(nodes: &'a [ProcessNode], target_pid: Pid) -> Option<&'a ProcessNode> {
+        fn find_node(nodes: &[ProcessNode], target_pid: Pid) -> Option<&ProcessNode> {
             for node in nodes {
                 if node.pid == target_pid {
                     return Some(node);
@@ -295,21 +300,9 @@ impl ProcessGroupManager {
     }
     
     /// Control (pause/resume) all processes in a group based on the parent PID
-    pub fn control_group(&mut self, system: &System, parent_pid: Pid, action: ProcessAction) -> Result<usize, String> {
-        let group_pids = self.get_group_pids(system, parent_pid);
-        let mut success_count = 0;
-        
-        for pid in group_pids {
-            // Skip if process doesn't exist anymore
-            if system.process(pid).is_none() {
-                continue;
-            }
-            
-            match self.process_controller.control_process(pid, action.clone()) {
-                Ok(_) => success_count += 1,
-                Err(_) => {} // Continue with other processes even if one fails
-            }
-        }
+    pub fn control_group(&mut self, processes: &[ProcessSnapshot], parent_pid: Pid, action: ProcessAction) -> Result<usize, String> {
+        let group_pids = self.get_group_pids(processes, parent_pid);
+        let success_count = self.control_pids(processes, &group_pids, action);
         
         if success_count > 0 {
             Ok(success_count)
@@ -317,15 +310,41 @@ impl ProcessGroupManager {
             Err("Failed to control any process in the group".to_string())
         }
     }
+
+    /// Pause the process behind `handle` (e.g. held since an alert fired) and
+    /// then its descendants
+    pub fn pause_group_of(&mut self, processes: &[ProcessSnapshot], handle: ProcessHandle) -> Result<usize, String> {
+        let root = Pid::from_u32(handle.pid());
+        self.process_controller.pause(handle)?;
+        let descendants: Vec<Pid> = self.get_group_pids(processes, root).into_iter().filter(|&pid| pid != root).collect();
+        Ok(1 + self.control_pids(processes, &descendants, ProcessAction::Pause))
+    }
+
+    // Pause or resume each of `pids` as seen in `processes`; returns how many went through
+    fn control_pids(&mut self, processes: &[ProcessSnapshot], pids: &[Pid], action: ProcessAction) -> usize {
+        let mut success_count = 0;
+        for &pid in pids {
+            // Skip if process doesn't exist anymore
+            let Some(process) = processes.iter().find(|p| p.pid == pid.as_u32()) else {
+                continue;
+            };
+            
+            // Continue with other processes even if one fails
+            if self.process_controller.control_process(pid, process.start_time, action.clone()).is_ok() {
+                success_count += 1;
+            }
+        }
+        success_count
+    }
     
     /// Check if a process group is paused (true if all processes are paused)
-    pub fn is_group_paused(&mut self, system: &System, parent_pid: Pid) -> bool {
-        let group_pids = self.get_group_pids(system, parent_pid);
+    pub fn is_group_paused(&mut self, processes: &[ProcessSnapshot], parent_pid: Pid) -> bool {
+        let group_pids = self.get_group_pids(processes, parent_pid);
         let mut all_paused = true;
         let mut any_process_exists = false;
         
         for pid in group_pids {
-            if let Some(_) = system.process(pid) {
+            if processes.iter().any(|p| p.pid == pid.as_u32()) {
                 any_process_exists = true;
                 if !self.process_controller.is_paused(&pid) {
                     all_paused = false;
@@ -347,31 +366,40 @@ impl ProcessGroupManager {
         self.process_controller.remove_terminated_process(pid);
     }
 
+    /// Resume every process paused through a group operation
+    pub fn resume_all(&mut self) {
+        self.process_controller.resume_all();
+    }
+
     /// Resume all processes in a group
-    pub fn resume_group(&mut self, system: &System, parent_pid: Pid) -> bool {
-        self.control_group(system, parent_pid, ProcessAction::Resume).is_ok()
+    pub fn resume_group(&mut self, processes: &[ProcessSnapshot], parent_pid: Pid) -> bool {
+        self.control_group(processes, parent_pid, ProcessAction::Resume).is_ok()
     }
 
     /// Pause all processes in a group
-    pub fn pause_group(&mut self, system: &System, parent_pid: Pid) -> bool {
-        self.control_group(system, parent_pid, ProcessAction::Pause).is_ok()
+    pub fn pause_group(&mut self, processes: &[ProcessSnapshot], parent_pid: Pid) -> bool {
+        self.control_group(processes, parent_pid, ProcessAction::Pause).is_ok()
     }
 
     /// Toggle pause/resume for a process group
-    pub fn toggle_process_group(&mut self, system: &System, parent_pid: Pid) -> Result<String, String> {
-        if self.is_group_paused(system, parent_pid) {
-            if self.resume_group(system, parent_pid) {
+    pub fn toggle_process_group(&mut self, processes: &[ProcessSnapshot], parent_pid: Pid) -> Result<String, String> {
+        if self.is_group_paused(processes, parent_pid) {
+            if self.resume_group(processes, parent_pid) {
                 Ok(format!("Resumed group of PID {}", parent_pid))
             } else {
                 Err(format!("Failed to resume group of PID {}", parent_pid))
             }
+        } else if self.pause_group(processes, parent_pid) {
+            Ok(format!("Paused group of PID {}", parent_pid))
         } else {
-            if self.pause_group(system, parent_pid) {
-                Ok(format!("Paused group of PID {}", parent_pid))
-            } else {
-                Err(format!("Failed to pause group of PID {}", parent_pid))
-            }
+            Err(format!("Failed to pause group of PID {}", parent_pid))
         }
     }
 
 }
+
+impl Default for ProcessGroupManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,272 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use serde_json::{Map, Value};
+use sysinfo::System;
+use crate::csv_export;
+use crate::export_diff::{self, ImportedExport};
+use crate::history::{self, format_time, History, HistoryRecord};
+use crate::snapshot::{ProcessSnapshot, SystemSummary};
+use crate::sqlite_export;
+
+// How far a step looks ahead for the next sample when some are missing
+const STEP_SEARCH: u64 = 3600;
+
+/// The processes and the system at one recorded moment
+#[derive(Clone)]
+pub struct ReplayFrame {
+    /// Seconds since the epoch
+    pub time: u64,
+    pub processes: Vec<ProcessSnapshot>,
+    pub summary: SystemSummary,
+}
+
+enum Source {
+    /// Read from the metrics history as the replay moves
+    History(Box<History>),
+    /// Loaded from exports up front, oldest first
+    Frames(Vec<ReplayFrame>, usize),
+}
+
+/// Recorded snapshots the monitor shows in place of live data, moved back and
+/// forth in time. Nothing is collected from the running system.
+pub struct Replay {
+    source: Source,
+    frame: ReplayFrame,
+    physical_cores: usize,
+}
+
+impl Replay {
+    /// Replay the metrics history, starting at the sample closest to `time`, or
+    /// at the most recent one
+    pub fn from_history(dir: &Path, time: Option<u64>) -> Result<Self, String> {
+        let mut history = History::open(dir)?;
+        let physical_cores = physical_cores();
+        let time = match time {
+            Some(time) => time,
+            None => history.latest().ok_or_else(|| format!("No history recorded in {}", dir.display()))?,
+        };
+        let record = history.at(time).ok_or_else(|| format!("No history recorded at {}", format_time(time)))?;
+        let frame = record_frame(&record, physical_cores);
+        Ok(Replay { source: Source::History(Box::new(history)), frame, physical_cores })
+    }
+
+    /// Replay exported snapshots: JSON and CSV exports (a snapshot each), JSON
+    /// streams and SQLite exports (a snapshot per interval or export)
+    pub fn from_files(paths: &[PathBuf]) -> Result<Self, String> {
+        let physical_cores = physical_cores();
+        let mut frames = Vec::new();
+        for path in paths {
+            frames.extend(load_frames(path, physical_cores)?);
+        }
+        frames.sort_by_key(|frame| frame.time);
+        let frame = frames.first().cloned().ok_or("no snapshots found")?;
+        Ok(Replay { source: Source::Frames(frames, 0), frame, physical_cores })
+    }
+
+    pub fn frame(&self) -> &ReplayFrame {
+        &self.frame
+    }
+
+    /// e.g. "3/12" when replaying exports; empty for the history
+    pub fn position(&self) -> String {
+        match &self.source {
+            Source::History(_) => String::new(),
+            Source::Frames(frames, index) => format!("{}/{}", index + 1, frames.len()),
+        }
+    }
+
+    /// Move `steps` snapshots forward (or back, when negative). Returns false
+    /// when there is nothing further that way.
+    pub fn step(&mut self, steps: i64) -> bool {
+        let mut moved = false;
+        for _ in 0..steps.unsigned_abs() {
+            let next = match &mut self.source {
+                Source::History(history) => next_record(history, self.frame.time, steps > 0)
+                    .map(|record| record_frame(&record, self.physical_cores)),
+                Source::Frames(frames, index) => {
+                    let next = if steps > 0 { index.checked_add(1) } else { index.checked_sub(1) };
+                    match next.filter(|next| *next < frames.len()) {
+                        Some(next) => {
+                            *index = next;
+                            Some(frames[next].clone())
+                        },
+                        None => None,
+                    }
+                },
+            };
+            match next {
+                Some(frame) => {
+                    self.frame = frame;
+                    moved = true;
+                },
+                None => break,
+            }
+        }
+        moved
+    }
+
+    /// Jump to the snapshot at or just before `time`, or the first one after it
+    pub fn seek(&mut self, time: u64) -> Result<(), String> {
+        self.frame = match &mut self.source {
+            Source::History(history) => {
+                let record = history.at(time).ok_or_else(|| format!("No history recorded at {}", format_time(time)))?;
+                record_frame(&record, self.physical_cores)
+            },
+            Source::Frames(frames, index) => {
+                *index = frames.iter().rposition(|frame| frame.time <= time).unwrap_or(0);
+                frames[*index].clone()
+            },
+        };
+        Ok(())
+    }
+
+    /// The snapshots between `from` and `to` as history records, for the graphs
+    pub fn records(&mut self, from: u64, to: u64) -> Vec<HistoryRecord> {
+        match &mut self.source {
+            Source::History(history) => history.range(from, to, to.saturating_sub(from) as usize),
+            Source::Frames(frames, _) => frames
+                .iter()
+                .filter(|frame| frame.time >= from && frame.time <= to)
+                .map(|frame| HistoryRecord::from_snapshot(frame.time, &frame.processes, &frame.summary, usize::MAX))
+                .collect(),
+        }
+    }
+}
+
+fn physical_cores() -> usize {
+    System::new().physical_core_count().unwrap_or(1)
+}
+
+fn record_frame(record: &HistoryRecord, physical_cores: usize) -> ReplayFrame {
+    ReplayFrame {
+        time: record.system.time,
+        processes: record.to_snapshots(),
+        summary: record.summary(physical_cores),
+    }
+}
+
+// The closest sample after (or before) `time`, skipping slots that were not recorded
+fn next_record(history: &mut History, time: u64, forward: bool) -> Option<HistoryRecord> {
+    let interval = history.interval();
+    let end = history::now();
+    (1..=STEP_SEARCH / interval).find_map(|n| {
+        let candidate = if forward { time + n * interval } else { time.checked_sub(n * interval)? };
+        if candidate > end {
+            return None;
+        }
+        history
+            .at(candidate)
+            .filter(|record| if forward { record.system.time > time } else { record.system.time < time })
+    })
+}
+
+// Errors name the file
+fn load_frames(path: &Path, physical_cores: usize) -> Result<Vec<ReplayFrame>, String> {
+    let in_file = |e: String| format!("{}: {}", path.display(), e);
+    let extension = path.extension().map(|e| e.to_string_lossy().to_lowercase()).unwrap_or_default();
+    if matches!(extension.as_str(), "db" | "sqlite" | "sqlite3") {
+        return Ok(sqlite_export::read_snapshots(path)
+            .map_err(in_file)?
+            .into_iter()
+            .map(|(snapshot, processes)| ReplayFrame {
+                time: snapshot.get("time").and_then(Value::as_u64).unwrap_or(0),
+                processes: processes.into_iter().map(|row| export_diff::to_snapshot(&export_diff::import_row(row))).collect(),
+                summary: csv_export::summary_from_fields(&snapshot, physical_cores),
+            })
+            .collect());
+    }
+    let text = fs::read_to_string(path).map_err(|e| in_file(e.to_string()))?;
+    if extension == "ndjson" || text.trim_start().starts_with('{') {
+        return stream_frames(&text, physical_cores).map_err(in_file);
+    }
+
+    // A single export; its time and summary come from the sidecar when there is one
+    let export = ImportedExport::load(path)?;
+    let sidecar = read_sidecar(path);
+    let modified = fs::metadata(path)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |d| d.as_secs());
+    Ok(vec![ReplayFrame {
+        time: sidecar.get("time").and_then(Value::as_u64).unwrap_or(modified),
+        processes: export.processes.iter().map(export_diff::to_snapshot).collect(),
+        summary: csv_export::summary_from_fields(&sidecar, physical_cores),
+    }])
+}
+
+// `NAME.summary.json` or `NAME.summary.csv` next to a CSV export; empty when neither exists
+fn read_sidecar(path: &Path) -> Map<String, Value> {
+    if let Ok(text) = fs::read_to_string(csv_export::summary_path(path, "json")) {
+        return serde_json::from_str(&text).unwrap_or_default();
+    }
+    let Ok(text) = fs::read_to_string(csv_export::summary_path(path, "csv")) else {
+        return Map::new();
+    };
+    let delimiter = [',', '\t', ';', '|'].into_iter().find(|d| text.contains(*d)).unwrap_or(',');
+    match csv_export::read_records(&text, delimiter).unwrap_or_default().as_slice() {
+        [names, values, ..] => names
+            .iter()
+            .zip(values)
+            .map(|(name, value)| {
+                let value = match value.parse::<i64>() {
+                    Ok(n) => Value::from(n),
+                    Err(_) => value.parse::<f64>().map(Value::from).unwrap_or_else(|_| Value::String(value.clone())),
+                };
+                (name.clone(), value)
+            })
+            .collect(),
+        _ => Map::new(),
+    }
+}
+
+// Rebuild the snapshot of every interval of `export --stream`. An interval of
+// `sample` lines replaces the processes; `--diff` intervals update them.
+fn stream_frames(text: &str, physical_cores: usize) -> Result<Vec<ReplayFrame>, String> {
+    let mut frames: Vec<ReplayFrame> = Vec::new();
+    let mut processes: Map<String, Value> = Map::new();
+    let mut time = None;
+    let mut full = true;
+    let finish = |frames: &mut Vec<ReplayFrame>, time: u64, processes: &Map<String, Value>| {
+        let processes = processes.values().filter_map(Value::as_object).map(export_diff::to_snapshot).collect();
+        let summary = csv_export::summary_from_fields(&Map::new(), physical_cores);
+        frames.push(ReplayFrame { time, processes, summary });
+    };
+
+    for (index, line) in text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+        let row: Map<String, Value> =
+            serde_json::from_str(line).map_err(|e| format!("line {}: {}", index + 1, e))?;
+        let line_time = row.get("time").and_then(Value::as_u64).ok_or(format!("line {}: no time", index + 1))?;
+        let event = row.get("event").and_then(Value::as_str).unwrap_or("sample").to_string();
+        if time != Some(line_time) {
+            if let Some(time) = time {
+                finish(&mut frames, time, &processes);
+            }
+            time = Some(line_time);
+            full = false;
+        }
+        let process = export_diff::import_row(row);
+        let pid = process.get("pid").and_then(Value::as_u64).unwrap_or(0).to_string();
+        match event.as_str() {
+            "sample" => {
+                // The first sample of an interval starts the snapshot afresh
+                if !full {
+                    processes.clear();
+                    full = true;
+                }
+                processes.insert(pid, Value::Object(process));
+            },
+            "exited" => {
+                processes.remove(&pid);
+            },
+            _ => {
+                processes.insert(pid, Value::Object(process));
+            },
+        }
+    }
+    if let Some(time) = time {
+        finish(&mut frames, time, &processes);
+    }
+    Ok(frames)
+}
@@ -1,682 +1,247 @@
-use std::process::{Command, Stdio};
-use sysinfo::{Pid, System};
-use std::{thread, time::Duration, fs, env};
-use std::path::Path;
-
-pub enum RestartResult {
-    Success,
-    KillFailed,
-    NotFound,
-    RestartFailed,
-    NotRunning,
-    NoExecutable,
-    Failed,
-}
-
-pub struct ProcessRestarter {
-    system: System,
+use std::fmt;
+use sysinfo::{Pid, ProcessStatus};
+use crate::health::HealthCheck;
+use crate::launch::{Attribute, LaunchSpec};
+use crate::process_groups::ProcessGroupManager;
+use crate::services::ServiceManagers;
+use crate::signal;
+use crate::snapshot::Collector;
+use crate::stop::{self, StopPolicies};
+
+/// Why a process was not restarted
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RestartError {
+    NotFound(u32),
+    /// A zombie, which only its parent can clear
+    NotRunning(u32),
+    /// The process could not be read, e.g. a kernel thread without a command line
+    NoCommandLine(u32, String),
+    /// Pulse would have to stop itself before starting the copy
+    OwnProcess(u32),
+    KillFailed(u32),
+    /// The process was stopped but did not come back healthy; why, for each attempt
+    Unhealthy(u32, Vec<String>),
+    /// The service manager that owns the process refused or failed
+    ServiceFailed(u32, String),
 }
 
-impl ProcessRestarter {
-    pub fn new() -> Self {
-        ProcessRestarter {
-            system: System::new_all(),
+impl fmt::Display for RestartError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RestartError::NotFound(pid) => write!(f, "Process {} not found", pid),
+            RestartError::NotRunning(pid) => write!(f, "Process {} is not running", pid),
+            RestartError::NoCommandLine(_, e) => write!(f, "{}", e),
+            RestartError::OwnProcess(pid) => write!(f, "Process {} is Pulse itself", pid),
+            RestartError::KillFailed(pid) => write!(f, "Failed to kill process {}", pid),
+            RestartError::Unhealthy(pid, attempts) => {
+                write!(f, "Process {} was stopped but did not come back healthy: {}", pid, attempts.join("; "))
+            },
+            RestartError::ServiceFailed(pid, e) => write!(f, "Process {}: {}", pid, e),
         }
     }
+}
 
-    pub fn restart_process(&mut self, pid: Pid) -> RestartResult {
-        // Refresh the system info to get the latest process data
-        self.system.refresh_all();
-        
-        // Check if process exists and get a snapshot of its details
-        let process = match self.system.process(pid) {
-            Some(p) => p,
-            None => return RestartResult::NotFound,
-        };
-
-        if process.status().to_string() != "Run" {
-            return RestartResult::NotRunning;
-        }
+impl std::error::Error for RestartError {}
+
+/// How a restarted process came back
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RestartMethod {
+    /// Started again by Pulse from what was read from `/proc`
+    Respawned { new_pid: u32 },
+    /// Every attempt with the new executable failed, so the one that was
+    /// running before was started instead
+    RolledBack { new_pid: u32 },
+    /// Restarted by the service manager that owns it; the new PID is known when
+    /// the manager reports one
+    ServiceManager { manager: &'static str, service: String, new_pid: Option<u32> },
+}
 
+/// What a restart did, and which parts of how the process was started it
+/// carried over
+#[derive(Debug, Clone)]
+pub struct RestartReport {
+    pub pid: u32,
+    pub method: RestartMethod,
+    pub preserved: Vec<Attribute>,
+    /// What the new process does not share with the old one, and why
+    pub lost: Vec<(Attribute, String)>,
+    /// Why the attempts before the healthy one failed
+    pub failed_attempts: Vec<String>,
+}
 
-        
-        // Store process details before killing it
-        let name = process.name().to_string();
-        let exe = process.exe().map(|p| p.to_path_buf());
-        let cwd = process.cwd().map(|p| p.to_path_buf());
-        let parent_pid = process.parent();
-        
-        // Determine if this is a GUI application vs terminal process
-        let is_gui_app = name.ends_with("-bin") || 
-                      ["brave", "firefox", "chrome", "chromium", "electron"].iter()
-                          .any(|app| name.contains(app));
-        
-        // Determine if this is a system service
-        let is_system_service = name == "pulse" || name == "pulseaudio" || 
-                               name.contains("daemon") || name.contains("service") || 
-                               name.contains("systemd") || self.is_system_service(&name);
-        
-        // Special handling for VS Code
-        let is_vscode = name == "code" || name.contains("code-oss") || name.contains("vscode");
-        
-        // Special handling for self (pulse program)
-        let is_self = name == "pulse" && exe.as_ref().map_or(false, |p| p.to_string_lossy().contains("process_manager"));
-        
-        // Get command line arguments
-        let cmdline = self.get_cmdline(pid).unwrap_or_else(|| {
-            if let Some(exe_path) = &exe {
-                vec![exe_path.to_string_lossy().to_string()]
-            } else {
-                vec![name.clone()]
-            }
-        });
-        
-        // Try to get environment variables for the process
-        let env_vars = self.get_environ(pid);
-        
-        // For terminal processes, try to track the terminal
-        let terminal_pid = if !is_gui_app && !is_system_service && parent_pid.is_some() {
-            // Try to find the terminal by looking up the process tree
-            self.find_terminal_in_hierarchy(parent_pid.unwrap())
-        } else {
-            None
+impl RestartReport {
+    /// e.g. "Process 12 restarted as 40 after 1 failed attempts"
+    pub fn headline(&self) -> String {
+        let restarted = match &self.method {
+            RestartMethod::Respawned { new_pid } => format!("Process {} restarted as {}", self.pid, new_pid),
+            RestartMethod::RolledBack { new_pid } => {
+                format!("Process {} rolled back to its previous executable as {}", self.pid, new_pid)
+            },
+            RestartMethod::ServiceManager { manager, service, new_pid: Some(new_pid) } => {
+                format!("Process {} restarted by {} ({}) as {}", self.pid, manager, service, new_pid)
+            },
+            RestartMethod::ServiceManager { manager, service, new_pid: None } => {
+                format!("Process {} restarted by {} ({})", self.pid, manager, service)
+            },
         };
-        
-        // Special handling for self-restart (pulse)
-        if is_self {
-            // Create a self-restart script in /tmp
-            let restart_script = "/tmp/restart_pulse.sh";
-            let exe_path_str = exe.as_ref().map_or("pulse".to_string(), |p| p.to_string_lossy().to_string());
-            let cwd_path_str = cwd.as_ref().map_or("/".to_string(), |p| p.to_string_lossy().to_string());
-            
-            // Create script content
-            let script_content = format!(
-                "#!/bin/bash\n\
-                # Kill the current pulse process\n\
-                kill {}\n\
-                # Wait for termination\n\
-                sleep 1\n\
-                # Start a new instance\n\
-                cd {} && {} &\n\
-                exit 0",
-                pid, cwd_path_str, exe_path_str
-            );
-            
-            // Write script to file
-            if let Ok(_) = fs::write(restart_script, script_content) {
-                // Make executable
-                let _ = Command::new("chmod").args(&["+x", restart_script]).output();
-                
-                // Execute script
-                if let Ok(_) = Command::new("bash").arg(restart_script).spawn() {
-                    return RestartResult::Success;
-                }
-            }
-            
-            // Fallback: fork and exec approach
-            unsafe {
-                match libc::fork() {
-                    -1 => return RestartResult::RestartFailed,
-                    0 => {
-                        // Child process
-                        thread::sleep(Duration::from_millis(500));
-                        // Kill parent
-                        let _ = Command::new("kill").arg("-9").arg(pid.to_string()).output();
-                        thread::sleep(Duration::from_millis(500));
-                        // Start new process
-                        let _ = Command::new(&exe_path_str)
-                            .current_dir(cwd.unwrap_or_else(|| Path::new("/").to_path_buf()))
-                            .spawn();
-                        std::process::exit(0);
-                    },
-                    _ => {
-                        // Parent process - return success and let child handle it
-                        return RestartResult::Success;
-                    }
-                }
-            }
+        match self.failed_attempts.len() {
+            0 => restarted,
+            1 => format!("{} after a failed attempt", restarted),
+            n => format!("{} after {} failed attempts", restarted, n),
         }
-        
-        // Special handling for VS Code
-        if is_vscode {
-            // Find all related VS Code processes
-            self.system.refresh_all();
-            let related_pids: Vec<Pid> = self.system.processes()
-                .iter()
-                .filter(|(_, proc)| {
-                    let proc_name = proc.name();
-                    proc_name.contains("code") || proc_name.contains("vscode")
-                })
-                .map(|(pid, _)| *pid)
-                .collect();
-            
-            // Kill all related processes, starting with child processes
-            let mut sorted_pids = related_pids.clone();
-            sorted_pids.sort_by(|a, b| b.cmp(a)); // Reverse sort to get child processes first
-            
-            for pid_to_kill in sorted_pids {
-                let _ = Command::new("kill").arg("-15").arg(pid_to_kill.to_string()).output();
-            }
-            
-            // Wait longer for VS Code to fully terminate
-            thread::sleep(Duration::from_secs(1));
-            
-            // Use original command line to restart VS Code
-            if !cmdline.is_empty() {
-                match self.spawn_with_cmdline(&cmdline, cwd.as_deref(), env_vars.as_deref()) {
-                    Ok(_) => return RestartResult::Success,
-                    Err(_) => {}
-                }
-            }
-            
-            // Fallback: try executable path
-            if let Some(path) = exe.as_deref() {
-                match self.spawn_with_exe(path, cwd.as_deref()) {
-                    Ok(_) => return RestartResult::Success,
-                    Err(_) => {}
-                }
-            }
-            
-            // Last resort: try known VS Code commands
-            for vscode_cmd in &["code", "code-oss", "vscode", "vscodium"] {
-                match Command::new(vscode_cmd)
-                    .stdin(Stdio::null())
-                    .stdout(Stdio::null())
-                    .stderr(Stdio::null())
-                    .spawn() {
-                    Ok(_) => return RestartResult::Success,
-                    Err(_) => continue,
-                }
-            }
-            
-            return RestartResult::RestartFailed;
-        }
-        
-        // For system services, we may not want to kill them directly
-        if !is_system_service {
-            // Kill process with proper cleanup handling
-            if !self.kill_process(pid) {
-                return RestartResult::KillFailed;
-            }
-            
-            // Wait for process to fully terminate
-            thread::sleep(Duration::from_millis(500));
-        }
-        
-        // For system services: use specialized restart methods
-        if is_system_service {
-            if let Some(result) = self.restart_system_service(&name) {
-                return result;
-            }
-            
-            // If specialized restart failed and we haven't killed it yet, try killing and regular restart
-            if !self.kill_process(pid) {
-                return RestartResult::KillFailed;
-            }
-            
-            thread::sleep(Duration::from_millis(500));
-        }
-        
-        // For GUI apps: ensure old instance is gone before starting new one
-        if is_gui_app {
-            thread::sleep(Duration::from_millis(500));
-            
-            // Close any zombie instances with the same name
-            self.cleanup_zombie_instances(&name);
-            
-            // Try different methods to restart the process
-            if let Some(result) = self.try_restart_methods(
-                &name, &cmdline, exe.as_deref(), cwd.as_deref(), env_vars.as_deref()
-            ) {
-                return result;
-            }
-        } else if let Some(term_pid) = terminal_pid {
-            // For terminal processes: try to send a restart command to the terminal
-            if let Some(result) = self.restart_in_terminal(
-                term_pid, &name, &cmdline, cwd.as_deref()
-            ) {
-                return result;
-            }
-            
-            // Fallback to standard methods if terminal-specific method fails
-            if let Some(result) = self.try_restart_methods(
-                &name, &cmdline, exe.as_deref(), cwd.as_deref(), env_vars.as_deref()
-            ) {
-                return result;
-            }
-        } else {
-            // Generic restart attempt
-            if let Some(result) = self.try_restart_methods(
-                &name, &cmdline, exe.as_deref(), cwd.as_deref(), env_vars.as_deref()
-            ) {
-                return result;
-            }
-        }
-        
-        RestartResult::RestartFailed
     }
 
-    // Helper to determine if a process is a system service
-    fn is_system_service(&self, name: &str) -> bool {
-        // Check if running as a systemd service
-        if let Ok(output) = Command::new("systemctl")
-            .args(["status", name])
-            .output() {
-            if output.status.success() {
-                return true;
-            }
-        }
-        
-        // Check if running as a user systemd service
-        if let Ok(output) = Command::new("systemctl")
-            .args(["--user", "status", name])
-            .output() {
-            if output.status.success() {
-                return true;
-            }
+    /// One line for the status bar, e.g. "Process 12 restarted as 40 (not kept:
+    /// stdout: pipe:[88] cannot be opened again)"
+    pub fn summary(&self) -> String {
+        if matches!(self.method, RestartMethod::ServiceManager { .. }) {
+            self.headline()
+        } else if self.lost.is_empty() {
+            format!("{} with everything kept", self.headline())
+        } else {
+            let lost: Vec<String> = self.lost.iter().map(|(attribute, why)| format!("{}: {}", attribute.name(), why)).collect();
+            format!("{} (not kept: {})", self.headline(), lost.join("; "))
         }
-        
-        false
     }
 
-    // Specialized method for restarting system services
-    fn restart_system_service(&self, name: &str) -> Option<RestartResult> {
-        // For pulseaudio specifically
-        if name == "pulse" || name == "pulseaudio" {
-            // Try systemctl user restart first
-            if let Ok(output) = Command::new("systemctl")
-                .args(["--user", "restart", "pulseaudio"])
-                .output() {
-                if output.status.success() {
-                    return Some(RestartResult::Success);
-                }
-            }
-            
-            // Try direct command restart as fallback
-            if let Ok(_) = Command::new("pulseaudio")
-                .args(["--kill"])
-                .output() {
-                
-                thread::sleep(Duration::from_millis(500));
-                
-                // Now start it again
-                if let Ok(_) = Command::new("pulseaudio")
-                    .args(["--start"])
-                    .spawn() {
-                    return Some(RestartResult::Success);
-                }
-                
-                // Last resort - try the pulse command
-                if let Ok(_) = Command::new("pulse")
-                    .args(["--start"])
-                    .spawn() {
-                    return Some(RestartResult::Success);
-                }
-            }
-        }
-        // For dbus-daemon, try dbus-launch
-        else if name == "dbus-daemon" {
-            if let Ok(_) = Command::new("dbus-launch")
-                .spawn() {
-                return Some(RestartResult::Success);
-            }
-        }
-        // For system services running under systemd
-        else if let Ok(output) = Command::new("systemctl")
-            .args(["restart", name])
-            .output() {
-            if output.status.success() {
-                return Some(RestartResult::Success);
-            }
-        }
-        // For user systemd services
-        else if let Ok(output) = Command::new("systemctl")
-            .args(["--user", "restart", name])
-            .output() {
-            if output.status.success() {
-                return Some(RestartResult::Success);
-            }
+    /// The kept and lost parts, a line each
+    pub fn details(&self) -> Vec<String> {
+        let mut lines: Vec<String> = self
+            .failed_attempts
+            .iter()
+            .enumerate()
+            .map(|(n, why)| format!("attempt {} failed: {}", n + 1, why))
+            .collect();
+        if !self.preserved.is_empty() {
+            let kept: Vec<&str> = self.preserved.iter().map(Attribute::name).collect();
+            lines.push(format!("kept: {}", kept.join(", ")));
         }
-        // For services with "service" in the name, try both systemctl and service command
-        else if name.contains("service") || name.contains("daemon") {
-            // Try systemctl first
-            if let Ok(output) = Command::new("systemctl")
-                .args(["restart", name])
-                .output() {
-                if output.status.success() {
-                    return Some(RestartResult::Success);
-                }
-            }
-            
-            // Then try the service command
-            if let Ok(output) = Command::new("service")
-                .args([name, "restart"])
-                .output() {
-                if output.status.success() {
-                    return Some(RestartResult::Success);
-                }
-            }
+        for (attribute, why) in &self.lost {
+            lines.push(format!("not kept: {}: {}", attribute.name(), why));
         }
-        
-        None
+        lines
     }
+}
 
-    // Helper method to kill a process with proper handling
-    fn kill_process(&self, pid: Pid) -> bool {
-        // Try SIGTERM first
-        if let Ok(_) = Command::new("kill").arg(pid.to_string()).output() {
-            // Wait for graceful termination
-            for _ in 0..5 {
-                thread::sleep(Duration::from_millis(300));
-                if !self.process_exists(pid) {
-                    return true;
-                }
-            }
-            
-            // If still running, use SIGKILL
-            if let Ok(_) = Command::new("kill").arg("-9").arg(pid.to_string()).output() {
-                for _ in 0..3 {
-                    thread::sleep(Duration::from_millis(300));
-                    if !self.process_exists(pid) {
-                        return true;
-                    }
-                }
-            }
-        }
-        
-        !self.process_exists(pid)
-    }
+pub struct ProcessRestarter {
+    collector: Collector,
+    /// What the restarted process must pass to count as restarted
+    pub health_check: HealthCheck,
+    /// Asked first; a process one of them owns is restarted through it
+    pub services: ServiceManagers,
+    /// How the old process is stopped; see `StopPolicies`
+    pub stop_policies: StopPolicies,
+}
 
-    // Try different restart methods and return the first successful one
-    fn try_restart_methods(
-        &self,
-        name: &str,
-        cmdline: &[String],
-        exe_path: Option<&Path>,
-        cwd: Option<&Path>,
-        env_vars: Option<&[(String, String)]>
-    ) -> Option<RestartResult> {
-        // Method 1: Use command line if available
-        if !cmdline.is_empty() {
-            match self.spawn_with_cmdline(cmdline, cwd, env_vars) {
-                Ok(_) => return Some(RestartResult::Success),
-                Err(e) => eprintln!("Failed to restart using cmdline: {}", e),
-            }
-        }
-        
-        // Method 2: Use executable path
-        if let Some(path) = exe_path {
-            match self.spawn_with_exe(path, cwd) {
-                Ok(_) => return Some(RestartResult::Success),
-                Err(e) => eprintln!("Failed to restart using exe path: {}", e),
-            }
-        }
-        
-        // Method 3: Use process name as command
-        match Command::new(name)
-            .stdin(Stdio::null())
-            .stdout(Stdio::null())
-            .stderr(Stdio::null())
-            .spawn() {
-            Ok(_) => return Some(RestartResult::Success),
-            Err(_) => None,
-        }
+impl Default for ProcessRestarter {
+    fn default() -> Self {
+        Self::new()
     }
+}
 
-    // Find the terminal in process hierarchy
-    fn find_terminal_in_hierarchy(&self, start_pid: Pid) -> Option<Pid> {
-        // List of common terminal process names
-        const TERMINAL_NAMES: [&str; 7] = [
-            "gnome-terminal", "konsole", "xterm", "alacritty", "kitty", "terminator", "tilix"
-        ];
-        
-        let mut current_pid = start_pid;
-        // Limit search depth to avoid infinite loops
-        for _ in 0..10 {
-            if let Some(process) = self.system.process(current_pid) {
-                let name = process.name();
-                if TERMINAL_NAMES.iter().any(|term| name.contains(term)) {
-                    return Some(current_pid);
-                }
-                
-                if let Some(parent) = process.parent() {
-                    current_pid = parent;
-                } else {
-                    return None;
-                }
-            } else {
-                return None;
-            }
+impl ProcessRestarter {
+    pub fn new() -> Self {
+        ProcessRestarter {
+            collector: Collector::new(),
+            health_check: HealthCheck::new(),
+            services: ServiceManagers::new(),
+            // A broken config must not keep processes from being restarted
+            stop_policies: StopPolicies::load().unwrap_or_default(),
         }
-        
-        None
     }
 
-    // Special handling for restarting processes in terminals
-    fn restart_in_terminal(
-        &self,
-        term_pid: Pid,
-        name: &str,
-        cmdline: &[String],
-        cwd: Option<&Path>
-    ) -> Option<RestartResult> {
-        // This is a simplified approach - a real implementation would need to
-        // send commands to the terminal, which is complex and terminal-specific
-        
-        if let Some(term_proc) = self.system.process(term_pid) {
-            let term_name = term_proc.name();
-            
-            // Construct a command string that would restart the process
-            let cmd_str = if cmdline.len() > 1 {
-                cmdline.join(" ")
+    /// Stop the process and start it again the way it was started: the same
+    /// arguments, environment, working directory, user, limits, nice value,
+    /// umask, standard streams and, where the kernel allows, session and
+    /// terminal. A process owned by a service manager (systemd, OpenRC or SysV,
+    /// runit, supervisord) is restarted through it instead. The restart only
+    /// succeeds once the new process passes `health_check`, after any retries.
+    pub fn restart_process(&mut self, pid: Pid) -> Result<RestartReport, RestartError> {
+        let pid_val = pid.as_u32();
+        self.collector.refresh();
+        let process = self.collector.system().process(pid).ok_or(RestartError::NotFound(pid_val))?;
+        if matches!(process.status(), ProcessStatus::Zombie | ProcessStatus::Dead) {
+            return Err(RestartError::NotRunning(pid_val));
+        }
+        if pid_val == std::process::id() {
+            return Err(RestartError::OwnProcess(pid_val));
+        }
+
+        if let Some((backend, service)) = self.services.owner(pid_val) {
+            let failed = |e: String| RestartError::ServiceFailed(pid_val, format!("{} could not restart {}: {}", backend.name(), service.name, e));
+            backend.restart(&service).map_err(failed)?;
+            // The manager picks the new PID; its status tells which
+            let status = backend.status(&service).map_err(failed)?;
+            if !status.running {
+                return Err(RestartError::Unhealthy(pid_val, vec![format!("{} is {}", service.name, status.detail)]));
+            }
+            let verified = match status.pid {
+                Some(new_pid) => self.health_check.verify(new_pid),
+                None => self.health_check.verify_probe(),
+            };
+            verified.map_err(|e| RestartError::Unhealthy(pid_val, vec![e]))?;
+            return Ok(RestartReport {
+                pid: pid_val,
+                method: RestartMethod::ServiceManager { manager: backend.name(), service: service.name, new_pid: status.pid },
+                preserved: Vec::new(),
+                lost: Vec::new(),
+                failed_attempts: Vec::new(),
+            });
+        }
+
+        // Everything is read while the process still runs, its open streams included
+        let launch = LaunchSpec::capture(pid_val).map_err(|e| {
+            if signal::process_exists(pid_val) {
+                RestartError::NoCommandLine(pid_val, e)
             } else {
-                name.to_string()
+                RestartError::NotFound(pid_val)
+            }
+        })?;
+        if !self.kill_process(pid) {
+            return Err(RestartError::KillFailed(pid_val));
+        }
+
+        let mut failed_attempts = Vec::new();
+        let rollback = self.health_check.rollback && launch.binary_replaced();
+        let last = self.health_check.retries as usize + usize::from(rollback);
+        for attempt in 0..=last {
+            let rolling_back = rollback && attempt == last;
+            let launched = match if rolling_back { launch.spawn_previous() } else { launch.spawn() } {
+                Ok(launched) => launched,
+                Err(e) => {
+                    failed_attempts.push(e);
+                    continue;
+                },
             };
-            
-            // Try to open new terminal with the command
-            match Command::new(term_name)
-                .arg("-e")
-                .arg(&cmd_str)
-                .current_dir(cwd.unwrap_or_else(|| Path::new("/")))
-                .stdin(Stdio::null())
-                .stdout(Stdio::null())
-                .stderr(Stdio::null())
-                .spawn() {
-                Ok(_) => return Some(RestartResult::Success),
-                Err(_) => {
-                    // Try alternative terminal args format
-                    match Command::new(term_name)
-                        .args(&["-e", &cmd_str])
-                        .current_dir(cwd.unwrap_or_else(|| Path::new("/")))
-                        .stdin(Stdio::null())
-                        .stdout(Stdio::null())
-                        .stderr(Stdio::null())
-                        .spawn() {
-                        Ok(_) => return Some(RestartResult::Success),
-                        Err(_) => {}
-                    }
-                }
-            }
-        }
-        
-        None
-    }
-
-    // Clean up any zombie instances that might prevent proper restart
-    fn cleanup_zombie_instances(&mut self, name: &str) {
-        self.system.refresh_all();
-        
-        // Find all processes with the same name
-        let zombie_pids: Vec<Pid> = self.system.processes()
-            .iter()
-            .filter(|(_, proc)| proc.name() == name)
-            .map(|(pid, _)| *pid)
-            .collect();
-        
-        // Try to kill any found instances
-        for pid in zombie_pids {
-            let _ = Command::new("kill").arg("-9").arg(pid.to_string()).output();
-        }
-        
-        // Small delay to let the system clean up
-        thread::sleep(Duration::from_millis(300));
-    }
-
-    fn process_exists(&self, pid: Pid) -> bool {
-        Command::new("kill")
-            .arg("-0")
-            .arg(pid.to_string())
-            .output()
-            .map(|output| output.status.success())
-            .unwrap_or(false)
+            if let Err(e) = self.health_check.verify(launched.pid) {
+                // An unhealthy copy makes way for the next attempt; the last one is left running
+                if attempt < last {
+                    self.kill_process(Pid::from_u32(launched.pid));
+                }
+                failed_attempts.push(e);
+                continue;
+            }
+            let new_pid = launched.pid;
+            return Ok(RestartReport {
+                pid: pid_val,
+                method: if rolling_back { RestartMethod::RolledBack { new_pid } } else { RestartMethod::Respawned { new_pid } },
+                preserved: launched.preserved,
+                lost: launched.lost,
+                failed_attempts,
+            });
+        }
+        Err(RestartError::Unhealthy(pid_val, failed_attempts))
     }
 
-    fn get_cmdline(&self, pid: Pid) -> Option<Vec<String>> {
-        let path = format!("/proc/{}/cmdline", pid);
-        match fs::read(path) {
-            Ok(data) => {
-                let parts: Vec<String> = data
-                    .split(|b| *b == 0)
-                    .filter(|s| !s.is_empty())
-                    .map(|s| String::from_utf8_lossy(s).to_string())
-                    .collect();
-                
-                if parts.is_empty() {
-                    None
-                } else {
-                    Some(parts)
-                }
-            },
-            Err(_) => None
-        }
-    }
-    
-    fn get_environ(&self, pid: Pid) -> Option<Vec<(String, String)>> {
-        let path = format!("/proc/{}/environ", pid);
-        match fs::read(path) {
-            Ok(data) => {
-                let vars: Vec<(String, String)> = data
-                    .split(|b| *b == 0)
-                    .filter(|s| !s.is_empty())
-                    .filter_map(|s| {
-                        let var = String::from_utf8_lossy(s).to_string();
-                        if let Some(pos) = var.find('=') {
-                            Some((var[..pos].to_string(), var[pos+1..].to_string()))
-                        } else {
-                            None
-                        }
-                    })
-                    .collect();
-                
-                if vars.is_empty() {
-                    None
-                } else {
-                    Some(vars)
-                }
-            },
-            Err(_) => None
-        }
-    }
-
-    fn spawn_with_cmdline(
-        &self, 
-        cmdline: &[String], 
-        cwd: Option<&Path>,
-        env_vars: Option<&[(String, String)]>
-    ) -> Result<(), std::io::Error> {
-        if cmdline.is_empty() {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::InvalidInput, 
-                "Empty command line"
-            ));
-        }
-        
-        let program = &cmdline[0];
-        let args = &cmdline[1..];
-        
-        let mut cmd = Command::new(program);
-        cmd.args(args)
-           .stdin(Stdio::null())
-           .stdout(Stdio::null())
-           .stderr(Stdio::null());
-        
-        // Set working directory if available
-        if let Some(dir) = cwd {
-            cmd.current_dir(dir);
-        }
-        
-        // Set environment variables if available
-        if let Some(vars) = env_vars {
-            for (key, value) in vars {
-                // Skip some variables that might cause issues when restarting
-                if !["PPID", "PWD", "OLDPWD", "SHLVL", "_"].contains(&key.as_str()) {
-                    cmd.env(key, value);
-                }
-            }
-        }
-        
-        // Special handling for browser processes to avoid multiple instances
-        if program.contains("brave") || program.contains("chrome") || program.contains("firefox") {
-            // For browsers, add a small delay and ensure single-instance flags are set
-            thread::sleep(Duration::from_millis(500));
-            
-            // Check if '--profile' is in args, if not, filter startup flags
-            let has_profile = args.iter().any(|arg| arg.contains("--profile"));
-            if !has_profile {
-                // Filter out arguments that might prevent proper restart
-                let filtered_args: Vec<&String> = args.iter()
-                    .filter(|&arg| !arg.starts_with("--user-data-dir") && 
-                                  !arg.starts_with("--no-startup") &&
-                                  !arg.starts_with("--incognito"))
-                    .collect();
-                
-                cmd = Command::new(program);
-                cmd.args(filtered_args)
-                   .stdin(Stdio::null())
-                   .stdout(Stdio::null())
-                   .stderr(Stdio::null());
-                
-                // Reset working directory
-                if let Some(dir) = cwd {
-                    cmd.current_dir(dir);
-                }
-                
-                // Reset environment variables
-                if let Some(vars) = env_vars {
-                    for (key, value) in vars {
-                        if !["PPID", "PWD", "OLDPWD", "SHLVL", "_"].contains(&key.as_str()) {
-                            cmd.env(key, value);
-                        }
-                    }
-                }
-            }
-        }
-        
-        // Try to spawn the process
-        cmd.spawn()?;
-        
-        Ok(())
-    }
-    
-    fn spawn_with_exe(&self, exe_path: &Path, cwd: Option<&Path>) -> Result<(), std::io::Error> {
-        let mut cmd = Command::new(exe_path);
-        
-        cmd.stdin(Stdio::null())
-           .stdout(Stdio::null())
-           .stderr(Stdio::null());
-           
-        // Set working directory if available
-        if let Some(dir) = cwd {
-            cmd.current_dir(dir);
-        }
-        
-        // Try current user's environment variables
-        for (key, value) in env::vars() {
-            cmd.env(key, value);
-        }
-        
-        cmd.spawn()?;
-        
-        Ok(())
+    // Stop the process the way its stop policy says, descendants included if
+    // it says so
+    fn kill_process(&mut self, pid: Pid) -> bool {
+        self.collector.refresh();
+        let processes = self.collector.snapshot();
+        let cores = self.collector.summary().physical_cores;
+        let policy = self.stop_policies.for_process(pid.as_u32(), &processes, cores);
+        let pids = stop::targets(pid.as_u32(), &policy, &mut ProcessGroupManager::new(), &processes);
+        let name = processes.iter().find(|p| p.pid == pid.as_u32()).map(|p| p.name.clone()).unwrap_or_default();
+        stop::stop_processes(&name, &pids, &policy, |_| {}).stopped()
     }
 }
@@ -0,0 +1,551 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use nix::unistd::geteuid;
+use users::get_user_by_uid;
+use crate::snapshot::{read_cgroup, read_stat};
+
+/// A service as its manager knows it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Service {
+    /// The backend that owns it, e.g. "systemd"
+    pub manager: &'static str,
+    /// e.g. `nginx.service`, or `workers:worker_01` under supervisord
+    pub name: String,
+    /// What the manager's command takes: the unit or program name, or a runit
+    /// service directory
+    pub address: String,
+    /// Arguments the command needs before the action, e.g. `--user`
+    pub args: Vec<String>,
+}
+
+/// What a manager reports about a service
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ServiceStatus {
+    pub running: bool,
+    /// The main process, when the manager tells
+    pub pid: Option<u32>,
+    /// The manager's own words, e.g. "active (running)"
+    pub detail: String,
+}
+
+/// An init system or process supervisor that can own processes. Pulse goes
+/// through it to restart, stop or check a process it owns, so the manager is
+/// not surprised by the process coming or going behind its back.
+pub trait ServiceBackend: Send {
+    fn name(&self) -> &'static str;
+    /// The service `pid` belongs to, if this manager runs it
+    fn owner(&self, pid: u32) -> Option<Service>;
+    fn restart(&self, service: &Service) -> Result<(), String>;
+    fn stop(&self, service: &Service) -> Result<(), String>;
+    fn status(&self, service: &Service) -> Result<ServiceStatus, String>;
+}
+
+/// The backends to ask, innermost first: a program run by supervisord inside a
+/// systemd unit belongs to supervisord
+pub struct ServiceManagers {
+    backends: Vec<Box<dyn ServiceBackend>>,
+}
+
+impl ServiceManagers {
+    /// supervisord, runit, systemd and OpenRC or SysV init scripts
+    pub fn new() -> Self {
+        Self::with_backends(vec![
+            Box::new(Supervisord),
+            Box::new(Runit),
+            Box::new(Systemd),
+            Box::new(InitScripts::new()),
+        ])
+    }
+
+    /// Ask these backends instead, e.g. a fake one in tests
+    pub fn with_backends(backends: Vec<Box<dyn ServiceBackend>>) -> Self {
+        ServiceManagers { backends }
+    }
+
+    /// The first manager that owns `pid`, and its service
+    pub fn owner(&self, pid: u32) -> Option<(&dyn ServiceBackend, Service)> {
+        self.backends.iter().find_map(|backend| backend.owner(pid).map(|service| (backend.as_ref(), service)))
+    }
+}
+
+impl Default for ServiceManagers {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Units found through `/proc/<pid>/cgroup`, so a process is only handed to
+/// systemd when it really runs in one of its services
+pub struct Systemd;
+
+impl Systemd {
+    fn systemctl(service: &Service, action: &str) -> Result<String, String> {
+        let mut args: Vec<&str> = service.args.iter().map(String::as_str).collect();
+        args.extend([action, service.address.as_str()]);
+        run("systemctl", &args)
+    }
+}
+
+impl ServiceBackend for Systemd {
+    fn name(&self) -> &'static str {
+        "systemd"
+    }
+
+    fn owner(&self, pid: u32) -> Option<Service> {
+        if !Path::new("/run/systemd/system").exists() {
+            return None;
+        }
+        let cgroup = read_cgroup(pid);
+        let parts: Vec<&str> = cgroup.split('/').filter(|part| !part.is_empty()).collect();
+        // The innermost unit; a scope (a login session, an app started from the
+        // desktop) is not a service and cannot be restarted
+        let index = parts.iter().rposition(|part| part.ends_with(".service") || part.ends_with(".scope"))?;
+        let unit = parts[index];
+        if unit.ends_with(".scope") {
+            return None;
+        }
+        // Units below user@UID.service belong to that user's manager
+        let owner_uid = parts[..index]
+            .iter()
+            .find_map(|part| part.strip_prefix("user@")?.strip_suffix(".service")?.parse::<u32>().ok());
+        let args = match owner_uid {
+            None => Vec::new(),
+            Some(uid) if uid == geteuid().as_raw() => vec!["--user".to_string()],
+            Some(uid) => {
+                let user = get_user_by_uid(uid)?.name().to_string_lossy().to_string();
+                vec!["--user".to_string(), format!("--machine={}@.host", user)]
+            },
+        };
+        Some(Service { manager: self.name(), name: unit.to_string(), address: unit.to_string(), args })
+    }
+
+    fn restart(&self, service: &Service) -> Result<(), String> {
+        Self::systemctl(service, "restart").map(drop)
+    }
+
+    fn stop(&self, service: &Service) -> Result<(), String> {
+        Self::systemctl(service, "stop").map(drop)
+    }
+
+    fn status(&self, service: &Service) -> Result<ServiceStatus, String> {
+        let mut args: Vec<&str> = service.args.iter().map(String::as_str).collect();
+        args.extend(["show", "--property=ActiveState,SubState,MainPID", service.address.as_str()]);
+        let output = run("systemctl", &args)?;
+        let properties: HashMap<&str, &str> = output.lines().filter_map(|line| line.split_once('=')).collect();
+        let active = properties.get("ActiveState").copied().unwrap_or("unknown");
+        let sub = properties.get("SubState").copied().unwrap_or("unknown");
+        Ok(ServiceStatus {
+            running: active == "active",
+            pid: properties.get("MainPID").and_then(|pid| pid.parse().ok()).filter(|pid| *pid > 0),
+            detail: format!("{} ({})", active, sub),
+        })
+    }
+}
+
+/// OpenRC services, found by their cgroup, and SysV init scripts, found by a
+/// pid file in `/run` that names the process or one of its ancestors
+pub struct InitScripts {
+    openrc: bool,
+}
+
+impl InitScripts {
+    pub fn new() -> Self {
+        InitScripts { openrc: Path::new("/run/openrc").exists() }
+    }
+
+    fn command(&self, service: &Service, action: &str) -> (String, Vec<String>) {
+        if self.openrc {
+            ("rc-service".to_string(), vec![service.address.clone(), action.to_string()])
+        } else {
+            (format!("/etc/init.d/{}", service.address), vec![action.to_string()])
+        }
+    }
+
+    fn service(&self, name: &str) -> Service {
+        Service { manager: self.name(), name: name.to_string(), address: name.to_string(), args: Vec::new() }
+    }
+}
+
+impl Default for InitScripts {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ServiceBackend for InitScripts {
+    fn name(&self) -> &'static str {
+        if self.openrc { "openrc" } else { "sysv" }
+    }
+
+    fn owner(&self, pid: u32) -> Option<Service> {
+        if self.openrc {
+            if let Some(name) = openrc_cgroup(pid) {
+                return Some(self.service(&name));
+            }
+        }
+        pid_files().remove(&main_process(pid)).map(|name| self.service(&name))
+    }
+
+    fn restart(&self, service: &Service) -> Result<(), String> {
+        let (program, args) = self.command(service, "restart");
+        run(&program, &args.iter().map(String::as_str).collect::<Vec<_>>()).map(drop)
+    }
+
+    fn stop(&self, service: &Service) -> Result<(), String> {
+        let (program, args) = self.command(service, "stop");
+        run(&program, &args.iter().map(String::as_str).collect::<Vec<_>>()).map(drop)
+    }
+
+    fn status(&self, service: &Service) -> Result<ServiceStatus, String> {
+        let (program, args) = self.command(service, "status");
+        // LSB: 0 running, 3 stopped; anything else is also not running
+        let (running, stdout, _) = output(&program, &args.iter().map(String::as_str).collect::<Vec<_>>())?;
+        Ok(ServiceStatus {
+            running,
+            pid: None,
+            detail: stdout.lines().map(str::trim).find(|line| !line.is_empty()).unwrap_or("no output").to_string(),
+        })
+    }
+}
+
+/// Services supervised by `runsv`, which runs in the service directory
+pub struct Runit;
+
+impl ServiceBackend for Runit {
+    fn name(&self) -> &'static str {
+        "runit"
+    }
+
+    fn owner(&self, pid: u32) -> Option<Service> {
+        let runsv = read_stat(main_process(pid))?.ppid as u32;
+        if comm(runsv) != "runsv" {
+            return None;
+        }
+        let directory = fs::read_link(format!("/proc/{}/cwd", runsv)).ok()?;
+        let name = directory.file_name()?.to_string_lossy().to_string();
+        Some(Service { manager: self.name(), name, address: directory.to_string_lossy().to_string(), args: Vec::new() })
+    }
+
+    fn restart(&self, service: &Service) -> Result<(), String> {
+        run("sv", &["restart", &service.address]).map(drop)
+    }
+
+    fn stop(&self, service: &Service) -> Result<(), String> {
+        run("sv", &["stop", &service.address]).map(drop)
+    }
+
+    fn status(&self, service: &Service) -> Result<ServiceStatus, String> {
+        // e.g. "run: /etc/service/web: (pid 123) 50s; run: log: (pid 99) 80s"
+        let output = run("sv", &["status", &service.address])?;
+        let detail = output.split(';').next().unwrap_or_default().trim().to_string();
+        Ok(ServiceStatus {
+            running: detail.starts_with("run:"),
+            pid: after_word(&detail, "(pid "),
+            detail,
+        })
+    }
+}
+
+/// Programs run by supervisord, which tells them their name and its server
+/// through the environment
+pub struct Supervisord;
+
+impl Supervisord {
+    fn supervisorctl(service: &Service, action: &str) -> Result<String, String> {
+        let mut args: Vec<&str> = service.args.iter().map(String::as_str).collect();
+        args.extend([action, service.address.as_str()]);
+        run("supervisorctl", &args)
+    }
+}
+
+impl ServiceBackend for Supervisord {
+    fn name(&self) -> &'static str {
+        "supervisord"
+    }
+
+    fn owner(&self, pid: u32) -> Option<Service> {
+        let environ = fs::read(format!("/proc/{}/environ", pid)).ok()?;
+        let variables: HashMap<String, String> = environ
+            .split(|b| *b == 0)
+            .filter_map(|entry| {
+                let entry = String::from_utf8_lossy(entry);
+                let (key, value) = entry.split_once('=')?;
+                key.starts_with("SUPERVISOR_").then(|| (key.to_string(), value.to_string()))
+            })
+            .collect();
+        let program = variables.get("SUPERVISOR_PROCESS_NAME")?;
+        let name = match variables.get("SUPERVISOR_GROUP_NAME") {
+            Some(group) if group != program => format!("{}:{}", group, program),
+            _ => program.clone(),
+        };
+        let args = match variables.get("SUPERVISOR_SERVER_URL") {
+            Some(url) => vec!["-s".to_string(), url.clone()],
+            None => Vec::new(),
+        };
+        Some(Service { manager: self.name(), name: name.clone(), address: name, args })
+    }
+
+    fn restart(&self, service: &Service) -> Result<(), String> {
+        Self::supervisorctl(service, "restart").map(drop)
+    }
+
+    fn stop(&self, service: &Service) -> Result<(), String> {
+        Self::supervisorctl(service, "stop").map(drop)
+    }
+
+    fn status(&self, service: &Service) -> Result<ServiceStatus, String> {
+        // e.g. "workers:worker_01   RUNNING   pid 42, uptime 0:01:10"; exits 3 when not running
+        let mut args: Vec<&str> = service.args.iter().map(String::as_str).collect();
+        args.extend(["status", service.address.as_str()]);
+        let (_, stdout, stderr) = output("supervisorctl", &args)?;
+        let line = stdout.lines().find(|line| line.split_whitespace().next() == Some(service.address.as_str()));
+        let Some(line) = line else {
+            return Err(format!("supervisorctl status {} failed: {}", service.address, if stderr.is_empty() { stdout } else { stderr }));
+        };
+        let detail = line.split_whitespace().skip(1).collect::<Vec<_>>().join(" ");
+        Ok(ServiceStatus { running: detail.starts_with("RUNNING"), pid: after_word(&detail, "pid "), detail })
+    }
+}
+
+// Run a manager's command: whether it succeeded, and its stdout and stderr
+fn output(program: &str, args: &[&str]) -> Result<(bool, String, String), String> {
+    let output = Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| format!("Failed to run {}: {}", program, e))?;
+    let stdout = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+    Ok((output.status.success(), stdout, stderr))
+}
+
+// Run a manager's command; the error is what it printed
+fn run(program: &str, args: &[&str]) -> Result<String, String> {
+    match output(program, args)? {
+        (true, stdout, _) => Ok(stdout),
+        (false, stdout, stderr) => {
+            let message = if stderr.is_empty() { stdout } else { stderr };
+            Err(format!("{} {} failed: {}", program, args.join(" "), message))
+        },
+    }
+}
+
+// The process a worker was forked from: the highest ancestor running the
+// same executable. Stopping there keeps e.g. a login shell from counting as
+// part of the sshd service it runs under.
+fn main_process(pid: u32) -> u32 {
+    let exe = |pid: u32| fs::read_link(format!("/proc/{}/exe", pid)).ok();
+    let Some(own) = exe(pid) else { return pid };
+    let mut current = pid;
+    while let Some(parent) = read_stat(current).map(|stat| stat.ppid as u32).filter(|ppid| *ppid > 1) {
+        if exe(parent).as_ref() != Some(&own) {
+            break;
+        }
+        current = parent;
+    }
+    current
+}
+
+fn comm(pid: u32) -> String {
+    fs::read_to_string(format!("/proc/{}/comm", pid)).unwrap_or_default().trim_end().to_string()
+}
+
+// The number after `word` in a manager's output, e.g. "(pid 123)"
+fn after_word(text: &str, word: &str) -> Option<u32> {
+    let rest = &text[text.find(word)? + word.len()..];
+    rest.split(|c: char| !c.is_ascii_digit()).next()?.parse().ok()
+}
+
+// OpenRC puts each service in a cgroup of its own: `openrc.NAME` in the
+// unified hierarchy, or NAME in its named v1 hierarchy
+fn openrc_cgroup(pid: u32) -> Option<String> {
+    let content = fs::read_to_string(format!("/proc/{}/cgroup", pid)).ok()?;
+    content.lines().find_map(|line| {
+        let mut parts = line.splitn(3, ':');
+        let _id = parts.next()?;
+        let controllers = parts.next()?;
+        let path = parts.next()?;
+        if controllers == "name=openrc" {
+            path.trim_start_matches('/').split('/').next().filter(|name| !name.is_empty()).map(str::to_string)
+        } else if controllers.is_empty() {
+            path.split('/').find_map(|part| part.strip_prefix("openrc.")).map(str::to_string)
+        } else {
+            None
+        }
+    })
+}
+
+// PIDs named by pid files in /run, with the init script each belongs to: the
+// file's name (`/run/sshd.pid`) or its directory (`/run/postgresql/14-main.pid`)
+fn pid_files() -> HashMap<u32, String> {
+    let mut services = HashMap::new();
+    let mut files: Vec<PathBuf> = Vec::new();
+    for dir in ["/run", "/var/run"] {
+        let Ok(entries) = fs::read_dir(dir) else { continue };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                if let Ok(inner) = fs::read_dir(&path) {
+                    files.extend(inner.flatten().map(|e| e.path()));
+                }
+            } else {
+                files.push(path);
+            }
+        }
+    }
+    for file in files.iter().filter(|f| f.extension().is_some_and(|e| e == "pid")) {
+        let Some(pid) = fs::read_to_string(file).ok().and_then(|text| text.lines().next()?.trim().parse::<u32>().ok()) else {
+            continue;
+        };
+        let stem = file.file_stem().map(|s| s.to_string_lossy().to_string());
+        let dir = file.parent().filter(|d| *d != Path::new("/run") && *d != Path::new("/var/run"));
+        let dir = dir.and_then(|d| d.file_name()).map(|d| d.to_string_lossy().to_string());
+        if let Some(name) = [stem, dir].into_iter().flatten().find(|name| Path::new("/etc/init.d").join(name).is_file()) {
+            services.entry(pid).or_insert(name);
+        }
+    }
+    services
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Child;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use sysinfo::Pid;
+    use crate::restart::{ProcessRestarter, RestartError, RestartMethod};
+
+    // Owns the PIDs it is given and records what it is asked to do
+    struct FakeBackend {
+        services: HashMap<u32, Service>,
+        // e.g. "restart web", in order
+        calls: Arc<Mutex<Vec<String>>>,
+        // When set, restart and stop fail with this message
+        fail: Option<String>,
+        status: Result<ServiceStatus, String>,
+    }
+
+    impl FakeBackend {
+        fn new() -> Self {
+            FakeBackend {
+                services: HashMap::new(),
+                calls: Arc::new(Mutex::new(Vec::new())),
+                fail: None,
+                status: Ok(ServiceStatus { running: true, pid: None, detail: "running".to_string() }),
+            }
+        }
+
+        fn own(mut self, pid: u32, name: &str) -> Self {
+            let service = Service { manager: "fake", name: name.to_string(), address: name.to_string(), args: Vec::new() };
+            self.services.insert(pid, service);
+            self
+        }
+
+        fn record(&self, action: &str, service: &Service) -> Result<(), String> {
+            self.calls.lock().unwrap().push(format!("{} {}", action, service.name));
+            match &self.fail {
+                Some(message) => Err(message.clone()),
+                None => Ok(()),
+            }
+        }
+    }
+
+    impl ServiceBackend for FakeBackend {
+        fn name(&self) -> &'static str {
+            "fake"
+        }
+
+        fn owner(&self, pid: u32) -> Option<Service> {
+            self.services.get(&pid).cloned()
+        }
+
+        fn restart(&self, service: &Service) -> Result<(), String> {
+            self.record("restart", service)
+        }
+
+        fn stop(&self, service: &Service) -> Result<(), String> {
+            self.record("stop", service)
+        }
+
+        fn status(&self, service: &Service) -> Result<ServiceStatus, String> {
+            self.calls.lock().unwrap().push(format!("status {}", service.name));
+            self.status.clone()
+        }
+    }
+
+    fn sleeper() -> Child {
+        Command::new("sleep").arg("30").spawn().unwrap()
+    }
+
+    fn restarter(backend: FakeBackend) -> ProcessRestarter {
+        let mut restarter = ProcessRestarter::new();
+        restarter.services = ServiceManagers::with_backends(vec![Box::new(backend)]);
+        restarter.health_check.alive_for = Duration::from_millis(100);
+        restarter
+    }
+
+    #[test]
+    fn first_owning_backend_wins() {
+        let managers = ServiceManagers::with_backends(vec![
+            Box::new(FakeBackend::new().own(10, "inner")),
+            Box::new(FakeBackend::new().own(10, "outer").own(11, "other")),
+        ]);
+        assert_eq!(managers.owner(10).map(|(_, service)| service.name), Some("inner".to_string()));
+        assert_eq!(managers.owner(11).map(|(_, service)| service.name), Some("other".to_string()));
+        assert!(managers.owner(12).is_none());
+    }
+
+    #[test]
+    fn restart_goes_through_the_owning_manager() {
+        let mut child = sleeper();
+        let backend = FakeBackend::new().own(child.id(), "web");
+        let calls = backend.calls.clone();
+        let report = restarter(backend).restart_process(Pid::from_u32(child.id()));
+        let method = report.map(|report| report.method);
+        assert_eq!(method, Ok(RestartMethod::ServiceManager { manager: "fake", service: "web".to_string(), new_pid: None }));
+        assert_eq!(*calls.lock().unwrap(), ["restart web", "status web"]);
+        // The manager restarted it, so Pulse left the process alone
+        assert!(child.try_wait().unwrap().is_none());
+        child.kill().unwrap();
+        child.wait().unwrap();
+    }
+
+    #[test]
+    fn failed_restart_is_a_service_error() {
+        let mut child = sleeper();
+        let pid = child.id();
+        let mut backend = FakeBackend::new().own(pid, "web");
+        backend.fail = Some("access denied".to_string());
+        let result = restarter(backend).restart_process(Pid::from_u32(pid));
+        assert_eq!(result.map(|_| ()), Err(RestartError::ServiceFailed(pid, "fake could not restart web: access denied".to_string())));
+        child.kill().unwrap();
+        child.wait().unwrap();
+    }
+
+    #[test]
+    fn failed_status_is_a_service_error() {
+        let mut child = sleeper();
+        let pid = child.id();
+        let mut backend = FakeBackend::new().own(pid, "web");
+        backend.status = Err("no such unit".to_string());
+        let result = restarter(backend).restart_process(Pid::from_u32(pid));
+        assert_eq!(result.map(|_| ()), Err(RestartError::ServiceFailed(pid, "fake could not restart web: no such unit".to_string())));
+        child.kill().unwrap();
+        child.wait().unwrap();
+    }
+
+    #[test]
+    fn service_not_running_after_restart_is_unhealthy() {
+        let mut child = sleeper();
+        let pid = child.id();
+        let mut backend = FakeBackend::new().own(pid, "web");
+        backend.status = Ok(ServiceStatus { running: false, pid: None, detail: "failed".to_string() });
+        let result = restarter(backend).restart_process(Pid::from_u32(pid));
+        assert_eq!(result.map(|_| ()), Err(RestartError::Unhealthy(pid, vec!["web is failed".to_string()])));
+        child.kill().unwrap();
+        child.wait().unwrap();
+    }
+}
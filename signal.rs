@@ -0,0 +1,385 @@
+use std::fmt;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use nix::errno::Errno;
+use nix::sys::signal::{self as nix_signal, Signal};
+use nix::unistd::{sysconf, Pid as NixPid, SysconfVar};
+use crate::snapshot::read_stat;
+
+/// Why a signal could not be delivered
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SignalError {
+    /// ESRCH: the process no longer exists
+    NoSuchProcess(u32),
+    /// EPERM: we are not allowed to signal the process
+    PermissionDenied(u32),
+    /// EINVAL: the signal number is not valid
+    InvalidSignal(i32),
+    /// The PID has been reused by a different process since it was opened
+    ProcessReplaced(u32),
+    /// Any other errno
+    Os(u32, Errno),
+}
+
+impl fmt::Display for SignalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SignalError::NoSuchProcess(pid) => write!(f, "Process {} does not exist", pid),
+            SignalError::PermissionDenied(pid) => write!(f, "Permission denied for process {}", pid),
+            SignalError::InvalidSignal(sig) => write!(f, "Invalid signal {}", sig),
+            SignalError::ProcessReplaced(pid) => write!(f, "PID {} now belongs to a different process", pid),
+            SignalError::Os(pid, errno) => write!(f, "Failed to signal process {}: {}", pid, errno.desc()),
+        }
+    }
+}
+
+impl std::error::Error for SignalError {}
+
+impl SignalError {
+    fn from_errno(pid: u32, signo: i32, errno: Errno) -> Self {
+        match errno {
+            Errno::ESRCH => SignalError::NoSuchProcess(pid),
+            Errno::EPERM => SignalError::PermissionDenied(pid),
+            Errno::EINVAL => SignalError::InvalidSignal(signo),
+            other => SignalError::Os(pid, other),
+        }
+    }
+
+    /// True when the target is gone (exited or replaced by a new process)
+    pub fn is_gone(&self) -> bool {
+        matches!(self, SignalError::NoSuchProcess(_) | SignalError::ProcessReplaced(_))
+    }
+}
+
+/// A reference to one specific process, robust against PID reuse.
+///
+/// On kernels with `pidfd_open(2)` (5.3+) the handle holds a pidfd, so signals
+/// can only ever reach the process that was opened. Elsewhere the process start
+/// time is recorded and re-checked before each `kill(2)`.
+#[derive(Debug)]
+pub struct ProcessHandle {
+    pid: u32,
+    pidfd: Option<OwnedFd>,
+    start_ticks: Option<u64>,
+}
+
+impl ProcessHandle {
+    pub fn open(pid: u32) -> Result<Self, SignalError> {
+        if pid == 0 || pid > i32::MAX as u32 {
+            return Err(SignalError::NoSuchProcess(pid));
+        }
+
+        let pidfd = match pidfd_open(pid) {
+            Ok(fd) => Some(fd),
+            Err(Errno::ESRCH) => return Err(SignalError::NoSuchProcess(pid)),
+            // ENOSYS: kernel without pidfd support
+            // EINVAL: pid is a thread rather than a thread-group leader
+            Err(_) => None,
+        };
+        // Read after the pidfd pins the process, so a start time that checks out
+        // is the pinned process's own
+        let start_ticks = read_stat(pid).map(|s| s.start_ticks);
+
+        if pidfd.is_none() && start_ticks.is_none() {
+            // Neither path could see the process; make sure it really exists
+            nix_signal::kill(NixPid::from_raw(pid as i32), None)
+                .map_err(|e| SignalError::from_errno(pid, 0, e))?;
+        }
+
+        Ok(ProcessHandle { pid, pidfd, start_ticks })
+    }
+
+    /// Open `pid` only if it is still the process a snapshot saw start at
+    /// `start_time` (seconds since the epoch, as in `ProcessSnapshot`), so a PID
+    /// picked from an older snapshot cannot reach a process that reused it. A
+    /// `start_time` of 0 means it is not known and skips the check.
+    pub fn open_seen(pid: u32, start_time: u64) -> Result<Self, SignalError> {
+        let handle = Self::open(pid)?;
+        if start_time != 0 && handle.start_ticks.is_some_and(|ticks| start_time_of(ticks) != start_time) {
+            return Err(SignalError::ProcessReplaced(pid));
+        }
+        Ok(handle)
+    }
+
+    pub fn pid(&self) -> u32 {
+        self.pid
+    }
+
+    /// A second handle to the same process, e.g. for a caller that keeps it
+    pub fn try_clone(&self) -> Result<Self, SignalError> {
+        let pidfd = match &self.pidfd {
+            Some(fd) => Some(
+                fd.try_clone()
+                    .map_err(|e| SignalError::Os(self.pid, Errno::from_i32(e.raw_os_error().unwrap_or(0))))?,
+            ),
+            None => None,
+        };
+        Ok(ProcessHandle { pid: self.pid, pidfd, start_ticks: self.start_ticks })
+    }
+
+    /// Whether signals go through a pidfd rather than a plain PID
+    pub fn uses_pidfd(&self) -> bool {
+        self.pidfd.is_some()
+    }
+
+    pub fn send(&self, signal: Signal) -> Result<(), SignalError> {
+        self.send_number(signal as i32)
+    }
+
+    /// Send a signal by number; needed for real-time signals, which nix has no variant for
+    pub fn send_number(&self, signo: i32) -> Result<(), SignalError> {
+        match &self.pidfd {
+            Some(fd) => pidfd_send_signal(fd, signo)
+                .map_err(|e| SignalError::from_errno(self.pid, signo, e)),
+            None => {
+                self.check_identity()?;
+                // SAFETY: kill(2) has no memory-safety requirements
+                Errno::result(unsafe { libc::kill(self.pid as libc::pid_t, signo) })
+                    .map(drop)
+                    .map_err(|e| SignalError::from_errno(self.pid, signo, e))
+            },
+        }
+    }
+
+    /// Check the process is still running without sending anything. A zombie
+    /// has exited, even though its parent has yet to collect it.
+    pub fn is_alive(&self) -> bool {
+        if read_stat(self.pid).is_some_and(|stat| stat.state == 'Z') {
+            return false;
+        }
+        let result = match &self.pidfd {
+            Some(fd) => pidfd_send_signal(fd, 0).map_err(|e| SignalError::from_errno(self.pid, 0, e)),
+            None => self.check_identity().and_then(|_| {
+                nix_signal::kill(NixPid::from_raw(self.pid as i32), None)
+                    .map_err(|e| SignalError::from_errno(self.pid, 0, e))
+            }),
+        };
+        matches!(result, Ok(()) | Err(SignalError::PermissionDenied(_)))
+    }
+
+    /// A duplicate of one of the process's file descriptors (`pidfd_getfd(2)`,
+    /// Linux 5.6+). Needs the same access as ptrace; ENOSYS without a pidfd.
+    pub fn duplicate_fd(&self, fd: i32) -> Result<OwnedFd, Errno> {
+        let pidfd = self.pidfd.as_ref().ok_or(Errno::ENOSYS)?;
+        // SAFETY: pidfd_getfd takes a pidfd, a descriptor number and flags and returns a new fd or -1
+        let ret = unsafe { libc::syscall(libc::SYS_pidfd_getfd, pidfd.as_raw_fd(), fd, 0 as libc::c_uint) };
+        if ret < 0 {
+            Err(Errno::last())
+        } else {
+            // SAFETY: the kernel just handed us ownership of this descriptor
+            Ok(unsafe { OwnedFd::from_raw_fd(ret as i32) })
+        }
+    }
+
+    fn check_identity(&self) -> Result<(), SignalError> {
+        let expected = match self.start_ticks {
+            Some(ticks) => ticks,
+            None => return Ok(()),
+        };
+        match read_stat(self.pid) {
+            Some(stat) if stat.start_ticks == expected => Ok(()),
+            Some(_) => Err(SignalError::ProcessReplaced(self.pid)),
+            None => Err(SignalError::NoSuchProcess(self.pid)),
+        }
+    }
+}
+
+/// Send a signal to a PID, going through a pidfd where the kernel supports it
+pub fn send_signal(pid: u32, signal: Signal) -> Result<(), SignalError> {
+    ProcessHandle::open(pid)?.send(signal)
+}
+
+/// Send a signal by number to a PID (see `ProcessHandle::send_number`)
+pub fn send_signal_number(pid: u32, signo: i32) -> Result<(), SignalError> {
+    ProcessHandle::open(pid)?.send_number(signo)
+}
+
+/// Send a signal by number to the process a snapshot saw (see `ProcessHandle::open_seen`)
+pub fn send_seen(pid: u32, start_time: u64, signo: i32) -> Result<(), SignalError> {
+    ProcessHandle::open_seen(pid, start_time)?.send_number(signo)
+}
+
+/// Send a signal to each PID in turn, keeping the outcome for every one of them
+pub fn send_to_all(pids: &[u32], signo: i32) -> Vec<(u32, Result<(), SignalError>)> {
+    pids.iter().map(|&pid| (pid, send_signal_number(pid, signo))).collect()
+}
+
+/// True if a process with this PID exists (even if we may not signal it)
+pub fn process_exists(pid: u32) -> bool {
+    if pid == 0 || pid > i32::MAX as u32 {
+        return false;
+    }
+    matches!(nix_signal::kill(NixPid::from_raw(pid as i32), None), Ok(()) | Err(Errno::EPERM))
+}
+
+// Seconds since the epoch from clock ticks after boot, the way sysinfo computes
+// a process's start time
+fn start_time_of(ticks: u64) -> u64 {
+    let ticks_per_second = sysconf(SysconfVar::CLK_TCK).ok().flatten().filter(|&t| t > 0).unwrap_or(100) as u64;
+    sysinfo::System::boot_time() + ticks / ticks_per_second
+}
+
+fn pidfd_open(pid: u32) -> Result<OwnedFd, Errno> {
+    // SAFETY: pidfd_open takes a pid and flags and returns a new fd or -1
+    let ret = unsafe { libc::syscall(libc::SYS_pidfd_open, pid as libc::pid_t, 0 as libc::c_uint) };
+    if ret < 0 {
+        Err(Errno::last())
+    } else {
+        // SAFETY: the kernel just handed us ownership of this descriptor
+        Ok(unsafe { OwnedFd::from_raw_fd(ret as i32) })
+    }
+}
+
+fn pidfd_send_signal(fd: &OwnedFd, signo: i32) -> Result<(), Errno> {
+    // SAFETY: a NULL siginfo makes the kernel fill in the same info as kill(2)
+    let ret = unsafe {
+        libc::syscall(
+            libc::SYS_pidfd_send_signal,
+            fd.as_raw_fd(),
+            signo,
+            std::ptr::null::<libc::siginfo_t>(),
+            0 as libc::c_uint,
+        )
+    };
+    if ret < 0 {
+        Err(Errno::last())
+    } else {
+        Ok(())
+    }
+}
+
+/// One entry of the signal menu
+#[derive(Clone, Debug)]
+pub struct SignalInfo {
+    pub number: i32,
+    pub name: String,
+    pub description: &'static str,
+}
+
+// The standard signals, in the order of signal(7)
+const STANDARD_SIGNALS: &[(i32, &str, &str)] = &[
+    (libc::SIGHUP, "SIGHUP", "Hangup; many daemons reload their configuration"),
+    (libc::SIGINT, "SIGINT", "Interrupt from keyboard (Ctrl+C)"),
+    (libc::SIGQUIT, "SIGQUIT", "Quit from keyboard (Ctrl+\\), dumps core"),
+    (libc::SIGILL, "SIGILL", "Illegal instruction"),
+    (libc::SIGTRAP, "SIGTRAP", "Trace/breakpoint trap"),
+    (libc::SIGABRT, "SIGABRT", "Abort, dumps core"),
+    (libc::SIGBUS, "SIGBUS", "Bus error (bad memory access)"),
+    (libc::SIGFPE, "SIGFPE", "Floating-point exception"),
+    (libc::SIGKILL, "SIGKILL", "Kill immediately; cannot be caught or ignored"),
+    (libc::SIGUSR1, "SIGUSR1", "User-defined signal 1"),
+    (libc::SIGSEGV, "SIGSEGV", "Invalid memory reference"),
+    (libc::SIGUSR2, "SIGUSR2", "User-defined signal 2"),
+    (libc::SIGPIPE, "SIGPIPE", "Broken pipe: write to pipe with no readers"),
+    (libc::SIGALRM, "SIGALRM", "Timer signal from alarm(2)"),
+    (libc::SIGTERM, "SIGTERM", "Polite request to terminate"),
+    (libc::SIGSTKFLT, "SIGSTKFLT", "Stack fault on coprocessor (unused)"),
+    (libc::SIGCHLD, "SIGCHLD", "Child stopped or terminated"),
+    (libc::SIGCONT, "SIGCONT", "Continue if stopped"),
+    (libc::SIGSTOP, "SIGSTOP", "Stop process; cannot be caught or ignored"),
+    (libc::SIGTSTP, "SIGTSTP", "Stop typed at terminal (Ctrl+Z)"),
+    (libc::SIGTTIN, "SIGTTIN", "Terminal input for background process"),
+    (libc::SIGTTOU, "SIGTTOU", "Terminal output for background process"),
+    (libc::SIGURG, "SIGURG", "Urgent condition on socket"),
+    (libc::SIGXCPU, "SIGXCPU", "CPU time limit exceeded"),
+    (libc::SIGXFSZ, "SIGXFSZ", "File size limit exceeded"),
+    (libc::SIGVTALRM, "SIGVTALRM", "Virtual alarm clock"),
+    (libc::SIGPROF, "SIGPROF", "Profiling timer expired"),
+    (libc::SIGWINCH, "SIGWINCH", "Window resize signal"),
+    (libc::SIGIO, "SIGIO", "I/O now possible"),
+    (libc::SIGPWR, "SIGPWR", "Power failure"),
+    (libc::SIGSYS, "SIGSYS", "Bad system call"),
+];
+
+/// Every signal Linux can deliver, real-time signals included
+pub fn all_signals() -> Vec<SignalInfo> {
+    let mut signals: Vec<SignalInfo> = STANDARD_SIGNALS
+        .iter()
+        .map(|&(number, name, description)| SignalInfo { number, name: name.to_string(), description })
+        .collect();
+
+    // The C library keeps the first few real-time signals for itself,
+    // so SIGRTMIN has to be asked for at runtime
+    for number in libc::SIGRTMIN()..=libc::SIGRTMAX() {
+        signals.push(SignalInfo {
+            number,
+            name: signal_name(number),
+            description: "Real-time signal (queued, meaning is application-defined)",
+        });
+    }
+    signals
+}
+
+/// Name of a signal number as printed by `kill -l`, e.g. SIGTERM or SIGRTMIN+3
+pub fn signal_name(number: i32) -> String {
+    if let Some(&(_, name, _)) = STANDARD_SIGNALS.iter().find(|(n, _, _)| *n == number) {
+        return name.to_string();
+    }
+    let (min, max) = (libc::SIGRTMIN(), libc::SIGRTMAX());
+    if (min..=max).contains(&number) {
+        // Like kill -l, count up from SIGRTMIN for the first half and down from SIGRTMAX after that
+        return if number == min {
+            "SIGRTMIN".to_string()
+        } else if number == max {
+            "SIGRTMAX".to_string()
+        } else if number - min <= (max - min) / 2 {
+            format!("SIGRTMIN+{}", number - min)
+        } else {
+            format!("SIGRTMAX-{}", max - number)
+        };
+    }
+    format!("SIG{}", number)
+}
+
+/// Parse a signal given as a number or a name (`TERM`, `SIGTERM`, `RTMIN+2`, ...)
+pub fn parse_signal(text: &str) -> Option<i32> {
+    let (min, max) = (libc::SIGRTMIN(), libc::SIGRTMAX());
+    if let Ok(number) = text.parse::<i32>() {
+        return (1..=max).contains(&number).then_some(number);
+    }
+
+    let upper = text.trim().to_uppercase();
+    let name = if upper.starts_with("SIG") { upper } else { format!("SIG{}", upper) };
+
+    if let Some(offset) = name.strip_prefix("SIGRTMIN+") {
+        let number = min + offset.parse::<i32>().ok()?;
+        return (number <= max).then_some(number);
+    }
+    if let Some(offset) = name.strip_prefix("SIGRTMAX-") {
+        let number = max - offset.parse::<i32>().ok()?;
+        return (number >= min).then_some(number);
+    }
+    match name.as_str() {
+        "SIGRTMIN" => return Some(min),
+        "SIGRTMAX" => return Some(max),
+        // Common aliases
+        "SIGIOT" => return Some(libc::SIGABRT),
+        "SIGPOLL" => return Some(libc::SIGIO),
+        "SIGCLD" => return Some(libc::SIGCHLD),
+        _ => {},
+    }
+    STANDARD_SIGNALS.iter().find(|(_, n, _)| *n == name).map(|(number, _, _)| *number)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+    use crate::snapshot::Collector;
+
+    #[test]
+    fn open_seen_tells_a_reused_pid_apart() {
+        let mut child = Command::new("sleep").arg("30").spawn().unwrap();
+        let mut collector = Collector::new();
+        collector.refresh();
+        let start_time = collector.snapshot().iter().find(|p| p.pid == child.id()).unwrap().start_time;
+
+        assert!(ProcessHandle::open_seen(child.id(), start_time).is_ok());
+        assert!(ProcessHandle::open_seen(child.id(), 0).is_ok());
+        assert_eq!(
+            ProcessHandle::open_seen(child.id(), start_time - 60).unwrap_err(),
+            SignalError::ProcessReplaced(child.id())
+        );
+        child.kill().unwrap();
+        child.wait().unwrap();
+    }
+}
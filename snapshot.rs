@@ -0,0 +1,422 @@
+use std::collections::HashMap;
+use std::fs;
+use sysinfo::{Pid, Process, ProcessStatus, System, ThreadKind};
+use users::get_user_by_uid;
+
+/// Scheduler state of a process, normalised from sysinfo / `/proc/<pid>/stat`
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ProcessState {
+    Running,
+    Sleep,
+    Idle,
+    Stopped,
+    Zombie,
+    Tracing,
+    Dead,
+    DiskSleep,
+    Waking,
+    Other,
+}
+
+impl ProcessState {
+    pub fn from_status(status: ProcessStatus) -> Self {
+        match status {
+            ProcessStatus::Run => ProcessState::Running,
+            ProcessStatus::Sleep => ProcessState::Sleep,
+            ProcessStatus::Idle => ProcessState::Idle,
+            ProcessStatus::Stop => ProcessState::Stopped,
+            ProcessStatus::Zombie => ProcessState::Zombie,
+            ProcessStatus::Tracing => ProcessState::Tracing,
+            ProcessStatus::Dead => ProcessState::Dead,
+            ProcessStatus::UninterruptibleDiskSleep => ProcessState::DiskSleep,
+            ProcessStatus::Waking | ProcessStatus::Wakekill => ProcessState::Waking,
+            _ => ProcessState::Other,
+        }
+    }
+
+    /// Map the single-letter state from `/proc/<pid>/stat`
+    pub fn from_code(code: char) -> Self {
+        match code {
+            'R' => ProcessState::Running,
+            'S' => ProcessState::Sleep,
+            'I' => ProcessState::Idle,
+            'T' => ProcessState::Stopped,
+            'Z' => ProcessState::Zombie,
+            't' => ProcessState::Tracing,
+            'X' | 'x' => ProcessState::Dead,
+            'D' => ProcessState::DiskSleep,
+            'W' | 'K' => ProcessState::Waking,
+            _ => ProcessState::Other,
+        }
+    }
+
+    /// Label shown in the process list and written by the exporters
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProcessState::Running => "Running",
+            ProcessState::Sleep => "Sleep",
+            ProcessState::Idle => "Idle",
+            ProcessState::Stopped => "Stopped",
+            ProcessState::Zombie => "Zombie",
+            ProcessState::Tracing => "Tracing",
+            ProcessState::Dead => "Dead",
+            ProcessState::DiskSleep => "Disk Sleep",
+            ProcessState::Waking => "Waking",
+            ProcessState::Other => "Other",
+        }
+    }
+
+    /// The state for a label written by `as_str`, e.g. read back from an export
+    pub fn from_label(label: &str) -> Self {
+        match label {
+            "Running" => ProcessState::Running,
+            "Sleep" => ProcessState::Sleep,
+            "Idle" => ProcessState::Idle,
+            "Stopped" => ProcessState::Stopped,
+            "Zombie" => ProcessState::Zombie,
+            "Tracing" => ProcessState::Tracing,
+            "Dead" => ProcessState::Dead,
+            "Disk Sleep" => ProcessState::DiskSleep,
+            "Waking" => ProcessState::Waking,
+            _ => ProcessState::Other,
+        }
+    }
+
+    /// Single-letter code as used by ps(1)
+    pub fn code(&self) -> char {
+        match self {
+            ProcessState::Running => 'R',
+            ProcessState::Sleep => 'S',
+            ProcessState::Idle => 'I',
+            ProcessState::Stopped => 'T',
+            ProcessState::Zombie => 'Z',
+            ProcessState::Tracing => 't',
+            ProcessState::Dead => 'X',
+            ProcessState::DiskSleep => 'D',
+            ProcessState::Waking => 'W',
+            ProcessState::Other => '?',
+        }
+    }
+}
+
+/// Fields we use from `/proc/<pid>/stat`
+pub struct StatFields {
+    pub state: char,
+    pub ppid: i32,
+    pub pgrp: i32,
+    pub session: i32,
+    /// Device number of the controlling terminal, 0 when there is none
+    pub tty_nr: i32,
+    pub tpgid: i32,
+    pub nice: i32,
+    pub threads: u32,
+    /// Start time in clock ticks after boot; with the PID it identifies a process
+    pub start_ticks: u64,
+}
+
+/// Parse the contents of `/proc/<pid>/stat`.
+///
+/// The command name (field 2) is wrapped in parentheses and may itself contain
+/// spaces or parentheses, so the remaining fields are split after the last `)`.
+pub fn parse_stat(content: &str) -> Option<StatFields> {
+    let end = content.rfind(')')?;
+    let fields: Vec<&str> = content[end + 1..].split_whitespace().collect();
+    // fields[0] is field 3 of proc(5)
+    let field = |n: usize| fields.get(n - 3).copied();
+
+    Some(StatFields {
+        state: field(3)?.chars().next()?,
+        ppid: field(4)?.parse().ok()?,
+        pgrp: field(5)?.parse().ok()?,
+        session: field(6)?.parse().ok()?,
+        tty_nr: field(7)?.parse().ok()?,
+        tpgid: field(8)?.parse().ok()?,
+        nice: field(19)?.parse().ok()?,
+        threads: field(20)?.parse().ok()?,
+        start_ticks: field(22)?.parse().ok()?,
+    })
+}
+
+/// Read and parse `/proc/<pid>/stat` for a single process
+pub fn read_stat(pid: u32) -> Option<StatFields> {
+    let content = fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    parse_stat(&content)
+}
+
+/// The cgroup of a process from `/proc/<pid>/cgroup`: the unified (v2) path,
+/// or on a v1-only system the systemd hierarchy
+pub fn read_cgroup(pid: u32) -> String {
+    let content = fs::read_to_string(format!("/proc/{}/cgroup", pid)).unwrap_or_default();
+    let entries: Vec<(&str, &str)> = content
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.splitn(3, ':');
+            let _id = parts.next()?;
+            Some((parts.next()?, parts.next()?))
+        })
+        .collect();
+    entries
+        .iter()
+        .find(|(controllers, _)| controllers.is_empty())
+        .or_else(|| entries.iter().find(|(controllers, _)| *controllers == "name=systemd"))
+        .or(entries.first())
+        .map(|(_, path)| path.to_string())
+        .unwrap_or_default()
+}
+
+/// A point-in-time view of one process, shared by the TUI, exporters and group manager
+#[derive(Clone, Debug)]
+pub struct ProcessSnapshot {
+    pub pid: u32,
+    pub ppid: Option<u32>,
+    pub name: String,
+    pub cmdline: Vec<String>,
+    pub uid: Option<u32>,
+    pub user: String,
+    /// CPU usage as reported by sysinfo (100% per fully used core)
+    pub cpu: f32,
+    /// Resident memory in bytes
+    pub memory: u64,
+    /// Virtual memory size in bytes
+    pub virtual_memory: u64,
+    /// Resident memory as a percentage of total RAM
+    pub mem: f64,
+    pub nice: i32,
+    pub pgrp: i32,
+    pub tpgid: i32,
+    pub state: ProcessState,
+    /// Start time in seconds since the epoch
+    pub start_time: u64,
+    pub threads: u32,
+    /// Bytes read from and written to storage since the process started
+    pub read_bytes: u64,
+    pub written_bytes: u64,
+    /// Control group path, e.g. `/system.slice/sshd.service` (empty when unknown)
+    pub cgroup: String,
+    /// A userland thread listed as its own entry; its CPU and memory are already
+    /// counted in the owning process
+    pub is_thread: bool,
+}
+
+impl ProcessSnapshot {
+    pub fn sys_pid(&self) -> Pid {
+        Pid::from_u32(self.pid)
+    }
+
+    /// A process is in the foreground when its group owns the terminal
+    pub fn is_foreground(&self) -> bool {
+        self.pgrp == self.tpgid
+    }
+
+    pub fn fg_bg(&self) -> &'static str {
+        if self.is_foreground() { "FG" } else { "BG" }
+    }
+
+    /// Full command line, falling back to the process name for kernel threads
+    pub fn command_line(&self) -> String {
+        if self.cmdline.is_empty() {
+            self.name.clone()
+        } else {
+            self.cmdline.join(" ")
+        }
+    }
+}
+
+/// Machine-wide figures that accompany a set of snapshots
+#[derive(Clone, Debug)]
+pub struct SystemSummary {
+    pub total_memory: u64,
+    pub used_memory: u64,
+    pub total_swap: u64,
+    pub used_swap: u64,
+    pub physical_cores: usize,
+    /// Usage across all CPUs, 0-100
+    pub cpu_usage: f32,
+    /// 1, 5 and 15 minute load averages
+    pub load_average: [f64; 3],
+}
+
+impl SystemSummary {
+    pub fn mem_percent(&self) -> f64 {
+        if self.total_memory == 0 {
+            0.0
+        } else {
+            (self.used_memory as f64 / self.total_memory as f64) * 100.0
+        }
+    }
+}
+
+/// Owns the sysinfo `System` and turns it into `ProcessSnapshot`s
+pub struct Collector {
+    system: System,
+    user_names: HashMap<u32, String>,
+}
+
+impl Collector {
+    pub fn new() -> Self {
+        Collector {
+            system: System::new_all(),
+            user_names: HashMap::new(),
+        }
+    }
+
+    /// Refresh process, memory and CPU information
+    pub fn refresh(&mut self) {
+        // CPUs first: refreshing processes updates the global CPU only, and sysinfo
+        // skips a second CPU refresh that comes too soon after it
+        self.system.refresh_cpu_usage();
+        self.system.refresh_processes();
+        self.system.refresh_memory();
+    }
+
+    /// Refresh a single process only (used for fast sampling)
+    pub fn refresh_process(&mut self, pid: u32) -> bool {
+        self.system.refresh_process(Pid::from_u32(pid))
+    }
+
+    pub fn system(&self) -> &System {
+        &self.system
+    }
+
+    /// Usage of each logical CPU, 0-100, as of the last refresh
+    pub fn core_usage(&self) -> Vec<f32> {
+        self.system.cpus().iter().map(|cpu| cpu.cpu_usage()).collect()
+    }
+
+    /// Snapshot every process currently known to sysinfo
+    pub fn snapshot(&mut self) -> Vec<ProcessSnapshot> {
+        let total_memory = self.system.total_memory();
+        let mut snapshots = Vec::with_capacity(self.system.processes().len());
+        for process in self.system.processes().values() {
+            let user = lookup_user(&mut self.user_names, process);
+            snapshots.push(build_snapshot(process, total_memory, user));
+        }
+        snapshots
+    }
+
+    /// Snapshot a single process, if it still exists
+    pub fn snapshot_pid(&mut self, pid: u32) -> Option<ProcessSnapshot> {
+        let total_memory = self.system.total_memory();
+        let process = self.system.process(Pid::from_u32(pid))?;
+        let user = lookup_user(&mut self.user_names, process);
+        Some(build_snapshot(process, total_memory, user))
+    }
+
+    pub fn summary(&self) -> SystemSummary {
+        let load = System::load_average();
+        SystemSummary {
+            total_memory: self.system.total_memory(),
+            used_memory: self.system.used_memory(),
+            total_swap: self.system.total_swap(),
+            used_swap: self.system.used_swap(),
+            physical_cores: self.system.physical_core_count().unwrap_or(1),
+            cpu_usage: self.system.global_cpu_info().cpu_usage(),
+            load_average: [load.one, load.five, load.fifteen],
+        }
+    }
+}
+
+impl Default for Collector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn lookup_user(cache: &mut HashMap<u32, String>, process: &Process) -> String {
+    let uid = match process.user_id() {
+        Some(uid) => **uid,
+        None => return "Unknown".to_string(),
+    };
+    cache
+        .entry(uid)
+        .or_insert_with(|| {
+            get_user_by_uid(uid)
+                .map(|u| u.name().to_string_lossy().into_owned())
+                .unwrap_or_else(|| "Unknown".to_string())
+        })
+        .clone()
+}
+
+fn build_snapshot(process: &Process, total_memory: u64, user: String) -> ProcessSnapshot {
+    let pid = process.pid().as_u32();
+    let stat = read_stat(pid);
+
+    let mem = if total_memory == 0 {
+        0.0
+    } else {
+        (process.memory() as f64 / total_memory as f64) * 100.0
+    };
+
+    let state = match &stat {
+        Some(s) => ProcessState::from_code(s.state),
+        None => ProcessState::from_status(process.status()),
+    };
+
+    let disk = process.disk_usage();
+
+    ProcessSnapshot {
+        pid,
+        ppid: process.parent().map(|p| p.as_u32()),
+        name: process.name().to_string(),
+        cmdline: process.cmd().to_vec(),
+        uid: process.user_id().map(|uid| **uid),
+        user,
+        cpu: process.cpu_usage(),
+        memory: process.memory(),
+        virtual_memory: process.virtual_memory(),
+        mem,
+        nice: stat.as_ref().map_or(0, |s| s.nice),
+        pgrp: stat.as_ref().map_or(0, |s| s.pgrp),
+        tpgid: stat.as_ref().map_or(0, |s| s.tpgid),
+        state,
+        start_time: process.start_time(),
+        threads: stat.as_ref().map_or(1, |s| s.threads),
+        read_bytes: disk.total_read_bytes,
+        written_bytes: disk.total_written_bytes,
+        cgroup: read_cgroup(pid),
+        is_thread: process.thread_kind() == Some(ThreadKind::Userland),
+    }
+}
+
+// Enum to track current sort mode
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SortMode {
+    Cpu,
+    Memory,
+    Pid,
+}
+
+/// Sort snapshots the way the process list shows them
+pub fn sort_snapshots(processes: &mut [ProcessSnapshot], mode: SortMode) {
+    match mode {
+        SortMode::Cpu => {
+            processes.sort_by(|a, b| b.cpu.total_cmp(&a.cpu));
+        },
+        SortMode::Memory => {
+            // Compare in 1MB buckets so processes with similar memory usage
+            // put running ones first, while keeping the ordering total
+            processes.sort_by(|a, b| {
+                (b.memory / 1_000_000)
+                    .cmp(&(a.memory / 1_000_000))
+                    .then_with(|| {
+                        let a_running = a.state == ProcessState::Running;
+                        let b_running = b.state == ProcessState::Running;
+                        b_running.cmp(&a_running)
+                    })
+                    .then_with(|| b.memory.cmp(&a.memory))
+            });
+        },
+        SortMode::Pid => {
+            // Keep PID ordering but bring active processes to the top
+            processes.sort_by(|a, b| {
+                let a_active = a.cpu > 0.1;
+                let b_active = b.cpu > 0.1;
+                match (a_active, b_active) {
+                    (true, false) => std::cmp::Ordering::Less,
+                    (false, true) => std::cmp::Ordering::Greater,
+                    _ => a.pid.cmp(&b.pid),
+                }
+            });
+        },
+    }
+}
@@ -0,0 +1,157 @@
+use std::path::Path;
+use rusqlite::types::Value as SqlValue;
+use rusqlite::{params_from_iter, Connection, OpenFlags};
+use serde_json::{Map, Value};
+use crate::columns::{ColumnKind, TypedValue};
+use crate::csv_export;
+use crate::exporter::{ExportData, Exporter};
+
+/// Adds each export to a SQLite database as a row of `snapshots` (the time and
+/// system summary) and its rows of `processes`, so exports taken over time can be
+/// queried together. Columns missing from an older database are added.
+pub struct SqliteExporter;
+
+impl Exporter for SqliteExporter {
+    fn extension(&self) -> &'static str {
+        "db"
+    }
+
+    fn export(&self, data: &ExportData, path: &Path) -> Result<String, String> {
+        let mut db = Connection::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+        let tx = db.transaction().map_err(|e| e.to_string())?;
+
+        // The summary fields become the columns of `snapshots`, typed by their values
+        let summary = csv_export::summary_fields(data.time, data.summary, data.processes.len());
+        let summary_columns: Vec<(String, &str)> = summary
+            .iter()
+            .map(|(name, value)| {
+                let kind = match value {
+                    Value::Number(n) if n.is_f64() => "REAL",
+                    Value::Number(_) => "INTEGER",
+                    _ => "TEXT",
+                };
+                (name.clone(), kind)
+            })
+            .collect();
+        let process_columns: Vec<(String, &str)> =
+            data.columns.iter().map(|c| (c.name().to_string(), sql_type(c.kind()))).collect();
+
+        tx.execute_batch(
+            "CREATE TABLE IF NOT EXISTS snapshots (id INTEGER PRIMARY KEY);
+             CREATE TABLE IF NOT EXISTS processes (snapshot_id INTEGER NOT NULL REFERENCES snapshots(id));
+             CREATE INDEX IF NOT EXISTS processes_snapshot ON processes(snapshot_id);",
+        )
+        .map_err(|e| e.to_string())?;
+        add_columns(&tx, "snapshots", &summary_columns)?;
+        add_columns(&tx, "processes", &process_columns)?;
+        // Every process with the time of its snapshot, for queries over time
+        tx.execute_batch(
+            "CREATE VIEW IF NOT EXISTS process_history AS
+                 SELECT snapshots.time, snapshots.host, processes.*
+                 FROM processes JOIN snapshots ON snapshots.id = processes.snapshot_id;",
+        )
+        .map_err(|e| e.to_string())?;
+
+        let sql = insert_sql("snapshots", summary.keys().map(String::as_str));
+        let values = summary.values().map(|v| match v {
+            Value::Number(n) if n.is_f64() => SqlValue::Real(n.as_f64().unwrap_or(0.0)),
+            Value::Number(n) => SqlValue::Integer(n.as_i64().unwrap_or(0)),
+            Value::String(s) => SqlValue::Text(s.clone()),
+            _ => SqlValue::Null,
+        });
+        tx.execute(&sql, params_from_iter(values)).map_err(|e| e.to_string())?;
+        let snapshot = tx.last_insert_rowid();
+
+        let names = std::iter::once("snapshot_id").chain(data.columns.iter().map(|c| c.name()));
+        {
+            let mut insert = tx.prepare(&insert_sql("processes", names)).map_err(|e| e.to_string())?;
+            for process in data.processes {
+                let values = std::iter::once(SqlValue::Integer(snapshot))
+                    .chain(data.columns.iter().map(|c| sql_value(c.typed(process))));
+                insert.execute(params_from_iter(values)).map_err(|e| e.to_string())?;
+            }
+        }
+        tx.commit().map_err(|e| e.to_string())?;
+
+        Ok(format!("Process data added to {} as snapshot {}", path.display(), snapshot))
+    }
+}
+
+fn sql_type(kind: ColumnKind) -> &'static str {
+    match kind {
+        ColumnKind::Integer => "INTEGER",
+        ColumnKind::Real => "REAL",
+        ColumnKind::Text => "TEXT",
+    }
+}
+
+fn sql_value(value: TypedValue) -> SqlValue {
+    match value {
+        TypedValue::Integer(n) => SqlValue::Integer(n),
+        TypedValue::Real(n) => SqlValue::Real(n),
+        TypedValue::Text(s) => SqlValue::Text(s),
+        TypedValue::Null => SqlValue::Null,
+    }
+}
+
+// Add the columns a table does not have yet
+fn add_columns(db: &Connection, table: &str, columns: &[(String, &str)]) -> Result<(), String> {
+    let existing: Vec<String> = db
+        .prepare(&format!("SELECT name FROM pragma_table_info('{}')", table))
+        .and_then(|mut query| query.query_map([], |row| row.get(0))?.collect())
+        .map_err(|e| e.to_string())?;
+    for (name, kind) in columns {
+        if !existing.contains(name) {
+            db.execute(&format!("ALTER TABLE {} ADD COLUMN \"{}\" {}", table, name, kind), [])
+                .map_err(|e| e.to_string())?;
+        }
+    }
+    Ok(())
+}
+
+fn insert_sql<'a>(table: &str, columns: impl Iterator<Item = &'a str>) -> String {
+    let names: Vec<String> = columns.map(|name| format!("\"{}\"", name)).collect();
+    let placeholders = vec!["?"; names.len()].join(", ");
+    format!("INSERT INTO {} ({}) VALUES ({})", table, names.join(", "), placeholders)
+}
+
+/// A `snapshots` row and its `processes` rows, keyed by column name
+pub(crate) type StoredSnapshot = (Map<String, Value>, Vec<Map<String, Value>>);
+
+/// Every snapshot in a database written by `SqliteExporter`, oldest first
+pub(crate) fn read_snapshots(path: &Path) -> Result<Vec<StoredSnapshot>, String> {
+    let db = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    let snapshots = read_rows(&db, "SELECT * FROM snapshots ORDER BY time, id", [])?;
+    let mut result = Vec::new();
+    for snapshot in snapshots {
+        let id = snapshot.get("id").and_then(Value::as_i64).unwrap_or(0);
+        let mut processes = read_rows(&db, "SELECT * FROM processes WHERE snapshot_id = ?", [id])?;
+        for process in &mut processes {
+            process.remove("snapshot_id");
+        }
+        result.push((snapshot, processes));
+    }
+    Ok(result)
+}
+
+fn read_rows(db: &Connection, sql: &str, params: impl rusqlite::Params) -> Result<Vec<Map<String, Value>>, String> {
+    let mut query = db.prepare(sql).map_err(|e| e.to_string())?;
+    let names: Vec<String> = query.column_names().into_iter().map(str::to_string).collect();
+    let rows = query
+        .query_map(params, |row| {
+            let mut object = Map::new();
+            for (index, name) in names.iter().enumerate() {
+                let value = match row.get::<_, SqlValue>(index)? {
+                    SqlValue::Integer(n) => Value::from(n),
+                    SqlValue::Real(n) => Value::from(n),
+                    SqlValue::Text(s) => Value::String(s),
+                    SqlValue::Null | SqlValue::Blob(_) => Value::Null,
+                };
+                object.insert(name.clone(), value);
+            }
+            Ok(object)
+        })
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<_, _>>().map_err(|e| e.to_string())
+}
@@ -0,0 +1,428 @@
+use std::fmt;
+use std::fs;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use sysinfo::Pid;
+use crate::alerts;
+use crate::columns::ColumnConfig;
+use crate::filter::ProcessFilter;
+use crate::process_groups::ProcessGroupManager;
+use crate::signal::{self, ProcessHandle, SignalError};
+use crate::snapshot::ProcessSnapshot;
+
+// How often a running stop looks whether the processes are gone
+const POLL: Duration = Duration::from_millis(100);
+// How long the last signal is given to take effect when the ladder names no wait
+const LAST_WAIT: Duration = Duration::from_secs(2);
+
+/// One rung of the ladder: send `signal`, then give the processes `wait` to exit
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StopStep {
+    pub signal: i32,
+    pub wait: Duration,
+}
+
+/// How to stop a process: signals tried in turn until it exits, e.g.
+/// SIGINT, 5s, SIGTERM, 10s, SIGKILL
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StopPolicy {
+    pub steps: Vec<StopStep>,
+    /// Stop its descendants along with it
+    pub subtree: bool,
+}
+
+impl StopPolicy {
+    /// SIGTERM, then SIGKILL after 5 seconds
+    pub fn new() -> Self {
+        StopPolicy {
+            steps: vec![
+                StopStep { signal: libc::SIGTERM, wait: Duration::from_secs(5) },
+                StopStep { signal: libc::SIGKILL, wait: LAST_WAIT },
+            ],
+            subtree: false,
+        }
+    }
+
+    /// Signals and waits in turn, e.g. "SIGINT 5s SIGTERM 10s SIGKILL" (arrows,
+    /// commas and a `wait` before the duration are allowed, as in "SIGINT →
+    /// wait 5s → SIGKILL"). The last signal may leave out its wait.
+    pub fn parse_ladder(text: &str) -> Result<Vec<StopStep>, String> {
+        let tokens: Vec<&str> = text
+            .split(|c: char| c.is_whitespace() || c == ',' || c == '→')
+            .filter(|token| !token.is_empty() && *token != "->" && !token.eq_ignore_ascii_case("wait"))
+            .collect();
+        let mut steps = Vec::new();
+        for pair in tokens.chunks(2) {
+            let signal = signal::parse_signal(pair[0]).ok_or_else(|| format!("unknown signal '{}' in '{}'", pair[0], text))?;
+            let wait = match pair.get(1) {
+                Some(wait) => Duration::from_secs(alerts::parse_duration(wait)?),
+                None => LAST_WAIT,
+            };
+            steps.push(StopStep { signal, wait });
+        }
+        if steps.is_empty() {
+            return Err("the stop ladder needs at least one signal".to_string());
+        }
+        Ok(steps)
+    }
+
+    /// The ladder the way `parse_ladder` takes it
+    pub fn ladder(&self) -> String {
+        let mut parts = Vec::new();
+        for (i, step) in self.steps.iter().enumerate() {
+            parts.push(signal::signal_name(step.signal));
+            if i + 1 < self.steps.len() {
+                parts.push(alerts::format_duration(step.wait.as_secs()));
+            }
+        }
+        parts.join(" ")
+    }
+}
+
+impl Default for StopPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for StopPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.ladder())?;
+        if self.subtree {
+            write!(f, " (with descendants)")?;
+        }
+        Ok(())
+    }
+}
+
+/// The stop policies from the `[[stop]]` tables of `config.toml`. The first
+/// whose `match` filter matches a process applies; a table without `match`
+/// applies to everything, and `StopPolicy::new()` when none does.
+#[derive(Clone, Debug, Default)]
+pub struct StopPolicies {
+    rules: Vec<(Option<ProcessFilter>, StopPolicy)>,
+}
+
+impl StopPolicies {
+    pub fn new() -> Self {
+        StopPolicies { rules: Vec::new() }
+    }
+
+    /// The saved policies; none when nothing was configured
+    pub fn load() -> Result<Self, String> {
+        let path = ColumnConfig::path();
+        if !path.exists() {
+            return Ok(Self::new());
+        }
+        let text = fs::read_to_string(&path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        Self::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// Parse the `[[stop]]` tables of a config file
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let table = text.parse::<toml::Table>().map_err(|e| e.to_string())?;
+        let entries = match table.get("stop") {
+            Some(toml::Value::Array(entries)) => entries.clone(),
+            Some(_) => return Err("'stop' must be a list of [[stop]] tables".to_string()),
+            None => Vec::new(),
+        };
+        let mut rules = Vec::new();
+        for (i, entry) in entries.iter().enumerate() {
+            let context = |e: String| format!("stop #{}: {}", i + 1, e);
+            let entry = entry.as_table().ok_or_else(|| context("must be a table".to_string()))?;
+            let mut filter = None;
+            let mut policy = StopPolicy::new();
+            for (key, value) in entry {
+                match (key.as_str(), value) {
+                    ("match", toml::Value::String(expression)) => filter = Some(ProcessFilter::parse(expression).map_err(context)?),
+                    ("ladder", toml::Value::String(ladder)) => policy.steps = StopPolicy::parse_ladder(ladder).map_err(context)?,
+                    ("subtree", toml::Value::Boolean(subtree)) => policy.subtree = *subtree,
+                    ("match" | "ladder", _) => return Err(context(format!("'{}' must be a string", key))),
+                    ("subtree", _) => return Err(context("'subtree' must be true or false".to_string())),
+                    (other, _) => return Err(context(format!("unknown key '{}' (expected match, ladder or subtree)", other))),
+                }
+            }
+            rules.push((filter, policy));
+        }
+        Ok(StopPolicies { rules })
+    }
+
+    /// The policy for a process; the default one when it is not in `processes`
+    pub fn for_process(&self, pid: u32, processes: &[ProcessSnapshot], physical_cores: usize) -> StopPolicy {
+        let Some(process) = processes.iter().find(|p| p.pid == pid) else { return StopPolicy::new() };
+        self.rules
+            .iter()
+            .find(|(filter, _)| filter.as_ref().is_none_or(|f| f.matches(process, physical_cores)))
+            .map(|(_, policy)| policy.clone())
+            .unwrap_or_default()
+    }
+}
+
+/// Where a stop has got to, for the status line
+#[derive(Clone, Debug)]
+pub struct StopProgress {
+    pub pid: u32,
+    pub name: String,
+    /// Index of the signal sent last
+    pub step: usize,
+    pub signal: i32,
+    /// Processes still running, out of `total`
+    pub left: usize,
+    pub total: usize,
+    /// The next signal and how long until it is sent
+    pub next: Option<(i32, Duration)>,
+}
+
+impl StopProgress {
+    /// e.g. "Stopping worker (1234): SIGINT sent, 3 of 4 processes left, SIGTERM in 4s"
+    pub fn message(&self) -> String {
+        let left = if self.total > 1 {
+            format!("{} of {} processes left", self.left, self.total)
+        } else {
+            "still running".to_string()
+        };
+        let next = match self.next {
+            Some((signal, wait)) => format!(", {} in {}s", signal::signal_name(signal), wait.as_secs() + 1),
+            None => String::new(),
+        };
+        format!("Stopping {} ({}): {} sent, {}{}", self.name, self.pid, signal::signal_name(self.signal), left, next)
+    }
+}
+
+/// How a stop ended
+#[derive(Clone, Debug)]
+pub struct StopReport {
+    pub pid: u32,
+    pub name: String,
+    /// How many processes were signalled, descendants included
+    pub total: usize,
+    /// The signal that was sent last
+    pub signal: Option<i32>,
+    /// Processes still running at the end
+    pub survivors: Vec<u32>,
+    /// Why signals could not be delivered
+    pub errors: Vec<SignalError>,
+}
+
+impl StopReport {
+    pub fn stopped(&self) -> bool {
+        self.survivors.is_empty()
+    }
+
+    /// e.g. "Stopped worker (1234) and 3 descendants after SIGTERM"
+    pub fn headline(&self) -> String {
+        let what = match self.total {
+            0 | 1 => format!("{} ({})", self.name, self.pid),
+            2 => format!("{} ({}) and 1 descendant", self.name, self.pid),
+            n => format!("{} ({}) and {} descendants", self.name, self.pid, n - 1),
+        };
+        let signal = self.signal.map(signal::signal_name).unwrap_or_else(|| "no signal".to_string());
+        if self.stopped() {
+            format!("Stopped {} after {}", what, signal)
+        } else if let Some(error) = self.errors.first() {
+            format!("Failed to stop {}: {}", what, error)
+        } else {
+            let survivors: Vec<String> = self.survivors.iter().map(u32::to_string).collect();
+            format!("{} still running after {}: PID {}", what, signal, survivors.join(", "))
+        }
+    }
+}
+
+/// The process and, when the policy says so, its descendants from the group
+/// manager, parents first, each with the start time `processes` saw (0 when
+/// they did not)
+pub fn targets(
+    pid: u32,
+    policy: &StopPolicy,
+    groups: &mut ProcessGroupManager,
+    processes: &[ProcessSnapshot],
+) -> Vec<(u32, u64)> {
+    let pids = if policy.subtree {
+        groups
+            .get_group_pids(processes, Pid::from_u32(pid))
+            .into_iter()
+            .map(|pid| pid.as_u32())
+            // Threads go with their process
+            .filter(|pid| !processes.iter().any(|p| p.pid == *pid && p.is_thread))
+            .collect()
+    } else {
+        vec![pid]
+    };
+    pids.into_iter()
+        .map(|pid| (pid, processes.iter().find(|p| p.pid == pid).map_or(0, |p| p.start_time)))
+        .collect()
+}
+
+/// Climb the ladder for `targets` (PIDs with the start time they were seen
+/// with, the first being the one asked for) until they have all exited,
+/// calling `progress` as it goes
+pub fn stop_processes(
+    name: &str,
+    targets: &[(u32, u64)],
+    policy: &StopPolicy,
+    mut progress: impl FnMut(&StopProgress),
+) -> StopReport {
+    let pid = targets.first().map(|&(pid, _)| pid).unwrap_or_default();
+    let mut report = StopReport { pid, name: name.to_string(), total: 0, signal: None, survivors: Vec::new(), errors: Vec::new() };
+    // Held throughout, so a later signal cannot reach a process that reused a
+    // PID; one already reused since it was seen counts as gone
+    let handles: Vec<ProcessHandle> = targets
+        .iter()
+        .filter_map(|&(pid, start_time)| match ProcessHandle::open_seen(pid, start_time) {
+            Ok(handle) => Some(handle),
+            Err(e) if e.is_gone() => None,
+            Err(e) => {
+                report.survivors.push(pid);
+                report.errors.push(e);
+                None
+            },
+        })
+        .collect();
+    report.total = handles.len() + report.errors.len();
+    // Processes that could not be opened stay survivors, with why
+    let unopened = report.errors.len();
+    if handles.is_empty() {
+        return report;
+    }
+
+    for (step, rung) in policy.steps.iter().enumerate() {
+        report.signal = Some(rung.signal);
+        let mut sent = 0;
+        for handle in handles.iter().filter(|h| h.is_alive()) {
+            match handle.send_number(rung.signal) {
+                Ok(()) => sent += 1,
+                Err(e) if e.is_gone() => sent += 1,
+                Err(e) => report.errors.push(e),
+            }
+        }
+        // Nothing reached any process still running (e.g. EPERM for all of
+        // them), so waiting out the ladder cannot change anything
+        if sent == 0 && handles.iter().any(ProcessHandle::is_alive) {
+            break;
+        }
+        let started = Instant::now();
+        loop {
+            let left = handles.iter().filter(|h| h.is_alive()).count();
+            if left == 0 {
+                report.errors.truncate(unopened);
+                return report;
+            }
+            let elapsed = started.elapsed();
+            if elapsed >= rung.wait {
+                break;
+            }
+            progress(&StopProgress {
+                pid,
+                name: name.to_string(),
+                step,
+                signal: rung.signal,
+                left,
+                total: handles.len(),
+                next: policy.steps.get(step + 1).map(|next| (next.signal, rung.wait - elapsed)),
+            });
+            thread::sleep(POLL);
+        }
+    }
+    report.survivors.extend(handles.iter().filter(|h| h.is_alive()).map(ProcessHandle::pid));
+    report
+}
+
+/// A stop climbing its ladder on a thread of its own, so the caller can show
+/// its progress
+pub struct StopJob {
+    pub pid: u32,
+    pub name: String,
+    progress: Arc<Mutex<Option<StopProgress>>>,
+    thread: JoinHandle<StopReport>,
+}
+
+impl StopJob {
+    /// Stop `targets` (see `stop_processes`)
+    pub fn start(name: &str, targets: Vec<(u32, u64)>, policy: StopPolicy) -> Self {
+        let progress = Arc::new(Mutex::new(None));
+        let shared = progress.clone();
+        let pid = targets.first().map(|&(pid, _)| pid).unwrap_or_default();
+        let thread_name = name.to_string();
+        let thread = thread::spawn(move || {
+            stop_processes(&thread_name, &targets, &policy, |update| {
+                *shared.lock().unwrap_or_else(|e| e.into_inner()) = Some(update.clone());
+            })
+        });
+        StopJob { pid, name: name.to_string(), progress, thread }
+    }
+
+    /// The latest progress; `None` until the first wait begins
+    pub fn progress(&self) -> Option<StopProgress> {
+        self.progress.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.thread.is_finished()
+    }
+
+    /// Wait for the stop to end
+    pub fn finish(self) -> StopReport {
+        let (pid, name) = (self.pid, self.name);
+        self.thread.join().unwrap_or_else(|_| StopReport {
+            pid,
+            name,
+            total: 1,
+            signal: None,
+            survivors: if signal::process_exists(pid) { vec![pid] } else { Vec::new() },
+            errors: Vec::new(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+
+    #[test]
+    fn ladders_parse_with_arrows_commas_and_wait() {
+        let expected = vec![
+            StopStep { signal: libc::SIGINT, wait: Duration::from_secs(5) },
+            StopStep { signal: libc::SIGTERM, wait: Duration::from_secs(10) },
+            StopStep { signal: libc::SIGKILL, wait: LAST_WAIT },
+        ];
+        for text in [
+            "SIGINT 5s SIGTERM 10s SIGKILL",
+            "SIGINT, 5s, SIGTERM, 10s, SIGKILL",
+            "SIGINT → 5s → SIGTERM → 10s → SIGKILL",
+            "SIGINT -> 5s -> SIGTERM -> 10s -> SIGKILL",
+            "SIGINT → wait 5s → SIGTERM → wait 10s → SIGKILL",
+            "INT wait 5s TERM WAIT 10s 9",
+        ] {
+            assert_eq!(StopPolicy::parse_ladder(text).unwrap(), expected, "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn bad_ladders_are_rejected() {
+        assert!(StopPolicy::parse_ladder("").is_err());
+        assert!(StopPolicy::parse_ladder("wait").is_err());
+        assert!(StopPolicy::parse_ladder("SIGNOPE 5s").unwrap_err().contains("unknown signal 'SIGNOPE'"));
+        assert!(StopPolicy::parse_ladder("SIGTERM soon SIGKILL").is_err());
+    }
+
+    #[test]
+    fn climbs_the_ladder_until_the_process_exits() {
+        let mut child = Command::new("sleep").arg("30").spawn().unwrap();
+        // SIGCONT does nothing to a running process, so SIGTERM has to follow
+        let policy = StopPolicy {
+            steps: vec![
+                StopStep { signal: libc::SIGCONT, wait: Duration::from_millis(300) },
+                StopStep { signal: libc::SIGTERM, wait: Duration::from_secs(5) },
+            ],
+            subtree: false,
+        };
+        let mut updates = 0;
+        let report = stop_processes("sleep", &[(child.id(), 0)], &policy, |_| updates += 1);
+        child.wait().unwrap();
+        assert!(report.stopped(), "{}", report.headline());
+        assert_eq!(report.signal, Some(libc::SIGTERM));
+        assert!(updates > 0);
+    }
+}
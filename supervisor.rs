@@ -0,0 +1,334 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+use crate::alerts;
+use crate::filter::ProcessFilter;
+use crate::launch::LaunchSpec;
+use crate::signal::ProcessHandle;
+use crate::snapshot::ProcessSnapshot;
+
+/// How a kept-alive process is brought back, and when Pulse gives up on it
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(default)]
+pub struct SupervisorPolicy {
+    /// Wait before the first relaunch; doubled after each quick exit
+    pub backoff: Duration,
+    pub max_backoff: Duration,
+    /// A process that ran this long was healthy, so the backoff starts over
+    pub reset_after: Duration,
+    /// This many exits within `window` is a crash loop, and relaunching stops
+    pub max_exits: u32,
+    pub window: Duration,
+}
+
+impl SupervisorPolicy {
+    pub fn new() -> Self {
+        SupervisorPolicy {
+            backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+            reset_after: Duration::from_secs(30),
+            max_exits: 5,
+            window: Duration::from_secs(60),
+        }
+    }
+
+    // The wait after `quick_exits` exits in a row that came before `reset_after`
+    fn delay(&self, quick_exits: u32) -> Duration {
+        self.backoff.saturating_mul(1 << quick_exits.min(16)).min(self.max_backoff)
+    }
+}
+
+impl Default for SupervisorPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// What to keep alive: one process, or whatever matches a filter expression
+/// (e.g. `worker` or `cmd:~/celery.*-Q jobs/`)
+#[derive(Clone, Debug)]
+pub enum Target {
+    Pid(u32),
+    Pattern(ProcessFilter),
+}
+
+impl Target {
+    /// A number is a PID, anything else a filter expression
+    pub fn parse(text: &str) -> Result<Self, String> {
+        match text.trim().parse::<u32>() {
+            Ok(pid) if pid > 0 => Ok(Target::Pid(pid)),
+            Ok(_) => Err(format!("invalid PID '{}'", text)),
+            Err(_) => ProcessFilter::parse(text).map(Target::Pattern),
+        }
+    }
+
+    fn label(&self) -> String {
+        match self {
+            Target::Pid(pid) => pid.to_string(),
+            Target::Pattern(filter) => filter.expression().to_string(),
+        }
+    }
+}
+
+/// One kept-alive target, as shown by `pulse daemon status` and in the TUI
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SupervisedStatus {
+    /// The PID or pattern it was added with
+    pub target: String,
+    pub name: String,
+    /// The process running now, if any
+    pub pid: Option<u32>,
+    pub restarts: u32,
+    pub crash_loop: bool,
+    /// e.g. "running", "restarting in 4s" or "waiting for a match"
+    pub state: String,
+}
+
+struct Supervised {
+    target: Target,
+    policy: SupervisorPolicy,
+    // How to start it again, read from the last process it was attached to
+    spec: Option<LaunchSpec>,
+    handle: Option<ProcessHandle>,
+    started: Instant,
+    restarts: u32,
+    quick_exits: u32,
+    exits: VecDeque<Instant>,
+    next_launch: Option<Instant>,
+    crash_loop: bool,
+}
+
+impl Supervised {
+    fn name(&self) -> String {
+        match &self.spec {
+            Some(spec) => spec.name.clone(),
+            None => self.target.label(),
+        }
+    }
+
+    fn attach(&mut self, pid: u32) -> Result<(), String> {
+        let spec = LaunchSpec::capture(pid)?;
+        self.handle = Some(ProcessHandle::open(pid).map_err(|e| e.to_string())?);
+        self.spec = Some(spec);
+        self.started = Instant::now();
+        self.next_launch = None;
+        Ok(())
+    }
+
+    // Count an exit or a failed launch and decide when to try again
+    fn exited(&mut self, what: String) -> String {
+        let now = Instant::now();
+        if self.started.elapsed() >= self.policy.reset_after {
+            self.quick_exits = 0;
+        }
+        self.exits.push_back(now);
+        while self.exits.front().is_some_and(|t| now.duration_since(*t) > self.policy.window) {
+            self.exits.pop_front();
+        }
+        if self.exits.len() >= self.policy.max_exits as usize {
+            self.crash_loop = true;
+            self.next_launch = None;
+            return format!(
+                "{}; {} exits within {}, so it is crash-looping and will not be restarted",
+                what,
+                self.exits.len(),
+                alerts::format_duration(self.policy.window.as_secs())
+            );
+        }
+        let delay = self.policy.delay(self.quick_exits);
+        self.quick_exits += 1;
+        self.next_launch = Some(now + delay);
+        format!("{}; restarting in {}", what, alerts::format_duration(delay.as_secs()))
+    }
+
+    fn launch(&mut self) -> String {
+        let Some(spec) = &self.spec else { return String::new() };
+        match spec.spawn() {
+            Ok(launched) => {
+                self.restarts += 1;
+                self.started = Instant::now();
+                self.next_launch = None;
+                self.handle = ProcessHandle::open(launched.pid).ok();
+                format!("Restarted {} as {} (restart {})", spec.name, launched.pid, self.restarts)
+            },
+            Err(e) => {
+                self.started = Instant::now();
+                let what = format!("Failed to restart {}: {}", spec.name, e);
+                self.exited(what)
+            },
+        }
+    }
+
+    fn status(&self) -> SupervisedStatus {
+        let state = if self.crash_loop {
+            "crash loop".to_string()
+        } else if self.handle.is_some() {
+            "running".to_string()
+        } else if let Some(at) = self.next_launch {
+            format!("restarting in {}", alerts::format_duration(at.saturating_duration_since(Instant::now()).as_secs()))
+        } else {
+            "waiting for a match".to_string()
+        };
+        SupervisedStatus {
+            target: self.target.label(),
+            name: self.name(),
+            pid: self.handle.as_ref().map(ProcessHandle::pid),
+            restarts: self.restarts,
+            crash_loop: self.crash_loop,
+            state,
+        }
+    }
+}
+
+/// Keeps processes running: when one exits it is started again the way it was
+/// started, with exponential backoff between attempts, until it exits too
+/// often and counts as crash-looping. `check` does the work, once per refresh.
+pub struct Supervisor {
+    entries: Vec<Supervised>,
+}
+
+impl Supervisor {
+    pub fn new() -> Self {
+        Supervisor { entries: Vec::new() }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Start keeping `target` alive. Adding a target again starts its crash-loop
+    /// count over. A pattern that matches nothing yet is kept until something
+    /// matches. Returns a message for the user.
+    pub fn keep_alive(
+        &mut self,
+        target: &str,
+        policy: SupervisorPolicy,
+        processes: &[ProcessSnapshot],
+        physical_cores: usize,
+    ) -> Result<String, String> {
+        let target = Target::parse(target)?;
+        let label = target.label();
+        if let Some(entry) = self.entries.iter_mut().find(|e| e.target.label() == label) {
+            entry.policy = policy;
+            if !entry.crash_loop {
+                return Ok(format!("Already keeping {} alive", entry.name()));
+            }
+            entry.crash_loop = false;
+            entry.exits.clear();
+            entry.quick_exits = 0;
+            entry.next_launch = entry.spec.as_ref().map(|_| Instant::now());
+            return Ok(format!("Keeping {} alive again", entry.name()));
+        }
+
+        let mut entry = Supervised {
+            target,
+            policy,
+            spec: None,
+            handle: None,
+            started: Instant::now(),
+            restarts: 0,
+            quick_exits: 0,
+            exits: VecDeque::new(),
+            next_launch: None,
+            crash_loop: false,
+        };
+        let message = match &entry.target {
+            Target::Pid(pid) => {
+                let pid = *pid;
+                if pid == std::process::id() {
+                    return Err(format!("Process {} is Pulse itself", pid));
+                }
+                entry.attach(pid)?;
+                format!("Keeping {} ({}) alive", entry.name(), pid)
+            },
+            Target::Pattern(_) => match self.find_match(&entry, processes, physical_cores) {
+                Some(pid) => {
+                    entry.attach(pid)?;
+                    format!("Keeping {} ({}) alive", entry.name(), pid)
+                },
+                None => format!("Nothing matches '{}' yet; it will be kept alive once something does", label),
+            },
+        };
+        self.entries.push(entry);
+        Ok(message)
+    }
+
+    /// Stop keeping a target alive; the process itself keeps running. Takes the
+    /// target as it was added, or the PID it runs as now.
+    pub fn release(&mut self, target: &str) -> Option<String> {
+        let target = target.trim();
+        let index = self.entries.iter().position(|e| {
+            e.target.label() == target || e.handle.as_ref().is_some_and(|h| h.pid().to_string() == target)
+        })?;
+        let entry = self.entries.remove(index);
+        Some(format!("No longer keeping {} alive", entry.name()))
+    }
+
+    /// After something else restarted a kept-alive process, follow the new one
+    /// rather than starting a second copy
+    pub fn follow(&mut self, old: u32, new: u32) {
+        if let Some(entry) = self.entries.iter_mut().find(|e| e.handle.as_ref().is_some_and(|h| h.pid() == old)) {
+            if entry.attach(new).is_ok() {
+                entry.restarts += 1;
+            }
+        }
+    }
+
+    /// Relaunch what exited once its backoff has passed, and pick up processes
+    /// that match a pattern. Returns the lines to log.
+    pub fn check(&mut self, processes: &[ProcessSnapshot], physical_cores: usize) -> Vec<String> {
+        let mut log = Vec::new();
+        for i in 0..self.entries.len() {
+            let entry = &mut self.entries[i];
+            if let Some(handle) = &entry.handle {
+                if handle.is_alive() {
+                    continue;
+                }
+                let what = format!("{} ({}) exited after {:.1}s", entry.name(), handle.pid(), entry.started.elapsed().as_secs_f64());
+                entry.handle = None;
+                log.push(entry.exited(what));
+            }
+            if entry.crash_loop {
+                continue;
+            }
+
+            // Something else started a match meanwhile: look after that one instead
+            if matches!(entry.target, Target::Pattern(_)) {
+                if let Some(pid) = self.find_match(&self.entries[i], processes, physical_cores) {
+                    let entry = &mut self.entries[i];
+                    if entry.attach(pid).is_ok() {
+                        log.push(format!("Keeping {} ({}) alive", entry.name(), pid));
+                        continue;
+                    }
+                }
+            }
+            let entry = &mut self.entries[i];
+            if entry.next_launch.is_some_and(|at| Instant::now() >= at) {
+                log.push(entry.launch());
+            }
+        }
+        log
+    }
+
+    pub fn status(&self) -> Vec<SupervisedStatus> {
+        self.entries.iter().map(Supervised::status).collect()
+    }
+
+    // A running process matching the entry's pattern that no other entry looks after
+    fn find_match(&self, entry: &Supervised, processes: &[ProcessSnapshot], physical_cores: usize) -> Option<u32> {
+        let Target::Pattern(filter) = &entry.target else { return None };
+        let own = std::process::id();
+        processes
+            .iter()
+            .filter(|p| !p.is_thread && p.pid != own && filter.matches(p, physical_cores))
+            .filter(|p| !self.entries.iter().any(|e| e.handle.as_ref().is_some_and(|h| h.pid() == p.pid)))
+            .map(|p| p.pid)
+            .find(|&pid| ProcessHandle::open(pid).is_ok_and(|h| h.is_alive()))
+    }
+}
+
+impl Default for Supervisor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,271 @@
+use std::collections::{HashMap, HashSet};
+use crate::process_groups::ProcessNode;
+use crate::snapshot::ProcessSnapshot;
+
+/// One visible line of the tree view
+#[derive(Clone, Debug)]
+pub struct TreeRow {
+    pub pid: u32,
+    pub name: String,
+    /// Box-drawing prefix, e.g. `│  ├─ `
+    pub prefix: String,
+    pub child_count: usize,
+    pub collapsed: bool,
+    pub is_thread: bool,
+    /// Usage of this process alone
+    pub cpu: f64,
+    pub mem: f64,
+    /// Usage of this process and every process below it (threads are not counted twice)
+    pub total_cpu: f64,
+    pub total_mem: f64,
+    pub descendants: usize,
+    /// True when the row matches the search query
+    pub matched: bool,
+}
+
+#[derive(Clone, Copy, Default)]
+struct Rollup {
+    cpu: f64,
+    mem: f64,
+    descendants: usize,
+}
+
+/// Collapsible process tree built from `ProcessNode`s, with the cursor kept on
+/// the same PID across rebuilds
+pub struct TreeView {
+    collapsed: HashSet<u32>,
+    rows: Vec<TreeRow>,
+    selected: usize,
+    selected_pid: Option<u32>,
+    scroll: usize,
+    query: String,
+}
+
+impl TreeView {
+    pub fn new() -> Self {
+        TreeView {
+            collapsed: HashSet::new(),
+            rows: Vec::new(),
+            selected: 0,
+            selected_pid: None,
+            scroll: 0,
+            query: String::new(),
+        }
+    }
+
+    /// Rebuild the visible rows. `physical_cores` scales CPU the same way as the process list.
+    pub fn rebuild(&mut self, nodes: &[ProcessNode], processes: &[ProcessSnapshot], physical_cores: usize) {
+        let cores = physical_cores.max(1) as f64;
+        let by_pid: HashMap<u32, &ProcessSnapshot> = processes.iter().map(|p| (p.pid, p)).collect();
+
+        let mut rollups = HashMap::new();
+        for node in nodes {
+            compute_rollup(node, &by_pid, cores, &mut rollups);
+        }
+
+        // While searching, show the matches and the path down to them, expanded
+        let query = self.query.to_lowercase();
+        let mut on_path = HashSet::new();
+        if !query.is_empty() {
+            for node in nodes {
+                mark_matches(node, &query, &mut on_path);
+            }
+        }
+
+        let mut rows = Vec::new();
+        let count = nodes.len();
+        for (i, node) in nodes.iter().enumerate() {
+            self.flatten(node, "", i + 1 == count, &query, &on_path, &by_pid, cores, &rollups, &mut rows);
+        }
+        self.rows = rows;
+
+        // Keep the cursor on the same process if it is still visible
+        if let Some(index) = self.selected_pid.and_then(|pid| self.rows.iter().position(|r| r.pid == pid)) {
+            self.selected = index;
+        }
+        self.selected = self.selected.min(self.rows.len().saturating_sub(1));
+        self.selected_pid = self.rows.get(self.selected).map(|r| r.pid);
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn flatten(
+        &self,
+        node: &ProcessNode,
+        prefix: &str,
+        is_last: bool,
+        query: &str,
+        on_path: &HashSet<u32>,
+        by_pid: &HashMap<u32, &ProcessSnapshot>,
+        cores: f64,
+        rollups: &HashMap<u32, Rollup>,
+        rows: &mut Vec<TreeRow>,
+    ) {
+        let pid = node.pid.as_u32();
+        let searching = !query.is_empty();
+        if searching && !on_path.contains(&pid) {
+            return;
+        }
+
+        let process = by_pid.get(&pid);
+        let rollup = rollups.get(&pid).copied().unwrap_or_default();
+        let collapsed = !searching && self.collapsed.contains(&pid) && !node.children.is_empty();
+        let branch = if is_last { "└─ " } else { "├─ " };
+
+        rows.push(TreeRow {
+            pid,
+            name: node.name.clone(),
+            prefix: format!("{}{}", prefix, branch),
+            child_count: node.children.len(),
+            collapsed,
+            is_thread: process.is_some_and(|p| p.is_thread),
+            cpu: process.map_or(0.0, |p| p.cpu as f64 / cores),
+            mem: process.map_or(0.0, |p| p.mem),
+            total_cpu: rollup.cpu,
+            total_mem: rollup.mem,
+            descendants: rollup.descendants,
+            matched: searching && node_matches(node, query),
+        });
+
+        if collapsed {
+            return;
+        }
+        let child_prefix = format!("{}{}", prefix, if is_last { "   " } else { "│  " });
+        let count = node.children.len();
+        for (i, child) in node.children.iter().enumerate() {
+            self.flatten(child, &child_prefix, i + 1 == count, query, on_path, by_pid, cores, rollups, rows);
+        }
+    }
+
+    pub fn rows(&self) -> &[TreeRow] {
+        &self.rows
+    }
+
+    pub fn selected_index(&self) -> usize {
+        self.selected
+    }
+
+    pub fn selected(&self) -> Option<&TreeRow> {
+        self.rows.get(self.selected)
+    }
+
+    pub fn selected_pid(&self) -> Option<u32> {
+        self.selected_pid
+    }
+
+    /// Move the cursor by `delta` rows, stopping at either end
+    pub fn move_by(&mut self, delta: isize) {
+        if self.rows.is_empty() {
+            return;
+        }
+        let last = self.rows.len() as isize - 1;
+        self.selected = (self.selected as isize).saturating_add(delta).clamp(0, last) as usize;
+        self.selected_pid = Some(self.rows[self.selected].pid);
+    }
+
+    /// First row to draw so the cursor stays within `height` visible rows
+    pub fn scroll_for(&mut self, height: usize) -> usize {
+        let height = height.max(1);
+        if self.selected < self.scroll {
+            self.scroll = self.selected;
+        } else if self.selected >= self.scroll + height {
+            self.scroll = self.selected + 1 - height;
+        }
+        self.scroll
+    }
+
+    /// Collapse the selected node, or go to its parent if it is already collapsed or a leaf
+    pub fn collapse(&mut self) {
+        let row = match self.selected() {
+            Some(row) => row.clone(),
+            None => return,
+        };
+        if row.child_count > 0 && !row.collapsed && self.query.is_empty() {
+            self.collapsed.insert(row.pid);
+            return;
+        }
+        // The parent is the closest row above with a shorter prefix
+        let depth = row.prefix.chars().count();
+        if let Some(parent) = self.rows[..self.selected].iter().rposition(|r| r.prefix.chars().count() < depth) {
+            self.selected = parent;
+            self.selected_pid = Some(self.rows[parent].pid);
+        }
+    }
+
+    pub fn expand(&mut self) {
+        if let Some(pid) = self.selected_pid {
+            self.collapsed.remove(&pid);
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        if let Some((pid, child_count)) = self.selected().map(|r| (r.pid, r.child_count)) {
+            if !self.collapsed.remove(&pid) && child_count > 0 {
+                self.collapsed.insert(pid);
+            }
+        }
+    }
+
+    pub fn expand_all(&mut self) {
+        self.collapsed.clear();
+    }
+
+    pub fn query(&self) -> &str {
+        &self.query
+    }
+
+    pub fn set_query(&mut self, query: String) {
+        self.query = query;
+    }
+
+    /// Move to the next row matching the search, wrapping around
+    pub fn next_match(&mut self) {
+        let count = self.rows.len();
+        if let Some(offset) = (1..=count).find(|i| self.rows[(self.selected + i) % count].matched) {
+            self.selected = (self.selected + offset) % count;
+            self.selected_pid = Some(self.rows[self.selected].pid);
+        }
+    }
+}
+
+impl Default for TreeView {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn compute_rollup(
+    node: &ProcessNode,
+    by_pid: &HashMap<u32, &ProcessSnapshot>,
+    cores: f64,
+    rollups: &mut HashMap<u32, Rollup>,
+) -> Rollup {
+    let mut rollup = match by_pid.get(&node.pid.as_u32()) {
+        // Threads share their process's memory and CPU accounting
+        Some(p) if !p.is_thread => Rollup { cpu: p.cpu as f64 / cores, mem: p.mem, descendants: 0 },
+        _ => Rollup::default(),
+    };
+    for child in &node.children {
+        let child_rollup = compute_rollup(child, by_pid, cores, rollups);
+        rollup.cpu += child_rollup.cpu;
+        rollup.mem += child_rollup.mem;
+        rollup.descendants += child_rollup.descendants + 1;
+    }
+    rollups.insert(node.pid.as_u32(), rollup);
+    rollup
+}
+
+fn node_matches(node: &ProcessNode, query: &str) -> bool {
+    node.name.to_lowercase().contains(query) || node.pid.as_u32().to_string() == query
+}
+
+// Record every node that matches or has a matching descendant; returns whether this node does
+fn mark_matches(node: &ProcessNode, query: &str, on_path: &mut HashSet<u32>) -> bool {
+    let mut found = node_matches(node, query);
+    for child in &node.children {
+        found |= mark_matches(child, query, on_path);
+    }
+    if found {
+        on_path.insert(node.pid.as_u32());
+    }
+    found
+}
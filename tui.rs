@@ -0,0 +1,2014 @@
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::fmt::Write as FmtWrite;
+use std::io::{stdout, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use std::thread;
+use signal_hook::consts::signal::SIGINT;
+use signal_hook::flag;
+use nix::sys::signal::Signal;
+use sysinfo::Pid;
+use termion::event::Key;
+use termion::input::TermRead;
+use termion::raw::IntoRawMode;
+use termion::{clear, cursor};
+
+use crate::alert_actions::{ActionContext, ActionOutcome, ActionRunner, PendingAction};
+use crate::alert_sinks::{self, SinkDispatcher};
+use crate::alerts::{self, AlertEngine, AlertEvent, AlertState, Severity};
+use crate::columns::{self, Column, ColumnConfig};
+use crate::csv_export::{CsvExporter, CsvOptions};
+use crate::daemon::{self, DaemonClient, DaemonStatus, Request};
+use crate::exporter::{ExportData, Exporter};
+use crate::filter::ProcessFilter;
+use crate::graph_panel::{GraphPanel, GraphWindow};
+use crate::help::get_help_text;
+use crate::history::{self, format_time, History, HistoryConfig, Recorder};
+use crate::json_export::JsonExporter;
+use crate::pause_resume::{ProcessAction, ProcessController};
+use crate::priority;
+use crate::process_groups::ProcessGroupManager;
+use crate::replay::Replay;
+use crate::restart::{ProcessRestarter, RestartError, RestartMethod, RestartReport};
+use crate::signal::{self, ProcessHandle, SignalInfo};
+use crate::tree_view::TreeView;
+use crate::snapshot::{sort_snapshots, Collector, ProcessSnapshot, ProcessState, SortMode, SystemSummary};
+use crate::stop::{self, StopJob, StopPolicies};
+use crate::supervisor::{SupervisedStatus, Supervisor, SupervisorPolicy};
+
+// Terminal colors and styles
+const RESET: &str = "\x1B[0m";
+const BOLD: &str = "\x1B[1m";
+const HEADER_COLOR: &str = "\x1B[38;5;39m"; // Bright blue for headers
+const TITLE_COLOR: &str = "\x1B[38;5;82m";  // Green for title
+const SEPARATOR_COLOR: &str = "\x1B[38;5;240m"; // Dark gray for separators
+const RUNNING_COLOR: &str = "\x1B[38;5;82m"; // Green for running processes
+const SLEEP_COLOR: &str = "\x1B[38;5;50m"; // Gray for sleeping processes
+const IDLE_COLOR: &str = "\x1B[38;5;201m"; // Pink for idle processes
+const STOPPED_COLOR: &str = "\x1B[38;5;124m"; // Red for stopped processes
+const ZOMBIE_COLOR: &str = "\x1B[38;5;27m"; // Blue for zombie processes
+const HIGH_USAGE_COLOR: &str = "\x1B[38;5;196m"; // Red for high CPU/memory usage
+const MEDIUM_USAGE_COLOR: &str = "\x1B[38;5;220m"; // Yellow for medium usage
+const USER_COLOR: &str = "\x1B[38;5;147m"; // Light purple for username
+const HELP_COLOR: &str = "\x1B[38;5;33m"; // Blue for help text
+const PAUSED_COLOR: &str = "\x1B[38;5;208m"; // Orange for paused processes
+const RESTART_COLOR: &str = "\x1B[38;5;183m"; // Light purple for restart text
+const FG_COLOR: &str = "\x1B[38;5;201m"; // bright pink for FG
+const BG_COLOR: &str = "\x1B[38;5;39m";  // bright light blue for BG
+const ALERT_COLOR: &str = "\x1B[38;5;196m";
+
+// Where the J/E shortcuts write their exports
+const EXPORT_DIR: &str = "/home/adham-mohamed/Desktop";
+
+// How often the process list is refreshed
+const REFRESH_INTERVAL: Duration = Duration::from_millis(1500);
+// How often we poll for key presses between refreshes
+const INPUT_POLL: Duration = Duration::from_millis(20);
+// How often the status line is redrawn while processes are being stopped
+const STOP_REDRAW: Duration = Duration::from_millis(250);
+
+const STATUS_TICKS: u32 = 6;
+const NOTIFICATION_TICKS: u32 = 4;
+// Older alert notifications are dropped beyond this many
+const MAX_NOTIFICATIONS: usize = 5;
+
+/// Optional features switched on by the individual Pulse binaries
+#[derive(Clone, Copy, Default)]
+pub struct TuiOptions {
+    /// F: move a process between foreground and background
+    pub fg_bg_switch: bool,
+    /// Evaluate alert rules (W adjusts the cpu/memory thresholds)
+    pub thresholds: bool,
+    /// V: live graphs of processes and system-wide usage
+    pub graphs: bool,
+    /// Record metrics history in the background while Pulse runs
+    pub record_history: bool,
+}
+
+// Enum to track current input mode
+#[derive(PartialEq)]
+enum InputMode {
+    Normal,
+    Search,
+    Kill,
+    Pause,
+    Restart,
+    KeepAlive,
+    Nice,
+    Groups,
+    Tree,
+    TreeSearch,
+    TreeNice,
+    TreeConfirm,
+    Help,
+    FgBgSwitch,
+    FgBgConfirm,
+    ThresholdConfig,
+    Graph,
+    GraphView,
+    Signal,
+    SignalMenu,
+    SignalReport,
+    AlertConfirm,
+    Columns,
+    ReplaySeek,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ThresholdKind {
+    Cpu,
+    Memory,
+}
+
+// Destructive tree actions wait for a y/n confirmation
+#[derive(Clone, Copy, PartialEq)]
+enum TreeAction {
+    Kill,
+    Restart,
+}
+
+// Which processes the signal menu sends to
+#[derive(Clone, Copy, PartialEq)]
+enum SignalTarget {
+    Process,
+    Subtree,
+    ProcessGroup,
+}
+
+impl SignalTarget {
+    fn label(&self) -> &'static str {
+        match self {
+            SignalTarget::Process => "Process",
+            SignalTarget::Subtree => "Subtree",
+            SignalTarget::ProcessGroup => "Process group",
+        }
+    }
+
+    fn next(&self) -> Self {
+        match self {
+            SignalTarget::Process => SignalTarget::Subtree,
+            SignalTarget::Subtree => SignalTarget::ProcessGroup,
+            SignalTarget::ProcessGroup => SignalTarget::Process,
+        }
+    }
+}
+
+struct Notification {
+    message: String,
+    color: &'static str,
+    ticks_left: u32,
+}
+
+struct App {
+    options: TuiOptions,
+    collector: Collector,
+    processes: Vec<ProcessSnapshot>,
+    summary: SystemSummary,
+    last_refresh: Option<Instant>,
+
+    sort_mode: SortMode,
+    input_mode: InputMode,
+    search_query: String,
+    filter: Option<ProcessFilter>,
+    pid_input: String,
+    quit: bool,
+    status_message: String,
+    status_timer: u32,
+
+    process_restarter: ProcessRestarter,
+    stop_policies: StopPolicies,
+    // Kills still climbing their stop ladder; their progress is the status line
+    stops: Vec<StopJob>,
+    // Processes this Pulse keeps alive itself; the daemon's are in its status
+    supervisor: Supervisor,
+    process_controller: ProcessController,
+    group_manager: ProcessGroupManager,
+
+    tree: TreeView,
+    tree_pending: Option<TreeAction>,
+
+    // Row cursor in the main list, kept on the same PID across refreshes
+    cursor_pid: Option<u32>,
+    list_scroll: usize,
+    list_rows: usize,
+    marked: BTreeSet<u32>,
+
+    manual_fg_bg_map: HashMap<u32, String>,
+    pending_fg_bg: Option<u32>,
+
+    notifications: Vec<Notification>,
+    alerts: AlertEngine,
+    actions: ActionRunner,
+    pending_actions: VecDeque<PendingAction>,
+    sinks: SinkDispatcher,
+    threshold_target: Option<ThresholdKind>,
+
+    // While attached, the daemon evaluates the alerts and keeps the processes paused through it
+    daemon: Option<DaemonClient>,
+    daemon_status: Option<DaemonStatus>,
+    daemon_events: u64,
+
+    graphs: GraphPanel,
+    history: Option<History>,
+    recorder: Option<Recorder>,
+
+    signals: Vec<SignalInfo>,
+    signal_pid: Option<u32>,
+    signal_index: usize,
+    signal_scroll: usize,
+    signal_target: SignalTarget,
+    signal_report: Vec<String>,
+
+    columns: ColumnConfig,
+
+    // Recorded snapshots shown instead of live data
+    replay: Option<Replay>,
+}
+
+/// Run the interactive Pulse process monitor until the user quits
+pub fn run(options: TuiOptions) {
+    run_app(options, None);
+}
+
+/// Browse recorded snapshots in the monitor, read-only: the list, filter, tree
+/// and graphs work as when live, and actions on processes are turned off
+pub fn replay(replay: Replay) {
+    let options = TuiOptions { graphs: true, ..TuiOptions::default() };
+    run_app(options, Some(replay));
+}
+
+fn run_app(options: TuiOptions, replay: Option<Replay>) {
+    let mut stdout = stdout().into_raw_mode().unwrap();
+    let mut buffer = String::new();
+
+    // Setup Ctrl+C handler
+    let running = Arc::new(AtomicBool::new(true));
+    flag::register(SIGINT, running.clone()).unwrap();
+
+    // Create input iterator ONCE for the main loop
+    let mut input = termion::async_stdin().keys();
+
+    // Show the help screen and wait for Enter before entering Pulse
+    write!(buffer, "{}{}{}", cursor::Hide, clear::All, cursor::Goto(1, 1)).unwrap();
+    write!(buffer, "{}\r\n", get_help_text()).unwrap();
+    stdout.write_all(buffer.as_bytes()).unwrap();
+    stdout.flush().unwrap();
+    buffer.clear();
+
+    while running.load(Ordering::Relaxed) {
+        if let Some(Ok(Key::Char('\n'))) = input.next() {
+            break;
+        }
+        thread::sleep(INPUT_POLL);
+    }
+
+    let mut app = App::new(options, replay);
+
+    while !app.quit && running.load(Ordering::Relaxed) {
+        if app.last_refresh.is_none_or(|t| t.elapsed() >= REFRESH_INTERVAL) {
+            app.refresh();
+        }
+
+        app.finish_stops();
+
+        let frame = app.render();
+        stdout.write_all(frame.as_bytes()).unwrap();
+        stdout.flush().unwrap();
+        let drawn = Instant::now();
+
+        // Wait for a key press or the next refresh, whichever comes first
+        loop {
+            if let Some(Ok(key)) = input.next() {
+                app.handle_key(key);
+                break;
+            }
+            if app.last_refresh.is_none_or(|t| t.elapsed() >= REFRESH_INTERVAL)
+                || (!app.stops.is_empty() && drawn.elapsed() >= STOP_REDRAW)
+                || !running.load(Ordering::Relaxed)
+            {
+                break;
+            }
+            thread::sleep(INPUT_POLL);
+        }
+    }
+
+    // Resume all paused processes before exiting
+    app.process_controller.resume_all();
+    app.group_manager.resume_all();
+
+    // Clean up terminal
+    write!(stdout, "{}{}{}", clear::All, cursor::Goto(1, 1), cursor::Show).unwrap();
+    stdout.flush().unwrap();
+
+    // Leaving halfway up a ladder could leave a process interrupted but running
+    if !app.stops.is_empty() {
+        write!(stdout, "Waiting for {} process(es) to stop...\r\n", app.stops.len()).unwrap();
+        stdout.flush().unwrap();
+        for job in app.stops.drain(..) {
+            job.finish();
+        }
+    }
+    app.actions.wait_for_stops();
+}
+
+impl App {
+    fn new(options: TuiOptions, replay: Option<Replay>) -> Self {
+        let collector = Collector::new();
+        let summary = collector.summary();
+        let (rules, rules_error) = if options.thresholds {
+            match alerts::load_default_rules() {
+                Ok(rules) => (rules, None),
+                Err(e) => (alerts::default_rules(), Some(format!("{}; using the built-in rules", e))),
+            }
+        } else {
+            (Vec::new(), None)
+        };
+        let (sinks, sinks_error) = if options.thresholds {
+            match alert_sinks::load_default_sinks() {
+                Ok(sinks) => (sinks, None),
+                Err(e) => (Vec::new(), Some(format!("{}; alerts are only shown on screen", e))),
+            }
+        } else {
+            (Vec::new(), None)
+        };
+        let (columns, columns_error) = match ColumnConfig::load() {
+            Ok(columns) => (columns, None),
+            Err(e) => (ColumnConfig::new(), Some(format!("{}; using the default columns", e))),
+        };
+        let (stop_policies, stop_error) = match StopPolicies::load() {
+            Ok(policies) => (policies, None),
+            Err(e) => (StopPolicies::new(), Some(format!("{}; using the default stop policy", e))),
+        };
+        // Another recorder (e.g. `pulse record`) may already own the history; then we only read it
+        let recorder = if options.record_history { Recorder::start(HistoryConfig::default()).ok() } else { None };
+        let mut app = App {
+            options,
+            collector,
+            processes: Vec::new(),
+            summary,
+            last_refresh: None,
+            sort_mode: SortMode::Cpu,
+            input_mode: InputMode::Normal,
+            search_query: String::new(),
+            filter: None,
+            pid_input: String::new(),
+            quit: false,
+            status_message: String::new(),
+            status_timer: 0,
+            process_restarter: ProcessRestarter::new(),
+            stop_policies,
+            stops: Vec::new(),
+            supervisor: Supervisor::new(),
+            process_controller: ProcessController::new(),
+            group_manager: ProcessGroupManager::new(),
+            tree: TreeView::new(),
+            tree_pending: None,
+            cursor_pid: None,
+            list_scroll: 0,
+            list_rows: 1,
+            marked: BTreeSet::new(),
+            manual_fg_bg_map: HashMap::new(),
+            pending_fg_bg: None,
+            notifications: Vec::new(),
+            alerts: AlertEngine::new(rules),
+            actions: ActionRunner::new(),
+            pending_actions: VecDeque::new(),
+            sinks: SinkDispatcher::start(sinks),
+            daemon: None,
+            daemon_status: None,
+            daemon_events: 0,
+            threshold_target: None,
+            graphs: GraphPanel::new(),
+            history: None,
+            recorder,
+            signals: signal::all_signals(),
+            signal_pid: None,
+            signal_index: 0,
+            signal_scroll: 0,
+            signal_target: SignalTarget::Process,
+            signal_report: Vec::new(),
+            columns,
+            replay,
+        };
+        if app.replay.is_none() && app.attach_daemon().is_ok() {
+            app.set_status("Attached to the Pulse daemon (D to detach)".to_string());
+        }
+        if let Some(error) = rules_error.or(sinks_error).or(columns_error).or(stop_error) {
+            app.set_status(error);
+        }
+        app
+    }
+
+    fn refresh(&mut self) {
+        if let Some(replay) = &self.replay {
+            let frame = replay.frame();
+            self.processes = frame.processes.clone();
+            self.summary = frame.summary.clone();
+        } else {
+            self.collector.refresh();
+            self.processes = self.collector.snapshot();
+            self.summary = self.collector.summary();
+        }
+        sort_snapshots(&mut self.processes, self.sort_mode);
+        self.group_manager.force_update(&self.processes);
+        self.last_refresh = Some(Instant::now());
+        if self.options.graphs && self.replay.is_none() {
+            self.graphs.sample(&self.processes, &self.summary, self.collector.core_usage());
+        }
+        if self.replay.is_none() {
+            for message in self.supervisor.check(&self.processes, self.summary.physical_cores) {
+                self.notifications.push(Notification { message, color: RESTART_COLOR, ticks_left: NOTIFICATION_TICKS });
+            }
+            for error in self.recorder.iter().flat_map(Recorder::errors) {
+                self.notifications.push(Notification { message: error, color: ALERT_COLOR, ticks_left: NOTIFICATION_TICKS });
+            }
+            let excess = self.notifications.len().saturating_sub(MAX_NOTIFICATIONS);
+            self.notifications.drain(..excess);
+        }
+        if self.daemon.is_some() {
+            self.sync_daemon_status();
+        }
+        if self.options.thresholds && self.daemon.is_some() {
+            self.check_daemon_alerts();
+        } else if self.options.thresholds {
+            self.check_alerts();
+            // Ask about queued actions once the user is not in the middle of something
+            if self.input_mode == InputMode::Normal && !self.pending_actions.is_empty() {
+                self.input_mode = InputMode::AlertConfirm;
+            }
+        }
+
+        // Forget marks on processes that have exited
+        let processes = &self.processes;
+        self.marked.retain(|pid| processes.iter().any(|p| p.pid == *pid));
+
+        // Status and notification timers count refreshes, not key presses
+        self.status_timer = self.status_timer.saturating_sub(1);
+        for notification in &mut self.notifications {
+            notification.ticks_left = notification.ticks_left.saturating_sub(1);
+        }
+        self.notifications.retain(|n| n.ticks_left > 0);
+    }
+
+    fn set_status(&mut self, message: String) {
+        self.status_message = message;
+        self.status_timer = STATUS_TICKS;
+    }
+
+    /// CPU usage as shown on screen: normalised to the number of physical cores
+    fn display_cpu(&self, process: &ProcessSnapshot) -> f64 {
+        process.cpu as f64 / self.summary.physical_cores.max(1) as f64
+    }
+
+    /// Processes in display order, narrowed by the active filter
+    fn display_processes(&self) -> Vec<&ProcessSnapshot> {
+        // While a filter is being typed, preview it as soon as it parses
+        let preview = match self.input_mode {
+            InputMode::Search => ProcessFilter::parse(&self.search_query).ok(),
+            _ => None,
+        };
+        let filter = preview.as_ref().or(self.filter.as_ref());
+        let pid_digits = self.input_mode == InputMode::FgBgSwitch && !self.search_query.is_empty();
+        let cores = self.summary.physical_cores;
+
+        self.processes
+            .iter()
+            .filter(|p| filter.is_none_or(|f| f.matches(p, cores)))
+            .filter(|p| !pid_digits || p.pid.to_string().contains(&self.search_query))
+            .collect()
+    }
+
+    /// The processes J/E write out: the marked rows, or everything matching the active filter
+    fn export_processes(&self) -> Vec<ProcessSnapshot> {
+        if !self.marked.is_empty() {
+            return self.processes.iter().filter(|p| self.marked.contains(&p.pid)).cloned().collect();
+        }
+        match &self.filter {
+            Some(filter) => filter.apply(&self.processes, self.summary.physical_cores),
+            None => self.processes.clone(),
+        }
+    }
+
+    fn render(&mut self) -> String {
+        let mut buffer = String::new();
+        let (width, height) = termion::terminal_size().unwrap_or((80, 24));
+
+        write!(buffer, "{}{}", cursor::Goto(1, 1), clear::All).unwrap();
+
+        match self.input_mode {
+            InputMode::Help => {
+                write!(buffer, "{}", get_help_text()).unwrap();
+                return buffer;
+            },
+            InputMode::GraphView => {
+                write!(buffer, "{}", self.graphs.render(width as usize, height.saturating_sub(1) as usize)).unwrap();
+                write!(buffer, "{}1/5/h or Tab: Window | V: Change PIDs | Esc: Return", cursor::Goto(1, height)).unwrap();
+                if self.replay.is_some() {
+                    write!(buffer, " | ←/→: Back/Forward").unwrap();
+                }
+                return buffer;
+            },
+            InputMode::SignalMenu => self.render_signal_menu(&mut buffer, height),
+            InputMode::SignalReport => {
+                for line in &self.signal_report {
+                    write!(buffer, "{}\r\n", line).unwrap();
+                }
+                write!(buffer, "\r\nPress Enter to return...").unwrap();
+                return buffer;
+            },
+            InputMode::Tree | InputMode::TreeSearch | InputMode::TreeNice | InputMode::TreeConfirm => {
+                self.render_tree(&mut buffer, width, height);
+            },
+            _ => {
+                self.render_process_list(&mut buffer, width, height);
+            },
+        }
+
+        self.render_footer(&mut buffer, height);
+        buffer
+    }
+
+    fn render_process_list(&mut self, buffer: &mut String, width: u16, height: u16) {
+        // Print the title with styling
+        write!(buffer, "{}{}Pulse - Linux Process Monitor{}", TITLE_COLOR, BOLD, RESET).unwrap();
+        if let Some(replay) = &self.replay {
+            write!(buffer, "{}{}  Replay: {} {}{}", RESTART_COLOR, BOLD, format_time(replay.frame().time), replay.position(), RESET).unwrap();
+        }
+        write!(buffer, "\r\n\r\n").unwrap();
+
+        // Column headers with padding to ensure alignment
+        let headers: Vec<String> = self.columns.screen.iter().map(|c| pad(c, c.header())).collect();
+        write!(buffer, "{}{}  {}\r\n", HEADER_COLOR, BOLD, headers.join("  ")).unwrap();
+        write!(buffer, "{}{}\r\n{}", SEPARATOR_COLOR, "─".repeat(width as usize), RESET).unwrap();
+
+        // Calculate how many processes we can show
+        let max_processes = (height as usize).saturating_sub(8 + self.notifications.len()).max(1);
+
+        // Scroll so the cursor row stays visible
+        let processes = self.display_processes();
+        let cursor = self.cursor_pid.and_then(|pid| processes.iter().position(|p| p.pid == pid));
+        let mut scroll = self.list_scroll.min(processes.len().saturating_sub(max_processes));
+        if let Some(cursor) = cursor {
+            if cursor < scroll {
+                scroll = cursor;
+            } else if cursor >= scroll + max_processes {
+                scroll = cursor + 1 - max_processes;
+            }
+        }
+
+        let mut rows = String::new();
+        for (i, process) in processes.iter().enumerate().skip(scroll).take(max_processes) {
+            let mark = if self.marked.contains(&process.pid) { '*' } else { ' ' };
+            let cells: Vec<String> = self.columns.screen.iter().map(|c| self.render_cell(process, *c)).collect();
+            let mut row = format!("{} {}", mark, cells.join("  "));
+            if Some(i) == cursor {
+                // Keep the highlight on across the colour resets inside the row
+                row = format!("\x1B[7m{}\x1B[0m", row.replace(RESET, "\x1B[0m\x1B[7m"));
+            }
+            write!(rows, "{}\r\n", row).unwrap();
+        }
+        buffer.push_str(&rows);
+        self.list_scroll = scroll;
+        self.list_rows = max_processes;
+    }
+
+    // One padded cell of the process list, coloured where the column has a meaning for it
+    fn render_cell(&self, process: &ProcessSnapshot, column: Column) -> String {
+        let (text, color) = match column {
+            Column::User => (process.user.clone(), USER_COLOR),
+            Column::Cpu => {
+                let cpu = self.display_cpu(process);
+                let color = if cpu > 15.0 {
+                    HIGH_USAGE_COLOR
+                } else if cpu > 10.0 {
+                    MEDIUM_USAGE_COLOR
+                } else {
+                    RESET
+                };
+                (format!("{:.1}", cpu), color)
+            },
+            Column::Mem => {
+                let color = if process.mem > 10.0 {
+                    HIGH_USAGE_COLOR
+                } else if process.mem > 5.0 {
+                    MEDIUM_USAGE_COLOR
+                } else {
+                    RESET
+                };
+                (format!("{:.1}", process.mem), color)
+            },
+            // Manual FG/BG override if set, else detected from the terminal process group
+            Column::FgBg => {
+                let fg_bg = self.manual_fg_bg_map.get(&process.pid).map(|s| s.as_str()).unwrap_or_else(|| process.fg_bg());
+                (fg_bg.to_string(), if fg_bg == "FG" { FG_COLOR } else { BG_COLOR })
+            },
+            // Override display if process is paused by our application
+            Column::State if self.is_paused(process.pid) => ("Paused".to_string(), PAUSED_COLOR),
+            Column::State => (process.state.as_str().to_string(), state_color(process.state)),
+            // Kept-alive processes show how often they were restarted
+            Column::Command => match self.supervised(process.pid) {
+                Some(entry) => (format!("{} [↻{}]", process.name, entry.restarts), RESTART_COLOR),
+                None => (process.name.clone(), ""),
+            },
+            _ => (column.display(process), ""),
+        };
+        if color.is_empty() {
+            pad(&column, &text)
+        } else {
+            format!("{}{}{}", color, pad(&column, &text), RESET)
+        }
+    }
+
+    fn render_tree(&mut self, buffer: &mut String, width: u16, height: u16) {
+        self.tree.rebuild(self.group_manager.process_tree(), &self.processes, self.summary.physical_cores);
+
+        write!(buffer, "{}{}Process Tree{}", BOLD, HEADER_COLOR, RESET).unwrap();
+        if !self.tree.query().is_empty() {
+            write!(buffer, "  (search: {})", self.tree.query()).unwrap();
+        }
+        write!(buffer, "\r\n\r\n").unwrap();
+
+        // The name column takes whatever the numeric columns leave over
+        let name_width = (width as usize).saturating_sub(42).max(20);
+        write!(buffer, "{}{}{:<name_width$}  {:>7}  {:>6}  {:>6}  {:>7}  {:>7}{}\r\n",
+            HEADER_COLOR, BOLD, "PROCESS", "PID", "CPU%", "MEM%", "ΣCPU%", "ΣMEM%", RESET
+        ).unwrap();
+
+        let paused = self.paused_pids();
+
+        let visible = (height as usize).saturating_sub(9).max(1);
+        let scroll = self.tree.scroll_for(visible);
+        let selected = self.tree.selected_index();
+        for (i, row) in self.tree.rows().iter().enumerate().skip(scroll).take(visible) {
+            let mut label = format!("{}{}{}", row.prefix, if row.collapsed { "[+] " } else { "" }, row.name);
+            if row.collapsed {
+                write!(label, " ({} hidden)", row.descendants).unwrap();
+            }
+            if paused.contains(&row.pid) {
+                label.push_str(" [paused]");
+            }
+            let label: String = label.chars().take(name_width).collect();
+            let padding = name_width.saturating_sub(label.chars().count());
+
+            let color = if row.matched {
+                MEDIUM_USAGE_COLOR
+            } else if row.is_thread {
+                SEPARATOR_COLOR
+            } else {
+                RESET
+            };
+            let line = format!("{}{}{}{}  {:>7}  {:>6.1}  {:>6.1}  {:>7.1}  {:>7.1}",
+                color, label, " ".repeat(padding), RESET, row.pid, row.cpu, row.mem, row.total_cpu, row.total_mem
+            );
+            if i == selected {
+                write!(buffer, "\x1B[7m{}\x1B[0m\r\n", line.replace(RESET, "\x1B[0m\x1B[7m")).unwrap(); // highlighted line
+            } else {
+                write!(buffer, "{}\r\n", line).unwrap();
+            }
+        }
+    }
+
+    fn render_signal_menu(&mut self, buffer: &mut String, height: u16) {
+        let pid = self.signal_pid.unwrap_or(0);
+        let name = self.processes.iter().find(|p| p.pid == pid).map_or("?", |p| p.name.as_str()).to_string();
+        write!(buffer, "{}{}Send Signal to {} ({}){}\r\n\r\n", BOLD, HEADER_COLOR, name, pid, RESET).unwrap();
+
+        // Show how many processes each target would reach
+        write!(buffer, "Target (Tab):").unwrap();
+        for target in [SignalTarget::Process, SignalTarget::Subtree, SignalTarget::ProcessGroup] {
+            let count = self.signal_target_pids(pid, target).len();
+            if target == self.signal_target {
+                write!(buffer, "  \x1B[7m{} [{}]\x1B[0m", target.label(), count).unwrap();
+            } else {
+                write!(buffer, "  {} [{}]", target.label(), count).unwrap();
+            }
+        }
+        write!(buffer, "\r\n{}{}{}\r\n", SEPARATOR_COLOR, "─".repeat(60), RESET).unwrap();
+
+        // Keep the selected signal inside the visible window
+        let visible = (height as usize).saturating_sub(10).max(1);
+        if self.signal_index < self.signal_scroll {
+            self.signal_scroll = self.signal_index;
+        } else if self.signal_index >= self.signal_scroll + visible {
+            self.signal_scroll = self.signal_index + 1 - visible;
+        }
+
+        for (i, info) in self.signals.iter().enumerate().skip(self.signal_scroll).take(visible) {
+            let line = format!("{:>3}  {:<12}  {}", info.number, info.name, info.description);
+            if i == self.signal_index {
+                write!(buffer, "\x1B[7m{}\x1B[0m\r\n", line).unwrap(); // highlighted line
+            } else {
+                write!(buffer, "{}\r\n", line).unwrap();
+            }
+        }
+    }
+
+    fn render_footer(&self, buffer: &mut String, height: u16) {
+        // Move to bottom of screen for stats
+        let extra_lines = self.notifications.len() as u16 + u16::from(self.options.thresholds);
+        let stats_line = height.saturating_sub(3 + extra_lines).max(1);
+        write!(buffer, "{}\r\n", cursor::Goto(1, stats_line)).unwrap();
+
+        for notification in &self.notifications {
+            write!(buffer, "{}{}{}\r\n", notification.color, notification.message, RESET).unwrap();
+        }
+        if self.options.thresholds {
+            match &self.daemon_status {
+                Some(status) => write!(buffer, "Daemon alert rules: {} | Sinks: {} | Firing: {}", status.rules.len(), status.sinks, status.firing).unwrap(),
+                None => write!(buffer, "Alert rules: {} | Sinks: {} | Firing: {}", self.alerts.rules().len(), self.sinks.len(), self.alerts.active_count()).unwrap(),
+            }
+            for (label, name) in [("CPU", "cpu"), ("Memory", "memory")] {
+                if let Some(threshold) = self.rule_threshold(name) {
+                    write!(buffer, " | {}: {:.2}%", label, threshold).unwrap();
+                }
+            }
+            write!(buffer, "\r\n").unwrap();
+        }
+
+        // Print memory and CPU info
+        let mem_gb = self.summary.total_memory as f64 / 1_073_741_824.0; // Convert to GB
+        let mem_used_gb = self.summary.used_memory as f64 / 1_073_741_824.0;
+        write!(buffer, "{}{}Memory: {:.1}GB / {:.1}GB ({:.1}%){}\r\n",
+            SEPARATOR_COLOR, BOLD, mem_used_gb, mem_gb, self.summary.mem_percent(), RESET
+        ).unwrap();
+
+        let paused_count = self.paused_pids().len();
+        write!(buffer, "{}{}CPUs: {} cores, Processes: {}, Paused: {}{}",
+            SEPARATOR_COLOR, BOLD, self.summary.physical_cores, self.display_processes().len(), paused_count, RESET
+        ).unwrap();
+        if !self.marked.is_empty() {
+            write!(buffer, " | Marked: {}", self.marked.len()).unwrap();
+        }
+        if self.daemon.is_some() {
+            write!(buffer, " | Daemon: attached").unwrap();
+        }
+
+        if let Some(filter) = &self.filter {
+            write!(buffer, " | Filter: {}", filter.expression()).unwrap();
+        }
+
+        // A stop in progress shows where it has got to; otherwise the status message, while its timer runs
+        if let Some(progress) = self.stop_progress() {
+            write!(buffer, " | {}{}{}", RESTART_COLOR, progress, RESET).unwrap();
+        } else if self.status_timer > 0 {
+            write!(buffer, " | {}{}{}", RESTART_COLOR, self.status_message, RESET).unwrap();
+        }
+        write!(buffer, "\r\n").unwrap();
+
+        // Help line at bottom
+        write!(buffer, "{}{}", HELP_COLOR, BOLD).unwrap();
+        match self.input_mode {
+            InputMode::Search => {
+                write!(buffer, "Filter: {} | Enter to apply (empty clears) | Esc to cancel", self.search_query).unwrap();
+            },
+            InputMode::Kill => {
+                write!(buffer, "Kill PID(s): {} | Enter to confirm | Esc to cancel", self.pid_input).unwrap();
+            },
+            InputMode::Pause => {
+                write!(buffer, "Enter PID(s) to pause/resume: {} | Enter to confirm | Esc to cancel", self.pid_input).unwrap();
+            },
+            InputMode::Restart => {
+                write!(buffer, "Enter PID(s) to restart: {} | Enter to confirm | Esc to cancel", self.pid_input).unwrap();
+            },
+            InputMode::KeepAlive => {
+                write!(buffer, "Enter PID(s) to keep alive/release: {} | Enter to confirm | Esc to cancel", self.pid_input).unwrap();
+            },
+            InputMode::Nice => {
+                write!(buffer, "Set NICE (PID:NICE): {} | Enter to confirm | Esc to cancel", self.pid_input).unwrap();
+            },
+            InputMode::Groups => {
+                write!(buffer, "Enter Group ID to pause/resume: {} | Enter to confirm | Esc to cancel", self.pid_input).unwrap();
+            },
+            InputMode::FgBgSwitch => {
+                write!(buffer, "FG/BG Mode | Enter PID: {} | Enter to switch | Esc to cancel", self.search_query).unwrap();
+            },
+            InputMode::FgBgConfirm => {
+                write!(buffer, "Switch FG/BG for PID {}? (y/n)", self.pending_fg_bg.unwrap_or(0)).unwrap();
+            },
+            InputMode::ThresholdConfig => match self.threshold_target {
+                Some(ThresholdKind::Cpu) => {
+                    write!(buffer, "Set CPU Threshold (Current: {:.2}%): {} | Enter to confirm | Esc to cancel", self.rule_threshold("cpu").unwrap_or(0.0), self.pid_input).unwrap();
+                },
+                Some(ThresholdKind::Memory) => {
+                    write!(buffer, "Set Memory Threshold (Current: {:.2}%): {} | Enter to confirm | Esc to cancel", self.rule_threshold("memory").unwrap_or(0.0), self.pid_input).unwrap();
+                },
+                None => {
+                    write!(buffer, "Set Threshold | Type 'CPU' or 'MEM' | Esc to cancel: {}", self.pid_input).unwrap();
+                },
+            },
+            InputMode::ReplaySeek => {
+                write!(buffer, "Go to time (e.g. 03:12, -10m, \"2024-05-01 03:12\"): {} | Enter to go | Esc to cancel", self.pid_input).unwrap();
+            },
+            InputMode::Columns => {
+                write!(buffer, "Columns (see 'pulse columns'; empty for the defaults): {} | Enter to save | Esc to cancel", self.pid_input).unwrap();
+            },
+            InputMode::Graph => {
+                write!(buffer, "PIDs to graph (comma-separated, empty for system only): {} | Enter to confirm | Esc to cancel", self.pid_input).unwrap();
+            },
+            InputMode::Signal => {
+                write!(buffer, "Enter PID to signal: {} | Enter to confirm | Esc to cancel", self.pid_input).unwrap();
+            },
+            InputMode::SignalMenu => {
+                write!(buffer, "Up/Down/PgUp/PgDn to choose a signal | Tab to change target | Enter to send | Esc to cancel").unwrap();
+            },
+            InputMode::Tree if self.replay.is_some() => {
+                write!(buffer, "←/→:Collapse/Expand | Enter:Toggle | *:Expand all | /:Search | n:Next match | [/]:Back/Forward 10 | Esc:Exit").unwrap();
+            },
+            InputMode::Tree => {
+                write!(buffer, "←/→:Collapse/Expand | Enter:Toggle | *:Expand all | /:Search | n:Next match | Z:Pause | G:Group | N:Nice | K:Kill | R:Restart | X:Signal | Esc:Exit").unwrap();
+            },
+            InputMode::TreeSearch => {
+                write!(buffer, "Search tree: {} | Enter when done | Esc to clear", self.tree.query()).unwrap();
+            },
+            InputMode::TreeNice => {
+                write!(buffer, "Nice value for PID {}: {} | Enter to confirm | Esc to cancel", self.tree.selected_pid().unwrap_or(0), self.pid_input).unwrap();
+            },
+            InputMode::TreeConfirm => {
+                let action = match self.tree_pending {
+                    Some(TreeAction::Restart) => "Restart",
+                    _ => "Kill",
+                };
+                write!(buffer, "{} PID {}? (y/n)", action, self.tree.selected_pid().unwrap_or(0)).unwrap();
+            },
+            InputMode::Normal if self.replay.is_some() => {
+                write!(buffer, "Q:Quit | ←/→:Back/Forward | [/]:Back/Forward 10 | @:Go to time | ↑/↓:Select | Space:Mark | C:CPU | M:Mem | P:PID | S:Filter | T:Show Tree | V:Graph | J: Export as Json file | E: Export as CSV file | H: Help").unwrap();
+            },
+            InputMode::Normal => {
+                write!(buffer, "Q:Quit | ↑/↓:Select | Space:Mark | C:CPU | M:Mem | P:PID | S:Filter | K:Kill | X:Signal | Z:Pause | R:Restart | A:Keep Alive | N:Nice | G:Group Pause").unwrap();
+                if self.options.fg_bg_switch {
+                    write!(buffer, " | F:Fg/Bg Switch").unwrap();
+                }
+                if self.options.thresholds {
+                    write!(buffer, " | W:Set Threshold").unwrap();
+                }
+                write!(buffer, " | T:Show Tree | J: Export as Json file | E: Export as CSV file | H: Help").unwrap();
+                if self.options.graphs {
+                    write!(buffer, " | V:Graph").unwrap();
+                }
+            },
+            InputMode::AlertConfirm => {
+                if let Some(pending) = self.pending_actions.front() {
+                    write!(buffer, "{}Alert '{}': {}? (y/n){}", ALERT_COLOR, pending.rule, pending.describe(), RESET).unwrap();
+                    if self.pending_actions.len() > 1 {
+                        write!(buffer, " | {} more waiting", self.pending_actions.len() - 1).unwrap();
+                    }
+                }
+            },
+            InputMode::Help | InputMode::GraphView | InputMode::SignalReport => {},
+        }
+        write!(buffer, "{}", RESET).unwrap();
+    }
+
+    fn handle_key(&mut self, key: Key) {
+        if self.replay.is_some() && self.handle_replay_key(key) {
+            return;
+        }
+        match self.input_mode {
+            InputMode::Normal => self.handle_normal_key(key),
+            InputMode::Search | InputMode::FgBgSwitch => self.handle_search_key(key),
+            InputMode::Tree => self.handle_tree_key(key),
+            InputMode::TreeSearch => self.handle_tree_search_key(key),
+            InputMode::TreeNice => self.handle_tree_nice_key(key),
+            InputMode::TreeConfirm => self.handle_tree_confirm_key(key),
+            InputMode::GraphView => self.handle_graph_key(key),
+            InputMode::Help | InputMode::SignalReport => {
+                if matches!(key, Key::Char('\n') | Key::Char('h') | Key::Esc) {
+                    self.input_mode = InputMode::Normal;
+                }
+            },
+            InputMode::FgBgConfirm => self.handle_fg_bg_confirm_key(key),
+            InputMode::SignalMenu => self.handle_signal_menu_key(key),
+            InputMode::AlertConfirm => self.handle_alert_confirm_key(key),
+            InputMode::Kill
+            | InputMode::Pause
+            | InputMode::Restart
+            | InputMode::KeepAlive
+            | InputMode::Nice
+            | InputMode::Groups
+            | InputMode::ThresholdConfig
+            | InputMode::Graph
+            | InputMode::Signal
+            | InputMode::Columns
+            | InputMode::ReplaySeek => self.handle_prompt_key(key),
+        }
+    }
+
+    // Moving through a replay, and refusing actions on processes that are only
+    // recorded. Returns true when the key was handled here.
+    fn handle_replay_key(&mut self, key: Key) -> bool {
+        let browsing = matches!(self.input_mode, InputMode::Normal | InputMode::Tree | InputMode::GraphView);
+        let steps = match key {
+            // Left and right fold the tree, so it only takes the brackets
+            Key::Left if self.input_mode != InputMode::Tree => -1,
+            Key::Right if self.input_mode != InputMode::Tree => 1,
+            Key::Char('[') => -10,
+            Key::Char(']') => 10,
+            Key::Char('@') if self.input_mode == InputMode::Normal => {
+                self.start_prompt(InputMode::ReplaySeek);
+                return true;
+            },
+            Key::Char('K' | 'X' | 'Z' | 'R' | 'A' | 'N' | 'G' | 'D')
+                if matches!(self.input_mode, InputMode::Normal | InputMode::Tree) =>
+            {
+                self.set_status("Replay is read-only; process actions are turned off".to_string());
+                return true;
+            },
+            _ => return false,
+        };
+        if !browsing {
+            return false;
+        }
+        self.step_replay(steps);
+        true
+    }
+
+    fn step_replay(&mut self, steps: i64) {
+        let moved = self.replay.as_mut().is_some_and(|replay| replay.step(steps));
+        self.refresh();
+        if self.input_mode == InputMode::GraphView {
+            self.backfill_graphs();
+        }
+        if !moved {
+            self.set_status(if steps > 0 { "No later snapshots" } else { "No earlier snapshots" }.to_string());
+        }
+    }
+
+    fn submit_seek(&mut self) -> bool {
+        let result = history::parse_time(&self.pid_input)
+            .and_then(|time| self.replay.as_mut().map_or(Ok(()), |replay| replay.seek(time)));
+        match result {
+            Ok(()) => {
+                self.refresh();
+                true
+            },
+            Err(e) => {
+                // Leave the prompt open so the time can be corrected
+                self.set_status(e);
+                false
+            },
+        }
+    }
+
+    fn handle_normal_key(&mut self, key: Key) {
+        match key {
+            Key::Char('Q') => self.quit = true,
+            Key::Char('C') => self.set_sort_mode(SortMode::Cpu),
+            Key::Char('M') => self.set_sort_mode(SortMode::Memory),
+            Key::Char('P') => self.set_sort_mode(SortMode::Pid),
+            Key::Char('S') => {
+                // Start from the active filter so it can be edited
+                self.input_mode = InputMode::Search;
+                self.search_query = self.filter.as_ref().map(|f| f.expression().to_string()).unwrap_or_default();
+            },
+            Key::Up => self.move_cursor(-1),
+            Key::Down => self.move_cursor(1),
+            Key::PageUp => self.move_cursor(-(self.list_rows as isize)),
+            Key::PageDown => self.move_cursor(self.list_rows as isize),
+            Key::Home => self.move_cursor(-(self.processes.len() as isize)),
+            Key::End => self.move_cursor(self.processes.len() as isize),
+            Key::Char(' ') => {
+                if let Some(pid) = self.cursor_pid {
+                    if !self.marked.remove(&pid) {
+                        self.marked.insert(pid);
+                    }
+                }
+                self.move_cursor(1);
+            },
+            Key::Esc => {
+                self.marked.clear();
+                self.cursor_pid = None;
+            },
+            Key::Char('K') => self.start_selection_prompt(InputMode::Kill),
+            Key::Char('X') => self.start_selection_prompt(InputMode::Signal),
+            Key::Char('Z') => self.start_selection_prompt(InputMode::Pause),
+            Key::Char('R') => self.start_selection_prompt(InputMode::Restart),
+            Key::Char('A') => self.start_selection_prompt(InputMode::KeepAlive),
+            Key::Char('N') => self.start_selection_prompt(InputMode::Nice),
+            Key::Char('G') => self.start_selection_prompt(InputMode::Groups),
+            Key::Char('T') => self.input_mode = InputMode::Tree,
+            Key::Char('F') if self.options.fg_bg_switch => {
+                self.input_mode = InputMode::FgBgSwitch;
+                self.search_query.clear();
+            },
+            Key::Char('W') if self.options.thresholds => {
+                self.threshold_target = None;
+                self.start_prompt(InputMode::ThresholdConfig);
+            },
+            Key::Char('V') if self.options.graphs => self.start_selection_prompt(InputMode::Graph),
+            Key::Char('D') => self.toggle_daemon(),
+            Key::Char('O') => {
+                // Start from the current columns so they can be edited
+                self.start_prompt(InputMode::Columns);
+                self.pid_input = columns::format_columns(&self.columns.screen);
+            },
+            Key::Char('H') => self.input_mode = InputMode::Help,
+            Key::Char('E') => self.export(&CsvExporter::new(CsvOptions::new())),
+            Key::Char('J') => self.export(&JsonExporter),
+            _ => {}
+        }
+    }
+
+    fn export(&mut self, exporter: &dyn Exporter) {
+        let processes = self.export_processes();
+        let data = ExportData {
+            time: self.replay.as_ref().map_or_else(history::now, |replay| replay.frame().time),
+            processes: &processes,
+            summary: &self.summary,
+            columns: &self.columns.export,
+            history: Vec::new(),
+        };
+        let filepath = Path::new(EXPORT_DIR).join(exporter.default_filename());
+        let message = match exporter.export(&data, &filepath) {
+            Ok(msg) => msg,
+            Err(e) => format!("Export failed: {}", e),
+        };
+        self.set_status(message);
+    }
+
+    fn set_sort_mode(&mut self, mode: SortMode) {
+        self.sort_mode = mode;
+        sort_snapshots(&mut self.processes, mode);
+    }
+
+    fn start_prompt(&mut self, mode: InputMode) {
+        self.input_mode = mode;
+        self.pid_input.clear();
+    }
+
+    /// Move the row cursor; the first move puts it on the top visible row
+    fn move_cursor(&mut self, delta: isize) {
+        let pids: Vec<u32> = self.display_processes().iter().map(|p| p.pid).collect();
+        if pids.is_empty() {
+            self.cursor_pid = None;
+            return;
+        }
+        let next = match self.cursor_pid.and_then(|pid| pids.iter().position(|&p| p == pid)) {
+            Some(current) => (current as isize).saturating_add(delta).clamp(0, pids.len() as isize - 1) as usize,
+            None => self.list_scroll.min(pids.len() - 1),
+        };
+        self.cursor_pid = Some(pids[next]);
+    }
+
+    /// PIDs an action applies to: the marked rows, or else the cursor row
+    fn selected_pids(&self) -> Vec<u32> {
+        if self.marked.is_empty() {
+            self.cursor_pid.into_iter().collect()
+        } else {
+            self.marked.iter().copied().collect()
+        }
+    }
+
+    /// Open a prompt with the selected PIDs already filled in
+    fn start_selection_prompt(&mut self, mode: InputMode) {
+        let pids = self.selected_pids();
+        // Group and signal prompts take a single PID
+        let pids = match mode {
+            InputMode::Groups | InputMode::Signal if pids.len() > 1 => self.cursor_pid.into_iter().collect(),
+            _ => pids,
+        };
+        let is_nice = mode == InputMode::Nice;
+        self.start_prompt(mode);
+        if !pids.is_empty() {
+            self.pid_input = pids.iter().map(|p| p.to_string()).collect::<Vec<_>>().join(",");
+            if is_nice {
+                self.pid_input.push(':');
+            }
+        }
+    }
+
+    /// Status line for an action applied to several processes
+    fn report_batch(&mut self, done: &str, total: usize, failures: Vec<String>) {
+        let message = match failures.first() {
+            None => format!("{} {} processes", done, total),
+            Some(first) => format!("{} {}/{} processes; {}", done, total - failures.len(), total, first),
+        };
+        self.set_status(message);
+    }
+
+    fn handle_search_key(&mut self, key: Key) {
+        match key {
+            Key::Esc => {
+                self.input_mode = InputMode::Normal;
+                self.search_query.clear();
+            },
+            Key::Char('\n') => {
+                if self.input_mode == InputMode::FgBgSwitch {
+                    match self.search_query.trim().parse::<u32>() {
+                        Ok(pid_val) => {
+                            self.pending_fg_bg = Some(pid_val);
+                            self.input_mode = InputMode::FgBgConfirm;
+                        },
+                        Err(_) => {
+                            self.set_status("Invalid PID format.".to_string());
+                            self.input_mode = InputMode::Normal;
+                        },
+                    }
+                    self.search_query.clear();
+                } else {
+                    match ProcessFilter::parse(&self.search_query) {
+                        Ok(filter) if filter.is_empty() => {
+                            self.filter = None;
+                            self.set_status("Filter cleared".to_string());
+                        },
+                        Ok(filter) => {
+                            let cores = self.summary.physical_cores;
+                            let count = self.processes.iter().filter(|p| filter.matches(p, cores)).count();
+                            self.set_status(format!("Filter applied, {} matching processes", count));
+                            self.filter = Some(filter);
+                        },
+                        Err(e) => {
+                            // Keep the prompt open so the expression can be fixed
+                            self.set_status(e);
+                            return;
+                        },
+                    }
+                    self.search_query.clear();
+                    self.input_mode = InputMode::Normal;
+                }
+            },
+            Key::Char(c) if c.is_ascii_digit() => self.search_query.push(c),
+            Key::Char(c) if self.input_mode == InputMode::Search && !c.is_control() => self.search_query.push(c),
+            Key::Backspace => {
+                self.search_query.pop();
+            },
+            _ => {}
+        }
+    }
+
+    fn handle_fg_bg_confirm_key(&mut self, key: Key) {
+        let pid_val = match self.pending_fg_bg {
+            Some(pid) => pid,
+            None => {
+                self.input_mode = InputMode::Normal;
+                return;
+            }
+        };
+        match key {
+            Key::Char('y') => {
+                let start_time = self.processes.iter().find(|p| p.pid == pid_val).map_or(0, |p| p.start_time);
+                let message = match toggle_fg_bg(pid_val, start_time) {
+                    Ok(_) => {
+                        let current_fg = self.manual_fg_bg_map
+                            .get(&pid_val)
+                            .map(|s| s == "FG")
+                            .unwrap_or(true);
+                        let next = if current_fg { "BG" } else { "FG" };
+                        self.manual_fg_bg_map.insert(pid_val, next.to_string());
+                        format!("Attempted to switch FG/BG for PID {}", pid_val)
+                    },
+                    Err(e) => format!("Failed to switch FG/BG for PID {}: {}", pid_val, e),
+                };
+                self.set_status(message);
+            },
+            Key::Char('n') | Key::Esc => self.set_status("Cancelled switching FG/BG.".to_string()),
+            _ => return,
+        }
+        self.pending_fg_bg = None;
+        self.input_mode = InputMode::Normal;
+    }
+
+    fn handle_tree_key(&mut self, key: Key) {
+        let page = self.list_rows.max(1) as isize;
+        match key {
+            Key::Esc => {
+                if self.tree.query().is_empty() {
+                    self.input_mode = InputMode::Normal;
+                } else {
+                    self.tree.set_query(String::new());
+                }
+            },
+            Key::Up => self.tree.move_by(-1),
+            Key::Down => self.tree.move_by(1),
+            Key::PageUp => self.tree.move_by(-page),
+            Key::PageDown => self.tree.move_by(page),
+            Key::Home => self.tree.move_by(isize::MIN),
+            Key::End => self.tree.move_by(isize::MAX),
+            Key::Left | Key::Char('-') => self.tree.collapse(),
+            Key::Right | Key::Char('+') => self.tree.expand(),
+            Key::Char('\n') | Key::Char(' ') => self.tree.toggle(),
+            Key::Char('*') => self.tree.expand_all(),
+            Key::Char('/') => {
+                self.tree.set_query(String::new());
+                self.input_mode = InputMode::TreeSearch;
+            },
+            Key::Char('n') => self.tree.next_match(),
+            _ => {
+                let pid_val = match self.tree.selected_pid() {
+                    Some(pid_val) => pid_val,
+                    None => return,
+                };
+                self.handle_tree_action_key(key, pid_val);
+            },
+        }
+    }
+
+    // Actions on the selected node
+    fn handle_tree_action_key(&mut self, key: Key, pid_val: u32) {
+        match key {
+            Key::Char('Z') => {
+                let (action, done) = if self.is_paused(pid_val) {
+                    (ProcessAction::Resume, "resumed")
+                } else {
+                    (ProcessAction::Pause, "paused")
+                };
+                let message = match self.set_paused(pid_val, action) {
+                    Ok(()) => format!("Process {} {}", pid_val, done),
+                    Err(e) => e,
+                };
+                self.set_status(message);
+            },
+            Key::Char('G') => {
+                let result = self.toggle_group(pid_val);
+                self.set_status(match result {
+                    Ok(_) => format!("Process {} group toggled", pid_val),
+                    Err(e) => format!("Error: {}", e),
+                });
+            },
+            Key::Char('N') => {
+                self.pid_input.clear();
+                self.input_mode = InputMode::TreeNice;
+            },
+            Key::Char('K') => {
+                self.tree_pending = Some(TreeAction::Kill);
+                self.input_mode = InputMode::TreeConfirm;
+            },
+            Key::Char('R') => {
+                self.tree_pending = Some(TreeAction::Restart);
+                self.input_mode = InputMode::TreeConfirm;
+            },
+            Key::Char('X') => self.open_signal_menu(pid_val),
+            _ => {}
+        }
+    }
+
+    fn handle_tree_search_key(&mut self, key: Key) {
+        let mut query = self.tree.query().to_string();
+        match key {
+            Key::Esc => {
+                query.clear();
+                self.input_mode = InputMode::Tree;
+            },
+            Key::Char('\n') => {
+                self.input_mode = InputMode::Tree;
+                self.tree.set_query(query);
+                self.tree.next_match();
+                return;
+            },
+            Key::Backspace => {
+                query.pop();
+            },
+            Key::Char(c) if !c.is_control() => query.push(c),
+            _ => {}
+        }
+        self.tree.set_query(query);
+    }
+
+    fn handle_tree_nice_key(&mut self, key: Key) {
+        match key {
+            Key::Esc => {
+                self.pid_input.clear();
+                self.input_mode = InputMode::Tree;
+            },
+            Key::Char('\n') => {
+                let message = match (self.tree.selected_pid(), self.pid_input.parse::<i32>()) {
+                    (Some(pid_val), Ok(nice)) => match priority::set_priority(pid_val as i32, nice) {
+                        Ok(msg) => msg,
+                        Err(e) => format!("Error: {}", e.desc()),
+                    },
+                    _ => "Invalid nice value".to_string(),
+                };
+                self.set_status(message);
+                self.pid_input.clear();
+                self.input_mode = InputMode::Tree;
+            },
+            Key::Backspace => {
+                self.pid_input.pop();
+            },
+            Key::Char(c) if c.is_ascii_digit() || c == '-' || c == '+' => self.pid_input.push(c),
+            _ => {}
+        }
+    }
+
+    fn handle_tree_confirm_key(&mut self, key: Key) {
+        match (key, self.tree_pending, self.tree.selected_pid()) {
+            (Key::Char('y'), Some(TreeAction::Kill), Some(pid_val)) => {
+                let message = self.stop(pid_val).unwrap_or_else(|e| e);
+                self.set_status(message);
+            },
+            (Key::Char('y'), Some(TreeAction::Restart), Some(pid_val)) => {
+                let message = match self.restart(pid_val) {
+                    Ok(report) => report.summary(),
+                    Err(e) => e.to_string(),
+                };
+                self.set_status(message);
+            },
+            (Key::Char('n'), _, _) | (Key::Esc, _, _) => {},
+            _ => return,
+        }
+        self.tree_pending = None;
+        self.input_mode = InputMode::Tree;
+    }
+
+    /// Keys for the modes that read a PID (or other value) into `pid_input`
+    fn handle_prompt_key(&mut self, key: Key) {
+        match key {
+            Key::Esc => {
+                self.input_mode = InputMode::Normal;
+                self.pid_input.clear();
+            },
+            Key::Char('\n') => {
+                let finished = self.submit_prompt();
+                if finished {
+                    if !matches!(self.input_mode, InputMode::GraphView | InputMode::SignalMenu) {
+                        self.input_mode = InputMode::Normal;
+                    }
+                    self.pid_input.clear();
+                }
+            },
+            Key::Backspace => {
+                self.pid_input.pop();
+            },
+            // Nice takes PID:NICE, the threshold prompt words and decimals and the columns prompt names
+            Key::Char(c) if matches!(self.input_mode, InputMode::Nice | InputMode::ThresholdConfig | InputMode::Columns | InputMode::ReplaySeek) => {
+                self.pid_input.push(c);
+            },
+            // Kill, pause, restart and graph take a comma-separated list of PIDs
+            Key::Char(',') if matches!(self.input_mode, InputMode::Kill | InputMode::Pause | InputMode::Restart | InputMode::KeepAlive | InputMode::Graph) => {
+                self.pid_input.push(',');
+            },
+            Key::Char(c) if c.is_ascii_digit() => self.pid_input.push(c),
+            _ => {}
+        }
+    }
+
+    /// Act on the value typed into a prompt. Returns false if the prompt stays open.
+    fn submit_prompt(&mut self) -> bool {
+        let pid_val = self.pid_input.parse::<u32>().ok();
+        let pid_list = parse_pid_list(&self.pid_input);
+        match self.input_mode {
+            InputMode::Kill => {
+                let mut failures = Vec::new();
+                for &pid_val in &pid_list {
+                    match self.stop(pid_val) {
+                        Ok(message) if pid_list.len() == 1 => self.set_status(message),
+                        Ok(_) => {},
+                        Err(e) if pid_list.len() == 1 => self.set_status(e),
+                        Err(e) => failures.push(e),
+                    }
+                }
+                if pid_list.len() > 1 {
+                    self.report_batch("Stopping", pid_list.len(), failures);
+                }
+                self.marked.clear();
+            },
+            InputMode::Pause => {
+                let mut failures = Vec::new();
+                for &pid_val in &pid_list {
+                    let action = if self.is_paused(pid_val) {
+                        ProcessAction::Resume
+                    } else {
+                        ProcessAction::Pause
+                    };
+                    if let Err(e) = self.set_paused(pid_val, action) {
+                        failures.push(e);
+                    }
+                }
+                if pid_list.len() > 1 {
+                    self.report_batch("Paused/resumed", pid_list.len(), failures);
+                } else if let Some(e) = failures.pop() {
+                    self.set_status(e);
+                }
+                self.marked.clear();
+            },
+            InputMode::Restart => {
+                let mut failures = Vec::new();
+                for &pid_val in &pid_list {
+                    match self.restart(pid_val) {
+                        Ok(report) if pid_list.len() == 1 => self.set_status(report.summary()),
+                        Ok(_) => {},
+                        Err(e) if pid_list.len() == 1 => self.set_status(e.to_string()),
+                        Err(e) => failures.push(e.to_string()),
+                    }
+                }
+                if pid_list.len() > 1 {
+                    self.report_batch("Restarted", pid_list.len(), failures);
+                }
+                self.marked.clear();
+            },
+            InputMode::KeepAlive => {
+                let mut failures = Vec::new();
+                for &pid_val in &pid_list {
+                    match self.toggle_keep_alive(pid_val) {
+                        Ok(message) if pid_list.len() == 1 => self.set_status(message),
+                        Ok(_) => {},
+                        Err(e) if pid_list.len() == 1 => self.set_status(e),
+                        Err(e) => failures.push(e),
+                    }
+                }
+                if pid_list.len() > 1 {
+                    self.report_batch("Kept alive/released", pid_list.len(), failures);
+                }
+                self.marked.clear();
+            },
+            InputMode::Groups => {
+                if let Some(pid_val) = pid_val {
+                    let result = self.toggle_group(pid_val);
+                    self.set_status(match result {
+                        Ok(_) => format!("Process {} group toggled", pid_val),
+                        Err(e) => format!("Error: {}", e),
+                    });
+                }
+            },
+            InputMode::Nice => {
+                let message = match self.pid_input.split_once(':') {
+                    Some((p_str, n_str)) => match (parse_pid_list(p_str), n_str.parse::<i32>()) {
+                        // Adjust the nice value without sudo
+                        (pids, Ok(nice)) if pids.len() == 1 => match priority::set_priority(pids[0] as i32, nice) {
+                            Ok(msg) => msg,
+                            Err(e) => format!("Error: {}", e.desc()),
+                        },
+                        (pids, Ok(nice)) if pids.len() > 1 => {
+                            let failures: Vec<String> = pids
+                                .iter()
+                                .filter_map(|&pid| priority::set_priority(pid as i32, nice).err().map(|e| format!("PID {}: {}", pid, e.desc())))
+                                .collect();
+                            self.report_batch(&format!("Nice set to {:+} for", nice), pids.len(), failures);
+                            self.marked.clear();
+                            return true;
+                        },
+                        _ => "Invalid PID:NICE format".to_string(),
+                    },
+                    None => "Format must be PID:NICE".to_string(),
+                };
+                self.marked.clear();
+                self.set_status(message);
+            },
+            InputMode::ThresholdConfig => return self.submit_threshold(),
+            InputMode::Columns => return self.submit_columns(),
+            InputMode::ReplaySeek => return self.submit_seek(),
+            InputMode::Graph => self.open_graphs(pid_list),
+            InputMode::Signal => {
+                if let Some(pid_val) = pid_val {
+                    self.open_signal_menu(pid_val);
+                }
+            },
+            _ => {}
+        }
+        true
+    }
+
+    fn open_signal_menu(&mut self, pid_val: u32) {
+        if !self.processes.iter().any(|p| p.pid == pid_val) {
+            self.set_status(format!("Process {} not found", pid_val));
+            self.input_mode = InputMode::Normal;
+            return;
+        }
+        self.signal_pid = Some(pid_val);
+        self.signal_target = SignalTarget::Process;
+        // Start on SIGTERM, the usual choice
+        self.signal_index = self.signals.iter().position(|s| s.number == libc::SIGTERM).unwrap_or(0);
+        self.signal_scroll = 0;
+        self.input_mode = InputMode::SignalMenu;
+    }
+
+    fn handle_signal_menu_key(&mut self, key: Key) {
+        let page = 10;
+        let last = self.signals.len().saturating_sub(1);
+        match key {
+            Key::Esc => {
+                self.signal_pid = None;
+                self.input_mode = InputMode::Normal;
+            },
+            Key::Up => self.signal_index = self.signal_index.saturating_sub(1),
+            Key::Down => self.signal_index = (self.signal_index + 1).min(last),
+            Key::PageUp => self.signal_index = self.signal_index.saturating_sub(page),
+            Key::PageDown => self.signal_index = (self.signal_index + page).min(last),
+            Key::Char('\t') => self.signal_target = self.signal_target.next(),
+            Key::Char('\n') => self.send_selected_signal(),
+            _ => {}
+        }
+    }
+
+    /// PIDs the signal menu would reach for the given target
+    fn signal_target_pids(&mut self, pid_val: u32, target: SignalTarget) -> Vec<u32> {
+        match target {
+            SignalTarget::Process => vec![pid_val],
+            SignalTarget::Subtree => self.group_manager
+                .get_group_pids(&self.processes, Pid::from_u32(pid_val))
+                .iter()
+                .map(|p| p.as_u32())
+                .collect(),
+            SignalTarget::ProcessGroup => {
+                match self.processes.iter().find(|p| p.pid == pid_val).map(|p| p.pgrp) {
+                    Some(pgrp) if pgrp > 0 => self.processes
+                        .iter()
+                        .filter(|p| p.pgrp == pgrp)
+                        .map(|p| p.pid)
+                        .collect(),
+                    _ => vec![pid_val],
+                }
+            },
+        }
+    }
+
+    /// Send the highlighted signal to the chosen target and show a per-PID report
+    fn send_selected_signal(&mut self) {
+        let (pid_val, info) = match (self.signal_pid, self.signals.get(self.signal_index)) {
+            (Some(pid_val), Some(info)) => (pid_val, info.clone()),
+            _ => {
+                self.input_mode = InputMode::Normal;
+                return;
+            },
+        };
+
+        let target = self.signal_target;
+        let pids = self.signal_target_pids(pid_val, target);
+        let own_pid = std::process::id();
+        let mut delivered = 0;
+
+        self.signal_report = vec![
+            format!("{}{}{} ({}) sent to {} of {} ({} processes){}", BOLD, HEADER_COLOR, info.name, info.number, target.label().to_lowercase(), pid_val, pids.len(), RESET),
+            String::new(),
+            format!("{}{:<8}  {:<20}  {}{}", BOLD, "PID", "NAME", "RESULT", RESET),
+        ];
+
+        for pid in pids {
+            let seen = self.processes.iter().find(|p| p.pid == pid);
+            let name = seen.map_or("?", |p| p.name.as_str()).to_string();
+            // The snapshot may be a few seconds old; its start time tells a reused PID apart
+            let start_time = seen.map_or(0, |p| p.start_time);
+            let result = if pid == own_pid {
+                Err("skipped (this is Pulse itself)".to_string())
+            } else {
+                match info.number {
+                    // Go through the controller (or daemon) so paused processes are tracked and resumed on exit
+                    libc::SIGSTOP => self.set_paused(pid, ProcessAction::Pause),
+                    libc::SIGCONT => self.set_paused(pid, ProcessAction::Resume),
+                    number => signal::send_seen(pid, start_time, number).map_err(|e| e.to_string()),
+                }
+            };
+            let line = match result {
+                Ok(()) => {
+                    delivered += 1;
+                    format!("{:<8}  {:<20}  {}OK{}", pid, name, RUNNING_COLOR, RESET)
+                },
+                Err(e) => format!("{:<8}  {:<20}  {}{}{}", pid, name, ALERT_COLOR, e, RESET),
+            };
+            self.signal_report.push(line);
+        }
+
+        let total = self.signal_report.len() - 3;
+        self.set_status(format!("{} delivered to {}/{} processes", info.name, delivered, total));
+        self.signal_pid = None;
+        self.input_mode = InputMode::SignalReport;
+    }
+
+    /// Show the typed columns and save them. Returns false if the prompt stays open.
+    fn submit_columns(&mut self) -> bool {
+        let parsed = if self.pid_input.trim().is_empty() {
+            Ok(columns::default_columns())
+        } else {
+            columns::parse_columns(&self.pid_input)
+        };
+        match parsed {
+            Ok(parsed) => {
+                self.columns.screen = parsed;
+                match self.columns.save() {
+                    Ok(path) => self.set_status(format!("Columns saved to {}", path.display())),
+                    Err(e) => self.set_status(format!("Columns changed but not saved: {}", e)),
+                }
+                true
+            },
+            Err(e) => {
+                // Keep the prompt open so the list can be fixed
+                self.set_status(e);
+                false
+            },
+        }
+    }
+
+    fn submit_threshold(&mut self) -> bool {
+        let choice = self.pid_input.trim().to_uppercase();
+        self.pid_input.clear();
+
+        if choice == "CPU" {
+            self.threshold_target = Some(ThresholdKind::Cpu);
+            return false;
+        }
+        if choice == "MEM" {
+            self.threshold_target = Some(ThresholdKind::Memory);
+            return false;
+        }
+
+        let message = match (choice.parse::<f64>(), self.threshold_target) {
+            // These adjust the rules named "cpu" and "memory", which are the built-in defaults
+            (Ok(value), Some(ThresholdKind::Cpu)) if self.set_rule_threshold("cpu", value) => {
+                format!("CPU Threshold set to {:.2}%", value)
+            },
+            (Ok(value), Some(ThresholdKind::Memory)) if self.set_rule_threshold("memory", value) => {
+                format!("Memory Threshold set to {:.2}%", value)
+            },
+            (Ok(_), Some(ThresholdKind::Cpu)) => "No alert rule named 'cpu' is loaded".to_string(),
+            (Ok(_), Some(ThresholdKind::Memory)) => "No alert rule named 'memory' is loaded".to_string(),
+            (Ok(_), None) => "Invalid option. Type 'CPU' or 'MEM' first.".to_string(),
+            (Err(_), _) => {
+                self.set_status("Invalid input. Type 'CPU', 'MEM', or a numeric value.".to_string());
+                return false;
+            },
+        };
+        self.threshold_target = None;
+        self.set_status(message);
+        true
+    }
+
+    fn rule_threshold(&self, name: &str) -> Option<f64> {
+        self.alerts.rules().iter().find(|r| r.name == name).map(|r| r.threshold)
+    }
+
+    // Run the alert rules against every process, not just the rows on screen
+    fn check_alerts(&mut self) {
+        let events = self.alerts.evaluate(history::now(), &self.processes, self.summary.physical_cores);
+        for event in events {
+            self.notifications.push(Notification {
+                message: event.message.clone(),
+                color: alert_color(&event),
+                ticks_left: NOTIFICATION_TICKS,
+            });
+            self.sinks.send(&event);
+
+            let rule = match self.alerts.rules().iter().find(|r| r.name == event.rule) {
+                Some(rule) => rule.clone(),
+                None => continue,
+            };
+            let mut context = ActionContext {
+                controller: &mut self.process_controller,
+                groups: &mut self.group_manager,
+                processes: &self.processes,
+                physical_cores: self.summary.physical_cores,
+            };
+            match self.actions.handle(&event, &rule, &mut context) {
+                Some(ActionOutcome::NeedsConfirm(pending)) => self.pending_actions.push_back(pending),
+                Some(outcome) => self.notify_action(&outcome),
+                None => {},
+            }
+        }
+        for outcome in self.actions.finished_stops() {
+            self.notify_action(&outcome);
+        }
+        // Failed deliveries from earlier refreshes
+        for error in self.sinks.errors() {
+            self.notifications.push(Notification { message: error, color: ALERT_COLOR, ticks_left: NOTIFICATION_TICKS });
+        }
+        let excess = self.notifications.len().saturating_sub(MAX_NOTIFICATIONS);
+        self.notifications.drain(..excess);
+    }
+
+    // Show what the daemon fired or resolved since the last refresh
+    fn check_daemon_alerts(&mut self) {
+        let Some(daemon) = &mut self.daemon else { return };
+        match daemon.events(self.daemon_events) {
+            Ok(batch) => {
+                self.daemon_events = batch.next;
+                for event in batch.events {
+                    self.notifications.push(Notification {
+                        message: event.message.clone(),
+                        color: alert_color(&event),
+                        ticks_left: NOTIFICATION_TICKS,
+                    });
+                }
+                let excess = self.notifications.len().saturating_sub(MAX_NOTIFICATIONS);
+                self.notifications.drain(..excess);
+            },
+            Err(e) => self.detach_daemon(e),
+        }
+    }
+
+    fn attach_daemon(&mut self) -> Result<(), String> {
+        let mut daemon = DaemonClient::connect(&daemon::default_socket_path())?;
+        let status = daemon.status()?;
+        // Only alerts from now on; older ones were shown wherever they happened
+        self.daemon_events = status.next_event;
+        self.daemon_status = Some(status);
+        self.daemon = Some(daemon);
+        Ok(())
+    }
+
+    fn detach_daemon(&mut self, reason: String) {
+        self.daemon = None;
+        self.daemon_status = None;
+        self.set_status(format!("Detached from the daemon: {}", reason));
+    }
+
+    fn toggle_daemon(&mut self) {
+        let message = match self.daemon.take() {
+            Some(daemon) => {
+                self.daemon_status = None;
+                format!("Detached from the daemon on {}; it keeps running", daemon.path().display())
+            },
+            None => match self.attach_daemon() {
+                Ok(()) => "Attached to the Pulse daemon (D to detach)".to_string(),
+                Err(e) => e,
+            },
+        };
+        self.set_status(message);
+    }
+
+    fn sync_daemon_status(&mut self) {
+        let Some(daemon) = &mut self.daemon else { return };
+        match daemon.status() {
+            Ok(status) => self.daemon_status = Some(status),
+            Err(e) => self.detach_daemon(e),
+        }
+    }
+
+    /// Processes paused by this Pulse or, while attached, by the daemon
+    fn paused_pids(&self) -> BTreeSet<u32> {
+        let mut paused: BTreeSet<u32> = self.process_controller.get_paused_processes().iter().map(|p| p.as_u32()).collect();
+        paused.extend(self.group_manager.get_paused_processes().iter().map(|p| p.as_u32()));
+        if let Some(status) = &self.daemon_status {
+            paused.extend(status.paused.iter().copied());
+        }
+        paused
+    }
+
+    fn is_paused(&self, pid: u32) -> bool {
+        self.process_controller.is_paused(&Pid::from_u32(pid))
+            || self.daemon_status.as_ref().is_some_and(|s| s.paused.contains(&pid))
+    }
+
+    // While attached, pausing goes through the daemon so the process stays tracked after we exit.
+    // Processes this Pulse paused itself are still resumed here.
+    fn set_paused(&mut self, pid: u32, action: ProcessAction) -> Result<(), String> {
+        // The snapshot may be a few seconds old; its start time tells a reused PID apart
+        let start_time = self.start_time(pid);
+        let result = match &mut self.daemon {
+            Some(daemon) if !self.process_controller.is_paused(&Pid::from_u32(pid)) => daemon.control(pid, start_time, action),
+            _ => return self.process_controller.control_process(Pid::from_u32(pid), start_time, action),
+        };
+        self.sync_daemon_status();
+        result
+    }
+
+    fn toggle_group(&mut self, pid: u32) -> Result<String, String> {
+        let start_time = self.start_time(pid);
+        let Some(daemon) = &mut self.daemon else {
+            return self.group_manager.toggle_process_group(&self.processes, Pid::from_u32(pid));
+        };
+        let paused = self.daemon_status.as_ref().is_some_and(|s| s.paused.contains(&pid));
+        let (action, done) = if paused { (ProcessAction::Resume, "Resumed") } else { (ProcessAction::Pause, "Paused") };
+        let result = daemon.control_group(pid, start_time, action);
+        self.sync_daemon_status();
+        result.map(|count| format!("{} group of PID {} ({} processes)", done, pid, count))
+    }
+
+    // When `pid` started as of the last refresh; 0 when it was not seen
+    fn start_time(&self, pid: u32) -> u64 {
+        self.processes.iter().find(|p| p.pid == pid).map_or(0, |p| p.start_time)
+    }
+
+    /// The kept-alive entry running as `pid`, here or, while attached, in the daemon
+    fn supervised(&self, pid: u32) -> Option<SupervisedStatus> {
+        let daemon = self.daemon_status.iter().flat_map(|s| s.supervised.iter().cloned());
+        self.supervisor.status().into_iter().chain(daemon).find(|entry| entry.pid == Some(pid))
+    }
+
+    // Restart, and keep looking after the new process if this one was kept alive
+    fn restart(&mut self, pid: u32) -> Result<RestartReport, RestartError> {
+        let report = self.process_restarter.restart_process(Pid::from_u32(pid))?;
+        if let RestartMethod::Respawned { new_pid } | RestartMethod::RolledBack { new_pid } = report.method {
+            self.supervisor.follow(pid, new_pid);
+        }
+        Ok(report)
+    }
+
+    // Stop the process by its stop policy, on a thread so the screen keeps updating
+    fn stop(&mut self, pid: u32) -> Result<String, String> {
+        let process = self.processes.iter().find(|p| p.pid == pid).ok_or_else(|| format!("Process {} not found", pid))?;
+        if pid == std::process::id() {
+            return Err(format!("Process {} is Pulse itself", pid));
+        }
+        if self.stops.iter().any(|job| job.pid == pid) {
+            return Err(format!("Process {} is already being stopped", pid));
+        }
+        let name = process.name.clone();
+        let policy = self.stop_policies.for_process(pid, &self.processes, self.summary.physical_cores);
+        let pids = stop::targets(pid, &policy, &mut self.group_manager, &self.processes);
+        let message = format!("Stopping {} ({}) with {}", name, pid, policy);
+        self.stops.push(StopJob::start(&name, pids, policy));
+        Ok(message)
+    }
+
+    // The status line while stops run, e.g. "Stopping worker (12): SIGINT sent, still running, SIGTERM in 3s"
+    fn stop_progress(&self) -> Option<String> {
+        let first = self.stops.iter().find_map(StopJob::progress)?;
+        match self.stops.len() {
+            1 => Some(first.message()),
+            n => Some(format!("{} (+{} more stopping)", first.message(), n - 1)),
+        }
+    }
+
+    fn finish_stops(&mut self) {
+        let mut i = 0;
+        while i < self.stops.len() {
+            if !self.stops[i].is_finished() {
+                i += 1;
+                continue;
+            }
+            let report = self.stops.remove(i).finish();
+            self.set_status(report.headline());
+            // Show the list without the stopped processes straight away
+            self.last_refresh = None;
+        }
+    }
+
+    // While attached, new processes are kept alive by the daemon, so they stay
+    // looked after once we exit. Processes kept alive here are released here.
+    fn toggle_keep_alive(&mut self, pid: u32) -> Result<String, String> {
+        if let Some(message) = self.supervisor.release(&pid.to_string()) {
+            return Ok(message);
+        }
+        let kept_by_daemon = self.daemon_status.as_ref().is_some_and(|s| s.supervised.iter().any(|e| e.pid == Some(pid)));
+        let result = match &mut self.daemon {
+            Some(daemon) if kept_by_daemon => daemon.release(&pid.to_string()),
+            Some(daemon) => daemon.keep_alive(&pid.to_string(), SupervisorPolicy::new()),
+            None => {
+                return self.supervisor.keep_alive(&pid.to_string(), SupervisorPolicy::new(), &self.processes, self.summary.physical_cores);
+            },
+        };
+        self.sync_daemon_status();
+        result
+    }
+
+    // The daemon evaluates the rules while attached, so the change has to reach it too
+    fn set_rule_threshold(&mut self, name: &str, value: f64) -> bool {
+        let local = self.alerts.set_threshold(name, value);
+        match &mut self.daemon {
+            Some(daemon) => daemon.request(&Request::SetThreshold { rule: name.to_string(), value }).is_ok(),
+            None => local,
+        }
+    }
+
+    fn notify_action(&mut self, outcome: &ActionOutcome) {
+        let color = match outcome {
+            ActionOutcome::Failed(_) => ALERT_COLOR,
+            ActionOutcome::Skipped(_) => MEDIUM_USAGE_COLOR,
+            _ => HELP_COLOR,
+        };
+        self.notifications.push(Notification { message: outcome.message(), color, ticks_left: NOTIFICATION_TICKS });
+    }
+
+    fn handle_alert_confirm_key(&mut self, key: Key) {
+        let confirmed = match key {
+            Key::Char('y') | Key::Char('Y') => true,
+            Key::Char('n') | Key::Char('N') | Key::Esc => false,
+            _ => return,
+        };
+        let pending = match self.pending_actions.pop_front() {
+            Some(pending) => pending,
+            None => {
+                self.input_mode = InputMode::Normal;
+                return;
+            },
+        };
+
+        let action = self
+            .alerts
+            .rules()
+            .iter()
+            .find(|r| r.name == pending.rule)
+            .and_then(|r| r.action.clone());
+        let outcome = match action {
+            Some(action) if confirmed => {
+                let mut context = ActionContext {
+                    controller: &mut self.process_controller,
+                    groups: &mut self.group_manager,
+                    processes: &self.processes,
+                    physical_cores: self.summary.physical_cores,
+                };
+                self.actions.execute(&pending, &action, &mut context)
+            },
+            _ => ActionOutcome::Skipped(format!("{}: declined to {}", pending.rule, pending.describe())),
+        };
+        self.set_status(outcome.message());
+
+        if self.pending_actions.is_empty() {
+            self.input_mode = InputMode::Normal;
+        }
+    }
+
+    /// Show the graph panel for `pids` (possibly none, for system graphs only)
+    fn open_graphs(&mut self, pids: Vec<u32>) {
+        let missing: Vec<String> = pids
+            .iter()
+            .filter(|pid| !self.processes.iter().any(|p| p.pid == **pid))
+            .map(|pid| pid.to_string())
+            .collect();
+        if !missing.is_empty() {
+            self.set_status(format!("Process {} not found", missing.join(", ")));
+            return;
+        }
+        self.graphs.watch(pids, &self.processes);
+        self.backfill_graphs();
+        self.marked.clear();
+        self.input_mode = InputMode::GraphView;
+    }
+
+    // Fill the graph window from the metrics history, if any has been recorded
+    fn backfill_graphs(&mut self) {
+        if let Some(replay) = self.replay.as_mut() {
+            let end = replay.frame().time;
+            let records = replay.records(end.saturating_sub(self.graphs.window().seconds()), end);
+            self.graphs.show_recorded(end, &records, self.summary.physical_cores);
+            return;
+        }
+        // Opened on first use so a recorder started after Pulse is picked up
+        if self.history.is_none() {
+            self.history = History::open(&history::default_history_dir()).ok();
+        }
+        if let Some(history) = self.history.as_mut() {
+            self.graphs.backfill(history);
+        }
+    }
+
+    fn handle_graph_key(&mut self, key: Key) {
+        let window = match key {
+            Key::Char('1') => GraphWindow::OneMinute,
+            Key::Char('5') => GraphWindow::FiveMinutes,
+            Key::Char('h') => GraphWindow::OneHour,
+            Key::Char('\t') => self.graphs.window().next(),
+            Key::Char('V') => {
+                self.start_prompt(InputMode::Graph);
+                self.pid_input = self.graphs.pids().iter().map(|p| p.to_string()).collect::<Vec<_>>().join(",");
+                return;
+            },
+            Key::Esc | Key::Char('\n') | Key::Char('q') => {
+                self.input_mode = InputMode::Normal;
+                return;
+            },
+            _ => return,
+        };
+        self.graphs.set_window(window);
+        self.backfill_graphs();
+    }
+}
+
+fn alert_color(event: &AlertEvent) -> &'static str {
+    match (event.state, event.severity) {
+        (AlertState::Resolved, _) => SEPARATOR_COLOR,
+        (_, Severity::Critical) => ALERT_COLOR,
+        (_, Severity::Warning) => MEDIUM_USAGE_COLOR,
+        (_, Severity::Info) => HELP_COLOR,
+    }
+}
+
+fn state_color(state: ProcessState) -> &'static str {
+    match state {
+        ProcessState::Running => RUNNING_COLOR,
+        ProcessState::Sleep => SLEEP_COLOR,
+        ProcessState::Idle => IDLE_COLOR,
+        ProcessState::Stopped => STOPPED_COLOR,
+        ProcessState::Zombie => ZOMBIE_COLOR,
+        _ => RESET,
+    }
+}
+
+// Pad or cut `text` to the width of `column`
+fn pad(column: &Column, text: &str) -> String {
+    let width = column.width();
+    let text = if text.chars().count() > width {
+        format!("{}...", text.chars().take(width.saturating_sub(3)).collect::<String>())
+    } else {
+        text.to_string()
+    };
+    if column.right_aligned() {
+        format!("{:>width$}", text)
+    } else {
+        format!("{:<width$}", text)
+    }
+}
+
+// "123,456" -> [123, 456]; anything that is not a PID is skipped
+fn parse_pid_list(input: &str) -> Vec<u32> {
+    input
+        .split(',')
+        .filter_map(|part| part.trim().parse::<u32>().ok())
+        .collect()
+}
+
+// Toggle FG/BG for a process by PID, if it is still the one started at `start_time`
+fn toggle_fg_bg(pid: u32, start_time: u64) -> Result<(), String> {
+    let handle = ProcessHandle::open_seen(pid, start_time).map_err(|e| e.to_string())?;
+    let stat = crate::snapshot::read_stat(pid).ok_or("Failed to read process stat file")?;
+
+    if stat.pgrp == stat.tpgid {
+        // Send process to background
+        handle.send(Signal::SIGTTOU).map_err(|e| e.to_string())?;
+    } else {
+        // Bring process to foreground
+        handle.send(Signal::SIGTTIN).map_err(|e| e.to_string())?;
+    }
+
+    Ok(())
+}